@@ -8,7 +8,10 @@ fn main() -> Result<()> {
         let args: Vec<String> = env::args().collect();
         if args.len() != 4 {
             eprintln!("need three arguments: imap-server login password");
-            Err(Error::Bad("need three arguments".into()))
+            Err(Error::Bad {
+                code: None,
+                text: "need three arguments".into(),
+            })
         } else {
             let res = fetch_inbox_top(&args[1], &args[2], &args[3]).await?;
             println!("**result:\n{}", res.unwrap());
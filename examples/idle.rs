@@ -11,7 +11,10 @@ fn main() -> Result<()> {
         let args: Vec<String> = env::args().collect();
         if args.len() != 4 {
             eprintln!("need three arguments: imap-server login password");
-            Err(Error::Bad("need three arguments".into()))
+            Err(Error::Bad {
+                code: None,
+                text: "need three arguments".into(),
+            })
         } else {
             fetch_and_idle(&args[1], &args[2], &args[3]).await?;
             Ok(())
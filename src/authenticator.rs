@@ -1,5 +1,9 @@
 /// This trait allows for pluggable authentication schemes. It is used by `Client::authenticate` to
 /// [authenticate using SASL](https://tools.ietf.org/html/rfc3501#section-6.2.2).
+///
+/// Mechanisms may take any number of rounds: every challenge the server sends is passed to
+/// [`process`](Authenticator::process) until the server completes the `AUTHENTICATE` command, so
+/// an implementation typically keeps track of the state of the exchange itself.
 pub trait Authenticator {
     /// The type of the response to the challenge. This will usually be a `Vec<u8>` or `String`.
     type Response: AsRef<[u8]>;
@@ -7,4 +11,183 @@ pub trait Authenticator {
     /// Each base64-decoded server challenge is passed to `process`.
     /// The returned byte-string is base64-encoded and then sent back to the server.
     fn process(&mut self, challenge: &[u8]) -> Self::Response;
+
+    /// Whether the mechanism starts with a client response, such as `PLAIN` or `XOAUTH2`.
+    ///
+    /// If so, and the server supports `SASL-IR` ([RFC 4959](https://tools.ietf.org/html/rfc4959)),
+    /// the response of `process` to an empty challenge is sent along with the `AUTHENTICATE`
+    /// command, saving a round trip. Defaults to `false`.
+    fn has_initial_response(&self) -> bool {
+        false
+    }
+}
+
+/// The `XOAUTH2` mechanism used by Gmail and Outlook to authenticate with an OAuth 2.0 access
+/// token, see [Google's documentation](https://developers.google.com/gmail/imap/xoauth2-protocol).
+///
+/// The first call to [`Authenticator::process`] returns the initial client response. If
+/// authentication fails, the server sends an error as a challenge, which must be answered with an
+/// empty response before the server completes the command; all later calls return an empty
+/// response for that reason.
+pub struct XOAuth2 {
+    user: String,
+    access_token: String,
+    sent_initial_response: bool,
+}
+
+impl XOAuth2 {
+    /// Creates an authenticator for `user` with the OAuth 2.0 `access_token`.
+    pub fn new(user: &str, access_token: &str) -> Self {
+        XOAuth2 {
+            user: user.to_string(),
+            access_token: access_token.to_string(),
+            sent_initial_response: false,
+        }
+    }
+}
+
+impl Authenticator for XOAuth2 {
+    type Response = Vec<u8>;
+
+    fn has_initial_response(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        if self.sent_initial_response {
+            return Vec::new();
+        }
+        self.sent_initial_response = true;
+        format!(
+            "user={}\x01auth=Bearer {}\x01\x01",
+            self.user, self.access_token
+        )
+        .into_bytes()
+    }
+}
+
+/// The `OAUTHBEARER` mechanism of [RFC 7628](https://tools.ietf.org/html/rfc7628) to authenticate
+/// with an OAuth 2.0 bearer token.
+///
+/// Use it with [`Client::authenticate`](crate::Client::authenticate) and the mechanism name
+/// `"OAUTHBEARER"`. The first call to [`Authenticator::process`] returns the initial client
+/// response. If authentication fails, the server sends an error as a challenge, which is answered
+/// with the `%x01` response required by [RFC 7628, section
+/// 3.2.3](https://tools.ietf.org/html/rfc7628#section-3.2.3).
+pub struct OAuthBearer {
+    user: String,
+    host: String,
+    port: u16,
+    access_token: String,
+    sent_initial_response: bool,
+}
+
+impl OAuthBearer {
+    /// Creates an authenticator for `user` with the OAuth 2.0 `access_token`, connected to the
+    /// server at `host` and `port`.
+    pub fn new(user: &str, host: &str, port: u16, access_token: &str) -> Self {
+        OAuthBearer {
+            user: user.to_string(),
+            host: host.to_string(),
+            port,
+            access_token: access_token.to_string(),
+            sent_initial_response: false,
+        }
+    }
+}
+
+impl Authenticator for OAuthBearer {
+    type Response = Vec<u8>;
+
+    fn has_initial_response(&self) -> bool {
+        true
+    }
+
+    fn process(&mut self, _challenge: &[u8]) -> Self::Response {
+        if self.sent_initial_response {
+            return b"\x01".to_vec();
+        }
+        self.sent_initial_response = true;
+        // `,` and `=` have to be escaped in the GS2 authzid, see RFC 5801, section 4.
+        let user = self.user.replace('=', "=3D").replace(',', "=2C");
+        format!(
+            "n,a={},\x01host={}\x01port={}\x01auth=Bearer {}\x01\x01",
+            user, self.host, self.port, self.access_token
+        )
+        .into_bytes()
+    }
+}
+
+/// The `CRAM-MD5` mechanism of [RFC 2195](https://tools.ietf.org/html/rfc2195), offered by some
+/// legacy servers.
+///
+/// Use it with [`Client::authenticate`](crate::Client::authenticate) and the mechanism name
+/// `"CRAM-MD5"`. The response to the server's challenge is the username followed by the
+/// hex-encoded HMAC-MD5 digest of the challenge, keyed with the shared secret. If the digest
+/// cannot be computed, an empty response is sent, which the server rejects.
+#[cfg(feature = "sasl")]
+pub struct CramMd5 {
+    username: String,
+    secret: String,
+}
+
+#[cfg(feature = "sasl")]
+impl CramMd5 {
+    /// Creates an authenticator for `username` with the shared `secret`.
+    pub fn new(username: &str, secret: &str) -> Self {
+        CramMd5 {
+            username: username.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "sasl")]
+impl Authenticator for CramMd5 {
+    type Response = Vec<u8>;
+
+    fn process(&mut self, challenge: &[u8]) -> Self::Response {
+        let digest = match crate::scram::hmac(
+            openssl::hash::MessageDigest::md5(),
+            self.secret.as_bytes(),
+            challenge,
+        ) {
+            Ok(digest) => digest,
+            Err(err) => {
+                log::error!("unable to compute CRAM-MD5 digest: {}", err);
+                return Vec::new();
+            }
+        };
+        let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!("{} {}", self.username, hex).into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oauthbearer_initial_response() {
+        let mut auth = OAuthBearer::new("user=a,b@example.com", "imap.example.com", 993, "token");
+        assert_eq!(
+            auth.process(b""),
+            b"n,a=user=3Da=2Cb@example.com,\x01host=imap.example.com\x01port=993\x01auth=Bearer token\x01\x01"
+                .to_vec()
+        );
+        assert_eq!(
+            auth.process(b"{\"status\":\"invalid_token\"}"),
+            b"\x01".to_vec()
+        );
+    }
+
+    #[cfg(feature = "sasl")]
+    #[test]
+    fn cram_md5_rfc2195() {
+        let mut auth = CramMd5::new("tim", "tanstaaftanstaaf");
+        assert_eq!(
+            auth.process(b"<1896.697170952@postoffice.reston.mci.net>"),
+            b"tim b913a602c7eda7a495b4e6e7334d3890".to_vec()
+        );
+    }
 }
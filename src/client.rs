@@ -1,22 +1,25 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
 use std::str;
+use std::time::Duration;
 
-use async_native_tls::{TlsConnector, TlsStream};
-use async_std::channel;
-use async_std::io::{self, Read, Write};
-use async_std::net::{TcpStream, ToSocketAddrs};
-use async_std::prelude::*;
-use imap_proto::{RequestId, Response};
+use async_native_tls::TlsConnector;
+use futures::channel::mpsc;
+use futures::io::{self, AsyncRead as Read, AsyncWrite as Write};
+use futures::prelude::*;
+use imap_proto::{AttributeValue, MailboxDatum, RequestId, Response};
 
-use super::authenticator::Authenticator;
+use super::authenticator::{Authenticator, XOAuth2};
 use super::error::{Error, ParseError, Result, ValidateError};
 use super::parse::*;
 use super::types::*;
 use crate::extensions;
-use crate::imap_stream::ImapStream;
+use crate::imap_stream::{DebugHook, Direction, ImapStream};
+use crate::runtime::{self, connect_tcp, connect_tls, TcpStream, TlsStream, ToSocketAddrs};
+use crate::utf7::encode_mailbox_name;
 
 macro_rules! quote {
     ($x:expr) => {
@@ -31,16 +34,53 @@ macro_rules! quote {
 /// a selected mailbox whose status has changed. See the note on [unilateral server responses
 /// in RFC 3501](https://tools.ietf.org/html/rfc3501#section-7). Any such messages are parsed out
 /// and sent on `Session::unsolicited_responses`.
+///
+/// Mailbox names are passed to the commands as plain Unicode strings, such as the ones returned by
+/// [`Name::name`], and are [encoded in modified UTF-7](crate::encode_mailbox_name) before they
+/// are sent to the server, unless `UTF8=ACCEPT` was enabled with [`Session::enable`].
+///
+/// Dropping a `Session` closes the connection abruptly, without flushing the underlying stream,
+/// as that can not be done asynchronously in `Drop`.  Some servers log such disconnects as
+/// errors or penalize them, so sessions should be ended with [`Session::logout`] or
+/// [`Session::close_connection`] instead.
 // Both `Client` and `Session` deref to [`Connection`](struct.Connection.html), the underlying
 // primitives type.
 #[derive(Debug)]
 pub struct Session<T: Read + Write + Unpin + fmt::Debug> {
     pub(crate) conn: Connection<T>,
-    pub(crate) unsolicited_responses_tx: channel::Sender<UnsolicitedResponse>,
+    pub(crate) unsolicited_responses_tx: UnsolicitedSender,
 
     /// Server responses that are not related to the current command. See also the note on
     /// [unilateral server responses in RFC 3501](https://tools.ietf.org/html/rfc3501#section-7).
-    pub unsolicited_responses: channel::Receiver<UnsolicitedResponse>,
+    ///
+    /// The channel holds up to 100 responses. Once it is full, further unsolicited responses are
+    /// dropped until some are received, so that commands never wait for the channel to be read.
+    /// How many were dropped is returned by [`Session::dropped_unsolicited_count`].
+    /// Responses that have already arrived are taken with `try_recv`, or awaited as a
+    /// [`Stream`], see [`Session::unsolicited_responses_stream`].
+    pub unsolicited_responses: mpsc::Receiver<UnsolicitedResponse>,
+
+    /// The capabilities last returned by [`Session::capabilities`], or sent by the server when
+    /// logging in.
+    capabilities: Option<Capabilities>,
+
+    /// The interval set with [`Session::set_keepalive`].
+    keepalive: Option<Duration>,
+
+    /// Whether `UTF8=ACCEPT` was enabled with [`Session::enable`].
+    utf8_enabled: bool,
+
+    /// Whether `IMAP4rev2` was enabled with [`Session::enable`].
+    rev2_enabled: bool,
+
+    /// Whether `QRESYNC` was enabled with [`Session::enable`].
+    qresync_enabled: bool,
+
+    /// The mailbox opened with [`Session::select`] or [`Session::examine`].
+    selected: Option<String>,
+
+    /// Whether events were requested with [`Session::notify`].
+    notifying: bool,
 }
 
 impl<T: Read + Write + Unpin + fmt::Debug> Unpin for Session<T> {}
@@ -55,6 +95,8 @@ impl<T: Read + Write + Unpin + fmt::Debug> Unpin for Connection<T> {}
 #[derive(Debug)]
 pub struct Client<T: Read + Write + Unpin + fmt::Debug> {
     conn: Connection<T>,
+    capabilities: Option<Capabilities>,
+    greeting: Option<Greeting>,
 }
 
 /// The underlying primitives type. Both `Client`(unauthenticated) and `Session`(after succesful
@@ -101,7 +143,9 @@ impl<T: Read + Write + Unpin + fmt::Debug> DerefMut for Session<T> {
 /// Connect to a server using a TLS-encrypted connection.
 ///
 /// The returned [`Client`] is unauthenticated; to access session-related methods (through
-/// [`Session`]), use [`Client::login`] or [`Client::authenticate`].
+/// [`Session`]), use [`Client::login`] or [`Client::authenticate`].  If the server greets the
+/// client with `PREAUTH`, no login is needed and [`Client::into_preauth_session`] returns the
+/// session.
 ///
 /// The domain must be passed in separately from the `TlsConnector` so that the certificate of the
 /// IMAP server can be validated.
@@ -123,20 +167,10 @@ pub async fn connect<A: ToSocketAddrs, S: AsRef<str>>(
     domain: S,
     ssl_connector: TlsConnector,
 ) -> Result<Client<TlsStream<TcpStream>>> {
-    let stream = TcpStream::connect(addr).await?;
-    let ssl_stream = ssl_connector.connect(domain.as_ref(), stream).await?;
-
-    let mut client = Client::new(ssl_stream);
-    let _greeting = match client.read_response().await {
-        Some(greeting) => greeting,
-        None => {
-            return Err(Error::Bad(
-                "could not read server Greeting after connect".into(),
-            ));
-        }
-    };
+    let stream = connect_tcp(addr).await?;
+    let ssl_stream = connect_tls(&ssl_connector, domain.as_ref(), stream).await?;
 
-    Ok(client)
+    Client::from_stream(ssl_stream).await
 }
 
 impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
@@ -144,17 +178,51 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
     ///
     /// The domain parameter is required to perform hostname verification.
     pub async fn secure<S: AsRef<str>>(
-        mut self,
+        self,
         domain: S,
         ssl_connector: TlsConnector,
     ) -> Result<Client<TlsStream<T>>> {
+        self.starttls(
+            |stream| async move { connect_tls(&ssl_connector, domain.as_ref(), stream).await },
+        )
+        .await
+    }
+
+    /// Issues the [`STARTTLS` command](https://tools.ietf.org/html/rfc3501#section-6.2.1) and
+    /// upgrades the connection with `upgrade`, which performs the TLS handshake over the plaintext
+    /// stream. This allows using any TLS implementation, for example with `async-tls`:
+    ///
+    /// ```ignore
+    /// let connector = async_tls::TlsConnector::default();
+    /// let client = client
+    ///     .starttls(|stream| connector.connect("imap.example.org", stream))
+    ///     .await?;
+    /// ```
+    ///
+    /// Nothing is read from the connection between the server's response to `STARTTLS` and the
+    /// handshake. If the server sent any data after its response, it was not protected by TLS and
+    /// may have been injected by an attacker, so the upgrade fails instead of processing it. The
+    /// returned client starts out without any [capabilities](Client::capabilities) known, as
+    /// they have to be requested again over the secure connection.
+    pub async fn starttls<F, Fut, S, E>(mut self, upgrade: F) -> Result<Client<S>>
+    where
+        F: FnOnce(T) -> Fut,
+        Fut: Future<Output = ::std::result::Result<S, E>>,
+        S: Read + Write + Unpin + fmt::Debug + Send,
+        E: Into<Error>,
+    {
         self.run_command_and_check_ok("STARTTLS", None).await?;
-        let ssl_stream = ssl_connector
-            .connect(domain.as_ref(), self.conn.stream.into_inner())
-            .await?;
+        if self.conn.stream.has_buffered_data() {
+            return Err(Error::Io(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected data after STARTTLS response",
+            )));
+        }
+        let tls_stream = upgrade(self.conn.stream.into_inner())
+            .await
+            .map_err(Into::into)?;
 
-        let client = Client::new(ssl_stream);
-        Ok(client)
+        Ok(Client::new(tls_stream))
     }
 }
 
@@ -181,7 +249,8 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
     /// rustls.rs in the examples/ directory.
     ///
     /// This method primarily exists for writing tests that mock the underlying transport, but can
-    /// also be used to support IMAP over custom tunnels.
+    /// also be used to support IMAP over custom tunnels.  It does not read the server's greeting;
+    /// use [`Client::from_stream`] for a connection that was just established.
     pub fn new(stream: T) -> Client<T> {
         let stream = ImapStream::new(stream);
 
@@ -190,7 +259,100 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
                 stream,
                 request_ids: IdGenerator::new(),
             },
+            capabilities: None,
+            greeting: None,
+        }
+    }
+
+    /// Creates a new client over the given stream, which is already connected to the server, and
+    /// reads the server's greeting.  This is what [`connect`] does after establishing the TLS
+    /// connection, so any transport can be used instead, e.g. a TLS implementation of your
+    /// choice, an SSH port forward, a [proxy tunnel](crate::connect_via_socks5) or an in-memory
+    /// stream.
+    ///
+    /// The greeting is available from [`Client::greeting`], and capabilities the server lists in
+    /// it are remembered, see [`Client::capabilities`].  Fails with [`Error::Bye`] if the server
+    /// rejects the connection with a `BYE` greeting, or if the connection is closed before the
+    /// greeting arrives.
+    pub async fn from_stream(stream: T) -> Result<Client<T>> {
+        let mut client = Client::new(stream);
+        let greeting = match client.read_response().await {
+            Some(greeting) => greeting?,
+            None => {
+                return Err(Error::Bad {
+                    code: None,
+                    text: "could not read server Greeting after connect".into(),
+                });
+            }
+        };
+        client.capabilities = match greeting.parsed() {
+            Response::Data { code, .. } => capabilities_code(code.as_ref()),
+            other => {
+                return Err(Error::Bad {
+                    code: None,
+                    text: format!("unexpected server Greeting: {:?}", other),
+                });
+            }
+        };
+        client.greeting = Some(Greeting::new(greeting));
+
+        Ok(client)
+    }
+
+    /// The greeting the server sent when the connection was established, if the client was
+    /// created by [`connect`] or [`Client::from_stream`].
+    pub fn greeting(&self) -> Option<&Greeting> {
+        self.greeting.as_ref()
+    }
+
+    /// Returns the session right away if the server's greeting was `PREAUTH`, i.e. the connection
+    /// is already authenticated, e.g. by a TLS client certificate or because the server was
+    /// started for a local user, and `LOGIN` would fail.  Otherwise the client is returned, to log
+    /// in as usual.
+    ///
+    /// Capabilities listed in the greeting are carried over to the session.
+    ///
+    /// ```no_run
+    /// # fn main() -> async_imap::error::Result<()> {
+    /// # async_std::task::block_on(async {
+    /// let tls = async_native_tls::TlsConnector::new();
+    /// let client = async_imap::connect(("imap.example.org", 993), "imap.example.org", tls).await?;
+    /// let session = match client.into_preauth_session() {
+    ///     Ok(session) => session,
+    ///     Err(client) => client.login("me", "secret").await.map_err(|e| e.0)?,
+    /// };
+    /// # Ok(())
+    /// # }) }
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn into_preauth_session(self) -> std::result::Result<Session<T>, Client<T>> {
+        if !self.greeting.as_ref().is_some_and(Greeting::is_preauth) {
+            return Err(self);
         }
+        let mut session = Session::new(self.conn);
+        session.capabilities = self.capabilities;
+        Ok(session)
+    }
+
+    /// Sets how long to wait for data from the server before a command fails with
+    /// [`Error::Timeout`], see [`Session::set_timeout`]. The timeout carries over to the session
+    /// after logging in.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.conn.stream.set_read_timeout(timeout);
+    }
+
+    /// Passes each line sent to and received from the server to `hook`, see
+    /// [`Session::set_debug`]. The hook carries over to the session after logging in.
+    pub fn set_debug<F>(&mut self, hook: F)
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.conn.stream.set_debug(Some(DebugHook::new(hook)));
+    }
+
+    /// Removes the hook set with [`Client::set_debug`].
+    pub fn clear_debug(&mut self) {
+        self.conn.stream.set_debug(None);
     }
 
     /// Convert this Client into the raw underlying stream.
@@ -205,6 +367,10 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
     /// prompting the user for credetials), ownership of the original `Client` needs to be
     /// transferred back to the caller.
     ///
+    /// If the capabilities known from [`Client::capabilities`] include `LOGINDISABLED`,
+    /// [`Error::LoginDisabled`] is returned without sending the credentials, as the server does
+    /// not accept them before the connection is secured with [`Client::starttls`].
+    ///
     /// ```no_run
     /// # fn main() -> async_imap::error::Result<()> {
     /// # async_std::task::block_on(async {
@@ -235,19 +401,89 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
         username: U,
         password: P,
     ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        if self.has_cached_capability("LOGINDISABLED") {
+            return Err((Error::LoginDisabled, self));
+        }
         let u = ok_or_unauth_client_err!(validate_str(username.as_ref()), self);
         let p = ok_or_unauth_client_err!(validate_str(password.as_ref()), self);
-        ok_or_unauth_client_err!(
-            self.run_command_and_check_ok(&format!("LOGIN {} {}", u, p), None)
-                .await,
-            self
-        );
+        let done = match self
+            .run_command_and_check_ok(&format!("LOGIN {} {}", u, p), None)
+            .await
+        {
+            Ok(done) => done,
+            Err(err) => {
+                let err = self.explain_login_failure(err).await;
+                // The server may echo the command, with the password quoted.
+                let err = err.redact(&[password.as_ref(), &p[1..p.len() - 1]]);
+                return Err((err, self));
+            }
+        };
+
+        let mut session = Session::new(self.conn);
+        session.capabilities = capabilities_code(done.code());
+        Ok(session)
+    }
+
+    /// Points out `LOGINDISABLED` if the server rejected a `LOGIN` command because of it.
+    async fn explain_login_failure(&mut self, err: Error) -> Error {
+        if !matches!(err, Error::No { .. } | Error::Bad { .. }) {
+            return err;
+        }
+        if !self.has_capability("LOGINDISABLED").await.unwrap_or(false) {
+            return err;
+        }
+        let hint = "the server advertises LOGINDISABLED, use STARTTLS or AUTHENTICATE instead";
+        match err {
+            Error::No { code, text } => Error::No {
+                code,
+                text: format!("{} ({})", text, hint),
+            },
+            Error::Bad { code, text } => Error::Bad {
+                code,
+                text: format!("{} ({})", text, hint),
+            },
+            err => err,
+        }
+    }
+
+    /// The [`CAPABILITY` command](https://tools.ietf.org/html/rfc3501#section-6.1.1) requests a
+    /// listing of capabilities that the server supports before authentication, such as the
+    /// available `AUTH=` mechanisms. See [`Capabilities`] for further details.
+    ///
+    /// The result is remembered by the client, e.g. to send the credentials of
+    /// [`Client::authenticate_plain`] along with the command if the server supports `SASL-IR`.
+    /// If the capabilities are already known, for example from the `CAPABILITY` response code
+    /// in the server's greeting, they are returned without sending the command.
+    pub async fn capabilities(&mut self) -> Result<Capabilities> {
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
+        }
+        // Unsolicited responses before authentication are of no interest.
+        let (tx, _rx) = mpsc::channel(0);
+        let id = self.run_command("CAPABILITY").await?;
+        let c = parse_capabilities(&mut self.conn.stream, UnsolicitedSender::new(tx), id).await?;
+        self.capabilities = Some(c.clone());
+        Ok(c)
+    }
+
+    /// Whether the server supports `capability`, such as `STARTTLS` or `AUTH=PLAIN`, according to
+    /// [`Client::capabilities`], which are only requested if they are not known yet.
+    pub async fn has_capability<S: AsRef<str>>(&mut self, capability: S) -> Result<bool> {
+        Ok(self.capabilities().await?.has_str(capability))
+    }
 
-        Ok(Session::new(self.conn))
+    /// Whether the capabilities last returned by [`Client::capabilities`] include `capability`.
+    fn has_cached_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|caps| caps.has_str(capability))
     }
 
     /// Authenticate with the server using the given custom `authenticator` to handle the server's
-    /// challenge.
+    /// challenges. Every challenge is passed to the authenticator until the server completes the
+    /// command, so mechanisms with several rounds are supported. If the authenticator
+    /// [has an initial response](Authenticator::has_initial_response) and the capabilities known
+    /// from [`Client::capabilities`] include `SASL-IR`, it is sent along with the command.
     ///
     /// ```no_run
     /// struct OAuth2 {
@@ -292,23 +528,180 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
     pub async fn authenticate<A: Authenticator, S: AsRef<str>>(
         mut self,
         auth_type: S,
-        authenticator: A,
+        mut authenticator: A,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        let mut secrets = Vec::new();
+        let command =
+            if authenticator.has_initial_response() && self.has_cached_capability("SASL-IR") {
+                let response = authenticator.process(&[]);
+                // An empty initial response is sent as `=`, see RFC 4959, section 3.
+                let response = if response.as_ref().is_empty() {
+                    "=".to_string()
+                } else {
+                    base64::encode(response)
+                };
+                let command = format!("AUTHENTICATE {} {}", auth_type.as_ref(), response);
+                secrets.push(response);
+                command
+            } else {
+                format!("AUTHENTICATE {}", auth_type.as_ref())
+            };
+        let id = ok_or_unauth_client_err!(self.run_command(&command).await, self);
+        let session = self.do_auth_handshake(id, secrets, authenticator).await?;
+        Ok(session)
+    }
+
+    /// Authenticate with `username` and `password` using the given SCRAM `mechanism`, see
+    /// [RFC 5802](https://tools.ietf.org/html/rfc5802).
+    ///
+    /// Channel binding is not used. Authentication only succeeds if the server proves that it
+    /// knows the password as well; if it does not, an [`Error::Sasl`] is returned, even if the
+    /// server accepted the credentials.
+    #[cfg(feature = "sasl")]
+    pub async fn authenticate_scram(
+        self,
+        username: &str,
+        password: &str,
+        mechanism: crate::ScramMechanism,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        let scram = ok_or_unauth_client_err!(
+            crate::scram::Scram::new(mechanism, username, password),
+            self
+        );
+        self.do_authenticate_scram(scram).await
+    }
+
+    #[cfg(feature = "sasl")]
+    async fn do_authenticate_scram(
+        mut self,
+        mut scram: crate::scram::Scram,
     ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
         let id = ok_or_unauth_client_err!(
-            self.run_command(&format!("AUTHENTICATE {}", auth_type.as_ref()))
+            self.run_command(&format!("AUTHENTICATE {}", scram.mechanism().name()))
                 .await,
             self
         );
-        let session = self.do_auth_handshake(id, authenticator).await?;
+        let session = self
+            .do_sasl_handshake(id, Vec::new(), |challenge| scram.step(challenge))
+            .await?;
+        if !scram.is_complete() {
+            let err = Error::Sasl("server did not send its signature".into());
+            let client = Client {
+                conn: session.conn,
+                capabilities: None,
+                greeting: None,
+            };
+            return Err((err, client));
+        }
         Ok(session)
     }
 
+    /// Authenticate using the `PLAIN` mechanism of [RFC 4616](https://tools.ietf.org/html/rfc4616),
+    /// acting as `authzid` (or as `authcid` if `None`) with the credentials `authcid` and
+    /// `password`.
+    ///
+    /// If the capabilities known from [`Client::capabilities`] include `SASL-IR`, the credentials
+    /// are sent along with the `AUTHENTICATE` command, saving a round trip. Otherwise they are
+    /// sent in response to the server's continuation request. Inputs containing NUL characters
+    /// are rejected with an [`Error::Validate`], as they would be misread by the server.
+    pub async fn authenticate_plain(
+        mut self,
+        authzid: Option<&str>,
+        authcid: &str,
+        password: &str,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        let inputs = [authzid.unwrap_or(""), authcid, password];
+        if inputs.iter().any(|input| input.contains('\0')) {
+            return Err((Error::Validate(ValidateError('\0')), self));
+        }
+        let mut credentials = Some(inputs.join("\0").into_bytes());
+        let mut secrets = vec![password.to_string()];
+
+        let command = if self.has_cached_capability("SASL-IR") {
+            let initial_response = credentials.take().map(base64::encode).unwrap_or_default();
+            let command = format!("AUTHENTICATE PLAIN {}", initial_response);
+            secrets.push(initial_response);
+            command
+        } else {
+            "AUTHENTICATE PLAIN".to_string()
+        };
+        let id = ok_or_unauth_client_err!(self.run_command(&command).await, self);
+        self.do_sasl_handshake(id, secrets, |_| {
+            credentials
+                .take()
+                .ok_or_else(|| Error::Sasl("unexpected challenge".into()))
+        })
+        .await
+    }
+
+    /// Authenticate `user` with an OAuth 2.0 `access_token` using the `XOAUTH2` mechanism
+    /// supported by Gmail and Outlook.
+    ///
+    /// The token is sent as the initial response of the `AUTHENTICATE` command, so the server
+    /// must support `SASL-IR` ([RFC 4959](https://tools.ietf.org/html/rfc4959)), as all servers
+    /// offering `XOAUTH2` do. If the token is rejected, the error challenge sent by the server is
+    /// acknowledged and the server's `NO` response is returned.
+    pub async fn authenticate_xoauth2(
+        mut self,
+        user: &str,
+        access_token: &str,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        let mut authenticator = XOAuth2::new(user, access_token);
+        let initial_response = base64::encode(authenticator.process(&[]));
+        let id = ok_or_unauth_client_err!(
+            self.run_command(&format!("AUTHENTICATE XOAUTH2 {}", initial_response))
+                .await,
+            self
+        );
+        let secrets = vec![access_token.to_string(), initial_response];
+        self.do_auth_handshake(id, secrets, authenticator).await
+    }
+
     /// This func does the handshake process once the authenticate command is made.
     async fn do_auth_handshake<A: Authenticator>(
-        mut self,
+        self,
         id: RequestId,
+        secrets: Vec<String>,
         mut authenticator: A,
     ) -> ::std::result::Result<Session<T>, (Error, Client<T>)> {
+        self.do_sasl_handshake(id, secrets, |challenge| {
+            Ok(authenticator.process(challenge).as_ref().to_vec())
+        })
+        .await
+    }
+
+    /// Answers each server challenge with the response computed by `step`, until the server
+    /// completes the `AUTHENTICATE` command. If `step` fails, the exchange is cancelled.
+    ///
+    /// The `secrets` already sent with the command, and the responses sent during the exchange,
+    /// are redacted from the returned error.
+    async fn do_sasl_handshake<F>(
+        self,
+        id: RequestId,
+        mut secrets: Vec<String>,
+        mut step: F,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)>
+    where
+        F: FnMut(&[u8]) -> Result<Vec<u8>>,
+    {
+        let result = self
+            .sasl_exchange(id, |challenge| {
+                let response = step(challenge)?;
+                secrets.push(base64::encode(&response));
+                Ok(response)
+            })
+            .await;
+        result.map_err(|(err, client)| (err.redact(&secrets), client))
+    }
+
+    async fn sasl_exchange<F>(
+        mut self,
+        id: RequestId,
+        mut step: F,
+    ) -> ::std::result::Result<Session<T>, (Error, Client<T>)>
+    where
+        F: FnMut(&[u8]) -> Result<Vec<u8>>,
+    {
         // explicit match blocks neccessary to convert error to tuple and not bind self too
         // early (see also comment on `login`)
         loop {
@@ -326,20 +719,32 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
                         } else {
                             Vec::new()
                         };
-                        let raw_response = &mut authenticator.process(&challenge);
-                        let auth_response = base64::encode(raw_response);
-
-                        ok_or_unauth_client_err!(
-                            self.conn.run_command_untagged(&auth_response).await,
-                            self
-                        );
+                        match step(&challenge) {
+                            Ok(raw_response) => {
+                                let auth_response = base64::encode(raw_response);
+                                ok_or_unauth_client_err!(
+                                    self.conn.run_command_untagged(&auth_response).await,
+                                    self
+                                );
+                            }
+                            Err(err) => {
+                                // The server answers the cancellation with a tagged BAD.
+                                let _ = self.conn.run_command_untagged("*").await;
+                                let _ = self.conn.read_done_ok(&id, None).await;
+                                return Err((err, self));
+                            }
+                        }
                     }
                     _ => {
-                        ok_or_unauth_client_err!(
-                            self.check_done_ok_from(&id, None, res).await,
+                        let done = ok_or_unauth_client_err!(
+                            self.read_done_ok_from(&id, None, res).await,
                             self
                         );
-                        return Ok(Session::new(self.conn));
+                        let mut session = Session::new(self.conn);
+                        if let Response::Done { code, .. } = done.parsed() {
+                            session.capabilities = capabilities_code(code.as_ref());
+                        }
+                        return Ok(session);
                     }
                 }
             } else {
@@ -350,22 +755,75 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Client<T> {
 }
 
 impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
-    unsafe_pinned!(conn: Connection<T>);
-
     pub(crate) fn get_stream(self: Pin<&mut Self>) -> Pin<&mut ImapStream<T>> {
-        self.conn().stream()
+        Pin::new(&mut self.get_mut().conn.stream)
     }
 
     // not public, just to avoid duplicating the channel creation code
     fn new(conn: Connection<T>) -> Self {
-        let (tx, rx) = channel::bounded(100);
+        // The shared sender adds one slot to the buffer, for 100 responses in total.
+        let (tx, rx) = mpsc::channel(99);
         Session {
             conn,
             unsolicited_responses: rx,
-            unsolicited_responses_tx: tx,
+            unsolicited_responses_tx: UnsolicitedSender::new(tx),
+            capabilities: None,
+            keepalive: None,
+            utf8_enabled: false,
+            rev2_enabled: false,
+            qresync_enabled: false,
+            selected: None,
+            notifying: false,
         }
     }
 
+    /// Returns a stream of the [unsolicited responses](Session::unsolicited_responses), for
+    /// example to wait for them alongside other events.  It reads from
+    /// [`Session::unsolicited_responses`], so each response is received only once.
+    pub fn unsolicited_responses_stream(
+        &mut self,
+    ) -> impl Stream<Item = UnsolicitedResponse> + Unpin + '_ {
+        &mut self.unsolicited_responses
+    }
+
+    /// Returns the number of unsolicited responses that were dropped because
+    /// [`Session::unsolicited_responses`] was full or closed, e.g. to resynchronize the selected
+    /// mailbox if the count grew since the responses were last read.
+    pub fn dropped_unsolicited_count(&self) -> u64 {
+        self.unsolicited_responses_tx.dropped()
+    }
+
+    /// Sets how long to wait for data from the server before a command fails with
+    /// [`Error::Timeout`], or `None` (the default) to wait forever.
+    ///
+    /// The timeout applies to each read, so large responses that arrive slowly but steadily do
+    /// not time out. It is suspended while waiting for changes with
+    /// [`IDLE`](extensions::idle::Handle), as the server legitimately stays silent then. After a
+    /// timeout the session can still be used, e.g. to check the connection with
+    /// [`Session::noop`]; the rest of a late response is then handled as unsolicited data.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.conn.stream.set_read_timeout(timeout);
+    }
+
+    /// Passes each line sent to and received from the server to `hook`, for debugging the
+    /// exchange on the wire.
+    ///
+    /// Lines are passed without their trailing `CRLF`. The credentials of `LOGIN`, the initial
+    /// response of `AUTHENTICATE` and the responses to authentication challenges are replaced by
+    /// `***`, and literals larger than 1 KiB, such as message bodies, are replaced by their
+    /// length in the form `[<length> bytes]`.
+    pub fn set_debug<F>(&mut self, hook: F)
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.conn.stream.set_debug(Some(DebugHook::new(hook)));
+    }
+
+    /// Removes the hook set with [`Session::set_debug`].
+    pub fn clear_debug(&mut self) {
+        self.conn.stream.set_debug(None);
+    }
+
     /// Selects a mailbox
     ///
     /// The `SELECT` command selects a mailbox so that messages in the mailbox can be accessed.
@@ -384,17 +842,26 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// [`Connection::run_command_and_read_response`], you *may* see additional untagged `RECENT`,
     /// `EXISTS`, `FETCH`, and `EXPUNGE` responses. You can get them from the
     /// `unsolicited_responses` channel of the [`Session`](struct.Session.html).
+    ///
+    /// Responses that do not belong to `SELECT` at all, such as `LIST` responses, as well as
+    /// untagged `NO` and `BAD` warnings, are sent to that channel too instead of failing the
+    /// command.
     pub async fn select<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<Mailbox> {
-        // TODO: also note READ/WRITE vs READ-only mode!
         let id = self
-            .run_command(&format!("SELECT {}", validate_str(mailbox_name.as_ref())?))
+            .run_command(&format!(
+                "SELECT {}",
+                self.validate_mailbox(mailbox_name.as_ref())?
+            ))
             .await?;
+        self.set_selected(None);
         let mbox = parse_mailbox(
             &mut self.conn.stream,
             self.unsolicited_responses_tx.clone(),
             id,
         )
         .await?;
+        self.unsolicited_responses_tx.set_exists(mbox.exists);
+        self.set_selected(Some(mailbox_name.as_ref().to_string()));
 
         Ok(mbox)
     }
@@ -405,31 +872,182 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// in particular, messagess cannot lose [`Flag::Recent`] in an examined mailbox.
     pub async fn examine<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<Mailbox> {
         let id = self
-            .run_command(&format!("EXAMINE {}", validate_str(mailbox_name.as_ref())?))
+            .run_command(&format!(
+                "EXAMINE {}",
+                self.validate_mailbox(mailbox_name.as_ref())?
+            ))
             .await?;
-        let mbox = parse_mailbox(
+        self.set_selected(None);
+        let mut mbox = parse_mailbox(
             &mut self.conn.stream,
             self.unsolicited_responses_tx.clone(),
             id,
         )
         .await?;
+        mbox.read_only = true;
+        self.unsolicited_responses_tx.set_exists(mbox.exists);
+        self.set_selected(Some(mailbox_name.as_ref().to_string()));
 
         Ok(mbox)
     }
 
+    /// Selects the mailbox of `state` and returns what changed in it since the synchronization
+    /// `state` was returned by, so that a local copy of the mailbox can be brought up to date.
+    ///
+    /// The best method the server supports is used:
+    ///
+    ///  - With [`QRESYNC`](https://tools.ietf.org/html/rfc7162#section-3.2), which is enabled if
+    ///    no mailbox is selected yet, `SELECT` itself reports the changed and expunged messages.
+    ///  - With `CONDSTORE`, the changed messages are fetched with `CHANGEDSINCE`, and the
+    ///    expunged ones found with `UID SEARCH ALL`.
+    ///  - Otherwise the flags of all messages are fetched.
+    ///
+    /// If the UID validity of the mailbox changed, or `state` is from [`SyncState::new`],
+    /// everything is synchronized from scratch.  Store [`SyncDelta::state`] for the next call.
+    pub async fn incremental_sync(&mut self, state: SyncState) -> Result<SyncDelta> {
+        self.capabilities().await?;
+        // `ENABLE` is only allowed while no mailbox is selected.
+        if self.has_cached_capability("QRESYNC") && !self.qresync_enabled && self.selected.is_none()
+        {
+            self.enable(&["QRESYNC"]).await?;
+        }
+        let condstore = self.qresync_enabled || self.has_cached_capability("CONDSTORE");
+
+        let mut command = format!("SELECT {}", self.validate_mailbox(&state.mailbox)?);
+        let qresync = match (state.uid_validity, state.highest_modseq) {
+            (Some(uid_validity), Some(modseq)) if self.qresync_enabled => {
+                command.push_str(&format!(" (QRESYNC ({} {}", uid_validity, modseq));
+                if !state.known_uids.is_empty() {
+                    let known: SequenceSet<Uid> = state.known_uids.iter().copied().collect();
+                    command.push_str(&format!(" {}", known));
+                }
+                command.push_str("))");
+                true
+            }
+            _ => {
+                if condstore {
+                    command.push_str(" (CONDSTORE)");
+                }
+                false
+            }
+        };
+        let id = self.run_command(&command).await?;
+        self.set_selected(None);
+        let (mailbox, changes) = parse_mailbox_changes(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+            qresync,
+        )
+        .await?;
+        self.unsolicited_responses_tx.set_exists(mailbox.exists);
+        self.set_selected(Some(state.mailbox.clone()));
+
+        let valid = state.uid_validity.is_some() && state.uid_validity == mailbox.uid_validity;
+        let known: HashSet<Uid> = if valid {
+            state.known_uids.iter().copied().collect()
+        } else {
+            HashSet::new()
+        };
+        let modseq = state
+            .highest_modseq
+            .filter(|_| valid && condstore && !mailbox.no_modseq);
+        let (flags, vanished) = if qresync && valid {
+            let mut flags = Vec::new();
+            let mut vanished = HashSet::new();
+            for resp in changes {
+                if let Response::Vanished { uids, .. } = resp.parsed() {
+                    vanished.extend(
+                        known
+                            .iter()
+                            .filter(|uid| uids.iter().any(|range| range.contains(&uid.0))),
+                    );
+                } else {
+                    flags.extend(uid_and_flags(&Fetch::new(resp)));
+                }
+            }
+            (flags, vanished)
+        } else if let Some(modseq) = modseq {
+            let flags = self.fetch_all_flags(Some(modseq)).await?;
+            let current = self.uid_search("ALL").await?;
+            (flags, known.difference(&current).copied().collect())
+        } else {
+            let flags = self.fetch_all_flags(None).await?;
+            let current: HashSet<Uid> = flags.iter().map(|(uid, _)| *uid).collect();
+            (flags, known.difference(&current).copied().collect())
+        };
+
+        let (mut new, mut changed): (Vec<_>, Vec<_>) =
+            flags.into_iter().partition(|(uid, _)| !known.contains(uid));
+        new.sort_by_key(|(uid, _)| *uid);
+        new.dedup_by_key(|(uid, _)| *uid);
+        changed.sort_by_key(|(uid, _)| *uid);
+        changed.dedup_by_key(|(uid, _)| *uid);
+        // Without a valid UID validity, none of the known messages can be trusted.
+        let mut vanished: Vec<Uid> = if valid {
+            vanished.into_iter().collect()
+        } else {
+            state.known_uids.clone()
+        };
+        vanished.sort();
+
+        let mut known_uids: Vec<Uid> = known
+            .into_iter()
+            .filter(|uid| vanished.binary_search(uid).is_err())
+            .chain(new.iter().map(|(uid, _)| *uid))
+            .collect();
+        known_uids.sort();
+        Ok(SyncDelta {
+            uid_validity_changed: state.uid_validity.is_some() && !valid,
+            new,
+            changed,
+            vanished,
+            state: SyncState {
+                mailbox: state.mailbox,
+                uid_validity: mailbox.uid_validity,
+                highest_modseq: mailbox.highest_modseq,
+                known_uids,
+            },
+            mailbox,
+        })
+    }
+
     /// Fetch retreives data associated with a set of messages in the mailbox.
     ///
+    /// The messages are given by their sequence numbers, as a [`SequenceSet`] or anything that
+    /// converts into one, such as a [`Seq`], a range of them or a string like `"1,4:7"`. Use
+    /// [`Session::uid_fetch`] to fetch messages by [`Uid`]. Like the other commands taking a
+    /// set of messages, this fails with [`Error::InvalidSequenceSet`] without sending anything if
+    /// the set is empty or not valid.
+    ///
     /// Note that the server *is* allowed to unilaterally include `FETCH` responses for other
     /// messages in the selected mailbox whose status has changed. See the note on [unilateral
     /// server responses in RFC 3501](https://tools.ietf.org/html/rfc3501#section-7).
     ///
-    /// `query` is a list of "data items" (space-separated in parentheses if `>1`). There are three
-    /// "macro items" which specify commonly-used sets of data items, and can be used instead of
-    /// data items.  A macro must be used by itself, and not in conjunction with other macros or
-    /// data items. They are:
+    /// The messages are returned in the order in which the server sent them, one `FETCH`
+    /// response after the other without any buffering.  Servers usually send them by ascending
+    /// sequence number, but are not required to, so sort the results if the order matters.
+    /// Several `FETCH` responses for the same message, e.g. a unilateral flag change after the
+    /// requested data, are returned separately.
+    ///
+    /// `query` is a [`FetchQuery`], which builds the query from its data items, or a string as
+    /// described below.  A [`FetchQuery`] fetches body sections with `BODY.PEEK`, so that the
+    /// messages are not marked as [`Flag::Seen`], unless [`FetchQuery::mark_seen`] is used;
+    /// `BODY[<section>]` in a query string does mark them as seen.
+    ///
+    /// As a string, `query` is a list of "data items" (space-separated in parentheses if `>1`).
+    /// There are three "macro items" which specify commonly-used sets of data items, and can be
+    /// used instead of data items.  A macro must be used by itself, and not in conjunction with
+    /// other macros or data items. They are:
     ///
     ///  - `ALL`: equivalent to: `(FLAGS INTERNALDATE RFC822.SIZE ENVELOPE)`
     ///  - `FAST`: equivalent to: `(FLAGS INTERNALDATE RFC822.SIZE)`
+    ///  - `FULL`: equivalent to: `(FLAGS INTERNALDATE RFC822.SIZE ENVELOPE BODY)`
+    ///
+    /// The server answers with the data items the macro stands for, so they are available from
+    /// [`Fetch`] as if they were requested one by one.  [`FetchQuery::fast`],
+    /// [`FetchQuery::all`] and [`FetchQuery::full`] add them to a query that also contains other
+    /// items.
     ///
     /// The currently defined data items that can be fetched are listen [in the
     /// RFC](https://tools.ietf.org/html/rfc3501#section-6.4.5), but here are some common ones:
@@ -438,36 +1056,46 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///  - `INTERNALDATE`: The internal date of the message.
     ///  - `BODY[<section>]`:
     ///
-    ///     The text of a particular body section.  The section specification is a set of zero or
-    ///     more part specifiers delimited by periods.  A part specifier is either a part number
-    ///     (see RFC) or one of the following: `HEADER`, `HEADER.FIELDS`, `HEADER.FIELDS.NOT`,
-    ///     `MIME`, and `TEXT`.  An empty section specification (i.e., `BODY[]`) refers to the
-    ///     entire message, including the header.
-    ///
-    ///     The `HEADER`, `HEADER.FIELDS`, and `HEADER.FIELDS.NOT` part specifiers refer to the
-    ///     [RFC-2822](https://tools.ietf.org/html/rfc2822) header of the message or of an
-    ///     encapsulated [MIME-IMT](https://tools.ietf.org/html/rfc2046)
-    ///     MESSAGE/[RFC822](https://tools.ietf.org/html/rfc822) message. `HEADER.FIELDS` and
-    ///     `HEADER.FIELDS.NOT` are followed by a list of field-name (as defined in
-    ///     [RFC-2822](https://tools.ietf.org/html/rfc2822)) names, and return a subset of the
-    ///     header.  The subset returned by `HEADER.FIELDS` contains only those header fields with
-    ///     a field-name that matches one of the names in the list; similarly, the subset returned
-    ///     by `HEADER.FIELDS.NOT` contains only the header fields with a non-matching field-name.
-    ///     The field-matching is case-insensitive but otherwise exact.  Subsetting does not
-    ///     exclude the [RFC-2822](https://tools.ietf.org/html/rfc2822) delimiting blank line
-    ///     between the header and the body; the blank line is included in all header fetches,
-    ///     except in the case of a message which has no body and no blank line.
-    ///
-    ///     The `MIME` part specifier refers to the [MIME-IMB](https://tools.ietf.org/html/rfc2045)
-    ///     header for this part.
-    ///
-    ///     The `TEXT` part specifier refers to the text body of the message,
-    ///     omitting the [RFC-2822](https://tools.ietf.org/html/rfc2822) header.
-    ///
-    ///     [`Flag::Seen`] is implicitly set when `BODY` is fetched; if this causes the flags to
-    ///     change, they will generally be included as part of the `FETCH` responses.
+    ///    The text of a particular body section.  The section specification is a set of zero or
+    ///    more part specifiers delimited by periods.  A part specifier is either a part number
+    ///    (see RFC) or one of the following: `HEADER`, `HEADER.FIELDS`, `HEADER.FIELDS.NOT`,
+    ///    `MIME`, and `TEXT`.  An empty section specification (i.e., `BODY[]`) refers to the
+    ///    entire message, including the header.
+    ///
+    ///    The `HEADER`, `HEADER.FIELDS`, and `HEADER.FIELDS.NOT` part specifiers refer to the
+    ///    [RFC-2822](https://tools.ietf.org/html/rfc2822) header of the message or of an
+    ///    encapsulated [MIME-IMT](https://tools.ietf.org/html/rfc2046)
+    ///    MESSAGE/[RFC822](https://tools.ietf.org/html/rfc822) message. `HEADER.FIELDS` and
+    ///    `HEADER.FIELDS.NOT` are followed by a list of field-name (as defined in
+    ///    [RFC-2822](https://tools.ietf.org/html/rfc2822)) names, and return a subset of the
+    ///    header.  The subset returned by `HEADER.FIELDS` contains only those header fields with
+    ///    a field-name that matches one of the names in the list; similarly, the subset returned
+    ///    by `HEADER.FIELDS.NOT` contains only the header fields with a non-matching field-name.
+    ///    The field-matching is case-insensitive but otherwise exact.  Subsetting does not
+    ///    exclude the [RFC-2822](https://tools.ietf.org/html/rfc2822) delimiting blank line
+    ///    between the header and the body; the blank line is included in all header fetches,
+    ///    except in the case of a message which has no body and no blank line.
+    ///
+    ///    The `MIME` part specifier refers to the [MIME-IMB](https://tools.ietf.org/html/rfc2045)
+    ///    header for this part.
+    ///
+    ///    The `TEXT` part specifier refers to the text body of the message,
+    ///    omitting the [RFC-2822](https://tools.ietf.org/html/rfc2822) header.
+    ///
+    ///    [`Flag::Seen`] is implicitly set when `BODY` is fetched; if this causes the flags to
+    ///    change, they will generally be included as part of the `FETCH` responses.
     ///  - `BODY.PEEK[<section>]`: An alternate form of `BODY[<section>]` that does not implicitly
     ///    set [`Flag::Seen`].
+    ///  - `BODY[<section>]<<offset>.<length>>`: At most `length` bytes of the section, starting
+    ///    at byte `offset`.  Read the result with [`Fetch::partial`], or use
+    ///    [`Session::fetch_partial`].
+    ///  - `BINARY[<section>]`, `BINARY.PEEK[<section>]`: Like `BODY[<section>]`, but the server
+    ///    decodes the content transfer encoding of the part (see
+    ///    [RFC 3516](https://tools.ietf.org/html/rfc3516)).  The section may only contain part
+    ///    numbers.  Read the result with [`Fetch::binary`].  If the server cannot decode the part,
+    ///    the command fails with [`Error::UnknownCte`].
+    ///  - `BINARY.SIZE[<section>]`: The size of the section after decoding, see
+    ///    [`Fetch::binary_size`].
     ///  - `ENVELOPE`: The envelope structure of the message.  This is computed by the server by
     ///    parsing the [RFC-2822](https://tools.ietf.org/html/rfc2822) header into the component
     ///    parts, defaulting various fields as necessary.
@@ -481,14 +1109,14 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         query: S2,
     ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send>
     where
-        S1: AsRef<str>,
-        S2: AsRef<str>,
+        S1: Into<SequenceSet<Seq>>,
+        S2: Into<FetchQuery>,
     {
         let id = self
-            .run_command(&format!(
+            .run_streaming_command(&format!(
                 "FETCH {} {}",
-                sequence_set.as_ref(),
-                query.as_ref()
+                sequence_set.into().validated()?,
+                query.into()
             ))
             .await?;
         let res = parse_fetches(
@@ -500,22 +1128,130 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         Ok(res)
     }
 
+    /// Fetches at most `length` bytes of the body section `section` of the messages in
+    /// `sequence_set`, starting at byte `offset`, e.g. to page through a large message.
+    /// `section` is given as in `BODY[<section>]`, e.g. `""` for the entire message or `"2.1"`
+    /// for a body part.
+    ///
+    /// The section is fetched with `BODY.PEEK[<section>]<<offset>.<length>>`, so that the
+    /// messages are not marked as [`Flag::Seen`]. Read the result with [`Fetch::partial`]; near
+    /// the end of the section, the server returns fewer than `length` bytes.
+    pub async fn fetch_partial<S1, S2>(
+        &mut self,
+        sequence_set: S1,
+        section: S2,
+        offset: u32,
+        length: u32,
+    ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send>
+    where
+        S1: Into<SequenceSet<Seq>>,
+        S2: AsRef<str>,
+    {
+        let query = format!("BODY.PEEK[{}]<{}.{}>", section.as_ref(), offset, length);
+        self.fetch(sequence_set, query).await
+    }
+
+    /// Like [`Session::fetch`], but waits for the command to complete and returns all messages
+    /// at once.
+    ///
+    /// [`Fetch`] owns the data it was parsed from, so the results can be kept and sent to other
+    /// tasks after the session is used for further commands.  For large results, consider
+    /// processing the stream returned by [`Session::fetch`] instead, which only holds one message
+    /// in memory at a time.
+    pub async fn fetch_collect<S1, S2>(&mut self, sequence_set: S1, query: S2) -> Result<Vec<Fetch>>
+    where
+        S1: Into<SequenceSet<Seq>>,
+        S2: Into<FetchQuery>,
+    {
+        self.fetch(sequence_set, query).await?.try_collect().await
+    }
+
+    /// Fetches the messages from `start` to the last one in the mailbox, i.e. `<start>:*`, see
+    /// [`Session::fetch`].
+    ///
+    /// `*` stands for the last message, so the server also returns the last message when `start`
+    /// is larger than the number of messages; such messages are left out of the result and sent
+    /// to [`Session::unsolicited_responses`] instead.  When the selected mailbox is empty,
+    /// servers reject `1:*` as an invalid sequence set, so no command is sent and the result is
+    /// empty.
+    pub async fn fetch_from<S: Into<FetchQuery>>(
+        &mut self,
+        start: impl Into<Seq>,
+        query: S,
+    ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send> {
+        let start = start.into();
+        if self.selected.is_some() && self.unsolicited_responses_tx.exists() == 0 {
+            return Ok(future::Either::Left(stream::empty()));
+        }
+        let unsolicited = self.unsolicited_responses_tx.clone();
+        let fetches = self.fetch(start.., query).await?;
+        Ok(future::Either::Right(fetches.filter_map(move |fetch| {
+            future::ready(match fetch {
+                Ok(fetch) if fetch.message < start => {
+                    send_unilateral(fetch.into_response(), &unsolicited);
+                    None
+                }
+                fetch => Some(fetch),
+            })
+        })))
+    }
+
+    /// Returns the [`Uid`] and flags of every message in the selected mailbox, with
+    /// `FETCH 1:* (UID FLAGS)`, e.g. to build or refresh a local cache of flags.
+    ///
+    /// With `changed_since`, only messages whose flags changed after that mod-sequence are
+    /// returned, using the `CHANGEDSINCE` modifier of the [`CONDSTORE`
+    /// extension](https://tools.ietf.org/html/rfc7162#section-3.1.4); this fails with
+    /// [`Error::MissingCapability`] if the server does not support `CONDSTORE`.  An empty
+    /// mailbox yields an empty result, see [`Session::fetch_from`].
+    pub async fn fetch_all_flags(
+        &mut self,
+        changed_since: Option<u64>,
+    ) -> Result<Vec<(Uid, Vec<Flag<'static>>)>> {
+        let query = match changed_since {
+            Some(modseq) => {
+                self.require_any_capability(&["CONDSTORE", "QRESYNC"])
+                    .await?;
+                format!("(UID FLAGS) (CHANGEDSINCE {})", modseq)
+            }
+            None => "(UID FLAGS)".to_string(),
+        };
+        let fetches = self
+            .fetch_from(1, query)
+            .await?
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(fetches.iter().filter_map(uid_and_flags).collect())
+    }
+
     /// Equivalent to [`Session::fetch`], except that all identifiers in `uid_set` are
     /// [`Uid`]s. See also the [`UID` command](https://tools.ietf.org/html/rfc3501#section-6.4.8).
+    ///
+    /// Note that `*` in `uid_set` stands for the highest [`Uid`] in use, so a range such as
+    /// `100:*` includes the last message even if its [`Uid`] is below 100, see
+    /// [`Session::uid_fetch_from`].
+    ///
+    /// As with [`Session::fetch`], the messages are returned in the order in which the server
+    /// sent them, which is not necessarily ordered by [`Uid`].
+    ///
+    /// Once `QRESYNC` is [enabled](Session::enable), the `VANISHED` modifier, as in the query
+    /// `"(FLAGS) (CHANGEDSINCE 12345 VANISHED)"`, also asks for the messages that were expunged
+    /// since.  They are sent on [`Session::unsolicited_responses`] as
+    /// [`UnsolicitedResponse::Vanished`].
     pub async fn uid_fetch<S1, S2>(
         &mut self,
         uid_set: S1,
         query: S2,
     ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send + Unpin>
     where
-        S1: AsRef<str>,
-        S2: AsRef<str>,
+        S1: Into<SequenceSet<Uid>>,
+        S2: Into<FetchQuery>,
     {
         let id = self
-            .run_command(&format!(
+            .run_streaming_command(&format!(
                 "UID FETCH {} {}",
-                uid_set.as_ref(),
-                query.as_ref()
+                uid_set.into().validated()?,
+                query.into()
             ))
             .await?;
         let res = parse_fetches(
@@ -526,22 +1262,380 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         Ok(res)
     }
 
-    /// Noop always succeeds, and it does nothing.
-    pub async fn noop(&mut self) -> Result<()> {
-        let id = self.run_command("NOOP").await?;
-        parse_noop(
-            &mut self.conn.stream,
-            self.unsolicited_responses_tx.clone(),
-            id,
-        )
-        .await?;
-        Ok(())
-    }
-
-    /// Logout informs the server that the client is done with the connection.
-    pub async fn logout(&mut self) -> Result<()> {
-        self.run_command_and_check_ok("LOGOUT").await?;
-        Ok(())
+    /// Like [`Session::uid_fetch`], but waits for the command to complete and returns all
+    /// messages at once, see [`Session::fetch_collect`].
+    pub async fn uid_fetch_collect<S1, S2>(&mut self, uid_set: S1, query: S2) -> Result<Vec<Fetch>>
+    where
+        S1: Into<SequenceSet<Uid>>,
+        S2: Into<FetchQuery>,
+    {
+        self.uid_fetch(uid_set, query).await?.try_collect().await
+    }
+
+    /// Fetches the messages with a [`Uid`] of at least `start`, i.e. `UID FETCH <start>:*`, see
+    /// [`Session::uid_fetch`].
+    ///
+    /// In a `UID` command, `*` stands for the highest [`Uid`] in use in the mailbox, not for a
+    /// number larger than all of them.  `<start>:*` therefore always includes the last message,
+    /// even if its [`Uid`] is smaller than `start`, e.g. when polling for new messages with the
+    /// next expected [`Uid`] and there are none.  Such messages are left out of the result and
+    /// sent to [`Session::unsolicited_responses`] instead.  When the selected mailbox is empty,
+    /// no command is sent and the result is empty.
+    pub async fn uid_fetch_from<S: Into<FetchQuery>>(
+        &mut self,
+        start: impl Into<Uid>,
+        query: S,
+    ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send + Unpin> {
+        let start = start.into();
+        if self.selected.is_some() && self.unsolicited_responses_tx.exists() == 0 {
+            return Ok(future::Either::Left(stream::empty()));
+        }
+        let unsolicited = self.unsolicited_responses_tx.clone();
+        let fetches = self.uid_fetch(start.., query).await?;
+        Ok(future::Either::Right(fetches.filter_map(move |fetch| {
+            future::ready(match fetch {
+                Ok(fetch) if fetch.uid.is_some_and(|uid| uid < start) => {
+                    send_unilateral(fetch.into_response(), &unsolicited);
+                    None
+                }
+                fetch => Some(fetch),
+            })
+        })))
+    }
+
+    /// Fetches the body section `section` of message `seq` and returns its bytes in chunks as
+    /// they arrive from the server, instead of holding the whole section in memory like
+    /// [`Session::fetch`] does. `section` is given as in `BODY[<section>]`, e.g. `""` for the
+    /// entire message or `"2.1"` for a body part.
+    ///
+    /// The section is fetched with `BODY.PEEK`, so that the message is not marked as
+    /// [`Flag::Seen`]. If the message does not exist, the stream ends without any data, and if
+    /// the server rejects the command, it yields the error. A `section` containing brackets,
+    /// braces or control characters fails with [`Error::Validate`] without sending the command.
+    /// If the stream is dropped before its end, the rest of the section is skipped before the
+    /// next command, without holding it in memory.
+    ///
+    /// ```no_run
+    /// # use async_std::prelude::*;
+    /// # async fn example(mut session: async_imap::Session<async_std::net::TcpStream>) -> async_imap::error::Result<()> {
+    /// let mut file = async_std::fs::File::create("attachment").await?;
+    /// let mut body = session.fetch_body_stream(3, "2").await?;
+    /// while let Some(chunk) = body.next().await {
+    ///     file.write_all(&chunk?).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn fetch_body_stream<S: AsRef<str>>(
+        &mut self,
+        seq: impl Into<Seq>,
+        section: S,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>>> + '_ + Send + Unpin> {
+        let seq = seq.into();
+        let section = validate_section(section.as_ref())?.to_string();
+        let id = self
+            .run_streaming_command(&format!("FETCH {} BODY.PEEK[{}]", seq, section))
+            .await?;
+        Ok(Box::pin(futures::stream::unfold(
+            (self, id, BodyState::Start),
+            move |(session, id, mut state)| {
+                let section = section.clone();
+                async move {
+                    let chunk = session
+                        .next_body_chunk(&id, seq, &section, &mut state)
+                        .await?;
+                    Some((chunk, (session, id, state)))
+                }
+            },
+        )))
+    }
+
+    /// Reads the next chunk of the body section requested by [`Session::fetch_body_stream`].
+    /// Only the `BODY[<section>]` of message `seq` is returned, any other `FETCH` response is
+    /// handled as unsolicited.
+    async fn next_body_chunk(
+        &mut self,
+        id: &RequestId,
+        seq: Seq,
+        section: &str,
+        state: &mut BodyState,
+    ) -> Option<Result<Vec<u8>>> {
+        let result = loop {
+            match *state {
+                BodyState::Finished => return None,
+                BodyState::Literal(0) => {
+                    let stream = &mut self.conn.stream;
+                    if let Err(err) = futures::future::poll_fn(|cx| stream.poll_skip_line(cx)).await
+                    {
+                        break Err(err.into());
+                    }
+                    *state = BodyState::Start;
+                }
+                BodyState::Literal(remaining) => {
+                    let stream = &mut self.conn.stream;
+                    match futures::future::poll_fn(|cx| stream.poll_literal(cx, remaining)).await {
+                        Ok(chunk) => {
+                            *state = BodyState::Literal(remaining - chunk.len());
+                            return Some(Ok(chunk));
+                        }
+                        Err(err) => break Err(err.into()),
+                    }
+                }
+                BodyState::Start => {
+                    let stream = &mut self.conn.stream;
+                    let start = futures::future::poll_fn(|cx| {
+                        stream.poll_fetch_literal_start(cx, seq.0, section)
+                    });
+                    match start.await {
+                        Ok(Some(len)) => {
+                            *state = BodyState::Literal(len);
+                            continue;
+                        }
+                        Ok(None) => {}
+                        Err(err) => break Err(err.into()),
+                    }
+                    let response = match self.conn.stream.next().await {
+                        Some(Ok(response)) => response,
+                        Some(Err(err)) => break Err(err.into()),
+                        None => break Err(Error::ConnectionLost),
+                    };
+                    match response.parsed() {
+                        Response::Done { tag, .. } if tag == id => {
+                            *state = BodyState::Finished;
+                            return self
+                                .conn
+                                .check_done_ok_from(
+                                    id,
+                                    Some(self.unsolicited_responses_tx.clone()),
+                                    response,
+                                )
+                                .await
+                                .err()
+                                .map(Err);
+                        }
+                        // Small sections may be sent as quoted strings instead of literals.
+                        Response::Fetch(message, attrs) if *message == seq.0 => {
+                            let path = parse_section_spec(section);
+                            let data = attrs.iter().find_map(|attr| match attr {
+                                AttributeValue::BodySection {
+                                    section: fetched,
+                                    index: None,
+                                    data: Some(data),
+                                } if path.as_ref() == Some(fetched) => Some(data.to_vec()),
+                                _ => None,
+                            });
+                            match data {
+                                Some(data) => return Some(Ok(data)),
+                                None => {
+                                    handle_unilateral(
+                                        response,
+                                        self.unsolicited_responses_tx.clone(),
+                                    )
+                                    .await
+                                }
+                            }
+                        }
+                        _ => {
+                            handle_unilateral(response, self.unsolicited_responses_tx.clone()).await
+                        }
+                    }
+                }
+            }
+        };
+        *state = BodyState::Finished;
+        Some(result)
+    }
+
+    /// Sets the interval at which [`Session::keepalive`] sends `NOOP` commands, or `None` (the
+    /// default) to not send any.
+    pub fn set_keepalive(&mut self, interval: Option<Duration>) {
+        self.keepalive = interval;
+    }
+
+    /// Keeps an otherwise unused session alive by sending a `NOOP` command at the interval set
+    /// with [`Session::set_keepalive`]. This keeps NAT mappings open and detects dead connections,
+    /// which are reported as an error.
+    ///
+    /// This is not a background task: the session is borrowed mutably, so no other command can be
+    /// in flight while it runs, and the crate has no task of its own to spawn or shut down. The
+    /// returned future only completes on error; stop keeping the session alive by dropping it,
+    /// for example once there is a command to run:
+    ///
+    /// ```no_run
+    /// # use async_std::prelude::*;
+    /// # use std::time::Duration;
+    /// # async fn example(mut session: async_imap::Session<async_std::net::TcpStream>) {
+    /// session.set_keepalive(Some(Duration::from_secs(5 * 60)));
+    /// let work = async_std::task::sleep(Duration::from_secs(3600));
+    /// let _ = session.keepalive().race(async { work.await; Ok(()) }).await;
+    /// session.noop().await.unwrap();
+    /// # }
+    /// ```
+    ///
+    /// Unsolicited responses received in the meantime are sent to
+    /// [`Session::unsolicited_responses`]. Dropping the future is safe at any point: a `NOOP` that
+    /// is only partly written or whose completion was not read yet is finished before the next
+    /// command is sent.
+    pub async fn keepalive(&mut self) -> Result<()> {
+        let interval = match self.keepalive {
+            Some(interval) => interval,
+            None => return futures::future::pending().await,
+        };
+        loop {
+            runtime::sleep(interval).await;
+            // Marked as unfinished, so that the `NOOP` is completed and its responses are read
+            // before the next command if this future is dropped in the middle of it.
+            let id = self.run_streaming_command("NOOP").await?;
+            parse_noop(
+                &mut self.conn.stream,
+                self.unsolicited_responses_tx.clone(),
+                id,
+            )
+            .await?;
+        }
+    }
+
+    /// Noop always succeeds, and it does nothing.
+    pub async fn noop(&mut self) -> Result<()> {
+        let id = self.run_command("NOOP").await?;
+        parse_noop(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Discards what is left of the responses to commands that were abandoned, so that they are
+    /// not mistaken for the responses to the next command.
+    ///
+    /// If a stream of responses such as the one returned by [`Session::fetch`] is dropped before
+    /// it ended, the rest of its responses are discarded before the next command is sent anyway.
+    /// This does so right away, and also discards the responses of other commands whose futures
+    /// were dropped, such as [`Session::noop`] in a `select!`.
+    ///
+    /// IMAP has no way to cancel a command, so this sends a `NOOP` and reads everything up to its
+    /// completion: the rest of the abandoned responses, which may be large for a `FETCH` of
+    /// message bodies, and one more round trip to the server.  `FETCH` and `LIST` responses and
+    /// the completions of other commands are discarded, all other responses are sent to
+    /// [`Session::unsolicited_responses`] as usual.
+    ///
+    /// This does not help with a command that was dropped while it was being sent, such as an
+    /// [`Session::append`] waiting to send its message, after which the connection has to be
+    /// closed.
+    pub async fn reset(&mut self) -> Result<()> {
+        let id = self.run_command("NOOP").await?;
+        let done = self.discard_until_done(&id).await?;
+        match done.parsed() {
+            Response::Done {
+                status,
+                code,
+                information,
+                ..
+            } => check_status_ok(status, code.as_ref(), information.as_deref()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Runs a command whose responses are returned as a stream, which marks the command as
+    /// unfinished until the stream reads its completion, so that the rest of the responses are
+    /// discarded before the next command if the stream is dropped early.
+    async fn run_streaming_command<S: AsRef<str>>(&mut self, command: S) -> Result<RequestId> {
+        self.finish_abandoned().await?;
+        self.conn.run_unfinished_command(command.as_ref()).await
+    }
+
+    /// Discards the rest of the responses to the command marked by
+    /// [`Session::run_streaming_command`], if its stream was dropped before the completion.
+    async fn finish_abandoned(&mut self) -> Result<()> {
+        if let Some(id) = self.conn.stream.take_unfinished() {
+            // The command may not even have been sent completely.
+            self.conn.stream.flush().await?;
+            log::debug!("discarding the rest of the responses to {:?}", id);
+            let stream = &mut self.conn.stream;
+            futures::future::poll_fn(|cx| stream.poll_skip_literal(cx)).await?;
+            // Whether the abandoned command failed does not matter.
+            self.discard_until_done(&id).await?;
+        }
+        Ok(())
+    }
+
+    /// Reads responses up to the tagged completion of `id`, which is returned.  Responses to
+    /// abandoned commands are discarded on the way, see [`Session::reset`].
+    async fn discard_until_done(&mut self, id: &RequestId) -> Result<ResponseData> {
+        while let Some(response) = self.conn.stream.next().await {
+            let response = response?;
+            match response.parsed() {
+                Response::Done { tag, .. } if tag == id => return Ok(response),
+                Response::Done { .. }
+                | Response::Fetch(..)
+                | Response::MailboxData(MailboxDatum::List { .. }) => {
+                    log::debug!("discarding response: {:?}", response);
+                }
+                _ => handle_unilateral(response, self.unsolicited_responses_tx.clone()).await,
+            }
+        }
+        Err(Error::ConnectionLost)
+    }
+
+    /// Logout informs the server that the client is done with the connection.
+    ///
+    /// The server answers with an untagged `BYE` response, which is expected here rather than
+    /// reported as [`Error::Bye`], before completing the command.  The connection is
+    /// closed afterwards.  Logging out of a connection that is already closed, e.g. because of an
+    /// earlier `logout` or because the server closed it, succeeds without doing anything.
+    pub async fn logout(&mut self) -> Result<()> {
+        let id = match self.run_command("LOGOUT").await {
+            Ok(id) => id,
+            Err(Error::Bye(_)) => return Ok(()),
+            Err(Error::Io(err)) if is_disconnected(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        loop {
+            let response = match self.conn.stream.next().await {
+                Some(Ok(response)) => response,
+                // The server is done once it sent the `BYE`, and may close the connection
+                // without completing the command.
+                None => break,
+                Some(Err(err)) => match Error::from(err) {
+                    Error::Bye(_) | Error::ConnectionLost => break,
+                    Error::Io(err) if is_disconnected(&err) => break,
+                    err => return Err(err),
+                },
+            };
+            match response.parsed() {
+                Response::Done {
+                    tag,
+                    status,
+                    code,
+                    information,
+                } if *tag == id => {
+                    check_status_ok(status, code.as_ref(), information.as_deref())?;
+                    break;
+                }
+                _ => handle_unilateral(response, self.unsolicited_responses_tx.clone()).await,
+            }
+        }
+        // The server closes the connection after logging out, so errors are expected here.
+        let _ = self.conn.stream.close().await;
+        Ok(())
+    }
+
+    /// Closes the connection without logging out, after the underlying stream has written out
+    /// anything it still buffers, e.g. so that a TLS stream sends its `close_notify` alert.
+    ///
+    /// Ending a session with [`Session::logout`] is preferable, as it also closes the connection
+    /// cleanly.  This method is for connections on which `LOGOUT` can not be sent, e.g. while the
+    /// server does not answer.  Closing a connection that is already closed succeeds without
+    /// doing anything.
+    pub async fn close_connection(&mut self) -> Result<()> {
+        let flushed = self.conn.stream.flush().await;
+        let closed = self.conn.stream.close().await;
+        match flushed.and(closed) {
+            Err(err) if !is_disconnected(&err) => Err(err.into()),
+            _ => Ok(()),
+        }
     }
 
     /// The [`CREATE` command](https://tools.ietf.org/html/rfc3501#section-6.3.3) creates a mailbox
@@ -567,8 +1661,46 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// See the description of the [`UID`
     /// command](https://tools.ietf.org/html/rfc3501#section-6.4.8) for more detail.
     pub async fn create<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<()> {
-        self.run_command_and_check_ok(&format!("CREATE {}", validate_str(mailbox_name.as_ref())?))
-            .await?;
+        self.run_command_and_check_ok(&format!(
+            "CREATE {}",
+            self.validate_mailbox(mailbox_name.as_ref())?
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Creates a mailbox like [`Session::create`], designating it for the given special `uses`
+    /// such as `\Sent` or `\Archive`, with the [`CREATE-SPECIAL-USE`
+    /// extension](https://tools.ietf.org/html/rfc6154#section-3).  The uses are given as
+    /// [`NameAttribute::Custom`], in the form they are listed in with [`Session::list`].
+    ///
+    /// Fails with [`Error::MissingCapability`] if the server does not support
+    /// `CREATE-SPECIAL-USE`, and with [`Error::UseAttr`] if it can not designate the mailbox for
+    /// the requested uses.  Without `uses`, a plain `CREATE` is sent.
+    pub async fn create_special_use<S: AsRef<str>>(
+        &mut self,
+        mailbox_name: S,
+        uses: &[NameAttribute<'_>],
+    ) -> Result<()> {
+        if uses.is_empty() {
+            return self.create(mailbox_name).await;
+        }
+        self.require_any_capability(&["CREATE-SPECIAL-USE"]).await?;
+        let uses = uses
+            .iter()
+            .map(|attribute| {
+                let attribute = attribute.to_string();
+                validate_atom(attribute.strip_prefix('\\').unwrap_or(&attribute))?;
+                Ok(attribute)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.run_command_and_check_ok(&format!(
+            "CREATE {} (USE ({}))",
+            self.validate_mailbox(mailbox_name.as_ref())?,
+            uses.join(" ")
+        ))
+        .await?;
 
         Ok(())
     }
@@ -593,16 +1725,24 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// See the description of the [`UID`
     /// command](https://tools.ietf.org/html/rfc3501#section-6.4.8) for more detail.
     pub async fn delete<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<()> {
-        self.run_command_and_check_ok(&format!("DELETE {}", validate_str(mailbox_name.as_ref())?))
-            .await?;
+        self.run_command_and_check_ok(&format!(
+            "DELETE {}",
+            self.validate_mailbox(mailbox_name.as_ref())?
+        ))
+        .await?;
 
         Ok(())
     }
 
     /// The [`RENAME` command](https://tools.ietf.org/html/rfc3501#section-6.3.5) changes the name
-    /// of a mailbox.  `Ok` is returned only if the mailbox has been renamed.  It is an error to
-    /// attempt to rename from a mailbox name that does not exist or to a mailbox name that already
-    /// exists.  Any error in renaming will return [`Error::No`].
+    /// of a mailbox.  `Ok` is returned only if the mailbox has been renamed, with the tagged
+    /// response completing the command, so that a response code the server included can be
+    /// inspected.  It is an error to attempt to rename from a mailbox name that does not exist or
+    /// to a mailbox name that already exists.  Any error in renaming will return [`Error::No`],
+    /// which includes the response code, e.g. `TRYCREATE`.
+    ///
+    /// Both names are encoded in modified UTF-7, unless `UTF8=ACCEPT` or `IMAP4rev2` is enabled,
+    /// like all other mailbox names.
     ///
     /// If the name has inferior hierarchical names, then the inferior hierarchical names will also
     /// be renamed.  For example, a rename of `foo` to `zap` will rename `foo/bar` (assuming `/` is
@@ -623,16 +1763,26 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// Renaming `INBOX` is permitted, and has special behavior.  It moves all messages in `INBOX`
     /// to a new mailbox with the given name, leaving `INBOX` empty.  If the server implementation
     /// supports inferior hierarchical names of `INBOX`, these are unaffected by a rename of
-    /// `INBOX`.
-    pub async fn rename<S1: AsRef<str>, S2: AsRef<str>>(&mut self, from: S1, to: S2) -> Result<()> {
-        self.run_command_and_check_ok(&format!(
-            "RENAME {} {}",
-            quote!(from.as_ref()),
-            quote!(to.as_ref())
-        ))
-        .await?;
+    /// `INBOX`.  So if `INBOX` is selected, it stays selected, while renaming any other selected
+    /// mailbox keeps it selected under its new name.
+    pub async fn rename<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        from: S1,
+        to: S2,
+    ) -> Result<CommandResult> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        let result = self
+            .run_command_and_check_ok(&format!(
+                "RENAME {} {}",
+                self.validate_mailbox(from)?,
+                self.validate_mailbox(to)?
+            ))
+            .await?;
 
-        Ok(())
+        if !from.eq_ignore_ascii_case("INBOX") && self.selected.as_deref() == Some(from) {
+            self.set_selected(Some(to.to_string()));
+        }
+        Ok(result)
     }
 
     /// The [`SUBSCRIBE` command](https://tools.ietf.org/html/rfc3501#section-6.3.6) adds the
@@ -644,8 +1794,11 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// However, it will not unilaterally remove an existing mailbox name from the subscription
     /// list even if a mailbox by that name no longer exists.
     pub async fn subscribe<S: AsRef<str>>(&mut self, mailbox: S) -> Result<()> {
-        self.run_command_and_check_ok(&format!("SUBSCRIBE {}", quote!(mailbox.as_ref())))
-            .await?;
+        self.run_command_and_check_ok(&format!(
+            "SUBSCRIBE {}",
+            self.validate_mailbox(mailbox.as_ref())?
+        ))
+        .await?;
         Ok(())
     }
 
@@ -654,15 +1807,103 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// returned by [`Session::lsub`].  This command returns `Ok` only if the unsubscription is
     /// successful.
     pub async fn unsubscribe<S: AsRef<str>>(&mut self, mailbox: S) -> Result<()> {
-        self.run_command_and_check_ok(&format!("UNSUBSCRIBE {}", quote!(mailbox.as_ref())))
-            .await?;
+        self.run_command_and_check_ok(&format!(
+            "UNSUBSCRIBE {}",
+            self.validate_mailbox(mailbox.as_ref())?
+        ))
+        .await?;
         Ok(())
     }
 
+    /// Subscribes to all `mailboxes` like [`Session::subscribe`], but
+    /// [pipelined](Session::pipeline) so that the commands take a single round trip.
+    ///
+    /// The outcome for each mailbox is returned in the order the mailboxes were given, so a
+    /// failure to subscribe to one mailbox does not keep the others from being subscribed to.
+    /// An error is only returned if the connection fails.
+    pub async fn subscribe_all<S: AsRef<str>>(
+        &mut self,
+        mailboxes: &[S],
+    ) -> Result<Vec<Result<()>>> {
+        let responses = self
+            .pipeline_per_mailbox(mailboxes, |mailbox| format!("SUBSCRIBE {}", mailbox))
+            .await?;
+        for response in responses.untagged {
+            handle_unilateral(response, self.unsolicited_responses_tx.clone()).await;
+        }
+        Ok(responses.results)
+    }
+
+    /// Unsubscribes from all `mailboxes` like [`Session::unsubscribe`], but
+    /// [pipelined](Session::pipeline), reporting the outcome for each mailbox like
+    /// [`Session::subscribe_all`].
+    pub async fn unsubscribe_all<S: AsRef<str>>(
+        &mut self,
+        mailboxes: &[S],
+    ) -> Result<Vec<Result<()>>> {
+        let responses = self
+            .pipeline_per_mailbox(mailboxes, |mailbox| format!("UNSUBSCRIBE {}", mailbox))
+            .await?;
+        for response in responses.untagged {
+            handle_unilateral(response, self.unsolicited_responses_tx.clone()).await;
+        }
+        Ok(responses.results)
+    }
+
+    /// Pipelines the command `command` builds from each of the quoted `mailboxes`, with one
+    /// result per mailbox in the returned [`PipelineResponses`].  Mailbox names that can not be
+    /// sent fail without affecting the others.
+    async fn pipeline_per_mailbox<S: AsRef<str>>(
+        &mut self,
+        mailboxes: &[S],
+        command: impl Fn(&str) -> String,
+    ) -> Result<PipelineResponses> {
+        let mut results = Vec::with_capacity(mailboxes.len());
+        let mut commands = Vec::new();
+        for mailbox in mailboxes {
+            match self.validate_mailbox(mailbox.as_ref()) {
+                Ok(mailbox) => {
+                    commands.push(command(&mailbox));
+                    results.push(None);
+                }
+                Err(err) => results.push(Some(Err(err))),
+            }
+        }
+        if commands.is_empty() {
+            return Ok(PipelineResponses {
+                untagged: Vec::new(),
+                results: results.into_iter().map(Option::unwrap).collect(),
+            });
+        }
+
+        let responses = self.pipeline(&commands).await?;
+        let mut sent = responses.results.into_iter();
+        Ok(PipelineResponses {
+            untagged: responses.untagged,
+            results: results
+                .into_iter()
+                .map(|result| {
+                    result.unwrap_or_else(|| sent.next().expect("one result per command"))
+                })
+                .collect(),
+        })
+    }
+
     /// The [`CAPABILITY` command](https://tools.ietf.org/html/rfc3501#section-6.1.1) requests a
     /// listing of capabilities that the server supports.  The server will include "IMAP4rev1" as
     /// one of the listed capabilities. See [`Capabilities`] for further details.
+    ///
+    /// The result is remembered by the session, so that extensions such as non-synchronizing
+    /// literals (`LITERAL+`) can be used automatically.  If the capabilities are already known,
+    /// for example from the `CAPABILITY` response code the server included in its response to
+    /// logging in, they are returned without sending the command.
+    ///
+    /// Capabilities known before logging in are not carried over to the session, as servers
+    /// usually advertise different ones once the user is authenticated.
     pub async fn capabilities(&mut self) -> Result<Capabilities> {
+        if let Some(capabilities) = &self.capabilities {
+            return Ok(capabilities.clone());
+        }
         let id = self.run_command("CAPABILITY").await?;
         let c = parse_capabilities(
             &mut self.conn.stream,
@@ -670,14 +1911,133 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
             id,
         )
         .await?;
+        self.capabilities = Some(c.clone());
         Ok(c)
     }
 
+    /// Whether the server supports `capability`, such as `IDLE` or `MOVE`, according to
+    /// [`Session::capabilities`], which are only requested if they are not known yet.
+    pub async fn has_capability<S: AsRef<str>>(&mut self, capability: S) -> Result<bool> {
+        Ok(self.capabilities().await?.has_str(capability))
+    }
+
+    /// Returns the capabilities of the server if they are known, without a network round trip.
+    ///
+    /// They are known once [`Session::capabilities`] was called, or if the server listed them
+    /// in a `CAPABILITY` response code when the session was logged in.
+    pub fn capabilities_cached(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// The [`ENABLE` command](https://tools.ietf.org/html/rfc5161) turns on server extensions
+    /// that change how the server behaves for the rest of the connection. The server has to
+    /// advertise the `ENABLE` capability, or `IMAP4rev2`, which includes it; otherwise this
+    /// fails with [`Error::MissingCapability`] without sending the command.
+    ///
+    /// Returns the extensions that the server enabled, which may be fewer than requested.
+    ///
+    /// Once `UTF8=ACCEPT` ([RFC 6855](https://tools.ietf.org/html/rfc6855)) is enabled, mailbox
+    /// names are sent as UTF-8 instead of modified UTF-7, and [`Session::append`] sends messages
+    /// as `UTF8` literals, which may contain UTF-8 header fields. Search criteria may then contain
+    /// UTF-8 without a `CHARSET`.
+    ///
+    /// Enabling `IMAP4rev2` switches to the [revised protocol](Session::is_rev2).
+    pub async fn enable<S: AsRef<str>>(&mut self, extensions: &[S]) -> Result<Capabilities> {
+        self.require_any_capability(&["ENABLE", "IMAP4rev2"])
+            .await?;
+        let extensions: Vec<&str> = extensions.iter().map(AsRef::as_ref).collect();
+        let id = self
+            .run_command(&format!("ENABLE {}", extensions.join(" ")))
+            .await?;
+        let enabled = parse_capabilities(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?;
+        if enabled.has_str("UTF8=ACCEPT") {
+            self.utf8_enabled = true;
+        }
+        if enabled.has_str("IMAP4rev2") {
+            self.rev2_enabled = true;
+        }
+        if enabled.has_str("QRESYNC") {
+            self.qresync_enabled = true;
+        }
+        Ok(enabled)
+    }
+
+    /// Whether the session uses [`IMAP4rev2`](https://tools.ietf.org/html/rfc9051), which
+    /// servers that advertise it switch to once it is [enabled](Session::enable).
+    ///
+    /// With `IMAP4rev2`, mailbox names are sent and received as UTF-8, [`Session::lsub`] is
+    /// answered with `LIST (SUBSCRIBED)`, and `SEARCH` results arrive as `ESEARCH` responses,
+    /// which [`Session::search`] understands either way.  [`Mailbox::recent`] is `None`, as the
+    /// `\Recent` flag no longer exists.
+    pub fn is_rev2(&self) -> bool {
+        self.rev2_enabled
+    }
+
+    /// The [`NOTIFY` command](https://tools.ietf.org/html/rfc5465) asks the server to report
+    /// the events in `spec`, for the selected mailbox as well as for other mailboxes, without
+    /// having to select them.  The server has to advertise the `NOTIFY` capability.
+    ///
+    /// The events are sent on [`Session::unsolicited_responses`] while commands are running,
+    /// including [`IDLE`](extensions::idle::Handle) and [`Session::noop`].  Messages that are
+    /// added to, expunged from or changed in the selected mailbox are reported as
+    /// [`UnsolicitedResponse::Notification`]s naming the mailbox, changes to other mailboxes as
+    /// [`UnsolicitedResponse::Status`] responses, as long as the requested events are active.
+    ///
+    /// An empty `spec` sends `NOTIFY NONE`, which turns all notifications off.
+    pub async fn notify(&mut self, spec: NotifySpec) -> Result<()> {
+        self.require_any_capability(&["NOTIFY"]).await?;
+        let command = if spec.is_empty() {
+            "NOTIFY NONE".to_string()
+        } else {
+            let mut command = "NOTIFY SET".to_string();
+            if spec.status {
+                command.push_str(" STATUS");
+            }
+            for (filter, events) in &spec.groups {
+                let filter = match filter {
+                    NotifyFilter::Selected => "selected".to_string(),
+                    NotifyFilter::SelectedDelayed => "selected-delayed".to_string(),
+                    NotifyFilter::Inboxes => "inboxes".to_string(),
+                    NotifyFilter::Personal => "personal".to_string(),
+                    NotifyFilter::Subscribed => "subscribed".to_string(),
+                    NotifyFilter::Subtree(mailboxes) => {
+                        format!("subtree ({})", self.validate_mailboxes(mailboxes)?)
+                    }
+                    NotifyFilter::Mailboxes(mailboxes) => {
+                        format!("mailboxes ({})", self.validate_mailboxes(mailboxes)?)
+                    }
+                };
+                let events = if events.is_empty() {
+                    "NONE".to_string()
+                } else {
+                    let events: Vec<String> = events.iter().map(ToString::to_string).collect();
+                    format!("({})", events.join(" "))
+                };
+                command.push_str(&format!(" ({} {})", filter, events));
+            }
+            command
+        };
+        self.run_command_and_check_ok(&command).await?;
+        self.notifying = !spec.is_empty();
+        self.set_selected(self.selected.clone());
+        Ok(())
+    }
+
     /// The [`EXPUNGE` command](https://tools.ietf.org/html/rfc3501#section-6.4.3) permanently
     /// removes all messages that have [`Flag::Deleted`] set from the currently selected mailbox.
     /// The message sequence number of each message that is removed is returned.
+    ///
+    /// The sequence numbers are yielded in the order the server sends them.  Each one is relative
+    /// to the mailbox with the messages yielded before it already removed, so a local copy of
+    /// the mailbox is kept up to date by removing the messages one by one in that order.  For
+    /// example, expunging messages 3 and 4 of a mailbox yields `3` twice.
     pub async fn expunge(&mut self) -> Result<impl Stream<Item = Result<Seq>> + '_ + Send> {
-        let id = self.run_command("EXPUNGE").await?;
+        let id = self.run_streaming_command("EXPUNGE").await?;
         let res = parse_expunge(
             &mut self.conn.stream,
             self.unsolicited_responses_tx.clone(),
@@ -686,6 +2046,12 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         Ok(res)
     }
 
+    /// Like [`Session::expunge`], but collects the sequence numbers of the removed messages, in
+    /// the order the server sent them.
+    pub async fn expunge_drain(&mut self) -> Result<Vec<Seq>> {
+        self.expunge().await?.try_collect().await
+    }
+
     /// The [`UID EXPUNGE` command](https://tools.ietf.org/html/rfc4315#section-2.1) permanently
     /// removes all messages that both have [`Flag::Deleted`] set and have a [`Uid`] that is
     /// included in the specified sequence set from the currently selected mailbox.  If a message
@@ -708,12 +2074,17 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///
     /// Alternatively, the client may fall back to using just [`Session::expunge`], risking the
     /// unintended removal of some messages.
-    pub async fn uid_expunge<S: AsRef<str>>(
+    ///
+    /// Like [`Session::expunge`], the returned stream yields the sequence numbers of the removed
+    /// messages.  Fails with [`Error::MissingCapability`] if the server does not support
+    /// `UIDPLUS`, without sending the command.
+    pub async fn uid_expunge<S: Into<SequenceSet<Uid>>>(
         &mut self,
         uid_set: S,
-    ) -> Result<impl Stream<Item = Result<Uid>> + '_ + Send> {
+    ) -> Result<impl Stream<Item = Result<Seq>> + '_ + Send> {
+        self.require_any_capability(&["UIDPLUS"]).await?;
         let id = self
-            .run_command(&format!("UID EXPUNGE {}", uid_set.as_ref()))
+            .run_streaming_command(&format!("UID EXPUNGE {}", uid_set.into().validated()?))
             .await?;
         let res = parse_expunge(
             &mut self.conn.stream,
@@ -732,7 +2103,9 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// [`Session::check`] is equivalent to [`Session::noop`].
     ///
     /// There is no guarantee that an `EXISTS` untagged response will happen as a result of
-    /// `CHECK`.  [`Session::noop`] SHOULD be used for new message polling.
+    /// `CHECK`.  [`Session::noop`] SHOULD be used for new message polling.  Any untagged
+    /// responses the server sends during the checkpoint are passed on to
+    /// [`Session::unsolicited_responses`] before `check` returns.
     pub async fn check(&mut self) -> Result<()> {
         self.run_command_and_check_ok("CHECK").await?;
         Ok(())
@@ -755,6 +2128,7 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// probably ignore) are sent.
     pub async fn close(&mut self) -> Result<()> {
         self.run_command_and_check_ok("CLOSE").await?;
+        self.set_selected(None);
         Ok(())
     }
 
@@ -786,7 +2160,14 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///
     ///  - `-FLAGS.SILENT <flag list>`: Equivalent to `-FLAGS`, but without returning a new value.
     ///
-    /// In all cases, `<flag list>` is a space-separated list enclosed in parentheses.
+    /// In all cases, `<flag list>` is a space-separated list enclosed in parentheses.  Each flag
+    /// in it is checked as when parsing a [`Flag`] from a string, and the command fails with
+    /// [`Error::InvalidFlag`] without being sent if one is invalid.  As a keyword containing a
+    /// space reads like two flags here, [`Session::store_flags`] is safer for user-defined flags.
+    ///
+    /// Servers that advertise the `X-GM-EXT-1` capability also accept `X-GM-LABELS`,
+    /// `+X-GM-LABELS` and `-X-GM-LABELS` (and their `.SILENT` variants) with a list of labels, and
+    /// return the new labels in [`Fetch::gmail_labels`].
     ///
     /// # Examples
     ///
@@ -798,7 +2179,7 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// use async_std::net::TcpStream;
     ///
     /// async fn delete(seq: Seq, s: &mut Session<TcpStream>) -> Result<()> {
-    ///     let updates_stream = s.store(format!("{}", seq), "+FLAGS (\\Deleted)").await?;
+    ///     let updates_stream = s.store(seq, "+FLAGS (\\Deleted)").await?;
     ///     let _updates: Vec<_> = updates_stream.collect::<Result<_>>().await?;
     ///     s.expunge().await?;
     ///     Ok(())
@@ -810,13 +2191,14 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         query: S2,
     ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send>
     where
-        S1: AsRef<str>,
+        S1: Into<SequenceSet<Seq>>,
         S2: AsRef<str>,
     {
+        validate_store_query(query.as_ref())?;
         let id = self
-            .run_command(&format!(
+            .run_streaming_command(&format!(
                 "STORE {} {}",
-                sequence_set.as_ref(),
+                sequence_set.into().validated()?,
                 query.as_ref()
             ))
             .await?;
@@ -836,13 +2218,14 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         query: S2,
     ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send>
     where
-        S1: AsRef<str>,
+        S1: Into<SequenceSet<Uid>>,
         S2: AsRef<str>,
     {
+        validate_store_query(query.as_ref())?;
         let id = self
-            .run_command(&format!(
+            .run_streaming_command(&format!(
                 "UID STORE {} {}",
-                uid_set.as_ref(),
+                uid_set.into().validated()?,
                 query.as_ref()
             ))
             .await?;
@@ -854,6 +2237,97 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         Ok(res)
     }
 
+    /// Changes the flags of the messages in `sequence_set` with [`Session::store`], building the
+    /// query from `flags`.
+    ///
+    /// Fails with [`Error::InvalidFlag`] before sending anything if one of the flags can not be
+    /// sent, such as a [`Flag::Custom`] keyword that contains a space.
+    pub async fn store_flags<S: Into<SequenceSet<Seq>>>(
+        &mut self,
+        sequence_set: S,
+        mode: StoreMode,
+        flags: &[Flag<'_>],
+    ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send> {
+        let query = flags_query(mode, flags)?;
+        self.store(sequence_set, query).await
+    }
+
+    /// Equivalent to [`Session::store_flags`], except that all identifiers in `uid_set` are
+    /// [`Uid`]s.
+    pub async fn uid_store_flags<S: Into<SequenceSet<Uid>>>(
+        &mut self,
+        uid_set: S,
+        mode: StoreMode,
+        flags: &[Flag<'_>],
+    ) -> Result<impl Stream<Item = Result<Fetch>> + '_ + Send> {
+        let query = flags_query(mode, flags)?;
+        self.uid_store(uid_set, query).await
+    }
+
+    /// Like [`Session::store`], but only updates the messages whose mod-sequence is at most
+    /// `modseq`, using the `UNCHANGEDSINCE` modifier of the [`CONDSTORE`
+    /// extension](https://tools.ietf.org/html/rfc7162#section-3.1.3).  This avoids overwriting
+    /// changes made by other clients since the flags were last fetched.
+    ///
+    /// The sequence numbers of the messages that were skipped because they were modified are
+    /// returned in [`ConditionalStore::modified`].  Fails with [`Error::MissingCapability`] if the
+    /// server does not support `CONDSTORE`.
+    pub async fn store_unchanged_since<S1, S2>(
+        &mut self,
+        sequence_set: S1,
+        modseq: u64,
+        query: S2,
+    ) -> Result<ConditionalStore>
+    where
+        S1: Into<SequenceSet<Seq>>,
+        S2: AsRef<str>,
+    {
+        let set = sequence_set.into().validated()?;
+        self.conditional_store("STORE", &set, modseq, query.as_ref())
+            .await
+    }
+
+    /// Equivalent to [`Session::store_unchanged_since`], except that all identifiers in
+    /// `uid_set`, and in the returned [`ConditionalStore::modified`], are [`Uid`]s.
+    pub async fn uid_store_unchanged_since<S1, S2>(
+        &mut self,
+        uid_set: S1,
+        modseq: u64,
+        query: S2,
+    ) -> Result<ConditionalStore>
+    where
+        S1: Into<SequenceSet<Uid>>,
+        S2: AsRef<str>,
+    {
+        let set = uid_set.into().validated()?;
+        self.conditional_store("UID STORE", &set, modseq, query.as_ref())
+            .await
+    }
+
+    async fn conditional_store(
+        &mut self,
+        command: &str,
+        set: &str,
+        modseq: u64,
+        query: &str,
+    ) -> Result<ConditionalStore> {
+        validate_store_query(query)?;
+        self.require_any_capability(&["CONDSTORE", "QRESYNC"])
+            .await?;
+        let id = self
+            .run_command(&format!(
+                "{} {} (UNCHANGEDSINCE {}) {}",
+                command, set, modseq, query
+            ))
+            .await?;
+        parse_conditional_store(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await
+    }
+
     /// The [`COPY` command](https://tools.ietf.org/html/rfc3501#section-6.4.7) copies the
     /// specified message(s) to the end of the specified destination mailbox.  The flags and
     /// internal date of the message(s) will generally be preserved, and [`Flag::Recent`] will
@@ -861,36 +2335,42 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///
     /// If the `COPY` command is unsuccessful for any reason, the server restores the destination
     /// mailbox to its state before the `COPY` attempt.
-    pub async fn copy<S1: AsRef<str>, S2: AsRef<str>>(
+    ///
+    /// If the server supports the [`UIDPLUS`](https://tools.ietf.org/html/rfc4315) extension,
+    /// the [`Uid`]s of the copies are returned, paired with the [`Uid`]s of the messages they
+    /// were copied from, unless there are more than ten million of them.
+    pub async fn copy<S1: Into<SequenceSet<Seq>>, S2: AsRef<str>>(
         &mut self,
         sequence_set: S1,
         mailbox_name: S2,
-    ) -> Result<()> {
-        self.run_command_and_check_ok(&format!(
-            "COPY {} {}",
-            sequence_set.as_ref(),
-            mailbox_name.as_ref()
-        ))
-        .await?;
+    ) -> Result<Option<CopyUid>> {
+        let result = self
+            .run_command_and_check_ok(&format!(
+                "COPY {} {}",
+                sequence_set.into().validated()?,
+                self.validate_mailbox(mailbox_name.as_ref())?
+            ))
+            .await?;
 
-        Ok(())
+        Ok(result.code().and_then(CopyUid::from_code))
     }
 
     /// Equivalent to [`Session::copy`], except that all identifiers in `sequence_set` are
     /// [`Uid`]s. See also the [`UID` command](https://tools.ietf.org/html/rfc3501#section-6.4.8).
-    pub async fn uid_copy<S1: AsRef<str>, S2: AsRef<str>>(
+    pub async fn uid_copy<S1: Into<SequenceSet<Uid>>, S2: AsRef<str>>(
         &mut self,
         uid_set: S1,
         mailbox_name: S2,
-    ) -> Result<()> {
-        self.run_command_and_check_ok(&format!(
-            "UID COPY {} {}",
-            uid_set.as_ref(),
-            mailbox_name.as_ref()
-        ))
-        .await?;
+    ) -> Result<Option<CopyUid>> {
+        let result = self
+            .run_command_and_check_ok(&format!(
+                "UID COPY {} {}",
+                uid_set.into().validated()?,
+                self.validate_mailbox(mailbox_name.as_ref())?
+            ))
+            .await?;
 
-        Ok(())
+        Ok(result.code().and_then(CopyUid::from_code))
     }
 
     /// The [`MOVE` command](https://tools.ietf.org/html/rfc6851#section-3.1) takes two
@@ -923,15 +2403,15 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// orphaned).  The server will generally not leave any message in both mailboxes (it would be
     /// bad for a partial failure to result in a bunch of duplicate messages).  This is true even
     /// if the server returns with [`Error::No`].
-    pub async fn mv<S1: AsRef<str>, S2: AsRef<str>>(
+    pub async fn mv<S1: Into<SequenceSet<Seq>>, S2: AsRef<str>>(
         &mut self,
         sequence_set: S1,
         mailbox_name: S2,
     ) -> Result<()> {
         self.run_command_and_check_ok(&format!(
             "MOVE {} {}",
-            sequence_set.as_ref(),
-            validate_str(mailbox_name.as_ref())?
+            sequence_set.into().validated()?,
+            self.validate_mailbox(mailbox_name.as_ref())?
         ))
         .await?;
 
@@ -942,15 +2422,15 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     /// [`Uid`]s. See also the [`UID` command](https://tools.ietf.org/html/rfc3501#section-6.4.8)
     /// and the [semantics of `MOVE` and `UID
     /// MOVE`](https://tools.ietf.org/html/rfc6851#section-3.3).
-    pub async fn uid_mv<S1: AsRef<str>, S2: AsRef<str>>(
+    pub async fn uid_mv<S1: Into<SequenceSet<Uid>>, S2: AsRef<str>>(
         &mut self,
         uid_set: S1,
         mailbox_name: S2,
     ) -> Result<()> {
         self.run_command_and_check_ok(&format!(
             "UID MOVE {} {}",
-            uid_set.as_ref(),
-            validate_str(mailbox_name.as_ref())?
+            uid_set.into().validated()?,
+            self.validate_mailbox(mailbox_name.as_ref())?
         ))
         .await?;
 
@@ -994,17 +2474,19 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         mailbox_pattern: Option<&str>,
     ) -> Result<impl Stream<Item = Result<Name>> + '_ + Send> {
         let id = self
-            .run_command(&format!(
+            .run_streaming_command(&format!(
                 "LIST {} {}",
-                quote!(reference_name.unwrap_or("")),
-                mailbox_pattern.unwrap_or("\"\"")
+                self.validate_mailbox(reference_name.unwrap_or(""))?,
+                self.validate_mailbox(mailbox_pattern.unwrap_or(""))?
             ))
             .await?;
+        let utf8 = self.utf8_mailbox_names();
 
         Ok(parse_names(
             &mut self.conn.stream,
             self.unsolicited_responses_tx.clone(),
             id,
+            utf8,
         ))
     }
 
@@ -1023,22 +2505,43 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///
     /// The server will not unilaterally remove an existing mailbox name from the subscription list
     /// even if a mailbox by that name no longer exists.
+    ///
+    /// `IMAP4rev2` removed `LSUB`, so [once it is enabled](Session::is_rev2), or if the
+    /// capabilities last returned by [`Session::capabilities`] include
+    /// [`LIST-EXTENDED`](https://tools.ietf.org/html/rfc5258), `LIST (SUBSCRIBED)` is sent
+    /// instead.  Its results differ from those of `LSUB`:
+    ///
+    ///  - all names are flagged `\Subscribed`, and carry the same attributes as they would with
+    ///    [`Session::list`];
+    ///  - subscribed mailboxes that no longer exist are flagged `\NonExistent` (as a
+    ///    [`NameAttribute::Custom`]) rather than [`NameAttribute::NoSelect`], which is kept for
+    ///    existing names that can not be selected;
+    ///  - with the `%` wildcard, a name that is not subscribed itself, such as `foo` above, is not
+    ///    returned for its subscribed children.
     pub async fn lsub(
         &mut self,
         reference_name: Option<&str>,
         mailbox_pattern: Option<&str>,
     ) -> Result<impl Stream<Item = Result<Name>> + '_ + Send> {
+        let command = if self.rev2_enabled || self.has_cached_capability("LIST-EXTENDED") {
+            "LIST (SUBSCRIBED)"
+        } else {
+            "LSUB"
+        };
         let id = self
-            .run_command(&format!(
-                "LSUB {} {}",
-                quote!(reference_name.unwrap_or("")),
-                mailbox_pattern.unwrap_or("")
+            .run_streaming_command(&format!(
+                "{} {} {}",
+                command,
+                self.validate_mailbox(reference_name.unwrap_or(""))?,
+                self.validate_mailbox(mailbox_pattern.unwrap_or(""))?
             ))
             .await?;
+        let utf8 = self.utf8_mailbox_names();
         let names = parse_names(
             &mut self.conn.stream,
             self.unsolicited_responses_tx.clone(),
             id,
+            utf8,
         );
 
         Ok(names)
@@ -1086,7 +2589,7 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         let id = self
             .run_command(&format!(
                 "STATUS {} {}",
-                validate_str(mailbox_name.as_ref())?,
+                self.validate_mailbox(mailbox_name.as_ref())?,
                 data_items.as_ref()
             ))
             .await?;
@@ -1099,6 +2602,83 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         Ok(mbox)
     }
 
+    /// Returns the number of messages in `mailbox` which do not have [`Flag::Seen`] set, using
+    /// `STATUS (UNSEEN)` so that the mailbox does not have to be selected.
+    ///
+    /// Note that this is a count, unlike [`Mailbox::first_unseen`] as returned by
+    /// [`Session::select`], which is the sequence number of the _first_ unseen message.  As with
+    /// [`Session::status`], this should not be used on the selected mailbox.
+    pub async fn unread_count<S: AsRef<str>>(&mut self, mailbox: S) -> Result<u32> {
+        let id = self
+            .run_command(&format!(
+                "STATUS {} (UNSEEN)",
+                self.validate_mailbox(mailbox.as_ref())?
+            ))
+            .await?;
+        parse_status(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?
+        .iter()
+        .find_map(|attribute| match attribute {
+            StatusAttribute::Unseen(unseen) => Some(*unseen),
+            _ => None,
+        })
+        .ok_or_else(missing_unseen)
+    }
+
+    /// Returns the [unread count](Session::unread_count) of each of `mailboxes`, in the order
+    /// the mailboxes were given, [pipelining](Session::pipeline) the `STATUS` commands so that
+    /// they take a single round trip.
+    ///
+    /// A failure for one mailbox, e.g. because it does not exist, does not keep the others from
+    /// being counted.  An error is only returned if the connection fails.
+    pub async fn unread_counts<S: AsRef<str>>(
+        &mut self,
+        mailboxes: &[S],
+    ) -> Result<Vec<Result<u32>>> {
+        let responses = self
+            .pipeline_per_mailbox(mailboxes, |mailbox| format!("STATUS {} (UNSEEN)", mailbox))
+            .await?;
+        let names: Vec<Cow<'_, str>> = mailboxes
+            .iter()
+            .map(|mailbox| self.mailbox_name(mailbox.as_ref()))
+            .collect();
+        let mut counts = vec![None; mailboxes.len()];
+        let mut unsolicited = Vec::new();
+        for response in responses.untagged {
+            let index = match response.parsed() {
+                Response::MailboxData(MailboxDatum::Status { mailbox, status }) => names
+                    .iter()
+                    .position(|name| {
+                        name == mailbox
+                            || (name.eq_ignore_ascii_case("INBOX")
+                                && mailbox.eq_ignore_ascii_case("INBOX"))
+                    })
+                    .zip(status.iter().find_map(|attribute| match attribute {
+                        StatusAttribute::Unseen(unseen) => Some(*unseen),
+                        _ => None,
+                    })),
+                _ => None,
+            };
+            match index {
+                Some((index, unseen)) => counts[index] = Some(unseen),
+                None => unsolicited.push(response),
+            }
+        }
+        for response in unsolicited {
+            handle_unilateral(response, self.unsolicited_responses_tx.clone()).await;
+        }
+        Ok(responses
+            .results
+            .into_iter()
+            .zip(counts)
+            .map(|(result, count)| result.and_then(|()| count.ok_or_else(missing_unseen)))
+            .collect())
+    }
+
     /// This method returns a handle that lets you use the [`IDLE`
     /// command](https://tools.ietf.org/html/rfc2177#section-3) to listen for changes to the
     /// currently selected mailbox.
@@ -1145,31 +2725,319 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         mailbox: S,
         content: B,
     ) -> Result<()> {
-        let content = content.as_ref();
-        self.run_command(&format!(
-            "APPEND \"{}\" {{{}}}",
-            mailbox.as_ref(),
-            content.len()
-        ))
-        .await?;
+        let message = AppendMessage::new(content.as_ref());
+        self.append_messages(mailbox.as_ref(), &[message], false)
+            .await?;
+        Ok(())
+    }
 
-        match self.read_response().await {
-            Some(Ok(res)) => {
-                if let Response::Continue { .. } = res.parsed() {
-                    self.stream.as_mut().write_all(content).await?;
-                    self.stream.as_mut().write_all(b"\r\n").await?;
-                    self.stream.flush().await?;
-                    self.read_response().await.transpose()?;
-                    Ok(())
-                } else {
-                    Err(Error::Append)
+    /// Equivalent to [`Session::append`], except that `content` is sent as a binary literal
+    /// (`~{len}`), which may contain any bytes including NUL.  The server decodes any content
+    /// transfer encoding of the message itself, see [RFC 3516](https://tools.ietf.org/html/rfc3516).
+    ///
+    /// This requires the server to advertise the `BINARY` capability.  If the server cannot
+    /// handle one of the content transfer encodings in the message, [`Error::UnknownCte`] is
+    /// returned.
+    pub async fn append_binary<S: AsRef<str>, B: AsRef<[u8]>>(
+        &mut self,
+        mailbox: S,
+        content: B,
+    ) -> Result<()> {
+        let message = AppendMessage::new(content.as_ref());
+        self.append_messages(mailbox.as_ref(), &[message], true)
+            .await?;
+        Ok(())
+    }
+
+    /// Appends several `messages` to `mailbox` with a single
+    /// [`MULTIAPPEND`](https://tools.ietf.org/html/rfc3502) `APPEND` command, which saves a
+    /// round trip per message.  Each message can carry its own flags and internal date.
+    ///
+    /// If the server supports the [`UIDPLUS`](https://tools.ietf.org/html/rfc4315) extension,
+    /// the [`Uid`]s assigned to the new messages are returned, in the order of `messages`.
+    ///
+    /// `MULTIAPPEND` is only used if the capabilities last returned by
+    /// [`Session::capabilities`] include it.  Otherwise the messages are appended one by one
+    /// with separate `APPEND` commands.  Note that this fallback is not atomic: if appending
+    /// one of the messages fails, the messages before it stay in the mailbox, whereas with
+    /// `MULTIAPPEND` the server either appends all messages or none of them.
+    pub async fn multi_append<S: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+        messages: &[AppendMessage<'_>],
+    ) -> Result<Option<AppendUid>> {
+        let mailbox = mailbox.as_ref();
+        if messages.is_empty() {
+            return Ok(None);
+        }
+        // Also check the flags of the later messages before appending one by one below.
+        for message in messages {
+            message.arguments()?;
+        }
+
+        if self.has_cached_capability("MULTIAPPEND") {
+            let done = self.append_messages(mailbox, messages, false).await?;
+            return Ok(AppendUid::from_response(&done));
+        }
+
+        // The `Uid`s are only returned if every message got one, from the same mailbox
+        // incarnation, so that they line up with `messages`.
+        let mut combined: Option<AppendUid> = None;
+        let mut complete = true;
+        for message in messages {
+            let done = self
+                .append_messages(mailbox, std::slice::from_ref(message), false)
+                .await?;
+            match (&mut combined, AppendUid::from_response(&done)) {
+                (Some(combined), Some(append_uid))
+                    if combined.uid_validity == append_uid.uid_validity =>
+                {
+                    combined.uids.extend(append_uid.uids)
+                }
+                (None, Some(append_uid)) => combined = Some(append_uid),
+                _ => complete = false,
+            }
+        }
+        Ok(combined.filter(|_| complete))
+    }
+
+    /// Appends a message to `mailbox` that the server assembles from `parts`, using the [`CATENATE`
+    /// extension](https://tools.ietf.org/html/rfc4469).  [`CatenatePart::Url`] parts refer to
+    /// messages or body parts already on the server, so that e.g. a message can be forwarded with
+    /// its attachments without downloading and uploading them again.  The new message gets the
+    /// given `flags`.
+    ///
+    /// If the server supports the [`UIDPLUS`](https://tools.ietf.org/html/rfc4315) extension,
+    /// the [`Uid`] assigned to the new message is returned.  Fails with
+    /// [`Error::MissingCapability`] if the server does not support `CATENATE`, and with
+    /// [`Error::Append`] if `parts` is empty, without sending the command.
+    pub async fn append_catenate<S: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+        flags: &[Flag<'_>],
+        parts: &[CatenatePart],
+    ) -> Result<Option<AppendUid>> {
+        self.require_any_capability(&["CATENATE"]).await?;
+        if parts.is_empty() {
+            return Err(Error::Append);
+        }
+        let mut line = format!("APPEND {} ", self.validate_mailbox(mailbox.as_ref())?);
+        if !flags.is_empty() {
+            let flags = flags
+                .iter()
+                .map(|flag| {
+                    let flag = flag.to_string();
+                    validate_flag(&flag)?;
+                    Ok(flag)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            line.push_str(&format!("({}) ", flags.join(" ")));
+        }
+        line.push_str("CATENATE (");
+        // Validate all URLs before sending anything.
+        let urls = parts
+            .iter()
+            .map(|part| match part {
+                CatenatePart::Url(url) => validate_str(url).map(Some),
+                CatenatePart::Text(_) => Ok(None),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut id = None;
+        for (i, (part, url)) in parts.iter().zip(urls).enumerate() {
+            if i > 0 {
+                line.push(' ');
+            }
+            match part {
+                CatenatePart::Url(_) => {
+                    let url = url.expect("URLs are validated above");
+                    line.push_str(&format!("URL {}", url));
+                }
+                CatenatePart::Text(text) => {
+                    let (literal, synchronizing) = self.literal_header(text.len(), false);
+                    line.push_str(&format!("TEXT {}", literal));
+                    let line = std::mem::take(&mut line);
+                    match &id {
+                        None => id = Some(self.run_command(&line).await?),
+                        Some(_) => self.write_line(&line).await?,
+                    }
+                    let id = id.as_ref().expect("APPEND command was sent");
+                    if synchronizing {
+                        self.wait_for_continuation(id).await?;
+                    }
+                    self.stream.write_literal(text).await?;
+                }
+            }
+        }
+        line.push(')');
+        let id = match id {
+            None => self.run_command(&line).await?,
+            Some(id) => {
+                self.write_line(&line).await?;
+                id
+            }
+        };
+        let done = self
+            .conn
+            .read_done_ok(&id, Some(self.unsolicited_responses_tx.clone()))
+            .await?;
+        Ok(AppendUid::from_response(&done))
+    }
+
+    /// Sends `line` and a line break, continuing a command after a literal.
+    async fn write_line(&mut self, line: &str) -> Result<()> {
+        let line = format!("{}\r\n", line);
+        self.stream.debug(Direction::Sent, line.as_bytes());
+        self.stream.as_mut().write_all(line.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    /// Sends an `APPEND` command for all `messages` and returns the tagged response completing
+    /// it.
+    async fn append_messages(
+        &mut self,
+        mailbox: &str,
+        messages: &[AppendMessage<'_>],
+        binary: bool,
+    ) -> Result<ResponseData> {
+        let mut id = None;
+        // With `UTF8=ACCEPT`, messages are sent as `UTF8 (~{<len>}<body>)`, see RFC 6855 section 4.
+        let utf8 = self.utf8_enabled && !binary;
+        // Validate the mailbox and the flags of all messages before sending anything.
+        let mailbox = self.validate_mailbox(mailbox)?;
+        let message_arguments = messages
+            .iter()
+            .map(AppendMessage::arguments)
+            .collect::<Result<Vec<_>>>()?;
+        for (message, message_arguments) in messages.iter().zip(message_arguments) {
+            let (literal, synchronizing) = self.literal_header(message.body.len(), binary || utf8);
+            let arguments = if utf8 {
+                format!("{}UTF8 ({}", message_arguments, literal)
+            } else {
+                format!("{}{}", message_arguments, literal)
+            };
+            match &id {
+                None => {
+                    id = Some(
+                        self.run_command(&format!("APPEND {} {}", mailbox, arguments))
+                            .await?,
+                    );
                 }
+                Some(_) => self.write_line(&format!(" {}", arguments)).await?,
+            }
+            let id = id.as_ref().expect("APPEND command was sent");
+
+            if synchronizing {
+                self.wait_for_continuation(id).await?;
+            }
+            self.stream.write_literal(message.body).await?;
+            if utf8 {
+                self.stream.debug(Direction::Sent, b")");
+                self.stream.as_mut().write_all(b")").await?;
+            }
+        }
+        let id = id.ok_or(Error::Append)?;
+        self.stream.as_mut().write_all(b"\r\n").await?;
+        self.stream.flush().await?;
+
+        self.conn
+            .read_done_ok(&id, Some(self.unsolicited_responses_tx.clone()))
+            .await
+    }
+
+    /// Waits for the server to request the data of a synchronizing literal.
+    async fn wait_for_continuation(&mut self, id: &RequestId) -> Result<()> {
+        loop {
+            match self.read_response().await {
+                Some(Ok(res)) => match res.parsed() {
+                    Response::Continue { .. } => return Ok(()),
+                    Response::Done { .. } => {
+                        self.conn
+                            .check_done_ok_from(
+                                id,
+                                Some(self.unsolicited_responses_tx.clone()),
+                                res,
+                            )
+                            .await?;
+                        return Err(Error::Append);
+                    }
+                    // The server may send untagged responses, e.g. about new messages, before
+                    // asking for the literal.
+                    _ => handle_unilateral(res, self.unsolicited_responses_tx.clone()).await,
+                },
+                Some(Err(err)) => return Err(err.into()),
+                None => return Err(Error::Append),
             }
-            Some(Err(err)) => Err(err.into()),
-            _ => Err(Error::Append),
         }
     }
 
+    /// Whether mailbox names are exchanged as UTF-8 instead of modified UTF-7, which is the case
+    /// once `UTF8=ACCEPT` or `IMAP4rev2` is enabled.
+    fn utf8_mailbox_names(&self) -> bool {
+        self.utf8_enabled || self.rev2_enabled
+    }
+
+    /// Converts a mailbox name to the form sent to the server, see
+    /// [`Session::utf8_mailbox_names`].
+    fn mailbox_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if self.utf8_mailbox_names() {
+            Cow::Borrowed(name)
+        } else {
+            Cow::Owned(encode_mailbox_name(name))
+        }
+    }
+
+    /// Converts a mailbox name with [`Session::mailbox_name`] and quotes it.
+    fn validate_mailbox(&self, name: &str) -> Result<String> {
+        validate_str(&self.mailbox_name(name))
+    }
+
+    /// Validates `names` with [`Session::validate_mailbox`], separated by spaces.
+    fn validate_mailboxes(&self, names: &[String]) -> Result<String> {
+        let names = names
+            .iter()
+            .map(|name| self.validate_mailbox(name))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(names.join(" "))
+    }
+
+    /// Remembers the selected mailbox, which unsolicited responses refer to while `NOTIFY` is
+    /// active.
+    fn set_selected(&mut self, mailbox: Option<String>) {
+        self.unsolicited_responses_tx.notify_mailbox =
+            mailbox.as_ref().filter(|_| self.notifying).cloned();
+        self.selected = mailbox;
+    }
+
+    /// Whether the capabilities last returned by [`Session::capabilities`] include `capability`.
+    fn has_cached_capability(&self, capability: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_some_and(|caps| caps.has_str(capability))
+    }
+
+    /// Returns the prefix announcing a literal of `len` bytes, e.g. `{5}` or `~{5+}`, and whether
+    /// the client has to wait for a continuation request before sending the literal.
+    ///
+    /// Non-synchronizing literals ([RFC 7888](https://tools.ietf.org/html/rfc7888)) are used when
+    /// the capabilities last returned by [`Session::capabilities`] include `LITERAL+`, or include
+    /// `LITERAL-` and the literal is at most 4096 bytes long.
+    fn literal_header(&self, len: usize, binary: bool) -> (String, bool) {
+        let non_synchronizing = if self.has_cached_capability("LITERAL+") {
+            true
+        } else {
+            self.has_cached_capability("LITERAL-") && len <= 4096
+        };
+        let literal = format!(
+            "{}{{{}{}}}",
+            if binary { "~" } else { "" },
+            len,
+            if non_synchronizing { "+" } else { "" }
+        );
+        (literal, !non_synchronizing)
+    }
+
     /// The [`SEARCH` command](https://tools.ietf.org/html/rfc3501#section-6.4.4) searches the
     /// mailbox for messages that match the given `query`.  `query` consist of one or more search
     /// keys separated by spaces.  The response from the server contains a listing of [`Seq`]s
@@ -1214,6 +3082,8 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
     ///
     ///  - `BEFORE <date>`: Messages whose internal date (disregarding time and timezone) is earlier than the specified date.
     ///  - `SINCE <date>`: Messages whose internal date (disregarding time and timezone) is within or later than the specified date.
+    ///
+    ///  - `YOUNGER <seconds>`, `OLDER <seconds>`: Messages whose internal date is at most or at least the given number of seconds ago.  These require the [`WITHIN`](https://tools.ietf.org/html/rfc5032) extension, see [`Within`] and [`Session::within_criterion`].
     pub async fn search<S: AsRef<str>>(&mut self, query: S) -> Result<HashSet<Seq>> {
         let id = self
             .run_command(&format!("SEARCH {}", query.as_ref()))
@@ -1225,7 +3095,53 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         )
         .await?;
 
-        Ok(seqs)
+        Ok(seqs.into_iter().map(Seq).collect())
+    }
+
+    /// Searches for messages that approximately match `query`, using the `FUZZY` search key of
+    /// the [`SEARCH=FUZZY` extension](https://tools.ietf.org/html/rfc6203), and asks for the
+    /// relevancy of each result.
+    ///
+    /// `query` consists of search keys as for [`Session::search`], all of which are matched
+    /// fuzzily.  What counts as a match and how the relevancy is scored is defined by the
+    /// server, see [`FuzzySearch`].  Fails with [`Error::MissingCapability`] if the server does
+    /// not support fuzzy search.
+    pub async fn fuzzy_search<S: AsRef<str>>(&mut self, query: S) -> Result<FuzzySearch> {
+        self.run_fuzzy_search("SEARCH", query.as_ref()).await
+    }
+
+    /// Equivalent to [`Session::fuzzy_search`], except that the returned identifiers are
+    /// [`Uid`]s.
+    pub async fn uid_fuzzy_search<S: AsRef<str>>(&mut self, query: S) -> Result<FuzzySearch> {
+        self.run_fuzzy_search("UID SEARCH", query.as_ref()).await
+    }
+
+    async fn run_fuzzy_search(&mut self, command: &str, query: &str) -> Result<FuzzySearch> {
+        self.require_any_capability(&["SEARCH=FUZZY"]).await?;
+        let id = self
+            .run_command(&format!(
+                "{} RETURN (ALL RELEVANCY) FUZZY ({})",
+                command, query
+            ))
+            .await?;
+        parse_fuzzy_search(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await
+    }
+
+    /// Returns the search key for `within`, if the server supports the `WITHIN` extension.
+    ///
+    /// Otherwise this fails with [`Error::MissingCapability`], unless `approximate` is set, in
+    /// which case the [date-based approximation](Within::to_date_criterion) is returned.
+    pub async fn within_criterion(&mut self, within: Within, approximate: bool) -> Result<String> {
+        match self.require_any_capability(&["WITHIN"]).await {
+            Ok(()) => Ok(within.to_string()),
+            Err(Error::MissingCapability(_)) if approximate => Ok(within.to_date_criterion()),
+            Err(err) => Err(err),
+        }
     }
 
     /// Equivalent to [`Session::search`], except that the returned identifiers
@@ -1242,89 +3158,661 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Session<T> {
         )
         .await?;
 
-        Ok(uids)
+        Ok(uids.into_iter().map(Uid).collect())
     }
 
-    // these are only here because they are public interface, the rest is in `Connection`
-    /// Runs a command and checks if it returns OK.
-    pub async fn run_command_and_check_ok<S: AsRef<str>>(&mut self, command: S) -> Result<()> {
-        self.conn
-            .run_command_and_check_ok(
-                command.as_ref(),
-                Some(self.unsolicited_responses_tx.clone()),
-            )
+    /// Searches for messages matching `query` like [`Session::search`], and groups them into
+    /// threads of messages that reply to each other, with the `THREAD` command of the [`THREAD`
+    /// extension](https://tools.ietf.org/html/rfc5256#section-4).
+    ///
+    /// `algorithm` is the threading algorithm, such as `REFERENCES` or `ORDEREDSUBJECT`, which
+    /// the server advertises as a `THREAD=<algorithm>` capability, otherwise this fails with
+    /// [`Error::MissingCapability`].  The threads are returned in the order the server sent
+    /// them in, see [`ThreadNode`] for how to list their messages in display order.
+    pub async fn thread<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        algorithm: S1,
+        query: S2,
+    ) -> Result<Vec<ThreadNode<Seq>>> {
+        let threads = self
+            .run_thread("THREAD", algorithm.as_ref(), query.as_ref())
             .await?;
-
-        Ok(())
+        Ok(threads.into_iter().map(|thread| thread.map(Seq)).collect())
     }
 
-    /// Runs any command passed to it.
-    pub async fn run_command<S: AsRef<str>>(&mut self, command: S) -> Result<RequestId> {
-        let id = self.conn.run_command(command.as_ref()).await?;
-
-        Ok(id)
+    /// Equivalent to [`Session::thread`], except that the returned identifiers are [`Uid`]
+    /// instead of [`Seq`].
+    pub async fn uid_thread<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        algorithm: S1,
+        query: S2,
+    ) -> Result<Vec<ThreadNode<Uid>>> {
+        let threads = self
+            .run_thread("UID THREAD", algorithm.as_ref(), query.as_ref())
+            .await?;
+        Ok(threads.into_iter().map(|thread| thread.map(Uid)).collect())
     }
 
-    /// Runs an arbitrary command, without adding a tag to it.
-    pub async fn run_command_untagged<S: AsRef<str>>(&mut self, command: S) -> Result<()> {
-        self.conn.run_command_untagged(command.as_ref()).await?;
-
-        Ok(())
+    async fn run_thread(
+        &mut self,
+        command: &str,
+        algorithm: &str,
+        query: &str,
+    ) -> Result<Vec<ThreadNode<u32>>> {
+        let algorithm = validate_atom(algorithm)?;
+        self.require_any_capability(&[&format!("THREAD={}", algorithm)])
+            .await?;
+        // Servers have to support UTF-8, which includes US-ASCII.
+        let id = self
+            .run_command(&format!("{} {} UTF-8 {}", command, algorithm, query))
+            .await?;
+        parse_thread(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await
     }
 
-    /// Read the next response on the connection.
-    pub async fn read_response(&mut self) -> Option<io::Result<ResponseData>> {
-        self.conn.read_response().await
+    /// The [`GETMETADATA` command](https://tools.ietf.org/html/rfc5464#section-4.2) retrieves
+    /// the values of the given metadata `entries` of `mailbox`, or of the server if `mailbox`
+    /// is empty.  Entry names start with `/private/` or `/shared/`, e.g. `/shared/comment`.
+    ///
+    /// The result maps entry names to their values.  Entries that do not exist are either
+    /// missing from the map or have a value of `None`.
+    ///
+    /// This requires the `METADATA` (or, for server metadata, `METADATA-SERVER`) capability.
+    pub async fn get_metadata<S: AsRef<str>, E: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+        entries: &[E],
+    ) -> Result<HashMap<String, Option<String>>> {
+        self.get_metadata_with_options(mailbox, entries, &MetadataOptions::default())
+            .await
     }
-}
 
-impl<T: Read + Write + Unpin + fmt::Debug> Connection<T> {
-    unsafe_pinned!(stream: ImapStream<T>);
-
-    /// Convert this connection into the raw underlying stream.
-    pub fn into_inner(self) -> T {
-        let Self { stream, .. } = self;
-        stream.into_inner()
+    /// Equivalent to [`Session::get_metadata`], but also allows to limit the size of returned
+    /// values (`MAXSIZE`) and to retrieve the entries below the given ones (`DEPTH`).  See
+    /// [`MetadataOptions`].
+    pub async fn get_metadata_with_options<S: AsRef<str>, E: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+        entries: &[E],
+        options: &MetadataOptions,
+    ) -> Result<HashMap<String, Option<String>>> {
+        self.require_metadata_capability(mailbox.as_ref()).await?;
+        let entries = entries
+            .iter()
+            .map(|entry| validate_str(entry.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+        let id = self
+            .run_command(&format!(
+                "GETMETADATA {}{} ({})",
+                options.arguments(),
+                self.validate_mailbox(mailbox.as_ref())?,
+                entries.join(" ")
+            ))
+            .await?;
+        parse_metadata(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await
     }
 
-    /// Read the next response on the connection.
-    pub async fn read_response(&mut self) -> Option<io::Result<ResponseData>> {
-        self.stream.next().await
+    /// The [`SETMETADATA` command](https://tools.ietf.org/html/rfc5464#section-4.3) sets the
+    /// given metadata entries of `mailbox`, or of the server if `mailbox` is empty.  An entry
+    /// with a value of `None` is removed.
+    ///
+    /// Values can not contain line breaks.
+    ///
+    /// This requires the `METADATA` (or, for server metadata, `METADATA-SERVER`) capability.
+    pub async fn set_metadata<S: AsRef<str>, E: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+        entry_values: &[(E, Option<V>)],
+    ) -> Result<()> {
+        self.require_metadata_capability(mailbox.as_ref()).await?;
+        let entry_values = entry_values
+            .iter()
+            .map(|(entry, value)| {
+                let value = match value {
+                    Some(value) => validate_str(value.as_ref())?,
+                    None => "NIL".to_string(),
+                };
+                Ok(format!("{} {}", validate_str(entry.as_ref())?, value))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.run_command_and_check_ok(&format!(
+            "SETMETADATA {} ({})",
+            self.validate_mailbox(mailbox.as_ref())?,
+            entry_values.join(" ")
+        ))
+        .await?;
+
+        Ok(())
     }
 
-    pub(crate) async fn run_command_untagged(&mut self, command: &str) -> Result<()> {
-        self.stream
-            .encode(Request(None, command.as_bytes().into()))
+    /// The [`GETQUOTAROOT` command](https://tools.ietf.org/html/rfc2087#section-4.3) returns
+    /// the quota roots `mailbox` belongs to, together with the resource usage and limits of each
+    /// of these roots.
+    ///
+    /// This requires the `QUOTA` capability.
+    pub async fn get_quota_root<S: AsRef<str>>(
+        &mut self,
+        mailbox: S,
+    ) -> Result<(QuotaRoot, Vec<Quota>)> {
+        self.require_any_capability(&["QUOTA"]).await?;
+        let id = self
+            .run_command(&format!(
+                "GETQUOTAROOT {}",
+                self.validate_mailbox(mailbox.as_ref())?
+            ))
             .await?;
-        self.stream.flush().await?;
-        Ok(())
+        let (mut quota_roots, quotas) = parse_quota(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?;
+        let quota_root = quota_roots.pop().ok_or_else(|| {
+            Error::Parse(ParseError::Unexpected(
+                "missing QUOTAROOT response".to_string(),
+            ))
+        })?;
+        Ok((quota_root, quotas))
     }
 
-    pub(crate) async fn run_command(&mut self, command: &str) -> Result<RequestId> {
-        let request_id = self.request_ids.next().unwrap(); // safe: never returns Err
-        self.stream
-            .encode(Request(Some(request_id.clone()), command.as_bytes().into()))
+    /// The [`GETQUOTA` command](https://tools.ietf.org/html/rfc2087#section-4.2) returns the
+    /// resource usage and limits of the quota root `root`.
+    ///
+    /// This requires the `QUOTA` capability.
+    pub async fn get_quota<S: AsRef<str>>(&mut self, root: S) -> Result<Vec<QuotaResource>> {
+        self.require_any_capability(&["QUOTA"]).await?;
+        let id = self
+            .run_command(&format!("GETQUOTA {}", validate_str(root.as_ref())?))
             .await?;
-        self.stream.flush().await?;
-        Ok(request_id)
+        let (_, quotas) = parse_quota(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?;
+        Ok(quotas
+            .into_iter()
+            .flat_map(|quota| quota.resources)
+            .collect())
     }
 
-    /// Execute a command and check that the next response is a matching done.
-    pub async fn run_command_and_check_ok(
+    /// The [`SETQUOTA` command](https://tools.ietf.org/html/rfc2087#section-4.1) sets the
+    /// resource limits of the quota root `root`, and returns the resulting usage and limits.
+    /// Resources that are not listed in `limits` become unlimited.  Usually only
+    /// administrators are allowed to do this.
+    ///
+    /// This requires the `QUOTA` capability.
+    pub async fn set_quota<S: AsRef<str>>(
         &mut self,
-        command: &str,
-        unsolicited: Option<channel::Sender<UnsolicitedResponse>>,
-    ) -> Result<()> {
-        let id = self.run_command(command).await?;
-        self.check_done_ok(&id, unsolicited).await?;
+        root: S,
+        limits: &[(QuotaResourceName, u64)],
+    ) -> Result<Vec<QuotaResource>> {
+        self.require_any_capability(&["QUOTA"]).await?;
+        let limits: Vec<String> = limits
+            .iter()
+            .map(|(name, limit)| format!("{} {}", name, limit))
+            .collect();
+        let id = self
+            .run_command(&format!(
+                "SETQUOTA {} ({})",
+                validate_str(root.as_ref())?,
+                limits.join(" ")
+            ))
+            .await?;
+        let (_, quotas) = parse_quota(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?;
+        Ok(quotas
+            .into_iter()
+            .flat_map(|quota| quota.resources)
+            .collect())
+    }
 
-        Ok(())
+    /// The [`GETACL` command](https://tools.ietf.org/html/rfc4314#section-3.3) returns the
+    /// access control list of `mailbox`.
+    ///
+    /// This requires the `ACL` capability.  If the user is not allowed to administer the
+    /// mailbox, [`Error::NoPerm`] is returned.
+    pub async fn get_acl<S: AsRef<str>>(&mut self, mailbox: S) -> Result<Acl> {
+        self.require_any_capability(&["ACL"]).await?;
+        let id = self
+            .run_command(&format!(
+                "GETACL {}",
+                self.validate_mailbox(mailbox.as_ref())?
+            ))
+            .await?;
+        parse_acl(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| Error::Parse(ParseError::Unexpected("missing ACL response".to_string())))
     }
 
-    pub(crate) async fn check_done_ok(
+    /// The [`SETACL` command](https://tools.ietf.org/html/rfc4314#section-3.1) changes the
+    /// rights of `identifier` on `mailbox`.
+    ///
+    /// `rights` is a string of right characters, such as `lrs`.  Without a prefix, it replaces
+    /// the current rights of the identifier.  With a `+` prefix, the rights are added to the
+    /// current rights, and with a `-` prefix they are removed from them.
+    ///
+    /// This requires the `ACL` capability.
+    pub async fn set_acl<S1, S2, S3>(
+        &mut self,
+        mailbox: S1,
+        identifier: S2,
+        rights: S3,
+    ) -> Result<()>
+    where
+        S1: AsRef<str>,
+        S2: AsRef<str>,
+        S3: AsRef<str>,
+    {
+        self.require_any_capability(&["ACL"]).await?;
+        self.run_command_and_check_ok(&format!(
+            "SETACL {} {} {}",
+            self.validate_mailbox(mailbox.as_ref())?,
+            validate_str(identifier.as_ref())?,
+            validate_str(rights.as_ref())?
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// The [`DELETEACL` command](https://tools.ietf.org/html/rfc4314#section-3.2) removes all
+    /// rights of `identifier` on `mailbox`.
+    ///
+    /// This requires the `ACL` capability.
+    pub async fn delete_acl<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        mailbox: S1,
+        identifier: S2,
+    ) -> Result<()> {
+        self.require_any_capability(&["ACL"]).await?;
+        self.run_command_and_check_ok(&format!(
+            "DELETEACL {} {}",
+            self.validate_mailbox(mailbox.as_ref())?,
+            validate_str(identifier.as_ref())?
+        ))
+        .await?;
+
+        Ok(())
+    }
+
+    /// The [`LISTRIGHTS` command](https://tools.ietf.org/html/rfc4314#section-3.4) returns the
+    /// rights that can be granted to `identifier` on `mailbox`.
+    ///
+    /// This requires the `ACL` capability.
+    pub async fn list_rights<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        mailbox: S1,
+        identifier: S2,
+    ) -> Result<ListRights> {
+        self.require_any_capability(&["ACL"]).await?;
+        let id = self
+            .run_command(&format!(
+                "LISTRIGHTS {} {}",
+                self.validate_mailbox(mailbox.as_ref())?,
+                validate_str(identifier.as_ref())?
+            ))
+            .await?;
+        parse_list_rights(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| {
+            Error::Parse(ParseError::Unexpected(
+                "missing LISTRIGHTS response".to_string(),
+            ))
+        })
+    }
+
+    /// The [`MYRIGHTS` command](https://tools.ietf.org/html/rfc4314#section-3.5) returns the
+    /// rights the logged in user has on `mailbox`.
+    ///
+    /// This requires the `ACL` capability.
+    pub async fn my_rights<S: AsRef<str>>(&mut self, mailbox: S) -> Result<MyRights> {
+        self.require_any_capability(&["ACL"]).await?;
+        let id = self
+            .run_command(&format!(
+                "MYRIGHTS {}",
+                self.validate_mailbox(mailbox.as_ref())?
+            ))
+            .await?;
+        parse_my_rights(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| {
+            Error::Parse(ParseError::Unexpected(
+                "missing MYRIGHTS response".to_string(),
+            ))
+        })
+    }
+
+    /// Generates an authorized version of the IMAP `url` with the `GENURLAUTH` command of the
+    /// [`URLAUTH` extension](https://tools.ietf.org/html/rfc4467), which another party such as a
+    /// submission server can use to fetch the referred message or body part without the user's
+    /// credentials.
+    ///
+    /// `url` must be an absolute URL ending in `;URLAUTH=<access>`, e.g.
+    /// `imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred`, and `mechanism` the
+    /// authorization mechanism to use, usually `INTERNAL`.  Returns the URL with the authorization
+    /// token appended.  Fails with [`Error::MissingCapability`] if the server does not support
+    /// `URLAUTH`.
+    pub async fn genurlauth<S1: AsRef<str>, S2: AsRef<str>>(
+        &mut self,
+        url: S1,
+        mechanism: S2,
+    ) -> Result<String> {
+        self.require_any_capability(&["URLAUTH"]).await?;
+        let command = format!(
+            "GENURLAUTH {} {}",
+            validate_str(url.as_ref())?,
+            validate_atom(mechanism.as_ref())?
+        );
+        let id = self.run_command(&command).await?;
+        parse_genurlauth(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await?
+        .pop()
+        .ok_or_else(|| {
+            Error::Parse(ParseError::Unexpected(
+                "missing GENURLAUTH response".to_string(),
+            ))
+        })
+    }
+
+    /// Invalidates URLs authorized with [`Session::genurlauth`] by resetting the access keys the
+    /// server uses for them, with the `RESETKEY` command of the [`URLAUTH`
+    /// extension](https://tools.ietf.org/html/rfc4467#section-7.3).
+    ///
+    /// Without a `mailbox`, the keys of all mailboxes are reset.  Otherwise only the keys of
+    /// `mailbox` are, and only for the given `mechanisms`, or for all mechanisms if none are
+    /// given.  Fails with [`Error::MissingCapability`] if the server does not support `URLAUTH`.
+    pub async fn resetkey(&mut self, mailbox: Option<&str>, mechanisms: &[&str]) -> Result<()> {
+        self.require_any_capability(&["URLAUTH"]).await?;
+        let mut command = "RESETKEY".to_string();
+        if let Some(mailbox) = mailbox {
+            command.push(' ');
+            command.push_str(&self.validate_mailbox(mailbox)?);
+            for mechanism in mechanisms {
+                command.push(' ');
+                command.push_str(validate_atom(mechanism)?);
+            }
+        }
+        self.run_command_and_check_ok(&command).await?;
+        Ok(())
+    }
+
+    /// Fetches the messages or body parts the IMAP `urls` refer to, with the `URLFETCH` command
+    /// of the [`URLAUTH` extension](https://tools.ietf.org/html/rfc4467#section-7.2).  This is
+    /// what a submission server does with URLs authorized by [`Session::genurlauth`].
+    ///
+    /// The returned list holds an entry for each URL the server answered for, in which `data`
+    /// is `None` if the URL could not be resolved.  Fails with [`Error::MissingCapability`] if
+    /// the server does not support `URLAUTH`.
+    pub async fn urlfetch<S: AsRef<str>>(&mut self, urls: &[S]) -> Result<Vec<UrlFetch>> {
+        self.require_any_capability(&["URLAUTH"]).await?;
+        if urls.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut command = "URLFETCH".to_string();
+        for url in urls {
+            command.push(' ');
+            command.push_str(&validate_str(url.as_ref())?);
+        }
+        let id = self.run_command(&command).await?;
+        parse_urlfetch(
+            &mut self.conn.stream,
+            self.unsolicited_responses_tx.clone(),
+            id,
+        )
+        .await
+    }
+
+    async fn require_metadata_capability(&mut self, mailbox: &str) -> Result<()> {
+        if mailbox.is_empty() {
+            self.require_any_capability(&["METADATA", "METADATA-SERVER"])
+                .await
+        } else {
+            self.require_any_capability(&["METADATA"]).await
+        }
+    }
+
+    /// Fails with [`Error::MissingCapability`] unless the server advertises one of the given
+    /// `capabilities`.  Capabilities are requested from the server if they are not known yet.
+    async fn require_any_capability(&mut self, capabilities: &[&str]) -> Result<()> {
+        self.capabilities().await?;
+        if capabilities
+            .iter()
+            .any(|cap| self.has_cached_capability(cap))
+        {
+            Ok(())
+        } else {
+            Err(Error::MissingCapability(capabilities.join(" or ")))
+        }
+    }
+
+    // these are only here because they are public interface, the rest is in `Connection`
+    /// Runs a command and checks if it returns OK. The tagged response completing the command is
+    /// returned, so that its response code and text can be inspected.
+    pub async fn run_command_and_check_ok<S: AsRef<str>>(
+        &mut self,
+        command: S,
+    ) -> Result<CommandResult> {
+        self.finish_abandoned().await?;
+        let id = self.conn.run_command(command.as_ref()).await?;
+        let response = self
+            .conn
+            .read_done_ok(&id, Some(self.unsolicited_responses_tx.clone()))
+            .await?;
+        Ok(CommandResult::new(response))
+    }
+
+    /// Runs any command passed to it.
+    pub async fn run_command<S: AsRef<str>>(&mut self, command: S) -> Result<RequestId> {
+        self.finish_abandoned().await?;
+        let id = self.conn.run_command(command.as_ref()).await?;
+
+        Ok(id)
+    }
+
+    /// Runs an arbitrary command and returns all responses to it, ending with the tagged response
+    /// completing it, without interpreting them.  This allows using extensions that this crate
+    /// does not support yet.
+    ///
+    /// The command is tagged like all other commands, and `command` must not contain the tag.
+    /// Unlike other commands, a `NO` or `BAD` completion is returned like any other response
+    /// rather than as an error, and unsolicited responses are returned too instead of being sent
+    /// to [`Session::unsolicited_responses`].  Responses still have to be understood by
+    /// [`imap_proto`]; those that are not make the stream fail with [`Error::Parse`].
+    ///
+    /// ```no_run
+    /// # async fn example(mut session: async_imap::Session<async_std::net::TcpStream>) -> async_imap::error::Result<()> {
+    /// use futures::TryStreamExt;
+    ///
+    /// let mut responses = session.run_raw("XLIST \"\" *").await?;
+    /// while let Some(response) = responses.try_next().await? {
+    ///     println!("{:?}", response.parsed());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_raw<S: AsRef<str>>(
+        &mut self,
+        command: S,
+    ) -> Result<impl Stream<Item = Result<ResponseData>> + '_ + Send + Unpin> {
+        let id = self.run_streaming_command(command).await?;
+        Ok(parse_raw(&mut self.conn.stream, id))
+    }
+
+    /// Runs an arbitrary command, without adding a tag to it.
+    pub async fn run_command_untagged<S: AsRef<str>>(&mut self, command: S) -> Result<()> {
+        self.conn.run_command_untagged(command.as_ref()).await?;
+
+        Ok(())
+    }
+
+    /// Sends all `commands` at once, without waiting for the response to one command before
+    /// sending the next, and collects their responses. This saves a round trip per command.
+    ///
+    /// Only commands that do not depend on each other may be pipelined: the server is free to
+    /// execute them in any order, so for example a `FETCH` must not be sent along with the
+    /// `SELECT` of its mailbox. Commands must not contain synchronizing literals either, as those
+    /// require waiting for the server. See [RFC 3501 section
+    /// 5.5](https://tools.ietf.org/html/rfc3501#section-5.5) for details.
+    ///
+    /// The outcome of each command is reported separately in the returned
+    /// [`PipelineResponses`], and an error is only returned if the connection fails.
+    pub async fn pipeline<S: AsRef<str>>(&mut self, commands: &[S]) -> Result<PipelineResponses> {
+        self.finish_abandoned().await?;
+        let mut ids = Vec::with_capacity(commands.len());
+        for command in commands {
+            let id = self.conn.request_ids.next().unwrap(); // safe: never returns Err
+            self.conn
+                .stream
+                .encode(Request(
+                    Some(id.clone()),
+                    command.as_ref().as_bytes().into(),
+                ))
+                .await?;
+            ids.push(id);
+        }
+        self.conn.stream.flush().await?;
+
+        let mut results: Vec<Option<Result<()>>> = ids.iter().map(|_| None).collect();
+        let mut untagged = Vec::new();
+        let mut pending = ids.len();
+        while pending > 0 {
+            let response = match self.conn.stream.next().await {
+                Some(response) => response?,
+                None => return Err(Error::ConnectionLost),
+            };
+            match response.parsed() {
+                Response::Done {
+                    tag,
+                    status,
+                    code,
+                    information,
+                } => match ids.iter().position(|id| id == tag) {
+                    Some(index) if results[index].is_none() => {
+                        results[index] = Some(check_status_ok(
+                            status,
+                            code.as_ref(),
+                            information.as_deref(),
+                        ));
+                        pending -= 1;
+                    }
+                    _ => handle_unilateral(response, self.unsolicited_responses_tx.clone()).await,
+                },
+                _ => untagged.push(response),
+            }
+        }
+
+        Ok(PipelineResponses {
+            untagged,
+            results: results.into_iter().map(Option::unwrap).collect(),
+        })
+    }
+
+    /// Read the next response on the connection.
+    pub async fn read_response(&mut self) -> Option<io::Result<ResponseData>> {
+        self.conn.read_response().await
+    }
+}
+
+impl<T: Read + Write + Unpin + fmt::Debug> Connection<T> {
+    /// Convert this connection into the raw underlying stream.
+    pub fn into_inner(self) -> T {
+        let Self { stream, .. } = self;
+        stream.into_inner()
+    }
+
+    /// Returns a reference to the raw underlying stream, e.g. to inspect what was sent over the
+    /// mock stream of the `test-util` feature in tests.  Reading from or writing to the
+    /// stream directly would interfere with the protocol.
+    pub fn get_ref(&self) -> &T {
+        &self.stream.inner
+    }
+
+    /// Read the next response on the connection.
+    pub async fn read_response(&mut self) -> Option<io::Result<ResponseData>> {
+        self.stream.next().await
+    }
+
+    pub(crate) async fn run_command_untagged(&mut self, command: &str) -> Result<()> {
+        self.stream
+            .encode(Request(None, command.as_bytes().into()))
+            .await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+
+    pub(crate) async fn run_command(&mut self, command: &str) -> Result<RequestId> {
+        let request_id = self.request_ids.next().unwrap(); // safe: never returns Err
+        self.stream
+            .encode(Request(Some(request_id.clone()), command.as_bytes().into()))
+            .await?;
+        self.stream.flush().await?;
+        Ok(request_id)
+    }
+
+    /// Like [`Connection::run_command`], but marks the command as unfinished before sending it,
+    /// see [`ImapStream::set_unfinished`], so that its responses are discarded if it is dropped
+    /// at any point before its completion is read.
+    pub(crate) async fn run_unfinished_command(&mut self, command: &str) -> Result<RequestId> {
+        let request_id = self.request_ids.next().unwrap(); // safe: never returns Err
+        self.stream.set_unfinished(request_id.clone());
+        self.stream
+            .encode(Request(Some(request_id.clone()), command.as_bytes().into()))
+            .await?;
+        self.stream.flush().await?;
+        Ok(request_id)
+    }
+
+    /// Execute a command and check that the next response is a matching done.
+    pub async fn run_command_and_check_ok(
+        &mut self,
+        command: &str,
+        unsolicited: Option<mpsc::Sender<UnsolicitedResponse>>,
+    ) -> Result<CommandResult> {
+        let id = self.run_command(command).await?;
+        let response = self
+            .read_done_ok(&id, unsolicited.map(UnsolicitedSender::new))
+            .await?;
+
+        Ok(CommandResult::new(response))
+    }
+
+    pub(crate) async fn check_done_ok(
         &mut self,
         id: &RequestId,
-        unsolicited: Option<channel::Sender<UnsolicitedResponse>>,
+        unsolicited: Option<UnsolicitedSender>,
     ) -> Result<()> {
         if let Some(first_res) = self.stream.next().await {
             self.check_done_ok_from(id, unsolicited, first_res?).await
@@ -1336,9 +3824,33 @@ impl<T: Read + Write + Unpin + fmt::Debug> Connection<T> {
     pub(crate) async fn check_done_ok_from(
         &mut self,
         id: &RequestId,
-        unsolicited: Option<channel::Sender<UnsolicitedResponse>>,
-        mut response: ResponseData,
+        unsolicited: Option<UnsolicitedSender>,
+        response: ResponseData,
     ) -> Result<()> {
+        self.read_done_ok_from(id, unsolicited, response).await?;
+        Ok(())
+    }
+
+    /// Like [`Connection::check_done_ok`], but returns the tagged response completing the
+    /// command, so that its response code can be inspected.
+    pub(crate) async fn read_done_ok(
+        &mut self,
+        id: &RequestId,
+        unsolicited: Option<UnsolicitedSender>,
+    ) -> Result<ResponseData> {
+        if let Some(first_res) = self.stream.next().await {
+            self.read_done_ok_from(id, unsolicited, first_res?).await
+        } else {
+            Err(Error::ConnectionLost)
+        }
+    }
+
+    pub(crate) async fn read_done_ok_from(
+        &mut self,
+        id: &RequestId,
+        unsolicited: Option<UnsolicitedSender>,
+        mut response: ResponseData,
+    ) -> Result<ResponseData> {
         loop {
             if let Response::Done {
                 status,
@@ -1347,10 +3859,14 @@ impl<T: Read + Write + Unpin + fmt::Debug> Connection<T> {
                 tag,
             } = response.parsed()
             {
-                self.check_status_ok(status, code.as_ref(), information.as_deref())?;
-
+                // Completions of other commands are passed on like untagged responses.
                 if tag == id {
-                    return Ok(());
+                    if let (Some(unsolicited), Some(alert)) = (&unsolicited, alert_text(&response))
+                    {
+                        unsolicited.try_send(UnsolicitedResponse::Alert(alert));
+                    }
+                    check_status_ok(status, code.as_ref(), information.as_deref())?;
+                    return Ok(response);
                 }
             }
 
@@ -1365,32 +3881,61 @@ impl<T: Read + Write + Unpin + fmt::Debug> Connection<T> {
             }
         }
     }
+}
 
-    pub(crate) fn check_status_ok(
-        &self,
-        status: &imap_proto::Status,
-        code: Option<&imap_proto::ResponseCode<'_>>,
-        information: Option<&str>,
-    ) -> Result<()> {
-        use imap_proto::Status;
-        match status {
-            Status::Ok => Ok(()),
-            Status::Bad => Err(Error::Bad(format!(
-                "code: {:?}, info: {:?}",
-                code, information
-            ))),
-            Status::No => Err(Error::No(format!(
-                "code: {:?}, info: {:?}",
-                code, information
-            ))),
-            _ => Err(Error::Io(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "status: {:?}, code: {:?}, information: {:?}",
-                    status, code, information
-                ),
-            ))),
-        }
+/// Where [`Session::fetch_body_stream`] is in the response to its `FETCH` command.
+enum BodyState {
+    /// Before a `FETCH` response or the tagged completion.
+    Start,
+    /// Within a literal, with the given number of bytes left.
+    Literal(usize),
+    /// The command completed, or failed.
+    Finished,
+}
+
+/// The error for a `STATUS (UNSEEN)` command that completed without reporting the count.
+fn missing_unseen() -> Error {
+    Error::Parse(ParseError::Unexpected(
+        "missing UNSEEN in STATUS response".to_string(),
+    ))
+}
+
+/// Whether `err` means that the connection was closed.
+fn is_disconnected(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// The [`Uid`] and flags of `fetch`, if it has a [`Uid`].
+fn uid_and_flags(fetch: &Fetch) -> Option<(Uid, Vec<Flag<'static>>)> {
+    let flags = fetch.flags().map(|flag| Flag::from(flag.to_string()));
+    Some((fetch.uid?, flags.collect()))
+}
+
+/// Checks that `value` can be sent as an atom, such as an authorization mechanism.
+fn validate_atom(value: &str) -> Result<&str> {
+    let is_atom_char =
+        |c: char| c.is_ascii() && !c.is_ascii_control() && !" (){%*\"\\]".contains(c);
+    match value.chars().find(|&c| !is_atom_char(c)) {
+        Some(c) => Err(Error::Validate(ValidateError(c))),
+        None if value.is_empty() => Err(Error::EmptyArgument),
+        None => Ok(value),
+    }
+}
+
+/// Checks that `section` can be sent as is in `BODY[<section>]`, e.g. `1.2` or
+/// `HEADER.FIELDS (Subject)`, without ending the section or the command early.
+fn validate_section(section: &str) -> Result<&str> {
+    let is_section_char = |c: char| c.is_ascii() && !c.is_ascii_control() && !"[]{}".contains(c);
+    match section.chars().find(|&c| !is_section_char(c)) {
+        Some(c) => Err(Error::Validate(ValidateError(c))),
+        None => Ok(section),
     }
 }
 
@@ -1450,187 +3995,2925 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn readline_delay_read() {
-        let greeting = "* OK Dovecot ready.\r\n";
-        let mock_stream = MockStream::default()
-            .with_buf(greeting.as_bytes().to_vec())
-            .with_delay();
+    async fn fetch_binary() {
+        let response = "* 2 FETCH (UID 7 BINARY[1] ~{5}\r\nfo\0o! BINARY.SIZE[1] 5)\r\n\
+                        A0001 OK FETCH completed\r\n";
+        let mut session = mock_session!(MockStream::new(response.as_bytes().to_vec()));
+        let fetches = session
+            .fetch("2", "(UID BINARY.PEEK[1] BINARY.SIZE[1])")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(fetches.len(), 1);
+        let fetch = fetches[0].as_ref().unwrap();
+        assert_eq!(fetch.message, 2);
+        assert_eq!(fetch.uid, Some(Uid(7)));
+        assert_eq!(fetch.binary(&[1]), Some(&b"fo\0o!"[..]));
+        assert_eq!(fetch.binary_size(&[1]), Some(5));
+        assert_eq!(fetch.binary(&[2]), None);
+        assert_eq!(fetch.binary_size(&[]), None);
+    }
 
-        let mut client = mock_client!(mock_stream);
-        let actual_response = client.read_response().await.unwrap().unwrap();
+    #[async_std::test]
+    async fn fetch_gmail_attributes() {
+        let response = "* 2 FETCH (X-GM-THRID 1278455344230334865 X-GM-MSGID 1278455344230334866 \
+                        X-GM-LABELS (\\Inbox \\Sent Important \"Muy Importante\" &AOQ-))\r\n\
+                        A0001 OK FETCH completed\r\n";
+        let mut session = mock_session!(MockStream::new(response.as_bytes().to_vec()));
+        let fetches = session
+            .fetch("2", "(X-GM-MSGID X-GM-THRID X-GM-LABELS)")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(fetches.len(), 1);
+        let fetch = fetches[0].as_ref().unwrap();
+        assert_eq!(fetch.gmail_msgid(), Some(1278455344230334866));
+        assert_eq!(fetch.gmail_thrid(), Some(1278455344230334865));
         assert_eq!(
-            actual_response.parsed(),
-            &Response::Data {
-                status: Status::Ok,
-                code: None,
-                information: Some(Cow::Borrowed("Dovecot ready.")),
-            }
+            fetch.gmail_labels(),
+            Some(
+                &[
+                    "\\Inbox".to_string(),
+                    "\\Sent".to_string(),
+                    "Important".to_string(),
+                    "Muy Importante".to_string(),
+                    "&AOQ-".to_string(),
+                ][..]
+            )
         );
     }
 
     #[async_std::test]
-    async fn readline_eof() {
-        let mock_stream = MockStream::default().with_eof();
-        let mut client = mock_client!(mock_stream);
-        let res = client.read_response().await;
-        assert!(res.is_none());
+    async fn store_gmail_labels() {
+        let response = "* 2 FETCH (X-GM-LABELS (Work \"Muy Importante\"))\r\n\
+                        A0001 OK STORE completed\r\n";
+        let mut session = mock_session!(MockStream::new(response.as_bytes().to_vec()));
+        let fetches = session
+            .store("2", "+X-GM-LABELS (\"Muy Importante\")")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(
+            session.stream.inner.written_buf,
+            b"A0001 STORE 2 +X-GM-LABELS (\"Muy Importante\")\r\n".to_vec()
+        );
+        let fetch = fetches[0].as_ref().unwrap();
+        assert_eq!(
+            fetch.gmail_labels(),
+            Some(&["Work".to_string(), "Muy Importante".to_string()][..])
+        );
+        assert_eq!(fetch.gmail_msgid(), None);
     }
 
     #[async_std::test]
-    #[should_panic]
-    async fn readline_err() {
+    async fn fetch_body_stream_dropped() {
+        let body = "0123456789".repeat(4_400);
+        let response = format!(
+            "* 3 FETCH (BODY[] {{{}}}\r\n{} FLAGS (\\Seen))\r\n\
+             * 4 EXISTS\r\n\
+             A0001 OK FETCH completed\r\n\
+             A0002 OK NOOP completed\r\n\
+             A0003 OK NOOP completed\r\n",
+            body.len(),
+            body
+        );
+        let mut session = mock_session!(MockStream::new(response.into_bytes()));
+        {
+            let mut chunks = session.fetch_body_stream(3, "").await.unwrap();
+            let chunk = chunks.next().await.unwrap().unwrap();
+            assert!(chunk.len() < body.len());
+        }
+        session.noop().await.unwrap();
+        session.noop().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 3 BODY.PEEK[]\r\nA0002 NOOP\r\nA0003 NOOP\r\n",
+            "Invalid commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_body_stream() {
+        let body = "0123456789".repeat(10_000);
+        let response = format!(
+            "* 4 EXISTS\r\n\
+             * 3 FETCH (UID 7 BODY[2] {{{}}}\r\n{})\r\n\
+             A0001 OK FETCH completed\r\n\
+             * 3 FETCH (BODY[] \"short\")\r\n\
+             A0002 OK FETCH completed\r\n\
+             A0003 BAD Invalid section\r\n",
+            body.len(),
+            body
+        );
+        let mut session = mock_session!(MockStream::new(response.into_bytes()));
+
+        let chunks: Vec<_> = session
+            .fetch_body_stream(3, "2")
+            .await
+            .unwrap()
+            .try_collect::<_>()
+            .await
+            .unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 8192));
+        assert_eq!(chunks.concat(), body.as_bytes());
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+
+        let chunks: Vec<_> = session
+            .fetch_body_stream(3, "")
+            .await
+            .unwrap()
+            .try_collect::<_>()
+            .await
+            .unwrap();
+        assert_eq!(chunks, vec![b"short".to_vec()]);
+
+        let result: Vec<_> = session
+            .fetch_body_stream(3, "9")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(matches!(result.as_slice(), [Err(Error::Bad { .. })]));
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 3 BODY.PEEK[2]\r\n\
+              A0002 FETCH 3 BODY.PEEK[]\r\n\
+              A0003 FETCH 3 BODY.PEEK[9]\r\n",
+            "Invalid fetch commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_body_stream_other_responses() {
+        let response = b"* 5 FETCH (BODY[2] {5}\r\nfifth)\r\n\
+            * 3 FETCH (BODY[1] {5}\r\nfirst)\r\n\
+            * 5 FETCH (BODY[2] \"short\")\r\n\
+            * 3 FETCH (BODY[2] {6}\r\nsecond)\r\n\
+            A0001 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let chunks: Vec<_> = session
+            .fetch_body_stream(3, "2")
+            .await
+            .unwrap()
+            .try_collect::<_>()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"second");
+        assert!(matches!(
+            session.unsolicited_responses.try_recv(),
+            Ok(UnsolicitedResponse::Other(_))
+        ));
+
+        let err = session
+            .fetch_body_stream(3, "1]<0.10> BODY[")
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, Error::Validate(ValidateError(']'))),
+            "{:?}",
+            err
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 3 BODY.PEEK[2]\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_partial() {
+        let response = "* 2 FETCH (BODY[1]<8192> {5}\r\nhello)\r\n\
+                        A0001 OK FETCH completed\r\n";
+        let mut session = mock_session!(MockStream::new(response.as_bytes().to_vec()));
+        let fetches = session
+            .fetch_partial("2", "1", 8192, 4096)
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 2 BODY.PEEK[1]<8192.4096>\r\n",
+            "Invalid fetch command"
+        );
+        let fetch = fetches[0].as_ref().unwrap();
+        assert_eq!(fetch.partial(), Some((8192, &b"hello"[..])));
+        assert_eq!(fetch.partial_offset(), Some(8192));
+    }
+
+    #[async_std::test]
+    async fn fetch_binary_unknown_cte() {
+        let response = "A0001 NO [UNKNOWN-CTE] Can't decode part 1\r\n";
+        let mut session = mock_session!(MockStream::new(response.as_bytes().to_vec()));
+        let fetches = session
+            .fetch("2", "BINARY[1]")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(fetches.len(), 1);
+        match &fetches[0] {
+            Err(Error::UnknownCte(text)) => assert_eq!(text, "Can't decode part 1"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[async_std::test]
+    async fn append_binary() {
+        let response = b"+ OK\r\nA0001 OK APPEND completed\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.append_binary("INBOX", b"fo\0o").await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 APPEND \"INBOX\" ~{4}\r\nfo\0o\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn append_binary_unknown_cte() {
+        let response = b"A0001 NO [UNKNOWN-CTE] Unknown encoding\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session.append_binary("INBOX", b"foo").await.unwrap_err();
+        assert!(matches!(err, Error::UnknownCte(_)), "{:?}", err);
+    }
+
+    #[async_std::test]
+    async fn append_literal_plus() {
+        let response = b"* CAPABILITY IMAP4rev1 LITERAL+\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        session.append("INBOX", b"hello").await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\nA0002 APPEND \"INBOX\" {5+}\r\nhello\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn append_literal_minus() {
+        let large = vec![b'a'; 4097];
+        let response = b"* CAPABILITY IMAP4rev1 LITERAL-\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK APPEND completed\r\n\
+                         + go ahead\r\n\
+                         A0003 OK APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        session.append("INBOX", b"hello").await.unwrap();
+        session.append("INBOX", &large).await.unwrap();
+
+        let mut expected =
+            b"A0001 CAPABILITY\r\nA0002 APPEND \"INBOX\" {5+}\r\nhello\r\nA0003 APPEND \"INBOX\" {4097}\r\n"
+                .to_vec();
+        expected.extend_from_slice(&large);
+        expected.extend_from_slice(b"\r\n");
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            &expected,
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn append_catenate() {
+        let response = b"* CAPABILITY IMAP4rev1 CATENATE UIDPLUS\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         + go ahead\r\n\
+                         + go ahead\r\n\
+                         A0002 OK [APPENDUID 38505 3957] CATENATE completed\r\n\
+                         A0003 OK [APPENDUID 38505 3958] CATENATE completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let url = "/INBOX;UIDVALIDITY=38505/;UID=3955/;SECTION=TEXT";
+        let parts = [
+            CatenatePart::Text(b"Subject: Fwd\r\n\r\n".to_vec()),
+            CatenatePart::Url(url.into()),
+            CatenatePart::Text(b"--\r\n".to_vec()),
+        ];
+        let append_uid = session
+            .append_catenate("Drafts", &[Flag::Draft], &parts)
+            .await
+            .unwrap();
+        assert_eq!(
+            append_uid,
+            Some(AppendUid {
+                uid_validity: 38505,
+                uids: vec![Uid(3957)],
+            })
+        );
+        let parts = [CatenatePart::Url(url.into())];
+        session
+            .append_catenate("Drafts", &[], &parts)
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 APPEND \"Drafts\" (\\Draft) CATENATE (TEXT {16}\r\n\
+              Subject: Fwd\r\n\r\n \
+              URL \"/INBOX;UIDVALIDITY=38505/;UID=3955/;SECTION=TEXT\" TEXT {4}\r\n\
+              --\r\n)\r\n\
+              A0003 APPEND \"Drafts\" CATENATE (URL \"/INBOX;UIDVALIDITY=38505/;UID=3955/;SECTION=TEXT\")\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn append_catenate_requires_catenate() {
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+                         A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let parts = [CatenatePart::Text(b"hello".to_vec())];
+        let err = session
+            .append_catenate("Drafts", &[], &parts)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+        assert_eq!(session.stream.inner.written_buf, b"A0001 CAPABILITY\r\n");
+    }
+
+    #[async_std::test]
+    async fn multi_append() {
+        let response = b"* CAPABILITY IMAP4rev1 MULTIAPPEND UIDPLUS\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         + go ahead\r\n\
+                         + go ahead\r\n\
+                         A0002 OK [APPENDUID 38505 3955:3956] APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        let messages = [
+            AppendMessage {
+                flags: vec![Flag::Seen],
+                ..AppendMessage::new(b"first")
+            },
+            AppendMessage::new(b"second"),
+        ];
+        let append_uid = session.multi_append("INBOX", &messages).await.unwrap();
+        assert_eq!(
+            append_uid,
+            Some(AppendUid {
+                uid_validity: 38505,
+                uids: vec![Uid(3955), Uid(3956)],
+            })
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 APPEND \"INBOX\" (\\Seen) {5}\r\nfirst {6}\r\nsecond\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn multi_append_fallback() {
+        let response = b"+ go ahead\r\n\
+                         A0001 OK [APPENDUID 38505 3955] APPEND completed\r\n\
+                         + go ahead\r\n\
+                         A0002 OK [APPENDUID 38505 3956] APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let messages = [AppendMessage::new(b"first"), AppendMessage::new(b"second")];
+        let append_uid = session.multi_append("INBOX", &messages).await.unwrap();
+        assert_eq!(
+            append_uid,
+            Some(AppendUid {
+                uid_validity: 38505,
+                uids: vec![Uid(3955), Uid(3956)],
+            })
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 APPEND \"INBOX\" {5}\r\nfirst\r\n\
+              A0002 APPEND \"INBOX\" {6}\r\nsecond\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn append_untagged_before_continuation() {
+        let response = b"* CAPABILITY IMAP4rev1 MULTIAPPEND\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * 3 EXISTS\r\n\
+                         + go ahead\r\n\
+                         * 4 EXISTS\r\n\
+                         + go ahead\r\n\
+                         A0002 OK APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        let messages = [AppendMessage::new(b"first"), AppendMessage::new(b"second")];
+        session.multi_append("INBOX", &messages).await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(3)
+        );
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 APPEND \"INBOX\" {5}\r\nfirst {6}\r\nsecond\r\n",
+            "Invalid append command"
+        );
+    }
+
+    #[async_std::test]
+    async fn multi_append_invalid_flag() {
+        let mut session = mock_session!(MockStream::new(Vec::new()));
+        let messages = [
+            AppendMessage::new(b"first"),
+            AppendMessage {
+                flags: vec![Flag::from("\\Seen)")],
+                ..AppendMessage::new(b"second")
+            },
+        ];
+        let err = session.multi_append("INBOX", &messages).await.unwrap_err();
+        assert!(matches!(err, Error::InvalidFlag(_)), "{:?}", err);
+        assert!(session.stream.inner.written_buf.is_empty());
+    }
+
+    #[async_std::test]
+    async fn multi_append_fallback_incomplete() {
+        let response = b"+ go ahead\r\n\
+                         A0001 OK APPEND completed\r\n\
+                         + go ahead\r\n\
+                         A0002 OK [APPENDUID 38505 3956] APPEND completed\r\n\
+                         + go ahead\r\n\
+                         A0003 OK [APPENDUID 38505 3957] APPEND completed\r\n\
+                         + go ahead\r\n\
+                         A0004 OK [APPENDUID 38506 1] APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let messages = [AppendMessage::new(b"first"), AppendMessage::new(b"second")];
+        // The first message got no `Uid`.
+        let append_uid = session.multi_append("INBOX", &messages).await.unwrap();
+        assert_eq!(append_uid, None);
+        // The mailbox was recreated in between.
+        let append_uid = session.multi_append("INBOX", &messages).await.unwrap();
+        assert_eq!(append_uid, None);
+    }
+
+    #[async_std::test]
+    async fn get_metadata() {
+        let response = b"* CAPABILITY IMAP4rev1 METADATA\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * METADATA \"INBOX\" (/shared/comment \"My comment\" /private/comment NIL)\r\n\
+                         A0002 OK GETMETADATA complete\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let options = MetadataOptions {
+            depth: MetadataDepth::Infinity,
+            max_size: Some(1024),
+        };
+        let metadata = session
+            .get_metadata_with_options("INBOX", &["/shared/comment", "/private/comment"], &options)
+            .await
+            .unwrap();
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata["/shared/comment"], Some("My comment".to_string()));
+        assert_eq!(metadata["/private/comment"], None);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 GETMETADATA (MAXSIZE 1024 DEPTH infinity) \"INBOX\" \
+              (\"/shared/comment\" \"/private/comment\")\r\n",
+            "Invalid getmetadata command"
+        );
+    }
+
+    #[async_std::test]
+    async fn set_metadata() {
+        let response = b"* CAPABILITY IMAP4rev1 METADATA\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK SETMETADATA complete\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session
+            .set_metadata(
+                "INBOX",
+                &[
+                    ("/shared/comment", Some("hello")),
+                    ("/private/comment", None),
+                ],
+            )
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 SETMETADATA \"INBOX\" (\"/shared/comment\" \"hello\" \"/private/comment\" NIL)\r\n",
+            "Invalid setmetadata command"
+        );
+    }
+
+    #[async_std::test]
+    async fn metadata_requires_capability() {
+        let response = b"* CAPABILITY IMAP4rev1 METADATA-SERVER\r\n\
+                         A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session
+            .get_metadata("INBOX", &["/shared/comment"])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+    }
+
+    #[async_std::test]
+    async fn get_quota_root() {
+        let response = b"* CAPABILITY IMAP4rev1 QUOTA\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * QUOTAROOT INBOX \"\"\r\n\
+                         * QUOTA \"\" (STORAGE 10 512)\r\n\
+                         A0002 OK Getquotaroot completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let (quota_root, quotas) = session.get_quota_root("INBOX").await.unwrap();
+        assert_eq!(
+            quota_root,
+            QuotaRoot {
+                mailbox_name: "INBOX".to_string(),
+                quota_root_names: vec!["".to_string()],
+            }
+        );
+        assert_eq!(
+            quotas,
+            vec![Quota {
+                root_name: "".to_string(),
+                resources: vec![QuotaResource {
+                    name: QuotaResourceName::Storage,
+                    usage: 10,
+                    limit: 512,
+                }],
+            }]
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\nA0002 GETQUOTAROOT \"INBOX\"\r\n",
+            "Invalid getquotaroot command"
+        );
+    }
+
+    #[async_std::test]
+    async fn get_quota() {
+        let response = b"* CAPABILITY IMAP4rev1 QUOTA\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * QUOTA \"\" (STORAGE 10 512 MESSAGE 3 100)\r\n\
+                         A0002 OK Getquota completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let resources = session.get_quota("").await.unwrap();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[1].name, QuotaResourceName::Message);
+        assert_eq!(resources[1].usage, 3);
+        assert_eq!(resources[1].limit, 100);
+    }
+
+    #[async_std::test]
+    async fn set_quota() {
+        let response = b"* CAPABILITY IMAP4rev1 QUOTA\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * QUOTA \"\" (STORAGE 10 1024)\r\n\
+                         A0002 OK Setquota completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let resources = session
+            .set_quota("", &[(QuotaResourceName::Storage, 1024)])
+            .await
+            .unwrap();
+        assert_eq!(resources[0].limit, 1024);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\nA0002 SETQUOTA \"\" (STORAGE 1024)\r\n",
+            "Invalid setquota command"
+        );
+    }
+
+    #[async_std::test]
+    async fn get_acl() {
+        let response = b"* CAPABILITY IMAP4rev1 ACL\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * ACL INBOX Fred rwipsldexta anyone lr\r\n\
+                         A0002 OK Getacl complete\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let acl = session.get_acl("INBOX").await.unwrap();
+        assert_eq!(acl.mailbox, "INBOX");
+        assert_eq!(acl.entries.len(), 2);
+        assert_eq!(acl.entries[0].identifier, "Fred");
+        assert_eq!(acl.entries[0].rights.to_string(), "adeilprstwx");
+        assert_eq!(acl.entries[1].rights, AclRights::from("rl"));
+        assert!(acl.entries[1].rights.contains('l'));
+        assert!(!acl.entries[1].rights.contains('w'));
+    }
+
+    #[async_std::test]
+    async fn set_acl() {
+        let response = b"* CAPABILITY IMAP4rev1 ACL\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK Setacl complete\r\n\
+                         A0003 OK Deleteacl complete\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.set_acl("INBOX", "Fred", "+lr").await.unwrap();
+        session.delete_acl("INBOX", "Fred").await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 SETACL \"INBOX\" \"Fred\" \"+lr\"\r\n\
+              A0003 DELETEACL \"INBOX\" \"Fred\"\r\n",
+            "Invalid acl commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn list_rights_and_my_rights() {
+        let response = b"* CAPABILITY IMAP4rev1 ACL\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * LISTRIGHTS ~/Mail/saved smith la r swicdkxte\r\n\
+                         A0002 OK Listrights completed\r\n\
+                         * MYRIGHTS INBOX rwiptsldaex\r\n\
+                         A0003 OK Myrights complete\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let list_rights = session.list_rights("~/Mail/saved", "smith").await.unwrap();
+        assert_eq!(list_rights.identifier, "smith");
+        assert_eq!(list_rights.required, AclRights::from("la"));
+        assert!(list_rights.optional.contains('r'));
+        assert!(list_rights.optional.contains('k'));
+        let my_rights = session.my_rights("INBOX").await.unwrap();
+        assert_eq!(my_rights.mailbox, "INBOX");
+        assert_eq!(my_rights.rights, AclRights::from("rwiptsldaex"));
+    }
+
+    #[async_std::test]
+    async fn urlauth() {
+        let response = b"* CAPABILITY IMAP4rev1 URLAUTH\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * GENURLAUTH \"imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a47\"\r\n\
+                         A0002 OK GENURLAUTH completed\r\n\
+                         * URLFETCH \"imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a47\" {5}\r\n\
+                         hello\r\n\
+                         * URLFETCH /INBOX/;uid=21 NIL\r\n\
+                         A0003 OK URLFETCH completed\r\n\
+                         A0004 OK RESETKEY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let url = session
+            .genurlauth(
+                "imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred",
+                "INTERNAL",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            url,
+            "imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a47"
+        );
+        let fetched = session
+            .urlfetch(&[url.as_str(), "/INBOX/;uid=21"])
+            .await
+            .unwrap();
+        assert_eq!(
+            fetched,
+            vec![
+                UrlFetch {
+                    url: url.clone(),
+                    data: Some(b"hello".to_vec()),
+                },
+                UrlFetch {
+                    url: "/INBOX/;uid=21".into(),
+                    data: None,
+                },
+            ]
+        );
+        session
+            .resetkey(Some("INBOX"), &["INTERNAL"])
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 GENURLAUTH \"imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred\" INTERNAL\r\n\
+              A0003 URLFETCH \"imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a47\" \"/INBOX/;uid=21\"\r\n\
+              A0004 RESETKEY \"INBOX\" INTERNAL\r\n",
+            "Invalid URLAUTH commands"
+        );
+
+        let err = session.genurlauth("/INBOX/;uid=20", "IN TERNAL").await;
+        assert!(matches!(err, Err(Error::Validate(ValidateError(' ')))));
+        let err = session.genurlauth("/INBOX/;uid=20", "").await;
+        assert!(matches!(err, Err(Error::EmptyArgument)));
+    }
+
+    #[async_std::test]
+    async fn urlauth_requires_urlauth() {
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+                         A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session.resetkey(None, &[]).await.unwrap_err();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+        assert_eq!(session.stream.inner.written_buf, b"A0001 CAPABILITY\r\n");
+    }
+
+    #[async_std::test]
+    async fn create_special_use() {
+        let response = b"* CAPABILITY IMAP4rev1 SPECIAL-USE CREATE-SPECIAL-USE\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK CREATE completed\r\n\
+                         A0003 NO [USEATTR] An \\Archive mailbox already exists\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session
+            .create_special_use("Sent", &[NameAttribute::from("\\Sent")])
+            .await
+            .unwrap();
+        let err = session
+            .create_special_use("Old", &[NameAttribute::from("\\Archive")])
+            .await
+            .unwrap_err();
+        match err {
+            Error::UseAttr(text) => assert_eq!(text, "An \\Archive mailbox already exists"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 CREATE \"Sent\" (USE (\\Sent))\r\n\
+              A0003 CREATE \"Old\" (USE (\\Archive))\r\n",
+            "Invalid create command"
+        );
+
+        let err = session
+            .create_special_use("Junk", &[NameAttribute::from("\\Junk)")])
+            .await;
+        assert!(matches!(err, Err(Error::Validate(ValidateError(')')))));
+    }
+
+    #[async_std::test]
+    async fn acl_noperm() {
+        let response = b"* CAPABILITY IMAP4rev1 ACL\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 NO [NOPERM] Permission denied\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session.get_acl("INBOX").await.unwrap_err();
+        match err {
+            Error::NoPerm(text) => assert_eq!(text, "Permission denied"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[async_std::test]
+    async fn from_stream() {
+        let greeting = b"* OK [CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED] Dovecot ready.\r\n";
+        let mut client = Client::from_stream(MockStream::new(greeting.to_vec()))
+            .await
+            .unwrap();
+        let capabilities = client.capabilities().await.unwrap();
+        assert!(capabilities.has_str("STARTTLS"));
+        assert!(client.stream.inner.written_buf.is_empty());
+
+        let greeting = client.greeting().unwrap();
+        assert_eq!(greeting.status(), &Status::Ok);
+        assert!(!greeting.is_preauth());
+        assert!(matches!(
+            greeting.code(),
+            Some(imap_proto::ResponseCode::Capabilities(_))
+        ));
+        assert_eq!(greeting.text(), "Dovecot ready.");
+
+        let greeting = b"* PREAUTH IMAP4rev1 server logged in as Smith\r\n";
+        let client = Client::from_stream(MockStream::new(greeting.to_vec()))
+            .await
+            .unwrap();
+        let greeting = client.greeting().unwrap();
+        assert!(greeting.is_preauth());
+        assert!(greeting.code().is_none());
+        assert_eq!(greeting.text(), "IMAP4rev1 server logged in as Smith");
+
+        let greeting = b"* BYE Too many connections\r\n";
+        match Client::from_stream(MockStream::new(greeting.to_vec())).await {
+            Err(Error::Bye(reason)) => assert_eq!(reason, "Too many connections"),
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let stream = MockStream::new(Vec::new()).with_eof();
+        assert!(Client::from_stream(stream).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn preauth_session() {
+        let greeting = b"* PREAUTH [CAPABILITY IMAP4rev1 IDLE] logged in as Smith\r\n\
+            * 2 EXISTS\r\n\
+            A0001 OK [READ-WRITE] SELECT completed\r\n";
+        let client = Client::from_stream(MockStream::new(greeting.to_vec()))
+            .await
+            .unwrap();
+        let mut session = client.into_preauth_session().unwrap();
+        assert!(session.has_cached_capability("IDLE"));
+        let mailbox = session.select("INBOX").await.unwrap();
+        assert_eq!(mailbox.exists, 2);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SELECT \"INBOX\"\r\n",
+            "the preauthenticated session must not log in"
+        );
+
+        let greeting = b"* OK Dovecot ready.\r\n";
+        let client = Client::from_stream(MockStream::new(greeting.to_vec()))
+            .await
+            .unwrap();
+        assert!(client.into_preauth_session().is_err());
+        let client = Client::new(MockStream::new(Vec::new()));
+        assert!(client.into_preauth_session().is_err());
+    }
+
+    #[async_std::test]
+    async fn readline_delay_read() {
+        let greeting = "* OK Dovecot ready.\r\n";
+        let mock_stream = MockStream::default()
+            .with_buf(greeting.as_bytes().to_vec())
+            .with_delay();
+
+        let mut client = mock_client!(mock_stream);
+        let actual_response = client.read_response().await.unwrap().unwrap();
+        assert_eq!(
+            actual_response.parsed(),
+            &Response::Data {
+                status: Status::Ok,
+                code: None,
+                information: Some(Cow::Borrowed("Dovecot ready.")),
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn readline_eof() {
+        let mock_stream = MockStream::default().with_eof();
+        let mut client = mock_client!(mock_stream);
+        let res = client.read_response().await;
+        assert!(res.is_none());
+    }
+
+    #[async_std::test]
+    async fn fuzzy_search() {
+        let response = b"* CAPABILITY IMAP4rev1 ESEARCH SEARCH=FUZZY\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * ESEARCH (TAG \"A0002\") ALL 1:3,5 RELEVANCY (4 99 42 80)\r\n\
+                         A0002 OK SEARCH completed\r\n\
+                         * ESEARCH (TAG \"A0003\") UID\r\n\
+                         A0003 OK SEARCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let search = session.fuzzy_search("SUBJECT \"helo\"").await.unwrap();
+        assert_eq!(search.ids, vec![1, 2, 3, 5]);
+        assert_eq!(search.relevancy, Some(vec![4, 99, 42, 80]));
+        assert_eq!(search.ranked()[0], (2, Some(99)));
+
+        let search = session.uid_fuzzy_search("TEXT \"x\"").await.unwrap();
+        assert!(search.ids.is_empty());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 SEARCH RETURN (ALL RELEVANCY) FUZZY (SUBJECT \"helo\")\r\n\
+              A0003 UID SEARCH RETURN (ALL RELEVANCY) FUZZY (TEXT \"x\")\r\n",
+            "Invalid fuzzy search command"
+        );
+    }
+
+    #[async_std::test]
+    async fn thread() {
+        let response = b"* CAPABILITY IMAP4rev1 THREAD=REFERENCES\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * THREAD (2)(3 6 (4 23)(44 7 96))\r\n\
+                         A0002 OK THREAD completed\r\n\
+                         * THREAD\r\n\
+                         A0003 OK THREAD completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let threads = session.thread("REFERENCES", "ALL").await.unwrap();
+        assert_eq!(threads.len(), 2);
+        assert_eq!(threads[0].flatten_depth_first(), vec![Seq(2)]);
+        assert_eq!(
+            threads[1].flatten_depth_first(),
+            [3, 6, 4, 23, 44, 7, 96]
+                .iter()
+                .map(|&id| Seq(id))
+                .collect::<Vec<_>>()
+        );
+        let threads = session.uid_thread("REFERENCES", "UNSEEN").await.unwrap();
+        assert!(threads.is_empty());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 THREAD REFERENCES UTF-8 ALL\r\n\
+              A0003 UID THREAD REFERENCES UTF-8 UNSEEN\r\n",
+            "Invalid thread command"
+        );
+
+        let err = session.thread("ORDEREDSUBJECT", "ALL").await.unwrap_err();
+        assert!(matches!(err, Error::MissingCapability(_)));
+    }
+
+    #[async_std::test]
+    async fn within_criterion() {
+        let response = b"* CAPABILITY IMAP4rev1 WITHIN\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * SEARCH 4 7\r\n\
+                         A0002 OK SEARCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let younger = session
+            .within_criterion(Within::Younger(86400), false)
+            .await
+            .unwrap();
+        assert_eq!(younger, "YOUNGER 86400");
+        let ids = session.search(format!("UNSEEN {}", younger)).await.unwrap();
+        assert_eq!(ids, [Seq(4), Seq(7)].iter().cloned().collect());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\nA0002 SEARCH UNSEEN YOUNGER 86400\r\n",
+            "Invalid search command"
+        );
+
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+                         A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session
+            .within_criterion(Within::Older(60), false)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+        let older = session
+            .within_criterion(Within::Older(60), true)
+            .await
+            .unwrap();
+        assert!(older.starts_with("BEFORE "), "{}", older);
+    }
+
+    #[async_std::test]
+    async fn store_unchanged_since() {
+        let response = b"* CAPABILITY IMAP4rev1 CONDSTORE\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * 5 FETCH (MODSEQ (320162350) FLAGS (\\Seen))\r\n\
+                         * OK [MODIFIED 7,9] Conditional STORE failed\r\n\
+                         A0002 OK Conditional STORE completed\r\n\
+                         A0003 OK [MODIFIED 12] Conditional STORE completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let result = session
+            .store_unchanged_since("5,7,9", 320162338, "+FLAGS.SILENT (\\Seen)")
+            .await
+            .unwrap();
+        assert_eq!(result.fetches.len(), 1);
+        assert_eq!(result.fetches[0].message, 5);
+        assert_eq!(result.modified, vec![7, 9]);
+
+        let result = session
+            .uid_store_unchanged_since("12", 7, "FLAGS ()")
+            .await
+            .unwrap();
+        assert!(result.fetches.is_empty());
+        assert_eq!(result.modified, vec![12]);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 STORE 5,7,9 (UNCHANGEDSINCE 320162338) +FLAGS.SILENT (\\Seen)\r\n\
+              A0003 UID STORE 12 (UNCHANGEDSINCE 7) FLAGS ()\r\n",
+            "Invalid conditional store command"
+        );
+    }
+
+    #[async_std::test]
+    async fn store_unchanged_since_modified_too_large() {
+        let response = b"* CAPABILITY IMAP4rev1 CONDSTORE\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * OK [MODIFIED 1:4294967295] Conditional STORE failed\r\n\
+                         A0002 OK Conditional STORE completed\r\n\
+                         A0003 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let result = session
+            .store_unchanged_since("1:*", 320162338, "+FLAGS.SILENT (\\Seen)")
+            .await;
+        assert!(matches!(result, Err(Error::Parse(_))), "{:?}", result);
+        session.noop().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn store_unchanged_since_requires_condstore() {
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+                         A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session
+            .store_unchanged_since("1", 1, "+FLAGS (\\Seen)")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+    }
+
+    #[async_std::test]
+    async fn connection_lost_within_response() {
+        let response = b"* 1 FETCH (UID 1 FLAGS (\\Se".to_vec();
+        let mut session = mock_session!(MockStream::new(response).with_eof());
+        let fetches = session
+            .fetch("1", "(UID FLAGS)")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(fetches[..], [Err(Error::ConnectionLost)]));
+    }
+
+    #[async_std::test]
+    async fn connection_lost_before_completion() {
+        let response = b"* 1 FETCH (UID 1)\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response).with_eof());
+        let fetches = session
+            .fetch("1", "UID")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(fetches.len(), 2);
+        assert_eq!(fetches[0].as_ref().unwrap().uid, Some(Uid(1)));
+        assert!(matches!(fetches[1], Err(Error::ConnectionLost)));
+
+        let response = b"* SEARCH 1 2\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response).with_eof());
+        let err = session.search("ALL").await.unwrap_err();
+        assert!(matches!(err, Error::ConnectionLost));
+        assert!(err.is_connection_lost());
+    }
+
+    #[async_std::test]
+    #[should_panic]
+    async fn readline_err() {
         // TODO Check the error test
         let mock_stream = MockStream::default().with_err();
         let mut client = mock_client!(mock_stream);
-        client.read_response().await.unwrap().unwrap();
+        client.read_response().await.unwrap().unwrap();
+    }
+
+    #[async_std::test]
+    async fn authenticate() {
+        let response = b"+ YmFy\r\n\
+                         A0001 OK Logged in\r\n"
+            .to_vec();
+        let command = "A0001 AUTHENTICATE PLAIN\r\n\
+                       Zm9v\r\n";
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        enum Authenticate {
+            Auth,
+        }
+        impl Authenticator for &Authenticate {
+            type Response = Vec<u8>;
+            fn process(&mut self, challenge: &[u8]) -> Self::Response {
+                assert!(challenge == b"bar", "Invalid authenticate challenge");
+                b"foo".to_vec()
+            }
+        }
+        let session = client
+            .authenticate("PLAIN", &Authenticate::Auth)
+            .await
+            .ok()
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn timeout() {
+        let response = b"* 1 EXISTS\r\n".to_vec();
+        let mock_stream = MockStream::new(response).with_pending();
+        let mut session = mock_session!(mock_stream);
+        session.set_timeout(Some(Duration::from_millis(10)));
+        let err = session.noop().await.unwrap_err();
+        assert!(matches!(err, Error::Timeout), "unexpected error: {:?}", err);
+    }
+
+    #[async_std::test]
+    async fn pipeline() {
+        let response = b"* STATUS INBOX (MESSAGES 3)\r\n\
+                         A0002 NO [NONEXISTENT] Mailbox doesn't exist\r\n\
+                         * STATUS Sent (MESSAGES 1)\r\n\
+                         A0001 OK Status completed\r\n\
+                         A0003 OK Status completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        let responses = session
+            .pipeline(&[
+                "STATUS INBOX (MESSAGES)",
+                "STATUS Missing (MESSAGES)",
+                "STATUS Sent (MESSAGES)",
+            ])
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 STATUS INBOX (MESSAGES)\r\n\
+              A0002 STATUS Missing (MESSAGES)\r\n\
+              A0003 STATUS Sent (MESSAGES)\r\n",
+            "Invalid pipelined commands"
+        );
+        assert_eq!(responses.untagged.len(), 2);
+        assert!(responses.results[0].is_ok());
+        assert!(matches!(responses.results[1], Err(Error::No { .. })));
+        assert!(responses.results[2].is_ok());
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn keepalive() {
+        let response = b"A0001 OK NOOP completed\r\n\
+                         * 3 EXISTS\r\n\
+                         A0002 OK NOOP completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response).with_pending();
+        let mut session = mock_session!(mock_stream);
+        session.set_keepalive(Some(Duration::from_millis(1)));
+        let res = crate::runtime::timeout(Duration::from_millis(100), session.keepalive()).await;
+        assert!(res.is_none(), "keepalive completed: {:?}", res);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 NOOP\r\nA0002 NOOP\r\nA0003 NOOP\r\n",
+            "Invalid keepalive commands"
+        );
+        assert!(matches!(
+            session.unsolicited_responses.try_recv(),
+            Ok(UnsolicitedResponse::Exists(3))
+        ));
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn keepalive_dropped_during_noop() {
+        let mock_stream = MockStream::default()
+            .with_pending()
+            .with_reply(b"A0001 OK NOOP completed\r\n")
+            .with_reply(b"")
+            .with_reply(b"* 4 EXISTS\r\nA0002 OK NOOP completed\r\n")
+            .with_reply(b"A0003 OK NOOP completed\r\n");
+        let mut session = mock_session!(mock_stream);
+        session.set_keepalive(Some(Duration::from_millis(1)));
+        let res = crate::runtime::timeout(Duration::from_millis(100), session.keepalive()).await;
+        assert!(res.is_none(), "keepalive completed: {:?}", res);
+        session.noop().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 NOOP\r\nA0002 NOOP\r\nA0003 NOOP\r\n",
+            "Invalid NOOP commands"
+        );
+        assert!(matches!(
+            session.unsolicited_responses.try_recv(),
+            Ok(UnsolicitedResponse::Exists(4))
+        ));
+    }
+
+    #[async_std::test]
+    async fn starttls() {
+        let response = b"A0001 OK Begin TLS negotiation now\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let client = client
+            .starttls(|stream: MockStream| async move {
+                assert_eq_bytes!(&stream.written_buf, b"A0001 STARTTLS\r\n", "");
+                // Stands in for the stream wrapped in TLS.
+                Ok::<_, Error>(MockStream::default())
+            })
+            .await
+            .unwrap();
+        assert!(client.capabilities.is_none());
+        assert!(client.stream.inner.written_buf.is_empty());
+    }
+
+    #[async_std::test]
+    async fn starttls_rejects_injected_data() {
+        let response = b"A0001 OK Begin TLS negotiation now\r\n\
+                         * CAPABILITY IMAP4rev1 AUTH=PLAIN\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let err = client
+            // Fails differently if the stream is upgraded despite the injected data.
+            .starttls(|_: MockStream| async { Err::<MockStream, _>(Error::ConnectionLost) })
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Io(_)), "unexpected error: {:?}", err);
+    }
+
+    #[async_std::test]
+    async fn authenticate_multi_step_initial_response() {
+        let response = b"* CAPABILITY IMAP4rev1 SASL-IR AUTH=X-TEST\r\n\
+                         A0001 OK Capability completed\r\n\
+                         + b25l\r\n\
+                         + dHdv\r\n\
+                         A0002 OK Logged in\r\n"
+            .to_vec();
+        let command = "A0001 CAPABILITY\r\n\
+                       A0002 AUTHENTICATE X-TEST aGVsbG8=\r\n\
+                       MQ==\r\n\
+                       Mg==\r\n";
+        struct Counting(Vec<Vec<u8>>);
+        impl Authenticator for &mut Counting {
+            type Response = Vec<u8>;
+            fn process(&mut self, challenge: &[u8]) -> Self::Response {
+                self.0.push(challenge.to_vec());
+                match self.0.len() {
+                    1 => b"hello".to_vec(),
+                    n => (n - 1).to_string().into_bytes(),
+                }
+            }
+            fn has_initial_response(&self) -> bool {
+                true
+            }
+        }
+        let mock_stream = MockStream::new(response);
+        let mut client = mock_client!(mock_stream);
+        client.capabilities().await.unwrap();
+        let mut auth = Counting(Vec::new());
+        let session = client.authenticate("X-TEST", &mut auth).await.ok().unwrap();
+        assert_eq!(auth.0, vec![b"".to_vec(), b"one".to_vec(), b"two".to_vec()]);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_xoauth2() {
+        let response = b"A0001 OK Success\r\n".to_vec();
+        let command = format!(
+            "A0001 AUTHENTICATE XOAUTH2 {}\r\n",
+            base64::encode("user=me@example.com\x01auth=Bearer token\x01\x01")
+        );
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let session = client
+            .authenticate_xoauth2("me@example.com", "token")
+            .await
+            .ok()
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_xoauth2_rejected() {
+        let error = base64::encode(r#"{"status":"401","schemes":"Bearer","scope":"mail"}"#);
+        let response = format!(
+            "+ {}\r\n\
+             A0001 NO [AUTHENTICATIONFAILED] Invalid credentials\r\n",
+            error
+        )
+        .into_bytes();
+        let command = format!(
+            "A0001 AUTHENTICATE XOAUTH2 {}\r\n\
+             \r\n",
+            base64::encode("user=me@example.com\x01auth=Bearer token\x01\x01")
+        );
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let (err, client) = client
+            .authenticate_xoauth2("me@example.com", "token")
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, Error::No { .. }),
+            "unexpected error: {:?}",
+            err
+        );
+        assert_eq_bytes!(
+            &client.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_oauthbearer_rejected() {
+        let error = base64::encode(r#"{"status":"invalid_token"}"#);
+        let response = format!(
+            "+ \r\n\
+             + {}\r\n\
+             A0001 NO SASL authentication failed\r\n",
+            error
+        )
+        .into_bytes();
+        let command = format!(
+            "A0001 AUTHENTICATE OAUTHBEARER\r\n\
+             {}\r\n\
+             AQ==\r\n",
+            base64::encode("n,a=me@example.com,\x01host=imap.example.com\x01port=993\x01auth=Bearer token\x01\x01")
+        );
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let auth = crate::OAuthBearer::new("me@example.com", "imap.example.com", 993, "token");
+        let (err, client) = client
+            .authenticate("OAUTHBEARER", auth)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, Error::No { .. }),
+            "unexpected error: {:?}",
+            err
+        );
+        assert_eq_bytes!(
+            &client.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[cfg(feature = "sasl")]
+    #[async_std::test]
+    async fn authenticate_scram() {
+        let response = format!(
+            "+ \r\n\
+             + {}\r\n\
+             + {}\r\n\
+             A0001 OK Logged in\r\n",
+            base64::encode("r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"),
+            base64::encode("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+        )
+        .into_bytes();
+        let command = format!(
+            "A0001 AUTHENTICATE SCRAM-SHA-256\r\n\
+             {}\r\n\
+             {}\r\n\
+             \r\n",
+            base64::encode("n,,n=user,r=rOprNGfwEbeRWgbNEkqO"),
+            base64::encode("c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=")
+        );
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let scram = crate::scram::Scram::with_nonce(
+            crate::ScramMechanism::Sha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO",
+        );
+        let session = client.do_authenticate_scram(scram).await.ok().unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[cfg(feature = "sasl")]
+    #[async_std::test]
+    async fn authenticate_scram_bad_server_signature() {
+        let response = format!(
+            "+ \r\n\
+             + {}\r\n\
+             + {}\r\n\
+             A0001 BAD Authentication cancelled\r\n",
+            base64::encode("r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"),
+            base64::encode("v=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=")
+        )
+        .into_bytes();
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let scram = crate::scram::Scram::with_nonce(
+            crate::ScramMechanism::Sha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO",
+        );
+        let (err, client) = client.do_authenticate_scram(scram).await.err().unwrap();
+        assert!(matches!(err, Error::Sasl(_)), "unexpected error: {:?}", err);
+        assert!(client.stream.inner.written_buf.ends_with(b"\r\n*\r\n"));
+    }
+
+    #[cfg(feature = "sasl")]
+    #[async_std::test]
+    async fn authenticate_cram_md5() {
+        let response = b"+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n\
+                         A0001 OK CRAM authentication successful\r\n"
+            .to_vec();
+        let command = "A0001 AUTHENTICATE CRAM-MD5\r\n\
+                       dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw\r\n";
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let session = client
+            .authenticate("CRAM-MD5", crate::CramMd5::new("tim", "tanstaaftanstaaf"))
+            .await
+            .ok()
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_plain() {
+        let response = b"+ \r\n\
+                         A0001 OK Logged in\r\n"
+            .to_vec();
+        let command = format!(
+            "A0001 AUTHENTICATE PLAIN\r\n\
+             {}\r\n",
+            base64::encode("\0tim\0tanstaaf")
+        );
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let session = client
+            .authenticate_plain(None, "tim", "tanstaaf")
+            .await
+            .ok()
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_plain_sasl_ir() {
+        let response = b"* CAPABILITY IMAP4rev1 SASL-IR AUTH=PLAIN\r\n\
+                         A0001 OK Capability completed\r\n\
+                         A0002 OK Logged in\r\n"
+            .to_vec();
+        let command = format!(
+            "A0001 CAPABILITY\r\n\
+             A0002 AUTHENTICATE PLAIN {}\r\n",
+            base64::encode("admin\0tim\0tanstaaf")
+        );
+        let mock_stream = MockStream::new(response);
+        let mut client = mock_client!(mock_stream);
+        client.capabilities().await.unwrap();
+        let session = client
+            .authenticate_plain(Some("admin"), "tim", "tanstaaf")
+            .await
+            .ok()
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            command.as_bytes(),
+            "Invalid authenticate command"
+        );
+    }
+
+    #[async_std::test]
+    async fn authenticate_plain_rejects_nul() {
+        let mock_stream = MockStream::default();
+        let client = mock_client!(mock_stream);
+        let (err, client) = client
+            .authenticate_plain(None, "tim", "tan\0staaf")
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::Validate(ValidateError('\0'))));
+        assert!(client.stream.inner.written_buf.is_empty());
+    }
+
+    #[async_std::test]
+    async fn login_disabled() {
+        let response = b"A0001 NO Plaintext authentication disallowed\r\n\
+                         * CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED\r\n\
+                         A0002 OK Capability completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        let (err, _client) = client.login("username", "password").await.err().unwrap();
+        match err {
+            Error::No { text, .. } => assert!(text.contains("LOGINDISABLED"), "{}", text),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[async_std::test]
+    async fn login_disabled_cached() {
+        let response = b"* CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED\r\n\
+                         A0001 OK Capability completed\r\n"
+            .to_vec();
+        let mut client = mock_client!(MockStream::new(response));
+        client.capabilities().await.unwrap();
+        client.stream.inner.written_buf.clear();
+        let (err, client) = client.login("username", "password").await.err().unwrap();
+        assert!(matches!(err, Error::LoginDisabled), "{:?}", err);
+        assert!(client.stream.inner.written_buf.is_empty());
+    }
+
+    #[async_std::test]
+    async fn login_failure_redacts_password() {
+        let response =
+            b"A0001 BAD Unexpected arguments: \"username\" \"pass\\\"word\"\r\n".to_vec();
+        let client = mock_client!(MockStream::new(response));
+        let (err, _client) = client.login("username", "pass\"word").await.err().unwrap();
+        assert!(matches!(err, Error::Bad { .. }), "{:?}", err);
+        for output in [err.to_string(), format!("{:?}", err)] {
+            assert!(!output.contains("word"), "{}", output);
+            assert!(output.contains("***"), "{}", output);
+        }
+    }
+
+    #[async_std::test]
+    async fn authenticate_failure_redacts_credentials() {
+        let initial_response = base64::encode("\0tim\0tanstaaf");
+        let response = format!(
+            "+ \r\nA0001 NO Invalid credentials {} (tanstaaf)\r\n",
+            initial_response
+        );
+        let client = mock_client!(MockStream::new(response.into_bytes()));
+        let (err, _client) = client
+            .authenticate_plain(None, "tim", "tanstaaf")
+            .await
+            .err()
+            .unwrap();
+        for output in [err.to_string(), format!("{:?}", err)] {
+            assert!(!output.contains("tanstaaf"), "{}", output);
+            assert!(!output.contains(&initial_response), "{}", output);
+        }
+    }
+
+    #[async_std::test]
+    async fn login() {
+        let response = b"A0001 OK Logged in\r\n".to_vec();
+        let username = "username";
+        let password = "password";
+        let command = format!("A0001 LOGIN {} {}\r\n", quote!(username), quote!(password));
+        let mock_stream = MockStream::new(response);
+        let client = mock_client!(mock_stream);
+        if let Ok(session) = client.login(username, password).await {
+            assert_eq!(
+                session.stream.inner.written_buf,
+                command.as_bytes().to_vec(),
+                "Invalid login command"
+            );
+        } else {
+            unreachable!("invalid login");
+        }
+    }
+
+    #[async_std::test]
+    async fn debug_hook() {
+        let body = "a".repeat(2000);
+        let response = format!(
+            "A0001 OK Logged in\r\n\
+             * 1 FETCH (UID 7 BODY[] {{{}}}\r\n{})\r\n\
+             A0002 OK FETCH completed\r\n\
+             + go ahead\r\n\
+             A0003 OK APPEND completed\r\n",
+            body.len(),
+            body
+        );
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut client = mock_client!(MockStream::new(response.into_bytes()));
+        let hook_lines = lines.clone();
+        client.set_debug(move |direction, line| {
+            let line = String::from_utf8(line.to_vec()).unwrap();
+            hook_lines.lock().unwrap().push((direction, line));
+        });
+        let mut session = client.login("username", "secret").await.unwrap();
+        session.fetch_collect("1", "BODY[]").await.unwrap();
+        session.append("INBOX", body.as_bytes()).await.unwrap();
+        session.clear_debug();
+        session.noop().await.unwrap_err();
+
+        let lines = lines.lock().unwrap();
+        assert_eq!(
+            *lines,
+            vec![
+                (Direction::Sent, "A0001 LOGIN ***".to_string()),
+                (Direction::Received, "A0001 OK Logged in".to_string()),
+                (Direction::Sent, "A0002 FETCH 1 BODY[]".to_string()),
+                (
+                    Direction::Received,
+                    "* 1 FETCH (UID 7 BODY[] {2000}".to_string()
+                ),
+                (Direction::Received, "[2000 bytes])".to_string()),
+                (Direction::Received, "A0002 OK FETCH completed".to_string()),
+                (Direction::Sent, "A0003 APPEND \"INBOX\" {2000}".to_string()),
+                (Direction::Received, "+ go ahead".to_string()),
+                (Direction::Sent, "[2000 bytes]".to_string()),
+                (Direction::Received, "A0003 OK APPEND completed".to_string()),
+            ]
+        );
+        assert!(lines.iter().all(|(_, line)| !line.contains("secret")));
+    }
+
+    #[test]
+    fn debug_redaction() {
+        use crate::imap_stream::ImapStream;
+
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_lines = lines.clone();
+        let mut stream = ImapStream::new(MockStream::default());
+        stream.set_debug(Some(DebugHook::new(move |_, line: &[u8]| {
+            hook_lines.lock().unwrap().push(line.to_vec());
+        })));
+        async_std::task::block_on(async {
+            for (tag, command) in [
+                (Some("A1"), "AUTHENTICATE PLAIN AGFsaWNlAHNlY3JldA=="),
+                (Some("A2"), "AUTHENTICATE XOAUTH2"),
+                (None, "dXNlcj1hbGljZQFhdXRoPUJlYXJlciB0b2tlbgEB"),
+                (None, "*"),
+                (None, "DONE"),
+            ] {
+                let tag = tag.map(|tag| RequestId(tag.to_string()));
+                stream
+                    .encode(Request(tag, command.as_bytes().into()))
+                    .await
+                    .unwrap();
+            }
+        });
+        assert_eq!(
+            *lines.lock().unwrap(),
+            vec![
+                b"A1 AUTHENTICATE PLAIN ***".to_vec(),
+                b"A2 AUTHENTICATE XOAUTH2".to_vec(),
+                b"***".to_vec(),
+                b"*".to_vec(),
+                b"DONE".to_vec(),
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_query() {
+        let response = b"* 1 FETCH (UID 7 FLAGS (\\Seen))\r\n\
+                         A0001 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let query = FetchQuery::new().uid().flags().body("HEADER");
+        let fetches = session.fetch_collect(Seq(1), query).await.unwrap();
+        assert_eq!(fetches[0].uid, Some(Uid(7)));
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 1 (UID FLAGS BODY.PEEK[HEADER])\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_fast() {
+        let response = b"* 1 FETCH (FLAGS (\\Seen \\Answered) \
+                         INTERNALDATE \"17-Jul-1996 02:44:25 -0700\" RFC822.SIZE 4286)\r\n\
+                         A0001 OK FETCH completed\r\n\
+                         * 1 FETCH (UID 9 FLAGS () INTERNALDATE \"01-Jan-2020 10:00:00 +0000\" \
+                         RFC822.SIZE 12)\r\n\
+                         A0002 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let fetches = session.fetch_collect(Seq(1), "FAST").await.unwrap();
+        let fetch = &fetches[0];
+        assert_eq!(
+            fetch.flags().collect::<Vec<_>>(),
+            vec![Flag::Seen, Flag::Answered]
+        );
+        assert_eq!(
+            fetch.internal_date().unwrap().to_rfc3339(),
+            "1996-07-17T02:44:25-07:00"
+        );
+        assert_eq!(fetch.size, Some(4286));
+
+        let query = FetchQuery::new().uid().fast();
+        let fetches = session.fetch_collect(Seq(1), query).await.unwrap();
+        assert_eq!(fetches[0].uid, Some(Uid(9)));
+        assert_eq!(fetches[0].size, Some(12));
+        assert!(fetches[0].internal_date().is_some());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 1 FAST\r\n\
+              A0002 FETCH 1 (UID FLAGS INTERNALDATE RFC822.SIZE)\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn no_and_bad_responses() {
+        let response = b"A0001 NO [AUTHENTICATIONFAILED] Authentication failed.\r\n".to_vec();
+        let client = mock_client!(MockStream::new(response));
+        let (err, _client) = client.login("username", "wrong").await.err().unwrap();
+        match err {
+            Error::No { code, text } => {
+                assert_eq!(code, Some(ResponseCode::AuthenticationFailed));
+                assert_eq!(text, "Authentication failed.");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        let response = b"A0001 NO [TRYCREATE] No such mailbox\r\n\
+                         A0002 BAD [CLIENTBUG] Invalid mailbox name\r\n\
+                         A0003 BAD Unknown command\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        match session.copy(Seq(1), "Archive").await {
+            Err(Error::No { code, text }) => {
+                assert_eq!(code, Some(ResponseCode::TryCreate));
+                assert_eq!(text, "No such mailbox");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match session.create("Archive").await {
+            Err(Error::Bad { code, text }) => {
+                assert_eq!(code, Some(ResponseCode::ClientBug));
+                assert_eq!(text, "Invalid mailbox name");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match session.create("Archive").await {
+            Err(err @ Error::Bad { code: None, .. }) => {
+                assert_eq!(err.to_string(), "bad response: Unknown command");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[async_std::test]
+    async fn login_capability_code() {
+        let response = b"A0001 OK [CAPABILITY IMAP4rev1 IDLE LITERAL+] Logged in\r\n".to_vec();
+        let client = mock_client!(MockStream::new(response));
+        let mut session = client.login("username", "password").await.ok().unwrap();
+        assert!(session.capabilities_cached().unwrap().has_str("IDLE"));
+        let capabilities = session.capabilities().await.unwrap();
+        assert_eq!(capabilities.len(), 3);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 LOGIN \"username\" \"password\"\r\n",
+            "capabilities were requested again"
+        );
+
+        let response = b"+ \r\n\
+                         A0001 OK [CAPABILITY IMAP4rev1 MOVE] Logged in\r\n"
+            .to_vec();
+        let client = mock_client!(MockStream::new(response));
+        let session = client
+            .authenticate_plain(None, "tim", "tanstaaf")
+            .await
+            .ok()
+            .unwrap();
+        assert!(session.capabilities_cached().unwrap().has_str("MOVE"));
+
+        let response = b"A0001 OK Logged in\r\n".to_vec();
+        let client = mock_client!(MockStream::new(response));
+        let session = client.login("username", "password").await.ok().unwrap();
+        assert!(session.capabilities_cached().is_none());
+    }
+
+    #[async_std::test]
+    async fn capabilities_not_carried_over() {
+        let response = b"* CAPABILITY IMAP4rev1 STARTTLS LOGINDISABLED\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK Begin TLS negotiation now\r\n"
+            .to_vec();
+        let mut client = mock_client!(MockStream::new(response));
+        assert!(client.has_capability("STARTTLS").await.unwrap());
+        let client = client
+            .starttls(|stream| async { Ok::<_, Error>(stream) })
+            .await
+            .unwrap();
+        assert!(client.capabilities.is_none());
+
+        let response = b"* CAPABILITY IMAP4rev1 AUTH=PLAIN\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         A0002 OK Logged in\r\n\
+                         * CAPABILITY IMAP4rev1 IDLE\r\n\
+                         A0003 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut client = mock_client!(MockStream::new(response));
+        assert!(client.has_capability("AUTH=PLAIN").await.unwrap());
+        let mut session = client.login("username", "password").await.ok().unwrap();
+        assert!(session.capabilities_cached().is_none());
+        assert!(session.has_capability("IDLE").await.unwrap());
+        assert!(!session.has_capability("AUTH=PLAIN").await.unwrap());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 LOGIN \"username\" \"password\"\r\n\
+              A0003 CAPABILITY\r\n",
+            "Invalid capability commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn logout() {
+        let response = b"A0001 OK Logout completed.\r\n".to_vec();
+        let command = "A0001 LOGOUT\r\n";
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session.logout().await.unwrap();
+        assert!(
+            session.stream.inner.written_buf == command.as_bytes().to_vec(),
+            "Invalid logout command"
+        );
+    }
+
+    #[async_std::test]
+    async fn logout_bye() {
+        let response = b"* 3 EXISTS\r\n\
+            * BYE Logging out\r\n\
+            A0001 OK Logout completed.\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.logout().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(3)
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+
+        // The connection is closed, so logging out again does nothing.
+        session.logout().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 LOGOUT\r\n",
+            "Invalid logout command"
+        );
+
+        // Servers may close the connection without completing the command.
+        let response = b"* BYE Logging out\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response).with_eof());
+        session.logout().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn close_connection() {
+        let mut session = mock_session!(MockStream::new(b"* 2 EXISTS\r\n".to_vec()));
+        session.close_connection().await.unwrap();
+        assert!(session.stream.inner.written_buf.is_empty());
+        session.close_connection().await.unwrap();
+
+        // The closed connection is not read from anymore.
+        assert!(session.noop().await.is_err());
+        assert!(session.unsolicited_responses.try_recv().is_err());
+    }
+
+    #[async_std::test]
+    async fn bye() {
+        let response = b"* 4 EXISTS\r\n\
+            * BYE Autologout; idle for too long\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session.noop().await.err().unwrap();
+        assert!(
+            matches!(&err, Error::Bye(reason) if reason == "Autologout; idle for too long"),
+            "{:?}",
+            err
+        );
+        assert!(err.is_connection_lost());
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+
+        // The session is dead, so further commands fail without being sent.
+        let err = session.select("INBOX").await.err().unwrap();
+        assert!(matches!(err, Error::Bye(_)), "{:?}", err);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 NOOP\r\n",
+            "Invalid command"
+        );
+        session.logout().await.unwrap();
+    }
+
+    #[async_std::test]
+    async fn rename() {
+        let response = b"A0001 OK RENAME completed\r\n".to_vec();
+        let current_mailbox_name = "INBOX";
+        let new_mailbox_name = "NEWINBOX";
+        let command = format!(
+            "A0001 RENAME {} {}\r\n",
+            quote!(current_mailbox_name),
+            quote!(new_mailbox_name)
+        );
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session
+            .rename(current_mailbox_name, new_mailbox_name)
+            .await
+            .unwrap();
+        assert!(
+            session.stream.inner.written_buf == command.as_bytes().to_vec(),
+            "Invalid rename command"
+        );
+    }
+
+    #[async_std::test]
+    async fn rename_international() {
+        let response = b"A0001 OK RENAME completed\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session
+            .rename("Entwürfe", "Отправленные & Co")
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 RENAME \"Entw&APw-rfe\" \"&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1- &- Co\"\r\n",
+            "Invalid rename command"
+        );
+    }
+
+    #[async_std::test]
+    async fn rename_selected() {
+        let response = b"* 0 EXISTS\r\n\
+                         * OK [UIDVALIDITY 1] UIDs valid\r\n\
+                         A0001 OK [READ-WRITE] SELECT completed\r\n\
+                         A0002 OK [HIGHESTMODSEQ 7] RENAME completed\r\n\
+                         A0003 OK RENAME completed\r\n\
+                         A0004 OK SELECT completed\r\n\
+                         A0005 OK RENAME completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.select("Drafts").await.unwrap();
+        let result = session.rename("Drafts", "Entwürfe").await.unwrap();
+        assert!(matches!(
+            result.code(),
+            Some(imap_proto::ResponseCode::HighestModSeq(7))
+        ));
+        assert_eq!(session.selected.as_deref(), Some("Entwürfe"));
+        session.rename("Sent", "Old").await.unwrap();
+        assert_eq!(session.selected.as_deref(), Some("Entwürfe"));
+
+        session.select("INBOX").await.unwrap();
+        session.rename("INBOX", "Archive").await.unwrap();
+        assert_eq!(session.selected.as_deref(), Some("INBOX"));
+    }
+
+    #[async_std::test]
+    async fn subscribe_all() {
+        let response = b"A0002 NO [NONEXISTENT] No such mailbox\r\n\
+                         A0001 OK SUBSCRIBE completed\r\n\
+                         * 3 EXISTS\r\n\
+                         A0003 OK SUBSCRIBE completed\r\n\
+                         A0004 OK UNSUBSCRIBE completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let results = session
+            .subscribe_all(&["INBOX", "Missing", "Entwürfe"])
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(
+            matches!(results[1], Err(Error::No { .. })),
+            "{:?}",
+            results[1]
+        );
+        assert!(results[2].is_ok());
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(3)
+        );
+        let results = session.unsubscribe_all(&["Old"]).await.unwrap();
+        assert!(matches!(results[..], [Ok(())]));
+        assert_eq!(session.unsubscribe_all::<&str>(&[]).await.unwrap().len(), 0);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SUBSCRIBE \"INBOX\"\r\n\
+              A0002 SUBSCRIBE \"Missing\"\r\n\
+              A0003 SUBSCRIBE \"Entw&APw-rfe\"\r\n\
+              A0004 UNSUBSCRIBE \"Old\"\r\n",
+            "Invalid subscribe commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn unread_count() {
+        let response = b"* STATUS INBOX (UNSEEN 3)\r\n\
+                         A0001 OK STATUS completed\r\n\
+                         * STATUS inbox (UNSEEN 5)\r\n\
+                         * STATUS Entw&APw-rfe (UNSEEN 0)\r\n\
+                         A0002 OK STATUS completed\r\n\
+                         A0003 NO [NONEXISTENT] No such mailbox\r\n\
+                         * STATUS Other (MESSAGES 1)\r\n\
+                         A0004 OK STATUS completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        assert_eq!(session.unread_count("INBOX").await.unwrap(), 3);
+        let counts = session
+            .unread_counts(&["INBOX", "Missing", "Entwürfe"])
+            .await
+            .unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts[0].as_ref().unwrap(), &5);
+        assert!(
+            matches!(counts[1], Err(Error::No { .. })),
+            "{:?}",
+            counts[1]
+        );
+        assert_eq!(counts[2].as_ref().unwrap(), &0);
+        assert!(matches!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Status { mailbox, .. } if mailbox == "Other"
+        ));
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 STATUS \"INBOX\" (UNSEEN)\r\n\
+              A0002 STATUS \"INBOX\" (UNSEEN)\r\n\
+              A0003 STATUS \"Missing\" (UNSEEN)\r\n\
+              A0004 STATUS \"Entw&APw-rfe\" (UNSEEN)\r\n",
+            "Invalid status commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_all_flags() {
+        let response = b"* CAPABILITY IMAP4rev1 CONDSTORE\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * 1 FETCH (UID 4 FLAGS (\\Seen $Forwarded))\r\n\
+                         * 2 FETCH (UID 7 FLAGS ())\r\n\
+                         * 2 FETCH (FLAGS (\\Deleted))\r\n\
+                         A0002 OK FETCH completed\r\n\
+                         * 2 FETCH (UID 7 MODSEQ (12) FLAGS (\\Flagged))\r\n\
+                         A0003 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        session.unsolicited_responses_tx.set_exists(2);
+        let flags = session.fetch_all_flags(None).await.unwrap();
+        assert_eq!(
+            flags,
+            vec![
+                (Uid(4), vec![Flag::Seen, Flag::Custom("$Forwarded".into())]),
+                (Uid(7), vec![]),
+            ]
+        );
+        let flags = session.fetch_all_flags(Some(10)).await.unwrap();
+        assert_eq!(flags, vec![(Uid(7), vec![Flag::Flagged])]);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 FETCH 1:* (UID FLAGS)\r\n\
+              A0003 FETCH 1:* (UID FLAGS) (CHANGEDSINCE 10)\r\n",
+            "Invalid fetch commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn incremental_sync_qresync() {
+        let response = b"* CAPABILITY IMAP4rev1 ENABLE CONDSTORE QRESYNC\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * ENABLED QRESYNC\r\n\
+                         A0002 OK ENABLE completed\r\n\
+                         * 3 EXISTS\r\n\
+                         * OK [UIDVALIDITY 67890007] UIDs valid\r\n\
+                         * OK [HIGHESTMODSEQ 90060128194045007] Highest\r\n\
+                         * VANISHED (EARLIER) 41,43:116\r\n\
+                         * 1 FETCH (UID 42 FLAGS (\\Seen) MODSEQ (90060128194045006))\r\n\
+                         * 3 FETCH (UID 150 FLAGS () MODSEQ (90060128194045007))\r\n\
+                         A0003 OK [READ-WRITE] SELECT completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let state = SyncState {
+            mailbox: "INBOX".into(),
+            uid_validity: Some(67890007),
+            highest_modseq: Some(90060128194045000),
+            known_uids: [41, 42, 43, 44, 45].iter().map(|&uid| Uid(uid)).collect(),
+        };
+        let delta = session.incremental_sync(state).await.unwrap();
+        assert!(!delta.uid_validity_changed);
+        assert_eq!(delta.new, vec![(Uid(150), vec![])]);
+        assert_eq!(delta.changed, vec![(Uid(42), vec![Flag::Seen])]);
+        assert_eq!(delta.vanished, vec![Uid(41), Uid(43), Uid(44), Uid(45)]);
+        assert_eq!(delta.mailbox.exists, 3);
+        assert_eq!(
+            delta.state,
+            SyncState {
+                mailbox: "INBOX".into(),
+                uid_validity: Some(67890007),
+                highest_modseq: Some(90060128194045007),
+                known_uids: vec![Uid(42), Uid(150)],
+            }
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 ENABLE QRESYNC\r\n\
+              A0003 SELECT \"INBOX\" (QRESYNC (67890007 90060128194045000 41:45))\r\n",
+            "Invalid sync commands"
+        );
     }
 
     #[async_std::test]
-    async fn authenticate() {
-        let response = b"+ YmFy\r\n\
-                         A0001 OK Logged in\r\n"
+    async fn incremental_sync_condstore() {
+        let response = b"* CAPABILITY IMAP4rev1 CONDSTORE\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * 2 EXISTS\r\n\
+                         * OK [UIDVALIDITY 5] UIDs valid\r\n\
+                         * OK [HIGHESTMODSEQ 90] Highest\r\n\
+                         A0002 OK [READ-WRITE] SELECT completed\r\n\
+                         * 1 FETCH (UID 3 FLAGS (\\Flagged) MODSEQ (85))\r\n\
+                         * 2 FETCH (UID 9 FLAGS () MODSEQ (90))\r\n\
+                         A0003 OK FETCH completed\r\n\
+                         * SEARCH 3 9\r\n\
+                         A0004 OK SEARCH completed\r\n"
             .to_vec();
-        let command = "A0001 AUTHENTICATE PLAIN\r\n\
-                       Zm9v\r\n";
-        let mock_stream = MockStream::new(response);
-        let client = mock_client!(mock_stream);
-        enum Authenticate {
-            Auth,
-        }
-        impl Authenticator for &Authenticate {
-            type Response = Vec<u8>;
-            fn process(&mut self, challenge: &[u8]) -> Self::Response {
-                assert!(challenge == b"bar", "Invalid authenticate challenge");
-                b"foo".to_vec()
+        let mut session = mock_session!(MockStream::new(response));
+        let state = SyncState {
+            mailbox: "INBOX".into(),
+            uid_validity: Some(5),
+            highest_modseq: Some(80),
+            known_uids: vec![Uid(3), Uid(4)],
+        };
+        let delta = session.incremental_sync(state).await.unwrap();
+        assert_eq!(delta.new, vec![(Uid(9), vec![])]);
+        assert_eq!(delta.changed, vec![(Uid(3), vec![Flag::Flagged])]);
+        assert_eq!(delta.vanished, vec![Uid(4)]);
+        assert_eq!(delta.state.highest_modseq, Some(90));
+        assert_eq!(delta.state.known_uids, vec![Uid(3), Uid(9)]);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 SELECT \"INBOX\" (CONDSTORE)\r\n\
+              A0003 FETCH 1:* (UID FLAGS) (CHANGEDSINCE 80)\r\n\
+              A0004 UID SEARCH ALL\r\n",
+            "Invalid sync commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn incremental_sync_uid_validity_changed() {
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+                         A0001 OK CAPABILITY completed\r\n\
+                         * 1 EXISTS\r\n\
+                         * OK [UIDVALIDITY 6] UIDs valid\r\n\
+                         A0002 OK [READ-WRITE] SELECT completed\r\n\
+                         * 1 FETCH (UID 1 FLAGS (\\Seen))\r\n\
+                         A0003 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let state = SyncState {
+            uid_validity: Some(5),
+            known_uids: vec![Uid(3), Uid(4)],
+            ..SyncState::new("INBOX")
+        };
+        let delta = session.incremental_sync(state).await.unwrap();
+        assert!(delta.uid_validity_changed);
+        assert_eq!(delta.new, vec![(Uid(1), vec![Flag::Seen])]);
+        assert!(delta.changed.is_empty());
+        assert_eq!(delta.vanished, vec![Uid(3), Uid(4)]);
+        assert_eq!(
+            delta.state,
+            SyncState {
+                mailbox: "INBOX".into(),
+                uid_validity: Some(6),
+                highest_modseq: None,
+                known_uids: vec![Uid(1)],
             }
-        }
-        let session = client
-            .authenticate("PLAIN", &Authenticate::Auth)
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+              A0002 SELECT \"INBOX\"\r\n\
+              A0003 FETCH 1:* (UID FLAGS)\r\n",
+            "Invalid sync commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn list_international() {
+        let response = b"* LIST () \"/\" \"Entw&APw-rfe\"\r\n\
+                         * LIST () \"/\" \"Broken&\"\r\n\
+                         A0001 OK LIST completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        let names = session
+            .list(Some("Entwürfe"), Some("*"))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
             .await
-            .ok()
             .unwrap();
         assert_eq_bytes!(
             &session.stream.inner.written_buf,
-            command.as_bytes(),
-            "Invalid authenticate command"
+            b"A0001 LIST \"Entw&APw-rfe\" \"*\"\r\n",
+            "Invalid list command"
         );
+        assert_eq!(names[0].name(), "Entwürfe");
+        assert_eq!(names[0].name_raw(), "Entw&APw-rfe");
+        assert_eq!(names[1].name(), "Broken&");
     }
 
     #[async_std::test]
-    async fn login() {
-        let response = b"A0001 OK Logged in\r\n".to_vec();
-        let username = "username";
-        let password = "password";
-        let command = format!("A0001 LOGIN {} {}\r\n", quote!(username), quote!(password));
+    async fn enable_requires_capability() {
+        let response = b"* CAPABILITY IMAP4rev1 IDLE\r\n\
+            A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let result = session.enable(&["CONDSTORE"]).await;
+        assert!(
+            matches!(result, Err(Error::MissingCapability(_))),
+            "{:?}",
+            result
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n",
+            "ENABLE sent without the capability"
+        );
+    }
+
+    #[async_std::test]
+    async fn enable_utf8_accept() {
+        let response = "* CAPABILITY IMAP4rev1 ENABLE UTF8=ACCEPT\r\n\
+                        A0001 OK CAPABILITY completed\r\n\
+                        * ENABLED UTF8=ACCEPT\r\n\
+                        A0002 OK ENABLE completed\r\n\
+                        A0003 OK [READ-WRITE] SELECT completed\r\n\
+                        * LIST () \"/\" \"Отправленные\"\r\n\
+                        * LIST () \"/\" \"a&b-c\"\r\n\
+                        A0004 OK LIST completed\r\n\
+                        + Ready for literal data\r\n\
+                        A0005 OK APPEND completed\r\n";
+        let mock_stream = MockStream::new(response.as_bytes().to_vec());
+        let mut session = mock_session!(mock_stream);
+        let enabled = session.enable(&["UTF8=ACCEPT"]).await.unwrap();
+        assert!(enabled.has_str("UTF8=ACCEPT"));
+        session.select("Отправленные").await.unwrap();
+        let names = session
+            .list(None, Some("*"))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(names[0].name(), "Отправленные");
+        assert_eq!(names[1].name(), "a&b-c");
+        session
+            .append("Entwürfe", "Subject: ü\r\n".as_bytes())
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            "A0001 CAPABILITY\r\n\
+             A0002 ENABLE UTF8=ACCEPT\r\n\
+             A0003 SELECT \"Отправленные\"\r\n\
+             A0004 LIST \"\" \"*\"\r\n\
+             A0005 APPEND \"Entwürfe\" UTF8 (~{13}\r\nSubject: ü\r\n)\r\n"
+                .as_bytes(),
+            "Invalid commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn subscribe() {
+        let response = b"A0001 OK SUBSCRIBE completed\r\n".to_vec();
+        let mailbox = "INBOX";
+        let command = format!("A0001 SUBSCRIBE {}\r\n", quote!(mailbox));
         let mock_stream = MockStream::new(response);
-        let client = mock_client!(mock_stream);
-        if let Ok(session) = client.login(username, password).await {
-            assert_eq!(
-                session.stream.inner.written_buf,
-                command.as_bytes().to_vec(),
-                "Invalid login command"
-            );
-        } else {
-            unreachable!("invalid login");
-        }
+        let mut session = mock_session!(mock_stream);
+        session.subscribe(mailbox).await.unwrap();
+        assert!(
+            session.stream.inner.written_buf == command.as_bytes().to_vec(),
+            "Invalid subscribe command"
+        );
     }
 
     #[async_std::test]
-    async fn logout() {
-        let response = b"A0001 OK Logout completed.\r\n".to_vec();
-        let command = "A0001 LOGOUT\r\n";
+    async fn unsubscribe() {
+        let response = b"A0001 OK UNSUBSCRIBE completed\r\n".to_vec();
+        let mailbox = "INBOX";
+        let command = format!("A0001 UNSUBSCRIBE {}\r\n", quote!(mailbox));
         let mock_stream = MockStream::new(response);
         let mut session = mock_session!(mock_stream);
-        session.logout().await.unwrap();
+        session.unsubscribe(mailbox).await.unwrap();
         assert!(
             session.stream.inner.written_buf == command.as_bytes().to_vec(),
-            "Invalid logout command"
+            "Invalid unsubscribe command"
+        );
+    }
+
+    #[async_std::test]
+    async fn expunge() {
+        let response = b"A0001 OK EXPUNGE completed\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session.expunge().await.unwrap().collect::<Vec<_>>().await;
+        assert!(
+            session.stream.inner.written_buf == b"A0001 EXPUNGE\r\n".to_vec(),
+            "Invalid expunge command"
+        );
+    }
+
+    #[async_std::test]
+    async fn expunge_drain() {
+        let response = b"* 3 EXPUNGE\r\n\
+            * 3 EXPUNGE\r\n\
+            * 1 EXPUNGE\r\n\
+            A0001 OK EXPUNGE completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        assert_eq!(session.expunge_drain().await.unwrap(), vec![3, 3, 1]);
+    }
+
+    #[async_std::test]
+    async fn idle_events() {
+        use extensions::idle::IdleEvent;
+
+        let response = b"+ idling\r\n\
+            * 3 EXISTS\r\n\
+            * OK Still here\r\n\
+            * 2 FETCH (FLAGS (\\Seen))\r\n\
+            * 1 EXPUNGE\r\n\
+            A0001 OK IDLE terminated\r\n"
+            .to_vec();
+        let session = mock_session!(MockStream::new(response));
+        let mut idle = session.idle();
+        idle.init().await.unwrap();
+        let events = idle.events().take(3).collect::<Vec<_>>().await;
+        assert!(matches!(events[0], Ok(IdleEvent::Exists(3))));
+        match &events[1] {
+            Ok(IdleEvent::Fetch(fetch)) => {
+                assert_eq!(fetch.message, 2);
+                assert_eq!(fetch.flags().collect::<Vec<_>>(), vec![Flag::Seen]);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(matches!(events[2], Ok(IdleEvent::Expunge(1))));
+        let session = idle.done().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 IDLE\r\nDONE\r\n",
+            "Invalid idle command"
+        );
+    }
+
+    #[async_std::test]
+    async fn idle_terminated_by_server() {
+        use extensions::idle::IdleEvent;
+
+        let response = b"+ idling\r\n\
+            * 4 EXISTS\r\n\
+            A0001 OK IDLE terminated after timeout\r\n"
+            .to_vec();
+        let session = mock_session!(MockStream::new(response));
+        let mut idle = session.idle();
+        idle.init().await.unwrap();
+        let events = idle.events().collect::<Vec<_>>().await;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], Ok(IdleEvent::Exists(4))));
+        assert!(matches!(events[1], Ok(IdleEvent::Terminated)));
+        let session = idle.done().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 IDLE\r\n",
+            "DONE sent after the server ended IDLE"
+        );
+    }
+
+    #[cfg_attr(feature = "runtime-async-std", async_std::test)]
+    #[cfg_attr(feature = "runtime-tokio", tokio::test)]
+    async fn idle_refresh() {
+        let mock_stream = MockStream::default()
+            .with_pending()
+            .with_reply(b"+ idling\r\n")
+            .with_reply(b"A0001 OK IDLE terminated\r\n")
+            .with_reply(b"+ idling\r\n* 4 EXISTS\r\n")
+            .with_reply(b"A0002 OK IDLE terminated\r\n");
+        let session = mock_session!(mock_stream);
+        let mut idle = session.idle();
+        idle.init().await.unwrap();
+        let (idle_wait, _interrupt) = idle.wait_with_timeout(Duration::from_millis(10));
+        match idle_wait.await.unwrap() {
+            extensions::idle::IdleResponse::NewData(data) => {
+                assert!(matches!(
+                    data.parsed(),
+                    Response::MailboxData(imap_proto::MailboxDatum::Exists(4))
+                ));
+            }
+            response => panic!("unexpected response {:?}", response),
+        }
+        let session = idle.done().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 IDLE\r\nDONE\r\nA0002 IDLE\r\nDONE\r\n",
+            "IDLE not re-issued"
+        );
+    }
+
+    #[async_std::test]
+    async fn notify() {
+        let response = b"* CAPABILITY IMAP4rev1 NOTIFY\r\n\
+            A0001 OK CAPABILITY completed\r\n\
+            A0002 OK NOTIFY completed\r\n\
+            A0003 OK NOTIFY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let spec = NotifySpec::new()
+            .selected(&[NotifyEvent::MessageNew, NotifyEvent::MessageExpunge])
+            .mailboxes(&["INBOX"], &[NotifyEvent::MessageNew]);
+        session.notify(spec).await.unwrap();
+        session.notify(NotifySpec::new()).await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+            A0002 NOTIFY SET (selected (MessageNew MessageExpunge)) \
+            (mailboxes (\"INBOX\") (MessageNew))\r\n\
+            A0003 NOTIFY NONE\r\n",
+            "Invalid notify command"
+        );
+    }
+
+    #[async_std::test]
+    async fn notify_requires_capability() {
+        let response = b"* CAPABILITY IMAP4rev1 IDLE\r\n\
+            A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let spec = NotifySpec::new().selected(&[NotifyEvent::MessageNew]);
+        assert!(matches!(
+            session.notify(spec).await,
+            Err(Error::MissingCapability(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn notify_notifications() {
+        let response = b"* CAPABILITY IMAP4rev1 NOTIFY\r\n\
+            A0001 OK CAPABILITY completed\r\n\
+            A0002 OK NOTIFY completed\r\n\
+            * 3 EXISTS\r\n\
+            A0003 OK [READ-WRITE] SELECT completed\r\n\
+            * 2 EXPUNGE\r\n\
+            * 1 FETCH (FLAGS (\\Seen))\r\n\
+            * STATUS Archive (MESSAGES 4)\r\n\
+            A0004 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let spec = NotifySpec::new()
+            .selected(&[NotifyEvent::MessageNew, NotifyEvent::MessageExpunge])
+            .filter(NotifyFilter::Personal, &[NotifyEvent::MessageNew]);
+        session.notify(spec).await.unwrap();
+        session.select("INBOX").await.unwrap();
+        session.noop().await.unwrap();
+
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Notification {
+                mailbox: "INBOX".to_string(),
+                response: Box::new(UnsolicitedResponse::Expunge(2)),
+            }
+        );
+        match session.unsolicited_responses.try_recv().unwrap() {
+            UnsolicitedResponse::Notification { mailbox, response } => {
+                assert_eq!(mailbox, "INBOX");
+                assert!(matches!(*response, UnsolicitedResponse::Other(_)));
+            }
+            response => panic!("unexpected response {:?}", response),
+        }
+        assert!(matches!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Status { mailbox, .. } if mailbox == "Archive"
+        ));
+        assert!(session.unsolicited_responses.try_recv().is_err());
+    }
+
+    #[async_std::test]
+    async fn fetch_collect() {
+        let response = b"* 1 FETCH (UID 7 FLAGS (\\Seen))\r\n\
+            * 2 FETCH (UID 9 FLAGS ())\r\n\
+            A0001 OK FETCH completed\r\n\
+            * 1 FETCH (UID 7 RFC822.SIZE 42)\r\n\
+            A0002 OK UID FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let fetches = session.fetch_collect("1:2", "(UID FLAGS)").await.unwrap();
+        let uids: Vec<_> = fetches.iter().map(|fetch| fetch.uid).collect();
+        assert_eq!(uids, vec![Some(Uid(7)), Some(Uid(9))]);
+        assert_eq!(fetches[0].flags().collect::<Vec<_>>(), vec![Flag::Seen]);
+
+        // The results outlive the borrow of the session.
+        let fetches = session.uid_fetch_collect("7", "RFC822.SIZE").await.unwrap();
+        assert_eq!(fetches[0].size, Some(42));
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 1:2 (UID FLAGS)\r\nA0002 UID FETCH 7 RFC822.SIZE\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_from() {
+        let response = b"* 0 EXISTS\r\n\
+            A0001 OK SELECT completed\r\n\
+            * 1 EXISTS\r\n\
+            A0002 OK NOOP completed\r\n\
+            * 1 FETCH (FLAGS ())\r\n\
+            A0003 OK FETCH completed\r\n\
+            A0004 BAD Invalid messageset\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.select("INBOX").await.unwrap();
+        // The mailbox is empty, so nothing is sent.
+        let fetches: Vec<_> = session
+            .fetch_from(1, "FLAGS")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(fetches.is_empty());
+
+        // Only messages from `start` on are returned, even though `*` is the last message.
+        session.noop().await.unwrap();
+        assert!(matches!(
+            session.unsolicited_responses.try_recv(),
+            Ok(UnsolicitedResponse::Exists(1))
+        ));
+        let fetches: Vec<_> = session
+            .fetch_from(2, "FLAGS")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(fetches.is_empty());
+        match session.unsolicited_responses.try_recv() {
+            Ok(UnsolicitedResponse::Other(res)) => {
+                assert!(matches!(res.parsed(), Response::Fetch(1, _)))
+            }
+            res => panic!("unexpected response: {:?}", res),
+        }
+
+        let fetches: Vec<_> = session
+            .fetch_from(1, "FLAGS")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(
+            matches!(fetches[..], [Err(Error::Bad { .. })]),
+            "{:?}",
+            fetches
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SELECT \"INBOX\"\r\nA0002 NOOP\r\n\
+              A0003 FETCH 2:* FLAGS\r\nA0004 FETCH 1:* FLAGS\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_from_errors() {
+        // Without a selected mailbox, the error is not mistaken for an empty mailbox.
+        let response = b"A0001 BAD No mailbox selected\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let fetches: Vec<_> = session
+            .fetch_from(1, "FLAGS")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(
+            matches!(fetches[..], [Err(Error::Bad { .. })]),
+            "{:?}",
+            fetches
+        );
+
+        // The mailbox is no longer empty after a message arrived during IDLE.
+        let response = b"* 0 EXISTS\r\n\
+            A0001 OK SELECT completed\r\n\
+            + idling\r\n\
+            * 1 EXISTS\r\n\
+            A0002 OK IDLE terminated\r\n\
+            A0003 BAD Invalid messageset\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.select("INBOX").await.unwrap();
+        let mut idle = session.idle();
+        idle.init().await.unwrap();
+        let (idle_wait, _interrupt) = idle.wait();
+        assert!(matches!(
+            idle_wait.await.unwrap(),
+            extensions::idle::IdleResponse::NewData(_)
+        ));
+        let mut session = idle.done().await.unwrap();
+        let fetches: Vec<_> = session
+            .uid_fetch_from(1, "FLAGS")
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(
+            matches!(fetches[..], [Err(Error::Bad { .. })]),
+            "{:?}",
+            fetches
         );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SELECT \"INBOX\"\r\nA0002 IDLE\r\nDONE\r\nA0003 UID FETCH 1:* FLAGS\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn uid_fetch_from() {
+        let response = b"* 3 EXISTS\r\n\
+            A0001 OK SELECT completed\r\n\
+            * 3 FETCH (UID 17 FLAGS ())\r\n\
+            A0002 OK FETCH completed\r\n\
+            * 2 FETCH (UID 15 FLAGS ())\r\n\
+            * 3 FETCH (UID 17 FLAGS ())\r\n\
+            A0003 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.select("INBOX").await.unwrap();
+        let fetches = session
+            .uid_fetch_from(18, "FLAGS")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(fetches.is_empty());
+        match session.unsolicited_responses.try_recv() {
+            Ok(UnsolicitedResponse::Other(res)) => {
+                assert!(matches!(res.parsed(), Response::Fetch(3, _)))
+            }
+            res => panic!("unexpected response: {:?}", res),
+        }
+        let fetches = session
+            .uid_fetch_from(Uid(15), "FLAGS")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let uids: Vec<_> = fetches.iter().map(|fetch| fetch.uid).collect();
+        assert_eq!(uids, vec![Some(Uid(15)), Some(Uid(17))]);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SELECT \"INBOX\"\r\nA0002 UID FETCH 18:* FLAGS\r\n\
+              A0003 UID FETCH 15:* FLAGS\r\n",
+            "Invalid fetch command"
+        );
+    }
+
+    #[async_std::test]
+    async fn store_flags() {
+        let response = b"* 2 FETCH (FLAGS (\\Seen $Forwarded))\r\n\
+            A0001 OK STORE completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let flags = [Flag::Seen, Flag::Custom("$Forwarded".into())];
+        let fetches = session
+            .store_flags("2", StoreMode::Add, &flags)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(fetches[0].flags().collect::<Vec<_>>(), flags);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 STORE 2 +FLAGS (\\Seen $Forwarded)\r\n",
+            "Invalid store command"
+        );
+    }
+
+    #[async_std::test]
+    async fn store_invalid_flag() {
+        let mut session = mock_session!(MockStream::default());
+        let flags = [Flag::Custom("my flag".into())];
+        assert!(matches!(
+            session.uid_store_flags("7", StoreMode::Add, &flags).await,
+            Err(Error::InvalidFlag(_))
+        ));
+        assert!(matches!(
+            session.store("1", "+FLAGS (my)flag)").await,
+            Err(Error::InvalidFlag(_))
+        ));
+        assert!(session.stream.inner.written_buf.is_empty());
     }
 
     #[async_std::test]
-    async fn rename() {
-        let response = b"A0001 OK RENAME completed\r\n".to_vec();
-        let current_mailbox_name = "INBOX";
-        let new_mailbox_name = "NEWINBOX";
-        let command = format!(
-            "A0001 RENAME {} {}\r\n",
-            quote!(current_mailbox_name),
-            quote!(new_mailbox_name)
-        );
-        let mock_stream = MockStream::new(response);
-        let mut session = mock_session!(mock_stream);
-        session
-            .rename(current_mailbox_name, new_mailbox_name)
+    async fn search_result_too_large() {
+        let response = b"* ESEARCH (TAG \"A0001\") ALL 1:4294967295\r\n\
+            A0001 OK SEARCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let result = session.search("ALL").await;
+        assert!(matches!(result, Err(Error::Parse(_))), "{:?}", result);
+    }
+
+    #[async_std::test]
+    async fn rev2() {
+        // `ENABLE` is part of `IMAP4rev2`.
+        let response = b"* CAPABILITY IMAP4rev1 IMAP4rev2\r\n\
+            A0001 OK CAPABILITY completed\r\n\
+            * ENABLED IMAP4rev2\r\n\
+            A0002 OK ENABLE completed\r\n\
+            * LIST (\\Subscribed) \"/\" \"Entw\xc3\xbcrfe\"\r\n\
+            A0003 OK LIST completed\r\n\
+            * ESEARCH (TAG \"A0004\") ALL 2:4\r\n\
+            A0004 OK SEARCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        assert!(!session.is_rev2());
+        session.enable(&["IMAP4rev2"]).await.unwrap();
+        assert!(session.is_rev2());
+
+        let names = session
+            .lsub(None, Some("*"))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert!(
-            session.stream.inner.written_buf == command.as_bytes().to_vec(),
-            "Invalid rename command"
+        assert_eq!(names[0].name(), "Entwürfe");
+
+        let ids = session.search("UNSEEN").await.unwrap();
+        assert_eq!(ids, [Seq(2), Seq(3), Seq(4)].iter().cloned().collect());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+            A0002 ENABLE IMAP4rev2\r\n\
+            A0003 LIST (SUBSCRIBED) \"\" \"*\"\r\n\
+            A0004 SEARCH UNSEEN\r\n",
+            "Invalid rev2 commands"
         );
     }
 
     #[async_std::test]
-    async fn subscribe() {
-        let response = b"A0001 OK SUBSCRIBE completed\r\n".to_vec();
-        let mailbox = "INBOX";
-        let command = format!("A0001 SUBSCRIBE {}\r\n", quote!(mailbox));
-        let mock_stream = MockStream::new(response);
-        let mut session = mock_session!(mock_stream);
-        session.subscribe(mailbox).await.unwrap();
-        assert!(
-            session.stream.inner.written_buf == command.as_bytes().to_vec(),
-            "Invalid subscribe command"
+    async fn lsub_list_extended() {
+        let response = b"* CAPABILITY IMAP4rev1 LIST-EXTENDED\r\n\
+            A0001 OK CAPABILITY completed\r\n\
+            * LIST (\\Subscribed \\NonExistent) \"/\" \"Old\"\r\n\
+            * LIST (\\Subscribed \\Noselect) \"/\" \"Shared\"\r\n\
+            A0002 OK LIST completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.capabilities().await.unwrap();
+        let names = session
+            .lsub(None, Some("*"))
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            names[0].attributes(),
+            [
+                NameAttribute::from("\\Subscribed"),
+                NameAttribute::from("\\NonExistent")
+            ]
+        );
+        assert_eq!(
+            names[1].attributes(),
+            [NameAttribute::from("\\Subscribed"), NameAttribute::NoSelect]
+        );
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 CAPABILITY\r\n\
+            A0002 LIST (SUBSCRIBED) \"\" \"*\"\r\n",
+            "Invalid lsub command"
         );
     }
 
     #[async_std::test]
-    async fn unsubscribe() {
-        let response = b"A0001 OK UNSUBSCRIBE completed\r\n".to_vec();
-        let mailbox = "INBOX";
-        let command = format!("A0001 UNSUBSCRIBE {}\r\n", quote!(mailbox));
-        let mock_stream = MockStream::new(response);
-        let mut session = mock_session!(mock_stream);
-        session.unsubscribe(mailbox).await.unwrap();
-        assert!(
-            session.stream.inner.written_buf == command.as_bytes().to_vec(),
-            "Invalid unsubscribe command"
+    async fn run_raw() {
+        let response = b"* ID (\"name\" \"Dovecot\")\r\n\
+            * 3 EXISTS\r\n\
+            A0001 NO ID failed\r\n\
+            A0002 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let responses = session
+            .run_raw("ID NIL")
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(responses.len(), 3);
+        assert!(matches!(responses[0].parsed(), Response::Id(Some(_))));
+        assert!(matches!(
+            responses[1].parsed(),
+            Response::MailboxData(imap_proto::MailboxDatum::Exists(3))
+        ));
+        assert_eq!(
+            responses[2].request_id(),
+            Some(&RequestId("A0001".to_string()))
+        );
+        assert!(matches!(
+            responses[2].parsed(),
+            Response::Done {
+                status: Status::No,
+                ..
+            }
+        ));
+        assert!(session.unsolicited_responses.try_recv().is_err());
+
+        session.noop().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 ID NIL\r\nA0002 NOOP\r\n",
+            "Invalid raw command"
         );
     }
 
     #[async_std::test]
-    async fn expunge() {
-        let response = b"A0001 OK EXPUNGE completed\r\n".to_vec();
-        let mock_stream = MockStream::new(response);
-        let mut session = mock_session!(mock_stream);
-        session.expunge().await.unwrap().collect::<Vec<_>>().await;
-        assert!(
-            session.stream.inner.written_buf == b"A0001 EXPUNGE\r\n".to_vec(),
-            "Invalid expunge command"
+    async fn run_raw_dropped() {
+        let response = b"* ID (\"name\" \"Dovecot\")\r\n\
+            * 3 EXISTS\r\n\
+            A0001 OK ID completed\r\n\
+            A0002 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let mut responses = session.run_raw("ID NIL").await.unwrap();
+        let first = responses.try_next().await.unwrap().unwrap();
+        assert!(matches!(first.parsed(), Response::Id(Some(_))));
+        drop(responses);
+
+        session.noop().await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 ID NIL\r\nA0002 NOOP\r\n",
+            "Invalid raw command"
         );
+        assert!(matches!(
+            session.unsolicited_responses.try_recv(),
+            Ok(UnsolicitedResponse::Exists(3))
+        ));
     }
 
     #[async_std::test]
     async fn uid_expunge() {
-        let response = b"* 2 EXPUNGE\r\n\
+        let response = b"* CAPABILITY IMAP4rev1 UIDPLUS\r\n\
+            A0001 OK CAPABILITY completed\r\n\
+            * 2 EXPUNGE\r\n\
             * 3 EXPUNGE\r\n\
-            * 4 EXPUNGE\r\n\
-            A0001 OK UID EXPUNGE completed\r\n"
+            * 3 EXPUNGE\r\n\
+            A0002 OK UID EXPUNGE completed\r\n"
             .to_vec();
         let mock_stream = MockStream::new(response);
         let mut session = mock_session!(mock_stream);
-        session
+        let expunged = session
             .uid_expunge("2:4")
             .await
             .unwrap()
-            .collect::<Vec<_>>()
-            .await;
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(expunged, vec![2, 3, 3]);
         assert!(
-            session.stream.inner.written_buf == b"A0001 UID EXPUNGE 2:4\r\n".to_vec(),
+            session.stream.inner.written_buf
+                == b"A0001 CAPABILITY\r\nA0002 UID EXPUNGE 2:4\r\n".to_vec(),
             "Invalid expunge command"
         );
     }
 
+    #[async_std::test]
+    async fn uid_expunge_requires_uidplus() {
+        let response = b"* CAPABILITY IMAP4rev1\r\n\
+            A0001 OK CAPABILITY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let err = session.uid_expunge("2:4").await.err().unwrap();
+        assert!(matches!(err, Error::MissingCapability(_)), "{:?}", err);
+        assert_eq!(session.stream.inner.written_buf, b"A0001 CAPABILITY\r\n");
+    }
+
     #[async_std::test]
     async fn check() {
         let response = b"A0001 OK CHECK completed\r\n".to_vec();
@@ -1643,6 +6926,43 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn check_unsolicited() {
+        let response = b"* 4 EXISTS\r\n\
+            * 1 RECENT\r\n\
+            A0001 OK CHECK completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.check().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Recent(1)
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+    }
+
+    #[async_std::test]
+    async fn select_read_only() {
+        let response = b"* 2 EXISTS\r\n\
+            A0001 OK [READ-WRITE] SELECT completed\r\n\
+            * 2 EXISTS\r\n\
+            A0002 OK [READ-ONLY] SELECT completed\r\n\
+            * 2 EXISTS\r\n\
+            A0003 OK EXAMINE completed\r\n\
+            A0004 NO Mailbox does not exist\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        assert!(!session.select("INBOX").await.unwrap().read_only);
+        assert!(session.select("Shared").await.unwrap().read_only);
+        assert!(session.examine("INBOX").await.unwrap().read_only);
+        let err = session.select("Missing").await.err().unwrap();
+        assert!(matches!(err, Error::No { .. }), "{:?}", err);
+    }
+
     #[async_std::test]
     async fn examine() {
         let response = b"* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n\
@@ -1663,11 +6983,13 @@ mod tests {
                 Flag::Draft,
             ],
             exists: 1,
-            recent: 1,
-            unseen: Some(1),
-            permanent_flags: vec![],
-            uid_next: Some(2),
+            recent: Some(1),
+            first_unseen: Some(1),
+            permanent_flags: PermanentFlags::default(),
+            uid_next: Some(Uid(2)),
             uid_validity: Some(1257842737),
+            read_only: true,
+            ..Mailbox::default()
         };
         let mailbox_name = "INBOX";
         let command = format!("A0001 EXAMINE {}\r\n", quote!(mailbox_name));
@@ -1702,18 +7024,22 @@ mod tests {
                 Flag::Draft,
             ],
             exists: 1,
-            recent: 1,
-            unseen: Some(1),
-            permanent_flags: vec![
-                Flag::MayCreate,
-                Flag::Answered,
-                Flag::Flagged,
-                Flag::Deleted,
-                Flag::Draft,
-                Flag::Seen,
-            ],
-            uid_next: Some(2),
+            recent: Some(1),
+            first_unseen: Some(1),
+            permanent_flags: PermanentFlags {
+                flags: vec![
+                    Flag::Answered,
+                    Flag::Flagged,
+                    Flag::Deleted,
+                    Flag::Draft,
+                    Flag::Seen,
+                ],
+                can_create_keywords: true,
+            },
+            uid_next: Some(Uid(2)),
             uid_validity: Some(1257842737),
+            read_only: true,
+            ..Mailbox::default()
         };
         let mailbox_name = "INBOX";
         let command = format!("A0001 SELECT {}\r\n", quote!(mailbox_name));
@@ -1727,6 +7053,45 @@ mod tests {
         assert_eq!(mailbox, expected_mailbox);
     }
 
+    #[async_std::test]
+    async fn select_unsolicited() {
+        let response = b"* 5 EXISTS\r\n\
+            * 2 RECENT\r\n\
+            * STATUS Drafts (MESSAGES 3)\r\n\
+            * OK [CLOSED] Previous mailbox closed.\r\n\
+            * 1 EXISTS\r\n\
+            * OK [UIDVALIDITY 7] UIDs valid\r\n\
+            A0001 OK [READ-WRITE] Select completed.\r\n\
+            * 2 EXISTS\r\n\
+            A0002 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let mailbox = session.select("INBOX").await.unwrap();
+        assert_eq!(mailbox.exists, 1);
+        assert_eq!(mailbox.recent, None);
+        assert_eq!(mailbox.uid_validity, Some(7));
+        assert!(mailbox.other_codes.is_empty());
+        assert!(matches!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Status { mailbox, .. } if mailbox == "Drafts"
+        ));
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(5)
+        );
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Recent(2)
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+
+        session.noop().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(2)
+        );
+    }
+
     #[async_std::test]
     async fn search() {
         let response = b"* SEARCH 1 2 3 4 5\r\n\
@@ -1735,12 +7100,11 @@ mod tests {
         let mock_stream = MockStream::new(response);
         let mut session = mock_session!(mock_stream);
         let ids = session.search("Unseen").await.unwrap();
-        let ids: HashSet<u32> = ids.iter().cloned().collect();
         assert!(
             session.stream.inner.written_buf == b"A0001 SEARCH Unseen\r\n".to_vec(),
             "Invalid search command"
         );
-        assert_eq!(ids, [1, 2, 3, 4, 5].iter().cloned().collect());
+        assert_eq!(ids, (1..=5).map(Seq).collect());
     }
 
     #[async_std::test]
@@ -1756,7 +7120,7 @@ mod tests {
             session.stream.inner.written_buf == b"A0001 UID SEARCH Unseen\r\n".to_vec(),
             "Invalid search command"
         );
-        assert_eq!(ids, [1, 2, 3, 4, 5].iter().cloned().collect());
+        assert_eq!(ids, (1..=5).map(Uid).collect());
     }
 
     #[async_std::test]
@@ -1773,7 +7137,7 @@ mod tests {
             session.stream.inner.written_buf == b"A0001 UID SEARCH Unseen\r\n".to_vec(),
             "Invalid search command"
         );
-        assert_eq!(ids, [1, 2, 3, 4, 5].iter().cloned().collect());
+        assert_eq!(ids, (1..=5).map(Uid).collect());
     }
 
     #[async_std::test]
@@ -1809,6 +7173,100 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn run_command_and_check_ok_result() {
+        let response = b"A0001 OK [READ-ONLY] Examine completed\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        let result = session
+            .run_command_and_check_ok("EXAMINE INBOX")
+            .await
+            .unwrap();
+        assert_eq!(result.status(), &imap_proto::Status::Ok);
+        assert_eq!(result.code(), Some(&imap_proto::ResponseCode::ReadOnly));
+        assert_eq!(result.text(), "Examine completed");
+    }
+
+    #[async_std::test]
+    async fn tagged_alert() {
+        let response = b"A0001 OK [ALERT] Mailbox almost full\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session.create("INBOX").await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Alert("Mailbox almost full".to_string())
+        );
+    }
+
+    #[async_std::test]
+    async fn unsolicited_responses_full() {
+        let mut response = Vec::new();
+        for n in 1..=150 {
+            response.extend_from_slice(format!("* {} EXISTS\r\n", n).as_bytes());
+        }
+        response.extend_from_slice(b"A0001 OK NOOP completed\r\n");
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session.noop().await.unwrap();
+
+        let received: Vec<_> = session
+            .unsolicited_responses_stream()
+            .take(100)
+            .collect()
+            .await;
+        assert_eq!(received.first(), Some(&UnsolicitedResponse::Exists(1)));
+        assert_eq!(received.last(), Some(&UnsolicitedResponse::Exists(100)));
+        assert!(session.unsolicited_responses.try_recv().is_err());
+        assert_eq!(session.dropped_unsolicited_count(), 50);
+    }
+
+    #[async_std::test]
+    async fn unsolicited_responses_closed() {
+        let response = b"* 3 EXISTS\r\n\
+            A0001 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.unsolicited_responses.close();
+        session.noop().await.unwrap();
+        assert_eq!(session.dropped_unsolicited_count(), 1);
+    }
+
+    #[async_std::test]
+    async fn create_unrelated_tagged_response() {
+        let response = b"A0007 NO Stray completion\r\n\
+                         A0001 OK CREATE completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        session.create("INBOX").await.unwrap();
+        match session.unsolicited_responses.try_recv() {
+            Ok(UnsolicitedResponse::Other(res)) => {
+                assert_eq!(res.request_id(), Some(&RequestId("A0007".into())))
+            }
+            other => panic!("unexpected unsolicited response: {:?}", other),
+        }
+    }
+
+    #[async_std::test]
+    async fn fetch_unrelated_tagged_response() {
+        let response = b"* 1 FETCH (UID 7)\r\n\
+                         A0007 NO Stray completion\r\n\
+                         * 2 FETCH (UID 8)\r\n\
+                         A0001 OK FETCH completed\r\n"
+            .to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        let fetches: Vec<_> = session
+            .fetch("1:2", "UID")
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        let uids: Vec<_> = fetches.into_iter().map(|f| f.unwrap().uid).collect();
+        assert_eq!(uids, vec![Some(Uid(7)), Some(Uid(8))]);
+    }
+
     #[async_std::test]
     async fn delete() {
         let response = b"A0001 OK DELETE completed\r\n".to_vec();
@@ -1835,6 +7293,106 @@ mod tests {
         );
     }
 
+    #[async_std::test]
+    async fn reset() {
+        let response = b"* 1 FETCH (UID 1)\r\n\
+            * 2 FETCH (UID 2)\r\n\
+            * 3 EXISTS\r\n\
+            * 3 FETCH (UID 3)\r\n\
+            A0001 OK FETCH completed\r\n\
+            A0002 OK NOOP completed\r\n\
+            * 1 FETCH (UID 1)\r\n\
+            A0003 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        {
+            let mut fetches = session.fetch("1:3", "UID").await.unwrap();
+            let first = fetches.next().await.unwrap().unwrap();
+            assert_eq!(first.uid, Some(Uid(1)));
+        }
+        session.reset().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(3)
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+
+        let fetches = session.fetch_collect("1", "UID").await.unwrap();
+        assert_eq!(fetches.len(), 1);
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 1:3 UID\r\nA0002 NOOP\r\nA0003 FETCH 1 UID\r\n",
+            "Invalid reset command"
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_order() {
+        let response = b"* 3 FETCH (UID 30)\r\n\
+            * 1 FETCH (UID 10)\r\n\
+            * 2 FETCH (UID 20)\r\n\
+            * 1 FETCH (FLAGS (\\Seen))\r\n\
+            A0001 OK FETCH completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let fetches = session.fetch_collect("1:*", "UID").await.unwrap();
+        let order: Vec<_> = fetches
+            .iter()
+            .map(|fetch| (fetch.message, fetch.uid))
+            .collect();
+        assert_eq!(
+            order,
+            [
+                (Seq(3), Some(Uid(30))),
+                (Seq(1), Some(Uid(10))),
+                (Seq(2), Some(Uid(20))),
+                (Seq(1), None),
+            ]
+        );
+    }
+
+    #[async_std::test]
+    async fn fetch_dropped_then_noop() {
+        let response = b"* 1 FETCH (UID 1)\r\n\
+            * 2 FETCH (UID 2)\r\n\
+            * 4 EXISTS\r\n\
+            * 3 FETCH (UID 3)\r\n\
+            A0001 OK FETCH completed\r\n\
+            A0002 OK NOOP completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        {
+            let mut fetches = session.fetch("1:3", "UID").await.unwrap();
+            let first = fetches.next().await.unwrap().unwrap();
+            assert_eq!(first.uid, Some(Uid(1)));
+        }
+        session.noop().await.unwrap();
+        assert_eq!(
+            session.unsolicited_responses.try_recv().unwrap(),
+            UnsolicitedResponse::Exists(4)
+        );
+        assert!(session.unsolicited_responses.try_recv().is_err());
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 FETCH 1:3 UID\r\nA0002 NOOP\r\n",
+            "Invalid noop command"
+        );
+    }
+
+    #[async_std::test]
+    async fn parse_error_data() {
+        let response = b"* 1 FETCH (@)\r\nA0001 OK NOOP completed\r\n".to_vec();
+        let mock_stream = MockStream::new(response);
+        let mut session = mock_session!(mock_stream);
+        match session.noop().await {
+            Err(Error::Parse(ParseError::Response { data, position })) => {
+                assert_eq!(data, b"* 1 FETCH (@)\r\n");
+                assert!(position < data.len());
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
     #[async_std::test]
     async fn close() {
         let response = b"A0001 OK CLOSE completed\r\n".to_vec();
@@ -1885,22 +7443,46 @@ mod tests {
                    * 4 FETCH (FLAGS (\\Deleted \\Flagged \\Seen))\r\n\
                    A0001 OK STORE completed\r\n";
 
-        generic_with_uid(res, "STORE", "2.4", "+FLAGS (\\Deleted)", prefix, op).await;
+        generic_with_uid(res, "STORE", "2:4", "+FLAGS (\\Deleted)", prefix, op).await;
     }
 
     #[async_std::test]
     async fn copy() {
-        generic_copy(" ", |c, set, query| async move {
-            c.lock().await.copy(set, query).await?;
+        generic_copy(" ", |c, set, _| async move {
+            c.lock().await.copy(set, "MEETING").await?;
             Ok(())
         })
         .await;
     }
 
+    #[async_std::test]
+    async fn invalid_sequence_set() {
+        let mut session = mock_session!(MockStream::new(Vec::new()));
+        let err = session.copy("0:4", "MEETING").await.err().unwrap();
+        assert!(matches!(err, Error::InvalidSequenceSet(_)), "{:?}", err);
+        let err = session
+            .uid_store(SequenceSet::new().range(Uid(9), Uid(3)), "+FLAGS (\\Seen)")
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, Error::InvalidSequenceSet(_)), "{:?}", err);
+        let err = session.fetch("1 BODY[]", "UID").await.err().unwrap();
+        assert!(matches!(err, Error::InvalidSequenceSet(_)), "{:?}", err);
+        assert!(session.stream.inner.written_buf.is_empty());
+
+        let set = SequenceSet::new().range(Seq(5), Seq(9)).single(Seq(2));
+        let mut session = mock_session!(MockStream::new(b"A0001 OK COPY completed\r\n".to_vec()));
+        session.copy(set, "MEETING").await.unwrap();
+        assert_eq!(
+            session.stream.inner.written_buf,
+            b"A0001 COPY 2,5:9 \"MEETING\"\r\n"
+        );
+    }
+
     #[async_std::test]
     async fn uid_copy() {
-        generic_copy(" UID ", |c, set, query| async move {
-            c.lock().await.uid_copy(set, query).await?;
+        generic_copy(" UID ", |c, set, _| async move {
+            c.lock().await.uid_copy(set, "MEETING").await?;
             Ok(())
         })
         .await;
@@ -1915,13 +7497,100 @@ mod tests {
             "OK COPY completed\r\n",
             "COPY",
             "2:4",
-            "MEETING",
+            "\"MEETING\"",
             prefix,
             op,
         )
         .await;
     }
 
+    #[async_std::test]
+    async fn copy_mailbox_name() {
+        let response = b"A0001 OK COPY completed\r\n\
+            A0002 OK COPY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session
+            .copy("1:2", "Gesendete Objekte/Entwürfe")
+            .await
+            .unwrap();
+        session
+            .uid_copy("41:42", "Gesendete Objekte/Entwürfe")
+            .await
+            .unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 COPY 1:2 \"Gesendete Objekte/Entw&APw-rfe\"\r\n\
+              A0002 UID COPY 41:42 \"Gesendete Objekte/Entw&APw-rfe\"\r\n",
+            "Invalid copy commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn mailbox_name_injection() {
+        // Without UTF-7, a line break in a mailbox name can not be sent.
+        let name = "x\"\r\nA1 DELETE INBOX";
+        let mut session = mock_session!(MockStream::new(Vec::new()));
+        session.utf8_enabled = true;
+        let results = [
+            session.append(name, b"hello").await.err(),
+            session.rename("INBOX", name).await.err(),
+            session.subscribe(name).await.err(),
+            session.unsubscribe(name).await.err(),
+            session.list(Some(name), None).await.err(),
+            session.list(None, Some(name)).await.err(),
+            session.lsub(None, Some(name)).await.err(),
+        ];
+        for err in results {
+            assert!(matches!(err, Some(Error::Validate(_))), "{:?}", err);
+        }
+        assert!(session.stream.inner.written_buf.is_empty());
+
+        let response = b"A0001 OK SUBSCRIBE completed\r\n\
+            + Ready for literal data\r\n\
+            A0002 OK APPEND completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        session.subscribe(name).await.unwrap();
+        session.append("a\"b", b"hello").await.unwrap();
+        assert_eq_bytes!(
+            &session.stream.inner.written_buf,
+            b"A0001 SUBSCRIBE \"x\\\"&AA0ACg-A1 DELETE INBOX\"\r\n\
+              A0002 APPEND \"a\\\"b\" {5}\r\nhello\r\n",
+            "Invalid commands"
+        );
+    }
+
+    #[async_std::test]
+    async fn copy_uid() {
+        let response = b"A0001 OK [COPYUID 38505 304,319:320 3956:3958] Done\r\n\
+            A0002 OK COPY completed\r\n"
+            .to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        let copy_uid = session
+            .uid_copy("304,319:320", "meeting")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(copy_uid.uid_validity, 38505);
+        assert_eq!(
+            copy_uid.uids,
+            vec![
+                (Uid(304), Uid(3956)),
+                (Uid(319), Uid(3957)),
+                (Uid(320), Uid(3958))
+            ]
+        );
+        assert_eq!(session.copy("1", "meeting").await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn copy_uid_too_large() {
+        let response = b"A0001 OK [COPYUID 38505 1:4294967295 1:4294967295] Done\r\n".to_vec();
+        let mut session = mock_session!(MockStream::new(response));
+        assert_eq!(session.uid_copy("1:*", "meeting").await.unwrap(), None);
+    }
+
     #[async_std::test]
     async fn mv() {
         let response = b"* OK [COPYUID 1511554416 142,399 41:42] Moved UIDs.\r\n\
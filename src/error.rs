@@ -6,6 +6,8 @@ use std::str::Utf8Error;
 
 use base64::DecodeError;
 
+use crate::types::ResponseCode;
+
 /// A convenience wrapper around `Result` for `imap::Error`.
 pub type Result<T> = result::Result<T, Error>;
 
@@ -15,14 +17,29 @@ pub type Result<T> = result::Result<T, Error>;
 pub enum Error {
     /// An `io::Error` that occurred while trying to read or write to a network stream.
     #[error("io: {0}")]
-    Io(#[from] IoError),
-    /// A BAD response from the IMAP server.
-    #[error("bad response: {0}")]
-    Bad(String),
-    /// A NO response from the IMAP server.
-    #[error("no response: {0}")]
-    No(String),
-    /// The connection was terminated unexpectedly.
+    Io(#[source] IoError),
+    /// A BAD response from the IMAP server, meaning that it did not accept the command as valid,
+    /// which usually points to a bug in the client or the server.  Retrying the same command is
+    /// unlikely to help.
+    #[error("bad response: {}", with_code(.code, .text))]
+    Bad {
+        /// The response code, such as [`ResponseCode::ClientBug`], if the server sent one.
+        code: Option<ResponseCode>,
+        /// The human-readable text of the response.
+        text: String,
+    },
+    /// A NO response from the IMAP server, meaning that the command was valid but failed, e.g.
+    /// because the credentials are wrong or the mailbox does not exist.
+    #[error("no response: {}", with_code(.code, .text))]
+    No {
+        /// The response code, such as [`ResponseCode::AuthenticationFailed`] or
+        /// [`ResponseCode::TryCreate`], if the server sent one.
+        code: Option<ResponseCode>,
+        /// The human-readable text of the response.
+        text: String,
+    },
+    /// The connection was terminated unexpectedly, as the server closed it while a command was
+    /// still in progress.
     #[error("connection lost")]
     ConnectionLost,
     /// Error parsing a server response.
@@ -32,14 +49,189 @@ pub enum Error {
     /// strings](https://tools.ietf.org/html/rfc3501#section-4.3).
     #[error("validate: {0}")]
     Validate(#[from] ValidateError),
+    /// A command argument that has to be an atom, such as an authentication mechanism, is
+    /// empty.
+    #[error("empty argument")]
+    EmptyArgument,
     /// `async_native_tls` error
     #[error("async_native_tls: {0}")]
     NativeTlsError(#[from] async_native_tls::Error),
+    /// A flag is not a system flag or a valid keyword, see
+    /// [`Flag::from_str`](crate::types::Flag::from_str).
+    #[error("invalid flag: {0:?}")]
+    InvalidFlag(String),
+    /// A [`SequenceSet`](crate::types::SequenceSet) passed to a command is empty, contains
+    /// `0` or is not a valid sequence set.
+    #[error("invalid sequence set: {0}")]
+    InvalidSequenceSet(String),
     /// Error appending an e-mail.
     #[error("could not append mail to mailbox")]
     Append,
+    /// The server could not decode the content transfer encoding of a message part, see
+    /// [RFC 3516](https://tools.ietf.org/html/rfc3516#section-4.4).
+    #[error("unknown content-transfer-encoding: {0}")]
+    UnknownCte(String),
+    /// The server does not advertise a capability required by the command.
+    #[error("server does not support {0}")]
+    MissingCapability(String),
+    /// The user lacks the access rights needed for the command (`NOPERM`, see
+    /// [RFC 4314 section 4](https://tools.ietf.org/html/rfc4314#section-4)).
+    #[error("permission denied: {0}")]
+    NoPerm(String),
+    /// The server can not create a mailbox with the requested special use (`USEATTR`, see
+    /// [RFC 6154 section 3](https://tools.ietf.org/html/rfc6154#section-3)), for example because
+    /// it does not support the use or allows only one mailbox with it.
+    #[error("special use not possible: {0}")]
+    UseAttr(String),
+    /// The client side of a SASL authentication exchange failed, for example because the server
+    /// could not prove that it knows the password.
+    #[error("sasl: {0}")]
+    Sasl(String),
+    /// The server advertises `LOGINDISABLED`, so the credentials were not sent with `LOGIN`.
+    /// The connection has to be secured with [`Client::starttls`](crate::Client::starttls)
+    /// first, or a mechanism offered for [`Client::authenticate`](crate::Client::authenticate)
+    /// used instead.
+    #[error("the server advertises LOGINDISABLED, use STARTTLS or AUTHENTICATE instead")]
+    LoginDisabled,
+    /// A proxy refused to open a tunnel to the server, see
+    /// [`connect_via_http_proxy`](crate::connect_via_http_proxy) and
+    /// [`connect_via_socks5`](crate::connect_via_socks5).
+    #[error("proxy: {0}")]
+    Proxy(String),
+    /// The server did not send any data within the duration set with
+    /// [`Session::set_timeout`](crate::Session::set_timeout).
+    #[error("timed out waiting for the server")]
+    Timeout,
+    /// The server sent an untagged `BYE` response with the given reason, e.g. because it is
+    /// shutting down or the connection was idle for too long, and is closing the connection.
+    /// All further commands on the session fail with this error.
+    #[error("server closed the connection: {0}")]
+    Bye(String),
+}
+
+impl Error {
+    /// Whether the error means that the connection to the server is unusable, as opposed to the
+    /// server rejecting a command or sending a response that could not be understood.
+    ///
+    /// After such an error the session has to be dropped, or
+    /// [reconnected](crate::ReconnectingSession::reconnect). A [timeout](Error::Timeout) is not
+    /// included, as the session can still be used after it; whether the connection is still
+    /// alive can be checked with [`Session::noop`](crate::Session::noop).
+    pub fn is_connection_lost(&self) -> bool {
+        matches!(
+            self,
+            Error::Io(_) | Error::ConnectionLost | Error::NativeTlsError(_) | Error::Bye(_)
+        )
+    }
+
+    /// Replaces each of `secrets` in the text of the error by `***`, so that credentials echoed
+    /// by the server in response to `LOGIN` or `AUTHENTICATE` do not end up in logs.
+    pub(crate) fn redact<S: AsRef<str>>(self, secrets: &[S]) -> Error {
+        let text = |text: String| redact_text(text, secrets);
+        match self {
+            Error::Bad { code, text: msg } => Error::Bad {
+                code,
+                text: text(msg),
+            },
+            Error::No { code, text: msg } => Error::No {
+                code,
+                text: text(msg),
+            },
+            Error::NoPerm(msg) => Error::NoPerm(text(msg)),
+            Error::UseAttr(msg) => Error::UseAttr(text(msg)),
+            Error::UnknownCte(msg) => Error::UnknownCte(text(msg)),
+            Error::Sasl(msg) => Error::Sasl(text(msg)),
+            Error::Parse(err) => Error::Parse(match err {
+                ParseError::Invalid(data) => ParseError::Invalid(redact_bytes(data, secrets)),
+                ParseError::Unexpected(msg) => ParseError::Unexpected(text(msg)),
+                ParseError::Authentication(msg, err) => ParseError::Authentication(text(msg), err),
+                ParseError::DataNotUtf8(data, err) => {
+                    ParseError::DataNotUtf8(redact_bytes(data, secrets), err)
+                }
+                ParseError::Response { data, position } => {
+                    let data = redact_bytes(data, secrets);
+                    let position = position.min(data.len());
+                    ParseError::Response { data, position }
+                }
+                err => err,
+            }),
+            err => err,
+        }
+    }
+}
+
+/// Formats the text of a `NO` or `BAD` response, preceded by its response code.
+fn with_code(code: &Option<ResponseCode>, text: &str) -> String {
+    match code {
+        Some(code) => format!("[{}] {}", code, text),
+        None => text.to_string(),
+    }
+}
+
+/// Replaces each non-empty secret in `text` by `***`, both as is and escaped, as some errors
+/// include the server's text in escaped form.
+fn redact_text<S: AsRef<str>>(mut text: String, secrets: &[S]) -> String {
+    for secret in secrets.iter().map(AsRef::as_ref) {
+        if secret.is_empty() {
+            continue;
+        }
+        for secret in [secret.to_string(), secret.escape_debug().to_string()] {
+            if text.contains(&secret) {
+                text = text.replace(&secret, "***");
+            }
+        }
+    }
+    text
+}
+
+/// Replaces each non-empty secret in `data` by `***`.
+fn redact_bytes<S: AsRef<str>>(mut data: Vec<u8>, secrets: &[S]) -> Vec<u8> {
+    for secret in secrets.iter().map(|secret| secret.as_ref().as_bytes()) {
+        if secret.is_empty() {
+            continue;
+        }
+        let mut redacted = Vec::with_capacity(data.len());
+        let mut rest = &data[..];
+        while let Some(pos) = rest.windows(secret.len()).position(|w| w == secret) {
+            redacted.extend_from_slice(&rest[..pos]);
+            redacted.extend_from_slice(b"***");
+            rest = &rest[pos + secret.len()..];
+        }
+        redacted.extend_from_slice(rest);
+        data = redacted;
+    }
+    data
 }
 
+impl From<IoError> for Error {
+    fn from(err: IoError) -> Self {
+        if err.kind() == std::io::ErrorKind::TimedOut {
+            return Error::Timeout;
+        }
+        // The response stream reports the server closing the connection in the middle of a
+        // response, or before completing a command, as `UnexpectedEof`.
+        if err.kind() == std::io::ErrorKind::UnexpectedEof {
+            return Error::ConnectionLost;
+        }
+        // The response stream reports parse errors as `io::Error`s.
+        if err.get_ref().is_some_and(|inner| inner.is::<ParseError>()) {
+            let inner = err.into_inner().expect("checked above");
+            return Error::Parse(*inner.downcast().expect("checked above"));
+        }
+        if err.get_ref().is_some_and(|inner| inner.is::<ServerBye>()) {
+            let inner = err.into_inner().expect("checked above");
+            let bye: ServerBye = *inner.downcast().expect("checked above");
+            return Error::Bye(bye.0);
+        }
+        Error::Io(err)
+    }
+}
+
+/// How the response stream reports an untagged `BYE` response, which becomes [`Error::Bye`].
+#[derive(thiserror::Error, Debug)]
+#[error("server closed the connection: {0}")]
+pub(crate) struct ServerBye(pub(crate) String);
+
 /// An error occured while trying to parse a server response.
 #[derive(thiserror::Error, Debug)]
 pub enum ParseError {
@@ -55,6 +247,23 @@ pub enum ParseError {
     /// The client received data that was not UTF-8 encoded.
     #[error("unable to parse data ({0:?}) as UTF-8 text: {1:?}")]
     DataNotUtf8(Vec<u8>, #[source] Utf8Error),
+    /// A server response could not be parsed. `data` holds the bytes received from the server
+    /// from the start of that response to the end of the line at which the parser gave up, and
+    /// `position` the offset in `data` at which it did, which is often the start of the response.
+    #[error(
+        "unable to parse response at byte {position}: \"{}\"",
+        .data.escape_ascii()
+    )]
+    Response {
+        /// The bytes that could not be parsed.
+        data: Vec<u8>,
+        /// The offset in `data` at which parsing failed.
+        position: usize,
+    },
+    /// A mailbox name was not valid [modified
+    /// UTF-7](https://tools.ietf.org/html/rfc3501#section-5.1.3).
+    #[error("invalid modified UTF-7 in mailbox name: {0:?}")]
+    ModifiedUtf7(String),
 }
 
 /// An [invalid character](https://tools.ietf.org/html/rfc3501#section-4.3) was found in an input
@@ -73,4 +282,65 @@ mod tests {
     fn test_send() {
         is_send::<Result<usize>>(Ok(3));
     }
+
+    #[test]
+    fn redact() {
+        let err = Error::Bad {
+            code: None,
+            text: "Invalid arguments: \"me\" \"hunter2\"".into(),
+        }
+        .redact(&["hunter2"]);
+        assert_eq!(
+            err.to_string(),
+            "bad response: Invalid arguments: \"me\" \"***\""
+        );
+
+        let err = Error::Parse(ParseError::Response {
+            data: b"* XLOGIN hunter2 failed\r\n".to_vec(),
+            position: 20,
+        })
+        .redact(&["", "hunter2"]);
+        match err {
+            Error::Parse(ParseError::Response { data, position }) => {
+                assert_eq!(data, b"* XLOGIN *** failed\r\n");
+                assert_eq!(position, 20);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn connection_lost() {
+        let reset = IoError::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(Error::from(reset).is_connection_lost());
+        assert!(Error::ConnectionLost.is_connection_lost());
+        assert!(!Error::Timeout.is_connection_lost());
+        let no = Error::No {
+            code: Some(ResponseCode::NonExistent),
+            text: "no such mailbox".into(),
+        };
+        assert_eq!(no.to_string(), "no response: [NONEXISTENT] no such mailbox");
+        assert!(!no.is_connection_lost());
+        assert!(!Error::Parse(ParseError::Invalid(b"* FOO".to_vec())).is_connection_lost());
+    }
+
+    #[test]
+    fn parse_error_from_io() {
+        let err = IoError::new(
+            std::io::ErrorKind::InvalidData,
+            ParseError::Response {
+                data: b"* 1 FETCH (\xff)\r\n".to_vec(),
+                position: 11,
+            },
+        );
+        let err = Error::from(err);
+        assert!(matches!(
+            err,
+            Error::Parse(ParseError::Response { position: 11, .. })
+        ));
+        assert_eq!(
+            err.to_string(),
+            r#"parse: unable to parse response at byte 11: "* 1 FETCH (\xff)\r\n""#
+        );
+    }
 }
@@ -0,0 +1,882 @@
+//! Parsing of server response data that `imap-proto` does not understand.
+//!
+//! When `imap-proto` fails to parse a `FETCH` response, the response is scanned for data items
+//! of extensions we know about. Those items are cut out of the response and decoded here, and
+//! the remainder is handed back to `imap-proto`.
+//!
+//! `ESEARCH` responses ([RFC 4731](https://tools.ietf.org/html/rfc4731)) are turned into the
+//! equivalent `SEARCH` response, with return data other than `ALL` kept as extension items.
+//!
+//! `GENURLAUTH` and `URLFETCH` responses ([RFC 4467](https://tools.ietf.org/html/rfc4467)) and
+//! `THREAD` responses ([RFC 5256](https://tools.ietf.org/html/rfc5256)) are decoded into
+//! extension items attached to a placeholder `OK` response.
+//!
+//! Other responses that `imap-proto` rejects are checked for quoted strings with 8-bit
+//! characters, which servers send once `UTF8=ACCEPT` is enabled
+//! ([RFC 6855](https://tools.ietf.org/html/rfc6855)). Those strings are rewritten as literals,
+//! which `imap-proto` accepts wherever a string is allowed.
+
+use std::convert::TryFrom;
+
+use crate::types::ThreadNode;
+
+/// A `FETCH` data item, `ESEARCH` return data item or the data of another response that is
+/// parsed by this crate instead of `imap-proto`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum ExtensionAttribute {
+    /// `BINARY[<section>]`, see [RFC 3516](https://tools.ietf.org/html/rfc3516#section-4.2).
+    Binary {
+        section: Vec<u32>,
+        data: Option<Vec<u8>>,
+    },
+    /// `BINARY.SIZE[<section>]`, see [RFC 3516](https://tools.ietf.org/html/rfc3516#section-4.2).
+    BinarySize { section: Vec<u32>, size: u32 },
+    /// `RELEVANCY`, the scores of the messages matching a fuzzy search, in the order of the
+    /// `ALL` result, see [RFC 6203](https://tools.ietf.org/html/rfc6203#section-4).
+    Relevancy(Vec<u8>),
+    /// The authorized URLs of a `GENURLAUTH` response, see
+    /// [RFC 4467 section 7.1](https://tools.ietf.org/html/rfc4467#section-7.1).
+    GenUrlAuth(Vec<String>),
+    /// The URLs and their data of a `URLFETCH` response, see
+    /// [RFC 4467 section 7.2](https://tools.ietf.org/html/rfc4467#section-7.2).
+    UrlFetch(Vec<(String, Option<Vec<u8>>)>),
+    /// The threads of a `THREAD` response, see
+    /// [RFC 5256 section 4](https://tools.ietf.org/html/rfc5256#section-4).
+    Thread(Vec<ThreadNode<u32>>),
+}
+
+/// A `FETCH` response split into the part `imap-proto` understands and the extension items.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct SplitFetch {
+    /// Number of input bytes making up the response, including the trailing CRLF.
+    pub(crate) consumed: usize,
+    /// The message sequence number of the response.
+    pub(crate) message: u32,
+    /// The response with all extension items removed, or `None` if no other items are left.
+    pub(crate) rest: Option<Vec<u8>>,
+    /// The extension items that were removed from the response.
+    pub(crate) extensions: Vec<ExtensionAttribute>,
+}
+
+/// Why scanning stopped before a complete response was found.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Stop {
+    /// More data is needed.
+    Incomplete,
+    /// The input is not a `FETCH` response, or does not contain any extension items.
+    NoMatch,
+}
+
+type Step<T> = std::result::Result<T, Stop>;
+
+/// Splits the extension items off an untagged `FETCH` response at the start of `input`.
+pub(crate) fn split_fetch(input: &[u8]) -> Step<SplitFetch> {
+    let mut cursor = Cursor { input, pos: 0 };
+    cursor.tag(b"* ")?;
+    let message = cursor.number()?;
+    cursor.tag(b" FETCH (")?;
+    let prefix_end = cursor.pos;
+
+    let mut kept: Vec<&[u8]> = Vec::new();
+    let mut extensions = Vec::new();
+    loop {
+        let start = cursor.pos;
+        let name = cursor.item_name()?;
+        cursor.tag(b" ")?;
+        match extension_item(&mut cursor, name)? {
+            Some(extension) => extensions.push(extension),
+            None => {
+                cursor.skip_value()?;
+                kept.push(&input[start..cursor.pos]);
+            }
+        }
+        match cursor.next()? {
+            b' ' => continue,
+            b')' => break,
+            _ => return Err(Stop::NoMatch),
+        }
+    }
+    cursor.tag(b"\r\n")?;
+
+    if extensions.is_empty() {
+        return Err(Stop::NoMatch);
+    }
+    let rest = if kept.is_empty() {
+        None
+    } else {
+        let mut rest = input[..prefix_end].to_vec();
+        rest.extend_from_slice(&kept.join(&b' '));
+        rest.extend_from_slice(b")\r\n");
+        Some(rest)
+    };
+
+    Ok(SplitFetch {
+        consumed: cursor.pos,
+        message: u32::try_from(message).map_err(|_| Stop::NoMatch)?,
+        rest,
+        extensions,
+    })
+}
+
+/// An `ESEARCH` response rewritten as a `SEARCH` response.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ESearch {
+    /// Number of input bytes making up the response, including the trailing CRLF.
+    pub(crate) consumed: usize,
+    /// The `SEARCH` response listing the messages of the `ALL` result.
+    pub(crate) search: Vec<u8>,
+    /// The return data items other than `ALL` that are known to this crate.
+    pub(crate) extensions: Vec<ExtensionAttribute>,
+}
+
+/// Rewrites the `ESEARCH` response at the start of `input` as a `SEARCH` response.
+///
+/// The search correlator and the `UID` indicator are dropped, as the response is read as part of
+/// the command it belongs to.  Unknown return data, such as `MIN` or `COUNT`, is skipped.
+pub(crate) fn esearch(input: &[u8]) -> Step<ESearch> {
+    let mut cursor = Cursor { input, pos: 0 };
+    cursor.tag(b"* ESEARCH")?;
+    let mut search = b"* SEARCH".to_vec();
+    let mut extensions = Vec::new();
+    loop {
+        match cursor.next()? {
+            b' ' => {}
+            b'\r' => {
+                cursor.tag(b"\n")?;
+                break;
+            }
+            _ => return Err(Stop::NoMatch),
+        }
+        if cursor.peek()? == b'(' {
+            cursor.skip_value()?;
+            continue;
+        }
+        let name = cursor.atom()?;
+        if name.eq_ignore_ascii_case(b"UID") {
+            continue;
+        }
+        cursor.tag(b" ")?;
+        if name.eq_ignore_ascii_case(b"ALL") {
+            let set = std::str::from_utf8(cursor.atom()?).map_err(|_| Stop::NoMatch)?;
+            for id in expand_sequence_set(set).ok_or(Stop::NoMatch)? {
+                search.extend_from_slice(format!(" {}", id).as_bytes());
+            }
+        } else if name.eq_ignore_ascii_case(b"RELEVANCY") {
+            cursor.tag(b"(")?;
+            let mut scores = Vec::new();
+            while cursor.peek()? != b')' {
+                if !scores.is_empty() {
+                    cursor.tag(b" ")?;
+                }
+                scores.push(u8::try_from(cursor.number()?).map_err(|_| Stop::NoMatch)?);
+            }
+            cursor.pos += 1;
+            extensions.push(ExtensionAttribute::Relevancy(scores));
+        } else if cursor.peek()? == b'(' {
+            cursor.skip_value()?;
+        } else {
+            cursor.atom()?;
+        }
+    }
+    search.extend_from_slice(b"\r\n");
+
+    Ok(ESearch {
+        consumed: cursor.pos,
+        search,
+        extensions,
+    })
+}
+
+/// A `GENURLAUTH`, `URLFETCH` or `THREAD` response, decoded into an extension item.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Placeholder {
+    /// Number of input bytes making up the response, including the trailing CRLF.
+    pub(crate) consumed: usize,
+    /// The placeholder response handed to `imap-proto` instead.
+    pub(crate) placeholder: &'static [u8],
+    /// The data of the response.
+    pub(crate) extension: ExtensionAttribute,
+}
+
+/// Decodes the `GENURLAUTH`, `URLFETCH` or `THREAD` response at the start of `input`.
+pub(crate) fn placeholder(input: &[u8]) -> Step<Placeholder> {
+    let mut cursor = Cursor { input, pos: 0 };
+    cursor.tag(b"* ")?;
+    let name = cursor.atom()?;
+    let (placeholder, extension): (&[u8], _) = if name.eq_ignore_ascii_case(b"GENURLAUTH") {
+        let mut urls = Vec::new();
+        while cursor.peek()? == b' ' {
+            cursor.pos += 1;
+            urls.push(cursor.astring()?);
+        }
+        (b"* OK GENURLAUTH\r\n", ExtensionAttribute::GenUrlAuth(urls))
+    } else if name.eq_ignore_ascii_case(b"URLFETCH") {
+        let mut items = Vec::new();
+        while cursor.peek()? == b' ' {
+            cursor.pos += 1;
+            let url = cursor.astring()?;
+            cursor.tag(b" ")?;
+            items.push((url, cursor.nstring()?));
+        }
+        (b"* OK URLFETCH\r\n", ExtensionAttribute::UrlFetch(items))
+    } else if name.eq_ignore_ascii_case(b"THREAD") {
+        let mut threads = Vec::new();
+        if cursor.peek()? == b' ' {
+            cursor.pos += 1;
+            threads = cursor.threads()?;
+        }
+        (b"* OK THREAD\r\n", ExtensionAttribute::Thread(threads))
+    } else {
+        return Err(Stop::NoMatch);
+    };
+    cursor.tag(b"\r\n")?;
+
+    Ok(Placeholder {
+        consumed: cursor.pos,
+        placeholder,
+        extension,
+    })
+}
+
+/// The most numbers a sequence set sent by the server is expanded to, so that a set such as
+/// `1:4294967295` can not exhaust the memory.  This is more than the number of messages in the
+/// largest mailboxes.
+pub(crate) const MAX_EXPANDED_IDS: usize = 10_000_000;
+
+/// How deep the lists of a `THREAD` response may be nested.  Reply chains do not add to the
+/// nesting, only branches do, e.g. `(1 (2 (3)(4)) (5))` is nested three deep.
+pub(crate) const MAX_THREAD_NESTING: usize = 1000;
+
+/// Lists the numbers in a sequence set without `*`, such as `1:3,5`, in the order given.
+/// Returns `None` if the set is not valid or has more than [`MAX_EXPANDED_IDS`] numbers.
+pub(crate) fn expand_sequence_set(set: &str) -> Option<Vec<u32>> {
+    let mut ids = Vec::new();
+    for member in set.split(',') {
+        let (first, last): (u32, u32) = match member.split_once(':') {
+            Some((first, last)) => (first.parse().ok()?, last.parse().ok()?),
+            None => (member.parse().ok()?, member.parse().ok()?),
+        };
+        let (first, last) = (first.min(last), first.max(last));
+        if (last - first) as usize >= MAX_EXPANDED_IDS - ids.len() {
+            return None;
+        }
+        ids.extend(first..=last);
+    }
+    Some(ids)
+}
+
+/// A response in which quoted strings with 8-bit characters were rewritten as literals.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Rewritten {
+    /// Number of input bytes making up the response, including the trailing CRLF.
+    pub(crate) consumed: usize,
+    /// The rewritten response.
+    pub(crate) data: Vec<u8>,
+}
+
+/// Rewrites the quoted strings with 8-bit characters in the response at the start of `input` as
+/// literals.
+pub(crate) fn quoted_to_literals(input: &[u8]) -> Step<Rewritten> {
+    let mut cursor = Cursor { input, pos: 0 };
+    let mut data = Vec::with_capacity(input.len());
+    let mut rewritten = false;
+    loop {
+        let start = cursor.pos;
+        match cursor.peek()? {
+            b'"' => {
+                let value = cursor.quoted()?;
+                if value.is_ascii() {
+                    data.extend_from_slice(&input[start..cursor.pos]);
+                } else {
+                    data.extend_from_slice(format!("{{{}}}\r\n", value.len()).as_bytes());
+                    data.extend_from_slice(&value);
+                    rewritten = true;
+                }
+            }
+            b'{' | b'~' => match cursor.literal() {
+                Ok(_) => data.extend_from_slice(&input[start..cursor.pos]),
+                Err(Stop::Incomplete) => return Err(Stop::Incomplete),
+                // Not a literal, but e.g. part of the text of a status response.
+                Err(Stop::NoMatch) => {
+                    cursor.pos = start + 1;
+                    data.push(input[start]);
+                }
+            },
+            b'\r' if cursor.input.get(start + 1) == Some(&b'\n') => {
+                cursor.pos += 2;
+                data.extend_from_slice(b"\r\n");
+                break;
+            }
+            byte => {
+                cursor.pos += 1;
+                data.push(byte);
+            }
+        }
+    }
+
+    if !rewritten {
+        return Err(Stop::NoMatch);
+    }
+    Ok(Rewritten {
+        consumed: cursor.pos,
+        data,
+    })
+}
+
+/// If `input` starts with a complete line of a `FETCH` response for message `seq` that ends with
+/// the announcement of the literal holding `BODY[<section>]`, returns the length of that line
+/// (including the CRLF) and of the literal.
+pub(crate) fn fetch_literal_start(input: &[u8], seq: u32, section: &str) -> Step<(usize, usize)> {
+    let mut cursor = Cursor { input, pos: 0 };
+    cursor.tag(b"* ")?;
+    if cursor.number()? != u64::from(seq) {
+        return Err(Stop::NoMatch);
+    }
+    cursor.tag(b" FETCH (")?;
+    let line_end = input[cursor.pos..]
+        .windows(2)
+        .position(|window| window == b"\r\n")
+        .ok_or(Stop::Incomplete)?
+        + cursor.pos;
+    let line = &input[..line_end];
+    let open = line.iter().rposition(|b| *b == b'{').ok_or(Stop::NoMatch)?;
+    let item = format!("BODY[{}] ", section);
+    let is_item = line[..open]
+        .len()
+        .checked_sub(item.len())
+        .is_some_and(|start| line[start..open].eq_ignore_ascii_case(item.as_bytes()));
+    if !is_item {
+        return Err(Stop::NoMatch);
+    }
+    let len = line[open + 1..]
+        .strip_suffix(b"}")
+        .and_then(|digits| std::str::from_utf8(digits).ok())
+        .and_then(|digits| digits.parse().ok())
+        .ok_or(Stop::NoMatch)?;
+    Ok((line_end + 2, len))
+}
+
+/// Parses the value of the item called `name` if it is an extension item.
+fn extension_item(cursor: &mut Cursor<'_>, name: &[u8]) -> Step<Option<ExtensionAttribute>> {
+    if let Some(section) = strip_prefix_ignore_case(name, b"BINARY.SIZE[") {
+        let section = parse_section(section)?;
+        let size = u32::try_from(cursor.number()?).map_err(|_| Stop::NoMatch)?;
+        Ok(Some(ExtensionAttribute::BinarySize { section, size }))
+    } else if let Some(section) = strip_prefix_ignore_case(name, b"BINARY[") {
+        let section = parse_section(section)?;
+        let data = cursor.nstring()?;
+        Ok(Some(ExtensionAttribute::Binary { section, data }))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Parses `1.2.3]` (optionally followed by a partial range) into its part numbers.
+fn parse_section(section: &[u8]) -> Step<Vec<u32>> {
+    let end = section
+        .iter()
+        .position(|b| *b == b']')
+        .ok_or(Stop::NoMatch)?;
+    let section = &section[..end];
+    if section.is_empty() {
+        return Ok(Vec::new());
+    }
+    section
+        .split(|b| *b == b'.')
+        .map(|part| {
+            std::str::from_utf8(part)
+                .ok()
+                .and_then(|part| part.parse().ok())
+                .ok_or(Stop::NoMatch)
+        })
+        .collect()
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    if value.len() >= prefix.len() && value[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        Some(&value[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+struct Cursor<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Step<u8> {
+        self.input.get(self.pos).copied().ok_or(Stop::Incomplete)
+    }
+
+    fn next(&mut self) -> Step<u8> {
+        let byte = self.peek()?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Consumes `expected`, compared case-insensitively.
+    fn tag(&mut self, expected: &[u8]) -> Step<()> {
+        let available = &self.input[self.pos..];
+        let len = expected.len().min(available.len());
+        if !available[..len].eq_ignore_ascii_case(&expected[..len]) {
+            return Err(Stop::NoMatch);
+        }
+        if len < expected.len() {
+            return Err(Stop::Incomplete);
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn number(&mut self) -> Step<u64> {
+        let start = self.pos;
+        while self.peek()?.is_ascii_digit() {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos])
+            .ok()
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(Stop::NoMatch)
+    }
+
+    /// Consumes an atom or number, up to the next space, parenthesis or line break.
+    fn atom(&mut self) -> Step<&'a [u8]> {
+        let start = self.pos;
+        while !matches!(self.peek()?, b' ' | b'(' | b')' | b'\r' | b'\n' | b'"') {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return Err(Stop::NoMatch);
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    /// Consumes a data item name, such as `UID` or `BODY[HEADER.FIELDS (DATE)]<0>`.
+    fn item_name(&mut self) -> Step<&'a [u8]> {
+        let start = self.pos;
+        loop {
+            match self.peek()? {
+                b' ' | b')' if self.pos > start => break,
+                b'[' => while self.next()? != b']' {},
+                b'\r' | b'\n' | b'(' | b')' | b' ' | b'"' | b'{' => return Err(Stop::NoMatch),
+                _ => self.pos += 1,
+            }
+        }
+        Ok(&self.input[start..self.pos])
+    }
+
+    /// Consumes an atom, a quoted string or a literal holding UTF-8 text.
+    fn astring(&mut self) -> Step<String> {
+        let value = match self.peek()? {
+            b'"' => self.quoted()?,
+            b'{' => self.literal()?.to_vec(),
+            _ => self.atom()?.to_vec(),
+        };
+        String::from_utf8(value).map_err(|_| Stop::NoMatch)
+    }
+
+    /// Consumes a `NIL`, a quoted string or a (binary) literal.
+    fn nstring(&mut self) -> Step<Option<Vec<u8>>> {
+        match self.peek()? {
+            b'"' => self.quoted().map(Some),
+            b'{' | b'~' => self.literal().map(|data| Some(data.to_vec())),
+            _ => {
+                self.tag(b"NIL")?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Step<Vec<u8>> {
+        self.tag(b"\"")?;
+        let mut value = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => return Ok(value),
+                b'\\' => value.push(self.next()?),
+                b'\r' | b'\n' => return Err(Stop::NoMatch),
+                byte => value.push(byte),
+            }
+        }
+    }
+
+    /// Consumes a `{len}` literal or a `~{len}` binary literal.
+    fn literal(&mut self) -> Step<&'a [u8]> {
+        if self.peek()? == b'~' {
+            self.pos += 1;
+        }
+        self.tag(b"{")?;
+        let len = usize::try_from(self.number()?).map_err(|_| Stop::NoMatch)?;
+        self.tag(b"}\r\n")?;
+        let end = self.pos.checked_add(len).ok_or(Stop::NoMatch)?;
+        if end > self.input.len() {
+            return Err(Stop::Incomplete);
+        }
+        let data = &self.input[self.pos..end];
+        self.pos = end;
+        Ok(data)
+    }
+
+    /// Consumes consecutive `thread-list`s, which some servers separate by spaces.
+    fn threads(&mut self) -> Step<Vec<ThreadNode<u32>>> {
+        let mut threads = Vec::new();
+        loop {
+            match self.peek()? {
+                b'(' => threads.push(self.thread()?),
+                b' ' => self.pos += 1,
+                _ => return Ok(threads),
+            }
+        }
+    }
+
+    /// Consumes a parenthesized `thread-list`, such as `(3 6 (4 23)(44 7 96))`.
+    ///
+    /// The nested lists are kept on a stack instead of being parsed recursively, and a response
+    /// nesting them more than [`MAX_THREAD_NESTING`] deep is rejected.
+    fn thread(&mut self) -> Step<ThreadNode<u32>> {
+        /// A `thread-list` whose closing parenthesis was not read yet.
+        #[derive(Default)]
+        struct List {
+            ids: Vec<u32>,
+            children: Vec<ThreadNode<u32>>,
+        }
+
+        self.tag(b"(")?;
+        let mut lists = vec![List::default()];
+        loop {
+            match self.peek()? {
+                b'(' => {
+                    if lists.len() >= MAX_THREAD_NESTING {
+                        return Err(Stop::NoMatch);
+                    }
+                    self.pos += 1;
+                    lists.push(List::default());
+                }
+                // Servers may separate the nested lists by spaces.
+                b' ' => self.pos += 1,
+                b')' => {
+                    self.pos += 1;
+                    let List { ids, children } = lists.pop().ok_or(Stop::NoMatch)?;
+                    if ids.is_empty() && children.is_empty() {
+                        return Err(Stop::NoMatch);
+                    }
+                    // Each message but the last has the next one as its only reply, and the
+                    // nested lists are the replies to the last one, or siblings without a
+                    // common parent if there are no messages.
+                    let mut ids = ids.into_iter().rev();
+                    let mut node = ThreadNode {
+                        id: ids.next(),
+                        children,
+                    };
+                    for id in ids {
+                        node = ThreadNode {
+                            id: Some(id),
+                            children: vec![node],
+                        };
+                    }
+                    match lists.last_mut() {
+                        Some(parent) => parent.children.push(node),
+                        None => return Ok(node),
+                    }
+                }
+                _ => {
+                    let list = lists.last_mut().ok_or(Stop::NoMatch)?;
+                    // Messages can not follow the nested lists.
+                    if !list.children.is_empty() {
+                        return Err(Stop::NoMatch);
+                    }
+                    list.ids
+                        .push(u32::try_from(self.number()?).map_err(|_| Stop::NoMatch)?);
+                }
+            }
+        }
+    }
+
+    /// Consumes any value: an atom, a number, a string, or a parenthesized list of those.
+    fn skip_value(&mut self) -> Step<()> {
+        match self.peek()? {
+            b'(' => {
+                self.pos += 1;
+                loop {
+                    match self.peek()? {
+                        b')' => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        b' ' => self.pos += 1,
+                        _ => self.skip_value()?,
+                    }
+                }
+            }
+            b'"' => self.quoted().map(drop),
+            b'{' | b'~' => self.literal().map(drop),
+            _ => {
+                let start = self.pos;
+                loop {
+                    match self.peek()? {
+                        b' ' | b'(' | b')' => break,
+                        b'\r' | b'\n' => return Err(Stop::NoMatch),
+                        b'[' => while self.next()? != b']' {},
+                        _ => self.pos += 1,
+                    }
+                }
+                if self.pos == start {
+                    return Err(Stop::NoMatch);
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_binary_items() {
+        let input = b"* 3 FETCH (UID 7 BINARY[1.2] ~{5}\r\nhe\0lo BINARY.SIZE[1.2] 5 FLAGS (\\Seen))\r\n* 4";
+        let split = split_fetch(input).unwrap();
+        assert_eq!(split.consumed, input.len() - 3);
+        assert_eq!(split.message, 3);
+        assert_eq!(
+            split.rest.as_deref(),
+            Some(&b"* 3 FETCH (UID 7 FLAGS (\\Seen))\r\n"[..])
+        );
+        assert_eq!(
+            split.extensions,
+            vec![
+                ExtensionAttribute::Binary {
+                    section: vec![1, 2],
+                    data: Some(b"he\0lo".to_vec()),
+                },
+                ExtensionAttribute::BinarySize {
+                    section: vec![1, 2],
+                    size: 5,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn esearch_relevancy() {
+        let input =
+            b"* ESEARCH (TAG \"A0002\") UID ALL 1:3,5 RELEVANCY (4 99 42 80) COUNT 4\r\n* 4";
+        let rewritten = esearch(input).unwrap();
+        assert_eq!(rewritten.consumed, input.len() - 3);
+        assert_eq!(rewritten.search, b"* SEARCH 1 2 3 5\r\n".to_vec());
+        assert_eq!(
+            rewritten.extensions,
+            vec![ExtensionAttribute::Relevancy(vec![4, 99, 42, 80])]
+        );
+
+        let rewritten = esearch(b"* ESEARCH (TAG \"A0002\")\r\n").unwrap();
+        assert_eq!(rewritten.search, b"* SEARCH\r\n".to_vec());
+        assert!(rewritten.extensions.is_empty());
+
+        assert_eq!(esearch(b"* ESEARCH ALL 1:3"), Err(Stop::Incomplete));
+        assert_eq!(
+            esearch(b"* ESEARCH ALL 1:4294967295\r\n"),
+            Err(Stop::NoMatch)
+        );
+        assert_eq!(esearch(b"* SEARCH 1 2\r\n"), Err(Stop::NoMatch));
+    }
+
+    #[test]
+    fn urlauth_responses() {
+        let input = b"* GENURLAUTH \"imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a473744909de610943775f92038\"\r\n* 4";
+        let decoded = placeholder(input).unwrap();
+        assert_eq!(decoded.consumed, input.len() - 3);
+        assert_eq!(decoded.placeholder, b"* OK GENURLAUTH\r\n");
+        assert_eq!(
+            decoded.extension,
+            ExtensionAttribute::GenUrlAuth(vec![
+                "imap://joe@example.com/INBOX/;uid=20;urlauth=submit+fred:internal:91354a473744909de610943775f92038".into()
+            ])
+        );
+
+        let decoded =
+            placeholder(b"* URLFETCH /INBOX/;uid=20 {5}\r\nhello \"/INBOX/;uid=21\" NIL\r\n")
+                .unwrap();
+        assert_eq!(
+            decoded.extension,
+            ExtensionAttribute::UrlFetch(vec![
+                ("/INBOX/;uid=20".into(), Some(b"hello".to_vec())),
+                ("/INBOX/;uid=21".into(), None),
+            ])
+        );
+
+        assert_eq!(
+            placeholder(b"* URLFETCH /INBOX/;uid=20 {5}\r\nhel"),
+            Err(Stop::Incomplete)
+        );
+        assert_eq!(placeholder(b"* OK done\r\n"), Err(Stop::NoMatch));
+    }
+
+    #[test]
+    fn thread_responses() {
+        fn node(id: u32, children: Vec<ThreadNode<u32>>) -> ThreadNode<u32> {
+            ThreadNode {
+                id: Some(id),
+                children,
+            }
+        }
+        let input = b"* THREAD (2)(3 6 (4 23)(44 7 96)) ((3)(5))\r\n* 4";
+        let decoded = placeholder(input).unwrap();
+        assert_eq!(decoded.consumed, input.len() - 3);
+        assert_eq!(decoded.placeholder, b"* OK THREAD\r\n");
+        assert_eq!(
+            decoded.extension,
+            ExtensionAttribute::Thread(vec![
+                node(2, vec![]),
+                node(
+                    3,
+                    vec![node(
+                        6,
+                        vec![
+                            node(4, vec![node(23, vec![])]),
+                            node(44, vec![node(7, vec![node(96, vec![])])]),
+                        ]
+                    )]
+                ),
+                ThreadNode {
+                    id: None,
+                    children: vec![node(3, vec![]), node(5, vec![])],
+                },
+            ])
+        );
+
+        assert_eq!(
+            placeholder(b"* THREAD\r\n").unwrap().extension,
+            ExtensionAttribute::Thread(Vec::new())
+        );
+        assert_eq!(placeholder(b"* THREAD (3 6 (4"), Err(Stop::Incomplete));
+        assert_eq!(placeholder(b"* THREAD (3 x)\r\n"), Err(Stop::NoMatch));
+        assert_eq!(placeholder(b"* THREAD ()\r\n"), Err(Stop::NoMatch));
+        assert_eq!(placeholder(b"* THREAD ((3) 4)\r\n"), Err(Stop::NoMatch));
+    }
+
+    #[test]
+    fn thread_deep() {
+        // A reply chain of 100000 messages is a tree of that depth.
+        let ids: Vec<String> = (1..=100_000).map(|id| id.to_string()).collect();
+        let input = format!("* THREAD ({} (100001)(100002))\r\n", ids.join(" "));
+        let thread = match placeholder(input.as_bytes()).unwrap().extension {
+            ExtensionAttribute::Thread(mut threads) => threads.pop().unwrap(),
+            other => panic!("unexpected extension: {:?}", other),
+        };
+        let flattened = thread.flatten_depth_first();
+        assert_eq!(flattened.len(), 100_002);
+        assert_eq!(flattened[..3], [1, 2, 3]);
+        assert_eq!(thread.flatten_by_latest()[100_000..], [100_002, 100_001]);
+        let copy = thread.clone().map(crate::types::Seq);
+        assert_eq!(copy.flatten_depth_first().len(), 100_002);
+        assert_eq!(thread.clone(), thread);
+        assert!(format!("{:?}", thread).starts_with("(1 (2 (3 "));
+        drop(thread);
+
+        let nested = format!("* THREAD {}1{}\r\n", "(".repeat(2000), ")".repeat(2000));
+        assert_eq!(placeholder(nested.as_bytes()), Err(Stop::NoMatch));
+        let nested = format!("* THREAD {}1{}\r\n", "(".repeat(100), ")".repeat(100));
+        assert!(placeholder(nested.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn split_only_binary_items() {
+        let split = split_fetch(b"* 1 FETCH (BINARY[] NIL)\r\n").unwrap();
+        assert_eq!(split.rest, None);
+        assert_eq!(
+            split.extensions,
+            vec![ExtensionAttribute::Binary {
+                section: vec![],
+                data: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn split_incomplete() {
+        assert_eq!(
+            split_fetch(b"* 1 FETCH (BINARY[1] ~{10}\r\nabc"),
+            Err(Stop::Incomplete)
+        );
+        assert_eq!(split_fetch(b"* 1 FETCH (BINARY[1] "), Err(Stop::Incomplete));
+    }
+
+    #[test]
+    fn rewrite_8bit_quoted() {
+        let input = "* LIST () \"/\" \"Отправленные\"\r\n* LIST () \"/\" \"b\"\r\n".as_bytes();
+        let rewritten = quoted_to_literals(input).unwrap();
+        assert_eq!(rewritten.consumed, input.len() - 19);
+        assert_eq!(
+            rewritten.data,
+            "* LIST () \"/\" {24}\r\nОтправленные\r\n".as_bytes()
+        );
+
+        let input = "* 1 FETCH (BODY[] {3}\r\n\"é ENVELOPE (\"ü\"))\r\n".as_bytes();
+        assert_eq!(
+            quoted_to_literals(input).unwrap().data,
+            "* 1 FETCH (BODY[] {3}\r\n\"é ENVELOPE ({2}\r\nü))\r\n".as_bytes()
+        );
+    }
+
+    #[test]
+    fn rewrite_nothing() {
+        assert_eq!(
+            quoted_to_literals(b"* OK {not a literal} \"ascii\"\r\n"),
+            Err(Stop::NoMatch)
+        );
+        assert_eq!(
+            quoted_to_literals("* LIST () \"/\" \"é".as_bytes()),
+            Err(Stop::Incomplete)
+        );
+    }
+
+    #[test]
+    fn fetch_literal() {
+        let input = b"* 12 FETCH (UID 7 BODY[1] {40000}\r\nabc";
+        assert_eq!(
+            fetch_literal_start(input, 12, "1"),
+            Ok((input.len() - 3, 40000))
+        );
+        assert_eq!(
+            fetch_literal_start(b"* 12 FETCH (BODY[1] {400", 12, "1"),
+            Err(Stop::Incomplete)
+        );
+        assert_eq!(
+            fetch_literal_start(b"* 12 FETCH (BODY[1] \"abc\")\r\n", 12, "1"),
+            Err(Stop::NoMatch)
+        );
+        assert_eq!(
+            fetch_literal_start(b"* LIST () \"/\" {3}\r\nabc\r\n", 12, "1"),
+            Err(Stop::NoMatch)
+        );
+
+        // Only the requested message and section are streamed.
+        let input = b"* 12 FETCH (BODY[header.fields (SUBJECT)] {40}\r\n";
+        assert_eq!(
+            fetch_literal_start(input, 12, "HEADER.FIELDS (SUBJECT)"),
+            Ok((input.len(), 40))
+        );
+        assert_eq!(
+            fetch_literal_start(input, 13, "HEADER.FIELDS (SUBJECT)"),
+            Err(Stop::NoMatch)
+        );
+        assert_eq!(fetch_literal_start(input, 12, "TEXT"), Err(Stop::NoMatch));
+        assert_eq!(
+            fetch_literal_start(b"* 12 FETCH (X-EXTRA {40}\r\n", 12, ""),
+            Err(Stop::NoMatch)
+        );
+    }
+
+    #[test]
+    fn split_no_match() {
+        assert_eq!(
+            split_fetch(b"* 1 FETCH (UID 1 BODY[HEADER.FIELDS (DATE)] {2}\r\nab)\r\n"),
+            Err(Stop::NoMatch)
+        );
+        assert_eq!(split_fetch(b"* OK hello\r\n"), Err(Stop::NoMatch));
+    }
+}
@@ -4,16 +4,18 @@ use std::fmt;
 use std::pin::Pin;
 use std::time::Duration;
 
-use async_std::io::{self, Read, Write};
-use async_std::prelude::*;
-use async_std::stream::Stream;
+use futures::io::{self, AsyncRead as Read, AsyncWrite as Write};
+use futures::prelude::*;
+use futures::ready;
 use futures::task::{Context, Poll};
-use imap_proto::{RequestId, Response, Status};
+use imap_proto::{MailboxDatum, RequestId, Response, Status};
+use stop_token::prelude::*;
 
 use crate::client::Session;
-use crate::error::Result;
-use crate::parse::handle_unilateral;
-use crate::types::ResponseData;
+use crate::error::{Error, Result};
+use crate::parse::{alert_text, check_status_ok, handle_unilateral};
+use crate::runtime;
+use crate::types::{Fetch, ResponseData};
 
 /// `Handle` allows a client to block waiting for changes to the remote mailbox.
 ///
@@ -25,15 +27,20 @@ use crate::types::ResponseData;
 /// Note that the server MAY consider a client inactive if it has an IDLE command running, and if
 /// such a server has an inactivity timeout it MAY log the client off implicitly at the end of its
 /// timeout period.  Because of that, clients using IDLE are advised to terminate the IDLE and
-/// re-issue it at least every 29 minutes to avoid being logged off. [`Handle::wait_keepalive`]
-/// does this. This still allows a client to receive immediate mailbox updates even though it need
-/// only "poll" at half hour intervals.
+/// re-issue it at least every 29 minutes to avoid being logged off.
+/// [`Handle::wait_with_timeout`] does this. This still allows a client to receive immediate
+/// mailbox updates even though it need only "poll" at half hour intervals.
 ///
 /// As long as a [`Handle`] is active, the mailbox cannot be otherwise accessed.
 #[derive(Debug)]
 pub struct Handle<T: Read + Write + Unpin + fmt::Debug> {
     session: Session<T>,
     id: Option<RequestId>,
+    /// The session's read timeout, suspended while idling.
+    timeout: Option<Duration>,
+    /// Whether the server completed the `IDLE` command on its own, so that `DONE` must not be
+    /// sent.
+    terminated: bool,
 }
 
 impl<T: Read + Write + Unpin + fmt::Debug> Unpin for Handle<T> {}
@@ -42,7 +49,15 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Stream for Handle<T> {
     type Item = io::Result<ResponseData>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.as_mut().session().get_stream().poll_next(cx)
+        let item = ready!(Pin::new(&mut self.session).get_stream().poll_next(cx));
+        if let Some(Ok(response)) = &item {
+            if let Response::Done { tag, .. } = response.parsed() {
+                if Some(tag) == self.id.as_ref() {
+                    self.terminated = true;
+                }
+            }
+        }
+        Poll::Ready(item)
     }
 }
 
@@ -56,8 +71,6 @@ pub struct IdleStream<'a, St> {
 impl<St: Unpin> Unpin for IdleStream<'_, St> {}
 
 impl<'a, St: Stream + Unpin> IdleStream<'a, St> {
-    unsafe_pinned!(stream: &'a mut St);
-
     pub(crate) fn new(stream: &'a mut St) -> Self {
         IdleStream { stream }
     }
@@ -73,7 +86,7 @@ impl<St: Stream + Unpin> Stream for IdleStream<'_, St> {
     type Item = St::Item;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        self.stream().poll_next(cx)
+        Pin::new(&mut *self.get_mut().stream).poll_next(cx)
     }
 }
 
@@ -83,16 +96,126 @@ pub enum IdleResponse {
     /// The manual interrupt was used to interrupt the idle connection..
     ManualInterrupt,
     /// The idle connection timed out, because of the user set timeout.
+    ///
+    /// [`Handle::wait_with_timeout`] re-issues `IDLE` on timeout instead of returning this.
     Timeout,
     /// The server has indicated that some new action has happened.
     NewData(ResponseData),
 }
 
-impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
-    unsafe_pinned!(session: Session<T>);
+/// A change to the selected mailbox that the server reported while idling, see
+/// [`Handle::events`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum IdleEvent {
+    /// The number of messages in the mailbox, sent when it changes, e.g. as new messages arrive.
+    Exists(u32),
+    /// The number of messages with [`Flag::Recent`](crate::types::Flag::Recent) set.
+    Recent(u32),
+    /// The message with this sequence number was removed, see
+    /// [`UnsolicitedResponse::Expunge`](crate::types::UnsolicitedResponse::Expunge).
+    Expunge(u32),
+    /// Data of a message changed, usually its flags.
+    Fetch(Box<Fetch>),
+    /// The server completed the `IDLE` command on its own, for example because it ended the
+    /// session after its inactivity timeout.  No further events follow, and
+    /// [`Handle::done`] returns the session without sending `DONE`.
+    Terminated,
+}
 
+impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
     pub(crate) fn new(session: Session<T>) -> Handle<T> {
-        Handle { session, id: None }
+        Handle {
+            session,
+            id: None,
+            timeout: None,
+            terminated: false,
+        }
+    }
+
+    /// Returns a stream of the changes the server reports while idling.
+    /// Must be called after [Handle::init].
+    ///
+    /// The stream ends after [`IdleEvent::Terminated`], and fails with
+    /// [`Error::ConnectionLost`] if the connection is closed.
+    /// Otherwise it continues until it is dropped, after which idling is ended with
+    /// [`Handle::done`].  Other responses, such as alerts, are sent to
+    /// [`Session::unsolicited_responses`].
+    ///
+    /// ```no_run
+    /// # async fn example(session: async_imap::Session<async_std::net::TcpStream>) -> async_imap::error::Result<()> {
+    /// use async_imap::extensions::idle::IdleEvent;
+    /// use futures::StreamExt;
+    ///
+    /// let mut idle = session.idle();
+    /// idle.init().await?;
+    /// let mut events = idle.events();
+    /// while let Some(event) = events.next().await {
+    ///     match event? {
+    ///         IdleEvent::Exists(_) => break,
+    ///         IdleEvent::Terminated => break,
+    ///         _ => {}
+    ///     }
+    /// }
+    /// drop(events);
+    /// let session = idle.done().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn events(&mut self) -> impl Stream<Item = Result<IdleEvent>> + '_ + Send {
+        assert!(
+            self.id.is_some(),
+            "Cannot listen to response without starting IDLE"
+        );
+        let finished = self.terminated;
+        Box::pin(futures::stream::unfold(
+            (self, finished),
+            |(handle, finished)| async move {
+                if finished {
+                    return None;
+                }
+                loop {
+                    let response = match handle.next().await {
+                        Some(Ok(response)) => response,
+                        Some(Err(err)) => return Some((Err(err.into()), (handle, true))),
+                        None => return Some((Err(Error::ConnectionLost), (handle, true))),
+                    };
+                    let event = match response.parsed() {
+                        parsed @ Response::MailboxData(MailboxDatum::Exists(n)) => {
+                            handle.session.unsolicited_responses_tx.track_exists(parsed);
+                            IdleEvent::Exists(*n)
+                        }
+                        Response::MailboxData(MailboxDatum::Recent(n)) => IdleEvent::Recent(*n),
+                        parsed @ Response::Expunge(n) => {
+                            handle.session.unsolicited_responses_tx.track_exists(parsed);
+                            IdleEvent::Expunge(*n)
+                        }
+                        Response::Fetch(..) => IdleEvent::Fetch(Box::new(Fetch::new(response))),
+                        Response::Done {
+                            tag,
+                            status,
+                            code,
+                            information,
+                        } if Some(tag) == handle.id.as_ref() => {
+                            let event =
+                                check_status_ok(status, code.as_ref(), information.as_deref())
+                                    .map(|()| IdleEvent::Terminated);
+                            return Some((event, (handle, true)));
+                        }
+                        Response::Continue { .. } => continue,
+                        Response::Data {
+                            status: Status::Ok, ..
+                        } if alert_text(&response).is_none() => continue,
+                        _ => {
+                            let sender = handle.session.unsolicited_responses_tx.clone();
+                            handle_unilateral(response, sender).await;
+                            continue;
+                        }
+                    };
+                    return Some((Ok(event), (handle, false)));
+                }
+            },
+        ))
     }
 
     /// Start listening to the server side resonses.
@@ -107,15 +230,28 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
             self.id.is_some(),
             "Cannot listen to response without starting IDLE"
         );
-        let sender = self.session.unsolicited_responses_tx.clone();
 
         let interrupt = stop_token::StopSource::new();
+        let fut = self.wait_until(interrupt.token());
+
+        (fut, interrupt)
+    }
+
+    fn wait_until(
+        &mut self,
+        stop_token: stop_token::StopToken,
+    ) -> impl Future<Output = Result<IdleResponse>> + '_ {
+        let sender = self.session.unsolicited_responses_tx.clone();
+        let id = self.id.clone();
+
         let raw_stream = IdleStream::new(self);
-        let mut interruptible_stream = interrupt.stop_token().stop_stream(raw_stream);
+        let mut interruptible_stream = raw_stream.timeout_at(stop_token);
 
-        let fut = async move {
-            while let Some(resp) = interruptible_stream.next().await {
+        async move {
+            // The stream yields an error once the `StopSource` is dropped.
+            while let Some(Ok(resp)) = interruptible_stream.next().await {
                 let resp = resp?;
+                sender.track_exists(resp.parsed());
                 match resp.parsed() {
                     Response::Data { status, .. } if status == &Status::Ok => {
                         // all good continue
@@ -123,6 +259,10 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
                     Response::Continue { .. } => {
                         // continuation, wait for it
                     }
+                    Response::Done { tag, .. } if Some(tag) == id.as_ref() => {
+                        // The server ended the IDLE command on its own.
+                        return Ok(IdleResponse::NewData(resp));
+                    }
                     Response::Done { .. } => {
                         handle_unilateral(resp, sender.clone()).await;
                     }
@@ -131,12 +271,18 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
             }
 
             Ok(IdleResponse::ManualInterrupt)
-        };
-
-        (fut, interrupt)
+        }
     }
 
-    /// Start listening to the server side resonses, stops latest after the passed in `timeout`.
+    /// Start listening to the server side resonses, like [Handle::wait], and re-issue the `IDLE`
+    /// command whenever `timeout` passes without a response.
+    ///
+    /// When the timeout elapses, `DONE` is sent and `IDLE` is entered again, after which waiting
+    /// continues, so the returned future only resolves on a response from the server, on
+    /// interruption or on error.  With a timeout below 29 minutes this keeps servers from logging
+    /// out the client for inactivity, as recommended by
+    /// [RFC 2177](https://tools.ietf.org/html/rfc2177#section-3).
+    ///
     /// Must be called after [Handle::init].
     pub fn wait_with_timeout(
         &mut self,
@@ -150,19 +296,42 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
             "Cannot listen to response without starting IDLE"
         );
 
-        let (waiter, interrupt) = self.wait();
+        let interrupt = stop_token::StopSource::new();
+        let stop_token = interrupt.token();
         let fut = async move {
-            match async_std::future::timeout(timeout, waiter).await {
-                Ok(res) => res,
-                Err(_err) => Ok(IdleResponse::Timeout),
+            loop {
+                let waiter = self.wait_until(stop_token.clone());
+                match runtime::timeout(timeout, waiter).await {
+                    Some(res) => return res,
+                    None => self.refresh().await?,
+                }
             }
         };
 
         (fut, interrupt)
     }
 
+    /// Ends the running `IDLE` command and starts a new one.
+    async fn refresh(&mut self) -> Result<()> {
+        log::debug!("refreshing IDLE");
+        self.session.run_command_untagged("DONE").await?;
+        let sender = self.session.unsolicited_responses_tx.clone();
+        self.session
+            .check_done_ok(self.id.as_ref().expect("invalid setup"), Some(sender))
+            .await?;
+        self.start().await
+    }
+
     /// Initialise the idle connection by sending the `IDLE` command to the server.
     pub async fn init(&mut self) -> Result<()> {
+        self.start().await?;
+        self.timeout = self.session.conn.stream.read_timeout();
+        self.session.conn.stream.set_read_timeout(None);
+        Ok(())
+    }
+
+    /// Sends the `IDLE` command and waits for the server to accept it.
+    async fn start(&mut self) -> Result<()> {
         let id = self.session.run_command("IDLE").await?;
         self.id = Some(id);
         while let Some(res) = self.session.stream.next().await {
@@ -199,11 +368,18 @@ impl<T: Read + Write + Unpin + fmt::Debug + Send> Handle<T> {
 
     /// Signal that we want to exit the idle connection, by sending the `DONE`
     /// command to the server.
+    ///
+    /// Returns the session once the server completed the `IDLE` command.  If the server already
+    /// did so on its own, `DONE` is not sent.
     pub async fn done(mut self) -> Result<Session<T>> {
         assert!(
             self.id.is_some(),
             "Cannot call DONE on a non initialized idle connection"
         );
+        self.session.conn.stream.set_read_timeout(self.timeout);
+        if self.terminated {
+            return Ok(self.session);
+        }
         self.session.run_command_untagged("DONE").await?;
         let sender = self.session.unsolicited_responses_tx.clone();
         self.session
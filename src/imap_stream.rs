@@ -1,14 +1,19 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
 
-use async_std::io::{self, Read, Write};
-use async_std::prelude::*;
-use async_std::stream::Stream;
-use async_std::sync::Arc;
 use byte_pool::{Block, BytePool};
+use futures::io::{self, AsyncRead as Read, AsyncWrite as Write};
+use futures::prelude::*;
+use futures::ready;
 use futures::task::{Context, Poll};
+use imap_proto::{RequestId, Response};
 use nom::Needed;
 
+use crate::error::{ParseError, ServerBye};
+use crate::ext_parse;
 use crate::types::{Request, ResponseData};
 
 lazy_static::lazy_static! {
@@ -16,6 +21,42 @@ lazy_static::lazy_static! {
     pub(crate) static ref POOL: Arc<BytePool> = Arc::new(BytePool::new());
 }
 
+/// Literals up to this size are passed to the [`DebugHook`] as they are, larger ones are
+/// replaced by a summary of their length.
+const MAX_LOGGED_LITERAL: usize = 1024;
+
+/// Whether data passed to a debug hook was sent to or received from the server, see
+/// [`Session::set_debug`](crate::Session::set_debug).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Data sent by the client.
+    Sent,
+    /// Data received from the server.
+    Received,
+}
+
+/// The function called by a [`DebugHook`].
+type DebugFn = dyn Fn(Direction, &[u8]) + Send + Sync;
+
+/// A callback receiving each line exchanged with the server.
+#[derive(Clone)]
+pub(crate) struct DebugHook(Arc<DebugFn>);
+
+impl DebugHook {
+    pub(crate) fn new<F>(hook: F) -> Self
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        DebugHook(Arc::new(hook))
+    }
+}
+
+impl fmt::Debug for DebugHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DebugHook")
+    }
+}
+
 /// Wraps a stream, and parses incoming data as imap server messages. Writes outgoing data
 /// as imap client messages.
 #[derive(Debug)]
@@ -30,6 +71,22 @@ pub struct ImapStream<R: Read + Write> {
     /// Whether there is any more items to return from the stream.  This is set to true once
     /// all decodable data in the buffer is returned and the underlying stream is closed.
     closed: bool,
+    /// The reason given in the untagged `BYE` response, once the server sent one.  All reads and
+    /// writes fail afterwards, as the server is about to close the connection.
+    bye: Option<String>,
+    /// Fails reads if the server does not send any data for a while.
+    read_timeout: ReadTimeout,
+    /// Receives the lines sent and received, if set.
+    debug: Option<DebugHook>,
+    /// The command whose responses are read by a stream that may be dropped before the tagged
+    /// completion, see [`ImapStream::set_unfinished`].
+    unfinished: Option<RequestId>,
+    /// The number of bytes left of a literal read with [`ImapStream::poll_literal`], which are
+    /// followed by the rest of its response line.
+    literal_left: Option<usize>,
+    /// The part of the last command line that was not written yet, because
+    /// [`ImapStream::encode`] was dropped.  It is written before anything else.
+    unwritten: Vec<u8>,
 }
 
 impl<R: Read + Write + Unpin> ImapStream<R> {
@@ -40,38 +97,122 @@ impl<R: Read + Write + Unpin> ImapStream<R> {
             buffer: Buffer::new(),
             decode_needs: None,
             closed: false,
+            bye: None,
+            read_timeout: ReadTimeout::default(),
+            debug: None,
+            unfinished: None,
+            literal_left: None,
+            unwritten: Vec::new(),
         }
     }
 
+    /// Sets the hook receiving the lines sent and received, or `None` to remove it.
+    pub(crate) fn set_debug(&mut self, hook: Option<DebugHook>) {
+        self.debug = hook;
+    }
+
+    /// Passes `data` to the debug hook line by line, with large literals summarized.
+    pub(crate) fn debug(&self, direction: Direction, data: &[u8]) {
+        if let Some(DebugHook(hook)) = &self.debug {
+            let data = summarize_literals(data);
+            let data = data.strip_suffix(b"\r\n").unwrap_or(&data);
+            for line in data.split(|&b| b == b'\n') {
+                hook(direction, line.strip_suffix(b"\r").unwrap_or(line));
+            }
+        }
+    }
+
+    /// Writes the contents of a literal, passing only its length to the debug hook.
+    pub(crate) async fn write_literal(&mut self, data: &[u8]) -> Result<(), io::Error> {
+        self.debug(Direction::Sent, literal_summary(data.len()).as_bytes());
+        self.inner.write_all(data).await
+    }
+
+    /// Marks the command `tag` as unfinished until its tagged completion is read, e.g. while its
+    /// responses are read by a stream handed out to the user, which may be dropped early.
+    pub(crate) fn set_unfinished(&mut self, tag: RequestId) {
+        self.unfinished = Some(tag);
+    }
+
+    /// Returns the command marked with [`ImapStream::set_unfinished`] if its completion was not
+    /// read yet, and removes the mark.
+    pub(crate) fn take_unfinished(&mut self) -> Option<RequestId> {
+        self.unfinished.take()
+    }
+
+    /// Sets how long to wait for data from the server before failing with
+    /// [`io::ErrorKind::TimedOut`], or `None` to wait forever.
+    pub fn set_read_timeout(&mut self, duration: Option<Duration>) {
+        self.read_timeout = ReadTimeout {
+            duration,
+            timer: None,
+        };
+    }
+
+    /// Returns the duration set with [`ImapStream::set_read_timeout`].
+    pub fn read_timeout(&self) -> Option<Duration> {
+        self.read_timeout.duration
+    }
+
     pub async fn encode(&mut self, msg: Request) -> Result<(), io::Error> {
+        self.check_bye()?;
         if self.closed {
             return Err(io::Error::new(
                 io::ErrorKind::BrokenPipe,
                 "inner stream closed",
             ));
         }
+        let redacted = redact_credentials(msg.0.is_some(), &msg.1);
         log::trace!(
             "encode: input: {:?}, {:?}",
             msg.0,
-            std::str::from_utf8(&msg.1)
+            String::from_utf8_lossy(&redacted)
         );
+        if self.debug.is_some() {
+            let line = match &msg.0 {
+                Some(tag) => [tag.as_bytes(), b" ", &redacted].concat(),
+                None => redacted.into_owned(),
+            };
+            self.debug(Direction::Sent, &line);
+        }
 
+        self.write_unwritten().await?;
         if let Some(tag) = msg.0 {
-            self.inner.write_all(tag.as_bytes()).await?;
-            self.inner.write(b" ").await?;
+            self.unwritten.extend_from_slice(tag.as_bytes());
+            self.unwritten.push(b' ');
         }
-        self.inner.write_all(&msg.1).await?;
-        self.inner.write_all(b"\r\n").await?;
+        self.unwritten.extend_from_slice(&msg.1);
+        self.unwritten.extend_from_slice(b"\r\n");
+        self.write_unwritten().await
+    }
 
+    /// Writes what is left of the last command line.  The line is consumed as it is written, so
+    /// that if this is dropped, the rest of it is written the next time instead of the server
+    /// receiving a partial line followed by the next command.
+    async fn write_unwritten(&mut self) -> Result<(), io::Error> {
+        while !self.unwritten.is_empty() {
+            let written = self.inner.write(&self.unwritten).await?;
+            if written == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            self.unwritten.drain(..written);
+        }
         Ok(())
     }
 
+    /// Whether data has been received that was not decoded yet.
+    pub fn has_buffered_data(&self) -> bool {
+        self.buffer.used() > 0
+    }
+
     pub fn into_inner(self) -> R {
         self.inner
     }
 
-    /// Flushes the underlying stream.
+    /// Writes what is left of a command line whose [`ImapStream::encode`] was dropped, and
+    /// flushes the underlying stream.
     pub async fn flush(&mut self) -> Result<(), io::Error> {
+        self.write_unwritten().await?;
         self.inner.flush().await
     }
 
@@ -79,6 +220,42 @@ impl<R: Read + Write + Unpin> ImapStream<R> {
         &mut self.inner
     }
 
+    /// Closes the underlying stream.  Further commands fail, as if the server had closed it.
+    pub(crate) async fn close(&mut self) -> Result<(), io::Error> {
+        self.closed = true;
+        futures::AsyncWriteExt::close(&mut self.inner).await
+    }
+
+    /// Fails with [`ServerBye`] once the server sent a `BYE` response.
+    fn check_bye(&self) -> io::Result<()> {
+        match &self.bye {
+            Some(reason) => Err(io::Error::new(
+                io::ErrorKind::ConnectionAborted,
+                ServerBye(reason.clone()),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns `response`, unless it is an untagged `BYE`, which is turned into an error.
+    fn check_response(&mut self, response: ResponseData) -> io::Result<ResponseData> {
+        if let Response::Data {
+            status: imap_proto::Status::Bye,
+            information,
+            ..
+        } = response.parsed()
+        {
+            self.bye = Some(information.as_deref().unwrap_or_default().to_string());
+            self.check_bye()?;
+        }
+        if let Response::Done { tag, .. } = response.parsed() {
+            if self.unfinished.as_ref() == Some(tag) {
+                self.unfinished = None;
+            }
+        }
+        Ok(response)
+    }
+
     /// End-Of-File return value.
     ///
     /// Return the appropriate EOF value for the stream depending on whether there is still
@@ -95,6 +272,141 @@ impl<R: Read + Write + Unpin> ImapStream<R> {
     }
 }
 
+/// Reading literals without decoding them, so that large ones do not have to be held in memory.
+impl<R: Read + Write + Unpin> ImapStream<R> {
+    /// Checks whether the next response is a `FETCH` response for message `seq` whose first line
+    /// ends with the announcement of the literal holding `BODY[<section>]`. If so, that line is
+    /// consumed and the length of the literal is returned, so that it can be read with
+    /// [`ImapStream::poll_literal`]. Otherwise, the response is left to be read from the stream as
+    /// usual.
+    pub(crate) fn poll_fetch_literal_start(
+        &mut self,
+        cx: &mut Context<'_>,
+        seq: u32,
+        section: &str,
+    ) -> Poll<io::Result<Option<usize>>> {
+        loop {
+            match ext_parse::fetch_literal_start(self.buffer.data(), seq, section) {
+                Ok((line_len, literal_len)) => {
+                    if self.debug.is_some() {
+                        let line = [
+                            &self.buffer.data()[..line_len],
+                            literal_summary(literal_len).as_bytes(),
+                        ]
+                        .concat();
+                        self.debug(Direction::Received, &line);
+                    }
+                    self.buffer.consume(line_len);
+                    self.decode_needs = None;
+                    self.literal_left = Some(literal_len);
+                    return Poll::Ready(Ok(Some(literal_len)));
+                }
+                Err(ext_parse::Stop::NoMatch) => return Poll::Ready(Ok(None)),
+                Err(ext_parse::Stop::Incomplete) => {
+                    if ready!(self.poll_fill(cx, None))? == 0 {
+                        return Poll::Ready(Ok(None));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the next at most `max` bytes of a literal.
+    pub(crate) fn poll_literal(
+        &mut self,
+        cx: &mut Context<'_>,
+        max: usize,
+    ) -> Poll<io::Result<Vec<u8>>> {
+        if self.buffer.used() == 0 && ready!(self.poll_fill(cx, None))? == 0 {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "inner stream closed within a literal",
+            )));
+        }
+        let len = max.min(self.buffer.used());
+        let chunk = self.buffer.data()[..len].to_vec();
+        self.buffer.consume(len);
+        if let Some(left) = &mut self.literal_left {
+            *left = left.saturating_sub(len);
+        }
+        Poll::Ready(Ok(chunk))
+    }
+
+    /// Skips what is left of a literal started with [`ImapStream::poll_fetch_literal_start`] and
+    /// the rest of its line, e.g. after the stream reading it was dropped, so that the following
+    /// responses can be decoded again.
+    pub(crate) fn poll_skip_literal(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(left) = self.literal_left {
+            if left == 0 {
+                ready!(self.poll_skip_line(cx))?;
+            } else {
+                if self.buffer.used() == 0 && ready!(self.poll_fill(cx, None))? == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "inner stream closed within a literal",
+                    )));
+                }
+                let len = left.min(self.buffer.used());
+                self.buffer.consume(len);
+                self.literal_left = Some(left - len);
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// Skips the rest of the current line, such as the end of a `FETCH` response after a
+    /// literal.
+    pub(crate) fn poll_skip_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            if let Some(pos) = self.buffer.data().windows(2).position(|w| w == b"\r\n") {
+                self.debug(Direction::Received, &self.buffer.data()[..pos]);
+                self.buffer.consume(pos + 2);
+                self.decode_needs = None;
+                self.literal_left = None;
+                return Poll::Ready(Ok(()));
+            }
+            if ready!(self.poll_fill(cx, None))? == 0 {
+                return Poll::Ready(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "inner stream closed within a response",
+                )));
+            }
+        }
+    }
+
+    /// Reads more data into the buffer, making room for at least `required` bytes if given.
+    /// Returns the number of bytes read, which is `0` once the underlying stream is closed.
+    fn poll_fill(
+        &mut self,
+        cx: &mut Context<'_>,
+        required: Option<usize>,
+    ) -> Poll<io::Result<usize>> {
+        if self.closed {
+            return Poll::Ready(Ok(0));
+        }
+        self.buffer.ensure_capacity(required)?;
+        let num_bytes_read =
+            match Pin::new(&mut self.inner).poll_read(cx, self.buffer.free_as_mut_slice()) {
+                Poll::Ready(result) => result?,
+                Poll::Pending => {
+                    if self.read_timeout.poll_expired(cx) {
+                        return Poll::Ready(Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "timed out waiting for the server",
+                        )));
+                    }
+                    return Poll::Pending;
+                }
+            };
+        self.read_timeout.reset();
+        if num_bytes_read == 0 {
+            self.closed = true;
+        }
+        self.buffer.extend_used(num_bytes_read);
+        Poll::Ready(Ok(num_bytes_read))
+    }
+}
+
 impl<R: Read + Write + Unpin> ImapStream<R> {
     fn maybe_decode(&mut self) -> io::Result<Option<ResponseData>> {
         if self.buffer.used() > self.decode_needs.unwrap_or(0) {
@@ -113,6 +425,7 @@ impl<R: Read + Write + Unpin> ImapStream<R> {
             log::trace!("decode: input: {:?}", std::str::from_utf8(buf));
             match imap_proto::parser::parse_response(buf) {
                 Ok((remaining, response)) => {
+                    self.debug(Direction::Received, &buf[..buf.len() - remaining.len()]);
                     // TODO: figure out if we can use a minimum required size for a response.
                     self.decode_needs = None;
                     self.buffer.reset_with_data(remaining);
@@ -130,26 +443,261 @@ impl<R: Read + Write + Unpin> ImapStream<R> {
                 }
                 Err(other) => {
                     self.decode_needs = None;
-                    Err(Some(io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("{:?} during parsing of {:?}", other, buf),
-                    )))
+                    Err(Some(parse_error(other, buf)))
                 }
             }
         });
         match res {
             Ok(response) => Ok(Some(response)),
-            Err(rental::RentalError(err, block)) => {
-                self.buffer.return_block(block);
-                match err {
-                    Some(err) => Err(err),
-                    None => Ok(None),
+            Err(rental::RentalError(err, block)) => match err {
+                Some(err) => self.decode_extension(block, err),
+                None => {
+                    self.buffer.return_block(block);
+                    Ok(None)
                 }
+            },
+        }
+    }
+
+    /// Tries to decode a response that `imap-proto` failed to parse with `err`, see
+    /// [`ext_parse`].
+    fn decode_extension(
+        &mut self,
+        block: Block<'static>,
+        err: io::Error,
+    ) -> io::Result<Option<ResponseData>> {
+        let data = &block[..self.buffer.used()];
+        let decoded = match ext_parse::split_fetch(data) {
+            Err(ext_parse::Stop::NoMatch) => match ext_parse::esearch(data) {
+                Err(ext_parse::Stop::NoMatch) => match ext_parse::placeholder(data) {
+                    Err(ext_parse::Stop::NoMatch) => ext_parse::quoted_to_literals(data)
+                        .map(|rewritten| (rewritten.consumed, Extension::Rewritten(rewritten))),
+                    placeholder => placeholder.map(|placeholder| {
+                        (placeholder.consumed, Extension::Placeholder(placeholder))
+                    }),
+                },
+                esearch => esearch.map(|esearch| (esearch.consumed, Extension::ESearch(esearch))),
+            },
+            split => split.map(|split| (split.consumed, Extension::SplitFetch(split))),
+        };
+        match decoded {
+            Ok((consumed, extension)) => {
+                self.debug(Direction::Received, &data[..consumed]);
+                self.buffer
+                    .reset_with_data(&block[consumed..self.buffer.used()]);
+                match extension {
+                    Extension::SplitFetch(split) => Self::decode_split_fetch(split),
+                    Extension::ESearch(esearch) => Self::decode_esearch(esearch),
+                    Extension::Rewritten(rewritten) => Self::decode_rewritten(rewritten.data),
+                    Extension::Placeholder(placeholder) => Self::decode_placeholder(placeholder),
+                }
+                .map(Some)
+            }
+            Err(ext_parse::Stop::Incomplete) => {
+                log::trace!("decode: incomplete extension data");
+                self.buffer.return_block(block);
+                Ok(None)
+            }
+            Err(ext_parse::Stop::NoMatch) => {
+                self.buffer.return_block(block);
+                Err(err)
+            }
+        }
+    }
+
+    /// Parses what is left of a `FETCH` response after the extension items were removed.
+    fn decode_split_fetch(split: ext_parse::SplitFetch) -> io::Result<ResponseData> {
+        let ext_parse::SplitFetch {
+            message,
+            rest,
+            extensions,
+            ..
+        } = split;
+        let rest = rest.unwrap_or_default();
+        let mut block = POOL.alloc(rest.len());
+        block.copy_from_slice(&rest);
+        ResponseData::try_new(block, |buf| {
+            if buf.is_empty() {
+                return Ok(Response::Fetch(message, Vec::new()));
             }
+            Self::parse_complete(buf)
+        })
+        .map(|response| response.with_extensions(extensions))
+        .map_err(|err| err.0)
+    }
+
+    /// Parses the `SEARCH` response an `ESEARCH` response was rewritten as.
+    fn decode_esearch(esearch: ext_parse::ESearch) -> io::Result<ResponseData> {
+        let mut block = POOL.alloc(esearch.search.len());
+        block.copy_from_slice(&esearch.search);
+        ResponseData::try_new(block, |buf| Self::parse_complete(buf))
+            .map(|response| response.with_extensions(esearch.extensions))
+            .map_err(|err| err.0)
+    }
+
+    /// Parses the placeholder response a `GENURLAUTH`, `URLFETCH` or `THREAD` response was
+    /// decoded into.
+    fn decode_placeholder(placeholder: ext_parse::Placeholder) -> io::Result<ResponseData> {
+        let mut block = POOL.alloc(placeholder.placeholder.len());
+        block.copy_from_slice(placeholder.placeholder);
+        ResponseData::try_new(block, |buf| Self::parse_complete(buf))
+            .map(|response| response.with_extensions(vec![placeholder.extension]))
+            .map_err(|err| err.0)
+    }
+
+    /// Parses a response in which quoted strings with 8-bit characters were turned into literals.
+    fn decode_rewritten(data: Vec<u8>) -> io::Result<ResponseData> {
+        let mut block = POOL.alloc(data.len());
+        block.copy_from_slice(&data);
+        ResponseData::try_new(block, |buf| Self::parse_complete(buf)).map_err(|err| err.0)
+    }
+
+    /// Parses `buf`, which holds exactly one complete response.
+    fn parse_complete(buf: &[u8]) -> io::Result<Response<'_>> {
+        match imap_proto::parser::parse_response(buf) {
+            Ok((_, response)) => Ok(response),
+            Err(err) => Err(parse_error(err, buf)),
         }
     }
 }
 
+/// Returns the text passed to the debug hook in place of a literal of `len` bytes.
+fn literal_summary(len: usize) -> String {
+    format!("[{} bytes]", len)
+}
+
+/// Replaces the contents of literals larger than [`MAX_LOGGED_LITERAL`] in `data` by a summary
+/// of their length.
+fn summarize_literals(data: &[u8]) -> Cow<'_, [u8]> {
+    let mut summarized = Vec::new();
+    let mut copied = 0;
+    let mut pos = 0;
+    while let Some(offset) = data[pos..].windows(3).position(|w| w == b"}\r\n") {
+        let end = pos + offset;
+        pos = end + 3;
+        let start = match data[..end].iter().rposition(|&b| b == b'{') {
+            Some(start) => start,
+            None => continue,
+        };
+        let len = std::str::from_utf8(&data[start + 1..end])
+            .ok()
+            .and_then(|len| len.trim_end_matches('+').parse::<usize>().ok());
+        match len {
+            Some(len) if len > MAX_LOGGED_LITERAL && pos + len <= data.len() => {
+                summarized.extend_from_slice(&data[copied..pos]);
+                summarized.extend_from_slice(literal_summary(len).as_bytes());
+                pos += len;
+                copied = pos;
+            }
+            Some(len) => pos = (pos + len).min(data.len()),
+            None => {}
+        }
+    }
+    if copied == 0 {
+        return Cow::Borrowed(data);
+    }
+    summarized.extend_from_slice(&data[copied..]);
+    Cow::Owned(summarized)
+}
+
+/// Returns the command `line`, without its tag, with the credentials of `LOGIN` and the
+/// initial response of `AUTHENTICATE` replaced by `***`.  Untagged lines other than `DONE` and
+/// the `*` cancelling an authentication are responses to `AUTHENTICATE` challenges and are
+/// replaced as a whole.
+fn redact_credentials(tagged: bool, line: &[u8]) -> Cow<'_, [u8]> {
+    if !tagged {
+        return match line {
+            b"DONE" | b"*" => Cow::Borrowed(line),
+            _ => Cow::Borrowed(b"***"),
+        };
+    }
+    let mut words = line.splitn(3, |&b| b == b' ');
+    let command = words.next().unwrap_or_default();
+    let keep = if command.eq_ignore_ascii_case(b"LOGIN") {
+        command.len()
+    } else if command.eq_ignore_ascii_case(b"AUTHENTICATE") {
+        let mechanism = words.next().unwrap_or_default();
+        command.len() + 1 + mechanism.len()
+    } else {
+        return Cow::Borrowed(line);
+    };
+    if keep >= line.len() {
+        return Cow::Borrowed(line);
+    }
+    Cow::Owned([&line[..keep], b" ***"].concat())
+}
+
+/// A response decoded by [`ext_parse`].
+enum Extension {
+    SplitFetch(ext_parse::SplitFetch),
+    ESearch(ext_parse::ESearch),
+    Rewritten(ext_parse::Rewritten),
+    Placeholder(ext_parse::Placeholder),
+}
+
+/// Converts an error from parsing `buf` to an [`io::Error`] carrying a [`ParseError::Response`]
+/// with the data up to the end of the line that failed to parse, which is turned into an
+/// [`Error::Parse`](crate::error::Error::Parse) by the commands.
+fn parse_error(err: nom::Err<nom::error::Error<&[u8]>>, buf: &[u8]) -> io::Error {
+    let position = match &err {
+        nom::Err::Error(err) | nom::Err::Failure(err) => buf.len() - err.input.len(),
+        nom::Err::Incomplete(_) => buf.len(),
+    };
+    log::debug!("decode: {:?} at position {}", err, position);
+    // The buffer may hold many more responses, which are left out.
+    let end = buf[position..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map_or(buf.len(), |i| position + i + 1);
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        ParseError::Response {
+            data: buf[..end].to_vec(),
+            position,
+        },
+    )
+}
+
+/// A timer that expires if no data is read for the configured duration.
+#[derive(Default)]
+struct ReadTimeout {
+    duration: Option<Duration>,
+    /// Started once a read has to wait for data, and dropped whenever data arrives.
+    timer: Option<Pin<Box<dyn Future<Output = ()> + Send + Sync>>>,
+}
+
+impl ReadTimeout {
+    /// Starts the timer if needed, and returns whether it expired.
+    fn poll_expired(&mut self, cx: &mut Context<'_>) -> bool {
+        let duration = match self.duration {
+            Some(duration) => duration,
+            None => return false,
+        };
+        let timer = self
+            .timer
+            .get_or_insert_with(|| Box::pin(crate::runtime::sleep(duration)));
+        if timer.as_mut().poll(cx).is_ready() {
+            self.timer = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn reset(&mut self) {
+        self.timer = None;
+    }
+}
+
+impl fmt::Debug for ReadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadTimeout")
+            .field("duration", &self.duration)
+            .field("running", &self.timer.is_some())
+            .finish()
+    }
+}
+
 /// Abstraction around needed buffer management.
 struct Buffer {
     /// The buffer itself.
@@ -174,6 +722,17 @@ impl Buffer {
         self.offset
     }
 
+    /// Returns the part of the buffer containing data.
+    fn data(&self) -> &[u8] {
+        &self.block[..self.offset]
+    }
+
+    /// Removes the first `num_bytes` bytes of data from the buffer.
+    fn consume(&mut self, num_bytes: usize) {
+        self.block.copy_within(num_bytes..self.offset, 0);
+        self.offset -= num_bytes;
+    }
+
     /// Returns the unused part of the buffer to which new data can be written.
     fn free_as_mut_slice(&mut self) -> &mut [u8] {
         &mut self.block[self.offset..]
@@ -220,10 +779,7 @@ impl Buffer {
             n => min_size + (Self::BLOCK_SIZE - n),
         };
         if new_size > Self::MAX_CAPACITY {
-            Err(io::Error::new(
-                io::ErrorKind::Other,
-                "incoming data too large",
-            ))
+            Err(io::Error::other("incoming data too large"))
         } else {
             self.block.realloc(new_size);
             Ok(())
@@ -280,28 +836,19 @@ impl<R: Read + Write + Unpin> Stream for ImapStream<R> {
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let this = &mut *self;
+        this.check_bye()?;
         if let Some(response) = this.maybe_decode()? {
-            return Poll::Ready(Some(Ok(response)));
+            return Poll::Ready(Some(this.check_response(response)));
         }
         if this.closed {
             return Poll::Ready(this.stream_eof_value());
         }
         loop {
-            this.buffer.ensure_capacity(this.decode_needs)?;
-            let num_bytes_read =
-                match Pin::new(&mut this.inner).poll_read(cx, this.buffer.free_as_mut_slice()) {
-                    Poll::Ready(result) => result?,
-                    Poll::Pending => {
-                        return Poll::Pending;
-                    }
-                };
-            if num_bytes_read == 0 {
-                this.closed = true;
+            if ready!(this.poll_fill(cx, this.decode_needs))? == 0 {
                 return Poll::Ready(this.stream_eof_value());
             }
-            this.buffer.extend_used(num_bytes_read);
             if let Some(response) = this.maybe_decode()? {
-                return Poll::Ready(Some(Ok(response)));
+                return Poll::Ready(Some(this.check_response(response)));
             }
         }
     }
@@ -432,6 +979,43 @@ mod tests {
         assert_eq!(buf.block.size(), Buffer::BLOCK_SIZE);
     }
 
+    #[async_std::test]
+    async fn test_response_outlives_stream() {
+        let data = b"* 1 EXISTS\r\n* 2 EXISTS\r\n* 3 EXISTS\r\n".to_vec();
+        let mut stream = ImapStream::new(crate::mock_stream::MockStream::new(data));
+        let first = stream.next().await.unwrap().unwrap();
+        // Decoding further responses reuses and reallocates the buffer of the stream.
+        let second = stream.next().await.unwrap().unwrap();
+        drop(stream);
+        assert_eq!(
+            first.parsed(),
+            &Response::MailboxData(imap_proto::MailboxDatum::Exists(1))
+        );
+        assert_eq!(
+            second.parsed(),
+            &Response::MailboxData(imap_proto::MailboxDatum::Exists(2))
+        );
+    }
+
+    #[async_std::test]
+    async fn test_encode_dropped() {
+        let mock_stream = crate::mock_stream::MockStream::default().with_stalled_writes();
+        let mut stream = ImapStream::new(mock_stream);
+        {
+            let encode = stream.encode(Request(Some(RequestId("A0001".into())), b"NOOP".to_vec()));
+            futures::pin_mut!(encode);
+            // Writes only "A" of the command line.
+            assert!(futures::poll!(&mut encode).is_pending());
+            assert!(futures::poll!(&mut encode).is_pending());
+        }
+        assert_eq!(stream.inner.written_buf, b"A");
+        stream
+            .encode(Request(Some(RequestId("A0002".into())), b"NOOP".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(stream.inner.written_buf, b"A0001 NOOP\r\nA0002 NOOP\r\n");
+    }
+
     #[test]
     fn test_buffer_debug() {
         assert_eq!(
@@ -9,6 +9,9 @@
 //! challenge/response authentication respectively. This in turn gives you an authenticated
 //! [`Session`], which lets you access the mailboxes at the server.
 //!
+//! The crate runs on async-std by default.  To use it with tokio instead, disable the default
+//! features and enable `runtime-tokio`, see the [`runtime`] module.
+//!
 //! The documentation within this crate borrows heavily from the various RFCs, but should not be
 //! considered a complete reference. If anything is unclear, follow the links to the RFCs embedded
 //! in the documentation for the various types and methods and read the raw text there!
@@ -62,9 +65,6 @@
 #![warn(missing_docs)]
 #![deny(rust_2018_idioms, unsafe_code)]
 
-#[macro_use]
-extern crate pin_utils;
-
 #[macro_use]
 extern crate rental;
 
@@ -74,13 +74,28 @@ pub use imap_proto;
 mod authenticator;
 mod client;
 pub mod error;
+mod ext_parse;
 pub mod extensions;
 mod imap_stream;
 mod parse;
+mod proxy;
+mod reconnect;
+pub mod runtime;
+#[cfg(feature = "sasl")]
+mod scram;
 pub mod types;
+mod utf7;
 
-pub use crate::authenticator::Authenticator;
+#[cfg(feature = "sasl")]
+pub use crate::authenticator::CramMd5;
+pub use crate::authenticator::{Authenticator, OAuthBearer, XOAuth2};
 pub use crate::client::*;
+pub use crate::imap_stream::Direction;
+pub use crate::proxy::{connect_via_http_proxy, connect_via_socks5};
+pub use crate::reconnect::{Reconnected, ReconnectingSession, SessionConfig};
+#[cfg(feature = "sasl")]
+pub use crate::scram::ScramMechanism;
+pub use crate::utf7::{decode_mailbox_name, encode_mailbox_name};
 
-#[cfg(test)]
-mod mock_stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock_stream;
@@ -1,52 +1,95 @@
+//! An in-memory stream for testing code that uses this crate without a server, available with
+//! the `test-util` feature.
+//!
+//! A [`MockStream`] is given the server's responses up front, or as replies to the commands
+//! the client sends, and records everything the client writes:
+//!
+//! ```
+//! # async_std::task::block_on(async {
+//! use async_imap::mock_stream::MockStream;
+//!
+//! let stream = MockStream::new(b"* OK [CAPABILITY IMAP4rev1] ready\r\n".to_vec())
+//!     .with_reply(b"A0001 OK LOGIN completed\r\n")
+//!     .with_reply(b"A0002 OK NOOP completed\r\n");
+//! let client = async_imap::Client::from_stream(stream).await.unwrap();
+//! let mut session = client.login("user", "pass").await.map_err(|e| e.0).unwrap();
+//! session.noop().await.unwrap();
+//! assert_eq!(
+//!     session.get_ref().written_buf,
+//!     b"A0001 LOGIN \"user\" \"pass\"\r\nA0002 NOOP\r\n"
+//! );
+//! # });
+//! ```
+
 use std::cmp::min;
+use std::collections::VecDeque;
 use std::pin::Pin;
 
-use async_std::io::{Error, ErrorKind, Read, Result, Write};
+use futures::io::{AsyncRead as Read, AsyncWrite as Write, Error, ErrorKind, Result};
 use futures::task::{Context, Poll};
 
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+/// A stream that returns scripted server responses and records what the client writes.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct MockStream {
     read_buf: Vec<u8>,
     read_pos: usize,
+    /// Everything written to the stream so far.
     pub written_buf: Vec<u8>,
     err_on_read: bool,
     eof_on_read: bool,
     read_delay: usize,
-}
-
-impl Default for MockStream {
-    fn default() -> Self {
-        MockStream {
-            read_buf: Vec::new(),
-            read_pos: 0,
-            written_buf: Vec::new(),
-            err_on_read: false,
-            eof_on_read: false,
-            read_delay: 0,
-        }
-    }
+    pending_on_read: bool,
+    stall_writes: bool,
+    write_stalled: bool,
+    replies: VecDeque<Vec<u8>>,
 }
 
 impl MockStream {
+    /// Creates a stream from which `read_buf` is read.  Once it is exhausted, reading fails
+    /// with [`ErrorKind::UnexpectedEof`].
     pub fn new(read_buf: Vec<u8>) -> MockStream {
         MockStream::default().with_buf(read_buf)
     }
 
+    /// Replaces the data read from the stream with `read_buf`.
     pub fn with_buf(mut self, read_buf: Vec<u8>) -> MockStream {
         self.read_buf = read_buf;
         self
     }
 
+    /// Closes the connection once `read_buf` is exhausted.
     pub fn with_eof(mut self) -> MockStream {
         self.eof_on_read = true;
         self
     }
 
+    /// Fails every read.
     pub fn with_err(mut self) -> MockStream {
         self.err_on_read = true;
         self
     }
 
+    /// Never returns any data once `read_buf` is exhausted, like a stalled server.
+    pub fn with_pending(mut self) -> MockStream {
+        self.pending_on_read = true;
+        self
+    }
+
+    /// Queues `reply` to become readable after the next flush, like a server answering the
+    /// command just sent.
+    pub fn with_reply(mut self, reply: &[u8]) -> MockStream {
+        self.replies.push_back(reply.to_vec());
+        self
+    }
+
+    /// Accepts only a single byte per write and stalls every other write, like a congested
+    /// connection.
+    pub fn with_stalled_writes(mut self) -> MockStream {
+        self.stall_writes = true;
+        self
+    }
+
+    /// Returns only a single byte on the first read, to exercise handling of partial reads.
     pub fn with_delay(mut self) -> MockStream {
         self.read_delay = 1;
         self
@@ -59,13 +102,16 @@ impl Read for MockStream {
         _cx: &mut Context<'_>,
         buf: &mut [u8],
     ) -> Poll<Result<usize>> {
-        if self.eof_on_read {
-            return Poll::Ready(Ok(0));
-        }
         if self.err_on_read {
-            return Poll::Ready(Err(Error::new(ErrorKind::Other, "MockStream Error")));
+            return Poll::Ready(Err(Error::other("MockStream Error")));
         }
         if self.read_pos >= self.read_buf.len() {
+            if self.pending_on_read {
+                return Poll::Pending;
+            }
+            if self.eof_on_read {
+                return Poll::Ready(Ok(0));
+            }
             return Poll::Ready(Err(Error::new(ErrorKind::UnexpectedEof, "EOF")));
         }
         let mut write_len = min(buf.len(), self.read_buf.len() - self.read_pos);
@@ -85,14 +131,27 @@ impl Read for MockStream {
 impl Write for MockStream {
     fn poll_write(
         mut self: Pin<&mut Self>,
-        _cx: &mut Context<'_>,
+        cx: &mut Context<'_>,
         buf: &[u8],
     ) -> Poll<Result<usize>> {
+        if self.stall_writes {
+            self.write_stalled = !self.write_stalled;
+            if self.write_stalled {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let buf = &buf[..min(buf.len(), 1)];
+            self.written_buf.extend_from_slice(buf);
+            return Poll::Ready(Ok(buf.len()));
+        }
         self.written_buf.extend_from_slice(buf);
         Poll::Ready(Ok(buf.len()))
     }
 
-    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+    fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if let Some(reply) = self.replies.pop_front() {
+            self.read_buf.extend_from_slice(&reply);
+        }
         Poll::Ready(Ok(()))
     }
 
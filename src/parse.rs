@@ -1,49 +1,44 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 
-use async_std::channel;
-use async_std::io;
-use async_std::prelude::*;
-use async_std::stream::Stream;
+use futures::channel::mpsc;
+use futures::io;
+use futures::prelude::*;
 use imap_proto::{self, MailboxDatum, RequestId, Response};
 
 use crate::error::{Error, Result};
+use crate::ext_parse::ExtensionAttribute;
 use crate::types::ResponseData;
 use crate::types::*;
 
 pub(crate) fn parse_names<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
+    utf8: bool,
 ) -> impl Stream<Item = Result<Name>> + '_ + Send + Unpin {
     use futures::{FutureExt, StreamExt};
 
-    StreamExt::filter_map(
-        StreamExt::take_while(stream, move |res| filter(res, &command_tag)),
-        move |resp| {
-            let unsolicited = unsolicited.clone();
-            async move {
-                match resp {
-                    Ok(resp) => match resp.parsed() {
-                        Response::MailboxData(MailboxDatum::List { .. }) => {
-                            let name = Name::from_mailbox_data(resp);
-                            Some(Ok(name))
-                        }
-                        _ => {
-                            handle_unilateral(resp, unsolicited).await;
-                            None
-                        }
-                    },
-                    Err(err) => Some(Err(err.into())),
-                }
+    StreamExt::filter_map(take_responses(stream, command_tag), move |resp| {
+        let unsolicited = unsolicited.clone();
+        async move {
+            match resp {
+                Ok(resp) => match resp.parsed() {
+                    Response::MailboxData(MailboxDatum::List { .. }) => {
+                        let name = Name::from_mailbox_data(resp, utf8);
+                        Some(Ok(name))
+                    }
+                    _ => {
+                        handle_unilateral(resp, unsolicited).await;
+                        None
+                    }
+                },
+                Err(err) => Some(Err(err.into())),
             }
-            .boxed()
-        },
-    )
-}
-
-fn filter(res: &io::Result<ResponseData>, command_tag: &RequestId) -> impl Future<Output = bool> {
-    let val = filter_sync(res, command_tag);
-    futures::future::ready(val)
+        }
+        .boxed()
+    })
 }
 
 fn filter_sync(res: &io::Result<ResponseData>, command_tag: &RequestId) -> bool {
@@ -58,78 +53,251 @@ fn filter_sync(res: &io::Result<ResponseData>, command_tag: &RequestId) -> bool
 
 pub(crate) fn parse_fetches<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
 ) -> impl Stream<Item = Result<Fetch>> + '_ + Send + Unpin {
     use futures::{FutureExt, StreamExt};
 
-    StreamExt::filter_map(
-        StreamExt::take_while(stream, move |res| filter(res, &command_tag)),
-        move |resp| {
-            let unsolicited = unsolicited.clone();
-
-            async move {
-                match resp {
-                    Ok(resp) => match resp.parsed() {
-                        Response::Fetch(..) => Some(Ok(Fetch::new(resp))),
-                        _ => {
-                            handle_unilateral(resp, unsolicited).await;
-                            None
-                        }
-                    },
-                    Err(err) => Some(Err(err.into())),
-                }
+    let done_tag = command_tag.clone();
+    StreamExt::filter_map(take_until_done(stream, command_tag), move |resp| {
+        let unsolicited = unsolicited.clone();
+        let done_tag = done_tag.clone();
+
+        async move {
+            match resp {
+                Ok(resp) => match resp.parsed() {
+                    Response::Fetch(..) => Some(Ok(Fetch::new(resp))),
+                    Response::Done {
+                        tag,
+                        status,
+                        code,
+                        information,
+                    } if *tag == done_tag => {
+                        check_status_ok(status, code.as_ref(), information.as_deref())
+                            .err()
+                            .map(Err)
+                    }
+                    _ => {
+                        handle_unilateral(resp, unsolicited).await;
+                        None
+                    }
+                },
+                Err(err) => Some(Err(err.into())),
+            }
+        }
+        .boxed()
+    })
+}
+
+/// Returns the next response, or an `UnexpectedEof` error if the stream ended, as the command
+/// being read is not completed yet.
+async fn next_or_eof<T: Stream<Item = io::Result<ResponseData>> + Unpin>(
+    stream: &mut T,
+) -> io::Result<ResponseData> {
+    stream.next().await.unwrap_or_else(|| {
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "connection closed before the command completed",
+        ))
+    })
+}
+
+/// Yields the responses to the command `command_tag` up to, but not including, the tagged
+/// response completing it, which is consumed.  The stream ends after yielding the first error,
+/// which is also yielded if `stream` ends before the command is completed.
+fn take_responses<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    command_tag: RequestId,
+) -> impl Stream<Item = io::Result<ResponseData>> + '_ + Send + Unpin {
+    Box::pin(futures::stream::unfold(
+        Some((stream, command_tag)),
+        |state| async move {
+            let (stream, command_tag) = state?;
+            let res = next_or_eof(stream).await;
+            match res {
+                Ok(_) if !filter_sync(&res, &command_tag) => None,
+                Ok(_) => Some((res, Some((stream, command_tag)))),
+                Err(_) => Some((res, None)),
             }
-            .boxed()
         },
-    )
+    ))
+}
+
+/// Yields responses up to and including the tagged response completing `command_tag`, or up to
+/// and including the first error.  Responses after that are left in `stream`.
+fn take_until_done<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    command_tag: RequestId,
+) -> impl Stream<Item = io::Result<ResponseData>> + '_ + Send + Unpin {
+    Box::pin(futures::stream::unfold(
+        Some((stream, command_tag)),
+        |state| async move {
+            let (stream, command_tag) = state?;
+            let res = next_or_eof(stream).await;
+            let next_state = if filter_sync(&res, &command_tag) {
+                Some((stream, command_tag))
+            } else {
+                None
+            };
+            Some((res, next_state))
+        },
+    ))
+}
+
+/// Yields all responses to the command `command_tag`, including the tagged one completing it,
+/// without interpreting them.
+pub(crate) fn parse_raw<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    command_tag: RequestId,
+) -> impl Stream<Item = Result<ResponseData>> + '_ + Send + Unpin {
+    take_until_done(stream, command_tag).map(|res| res.map_err(Into::into))
 }
 
 pub(crate) fn parse_expunge<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
-) -> impl Stream<Item = Result<u32>> + '_ + Send {
+) -> impl Stream<Item = Result<Seq>> + '_ + Send {
     use futures::StreamExt;
 
-    StreamExt::filter_map(
-        StreamExt::take_while(stream, move |res| filter(res, &command_tag)),
-        move |resp| {
-            let unsolicited = unsolicited.clone();
-
-            async move {
-                match resp {
-                    Ok(resp) => match resp.parsed() {
-                        Response::Expunge(id) => Some(Ok(*id)),
-                        _ => {
-                            handle_unilateral(resp, unsolicited).await;
-                            None
-                        }
-                    },
-                    Err(err) => Some(Err(err.into())),
-                }
+    StreamExt::filter_map(take_responses(stream, command_tag), move |resp| {
+        let unsolicited = unsolicited.clone();
+
+        async move {
+            match resp {
+                Ok(resp) => match resp.parsed() {
+                    Response::Expunge(id) => Some(Ok(Seq(*id))),
+                    _ => {
+                        handle_unilateral(resp, unsolicited).await;
+                        None
+                    }
+                },
+                Err(err) => Some(Err(err.into())),
             }
-        },
-    )
+        }
+    })
 }
 
-pub(crate) async fn parse_capabilities<T: Stream<Item = io::Result<ResponseData>> + Unpin>(
+/// Turns the status of a tagged response into a `Result`.
+pub(crate) fn check_status_ok(
+    status: &imap_proto::Status,
+    code: Option<&imap_proto::ResponseCode<'_>>,
+    information: Option<&str>,
+) -> Result<()> {
+    use imap_proto::Status;
+    match status {
+        Status::Ok => Ok(()),
+        Status::Bad => Err(status_error(status, code, information)),
+        Status::No => {
+            if let Some(text) = strip_unknown_code(information, "UNKNOWN-CTE") {
+                return Err(Error::UnknownCte(text.to_string()));
+            }
+            if let Some(text) = strip_unknown_code(information, "NOPERM") {
+                return Err(Error::NoPerm(text.to_string()));
+            }
+            if let Some(text) = strip_unknown_code(information, "USEATTR") {
+                return Err(Error::UseAttr(text.to_string()));
+            }
+            Err(status_error(status, code, information))
+        }
+        _ => Err(status_error(status, code, information)),
+    }
+}
+
+/// Returns the error for a response with a status other than `OK`, i.e. [`Error::No`] or
+/// [`Error::Bad`], carrying the response code of the response.
+pub(crate) fn status_error(
+    status: &imap_proto::Status,
+    code: Option<&imap_proto::ResponseCode<'_>>,
+    information: Option<&str>,
+) -> Error {
+    use imap_proto::Status;
+    let (code, text) = match code {
+        Some(code) => (Some(code_text(code)), information.unwrap_or_default()),
+        None => split_unknown_code(information),
+    };
+    let code = code.as_deref().map(crate::types::ResponseCode::parse);
+    let text = text.to_string();
+    match status {
+        Status::No => Error::No { code, text },
+        Status::Bad => Error::Bad { code, text },
+        _ => Error::Io(io::Error::other(format!(
+            "status: {:?}, code: {:?}, information: {:?}",
+            status, code, text
+        ))),
+    }
+}
+
+/// Splits a response code that `imap-proto` does not know about, such as
+/// `[AUTHENTICATIONFAILED]`, off the start of `information`.
+fn split_unknown_code(information: Option<&str>) -> (Option<String>, &str) {
+    let information = information.unwrap_or_default();
+    let code = information
+        .strip_prefix('[')
+        .and_then(|text| text.split_once(']'));
+    match code {
+        Some((code, text)) => (Some(code.to_string()), text.trim_start()),
+        None => (None, information),
+    }
+}
+
+/// Returns a response code parsed by `imap-proto` as it is written in responses, without the
+/// brackets, and without the arguments of codes that have long ones.
+fn code_text(code: &imap_proto::ResponseCode<'_>) -> String {
+    use imap_proto::ResponseCode;
+    match code {
+        ResponseCode::Alert => "ALERT".into(),
+        ResponseCode::BadCharset(_) => "BADCHARSET".into(),
+        ResponseCode::Capabilities(_) => "CAPABILITY".into(),
+        ResponseCode::HighestModSeq(modseq) => format!("HIGHESTMODSEQ {}", modseq),
+        ResponseCode::Parse => "PARSE".into(),
+        ResponseCode::PermanentFlags(_) => "PERMANENTFLAGS".into(),
+        ResponseCode::ReadOnly => "READ-ONLY".into(),
+        ResponseCode::ReadWrite => "READ-WRITE".into(),
+        ResponseCode::TryCreate => "TRYCREATE".into(),
+        ResponseCode::UidNext(uid) => format!("UIDNEXT {}", uid),
+        ResponseCode::UidValidity(validity) => format!("UIDVALIDITY {}", validity),
+        ResponseCode::Unseen(seq) => format!("UNSEEN {}", seq),
+        ResponseCode::AppendUid(..) => "APPENDUID".into(),
+        ResponseCode::CopyUid(..) => "COPYUID".into(),
+        ResponseCode::UidNotSticky => "UIDNOTSTICKY".into(),
+        ResponseCode::MetadataLongEntries(size) => format!("METADATA LONGENTRIES {}", size),
+        ResponseCode::MetadataMaxSize(size) => format!("METADATA MAXSIZE {}", size),
+        ResponseCode::MetadataTooMany => "METADATA TOOMANY".into(),
+        ResponseCode::MetadataNoPrivate => "METADATA NOPRIVATE".into(),
+        code => format!("{:?}", code),
+    }
+}
+
+/// Checks whether `information` starts with the response code `[code]`, which `imap-proto`
+/// does not know about and therefore leaves in the text, and returns the remaining text.
+fn strip_unknown_code<'a>(information: Option<&'a str>, code: &str) -> Option<&'a str> {
+    let text = information?.strip_prefix('[')?;
+    let end = text.find(']')?;
+    let (name, rest) = text.split_at(end);
+    if name.eq_ignore_ascii_case(code) {
+        Some(rest[1..].trim_start())
+    } else {
+        None
+    }
+}
+
+pub(crate) async fn parse_capabilities<
+    T: Stream<Item = io::Result<ResponseData>> + Unpin + Send,
+>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
 ) -> Result<Capabilities> {
-    let mut caps: HashSet<Capability> = HashSet::new();
+    let mut caps = Capabilities::default();
 
-    while let Some(resp) = stream
-        .take_while(|res| filter_sync(res, &command_tag))
-        .next()
-        .await
-    {
+    let mut responses = take_responses(stream, command_tag);
+    while let Some(resp) = responses.next().await {
         let resp = resp?;
         match resp.parsed() {
             Response::Capabilities(cs) => {
                 for c in cs {
-                    caps.insert(Capability::from(c)); // TODO: avoid clone
+                    caps.insert(c);
                 }
             }
             _ => {
@@ -138,19 +306,33 @@ pub(crate) async fn parse_capabilities<T: Stream<Item = io::Result<ResponseData>
         }
     }
 
-    Ok(Capabilities(caps))
+    Ok(caps)
+}
+
+/// Returns the capabilities listed in a `CAPABILITY` response code, which servers may include
+/// in their greeting and in the response completing authentication.
+pub(crate) fn capabilities_code(
+    code: Option<&imap_proto::ResponseCode<'_>>,
+) -> Option<Capabilities> {
+    match code {
+        Some(imap_proto::ResponseCode::Capabilities(cs)) => {
+            let mut caps = Capabilities::default();
+            for c in cs {
+                caps.insert(c);
+            }
+            Some(caps)
+        }
+        _ => None,
+    }
 }
 
-pub(crate) async fn parse_noop<T: Stream<Item = io::Result<ResponseData>> + Unpin>(
+pub(crate) async fn parse_noop<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
 ) -> Result<()> {
-    while let Some(resp) = stream
-        .take_while(|res| filter_sync(res, &command_tag))
-        .next()
-        .await
-    {
+    let mut responses = take_responses(stream, command_tag);
+    while let Some(resp) = responses.next().await {
         let resp = resp?;
         handle_unilateral(resp, unsolicited.clone()).await;
     }
@@ -158,112 +340,168 @@ pub(crate) async fn parse_noop<T: Stream<Item = io::Result<ResponseData>> + Unpi
     Ok(())
 }
 
-pub(crate) async fn parse_mailbox<T: Stream<Item = io::Result<ResponseData>> + Unpin>(
+pub(crate) async fn parse_mailbox<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
 ) -> Result<Mailbox> {
-    let mut mailbox = Mailbox::default();
+    let (mailbox, _) = parse_mailbox_changes(stream, unsolicited, command_tag, false).await?;
+    Ok(mailbox)
+}
 
-    while let Some(resp) = stream
-        .take_while(|res| filter_sync(res, &command_tag))
-        .next()
-        .await
-    {
+/// Like [`parse_mailbox`], but if `collect` is set, the `FETCH` and `VANISHED` responses for the
+/// selected mailbox are returned instead of being passed on, as sent by `SELECT` with the
+/// `QRESYNC` parameter.
+pub(crate) async fn parse_mailbox_changes<
+    T: Stream<Item = io::Result<ResponseData>> + Unpin + Send,
+>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+    collect: bool,
+) -> Result<(Mailbox, Vec<ResponseData>)> {
+    let mut mailbox = Mailbox::default();
+    let mut changes = Vec::new();
+    // `EXISTS` and `RECENT` responses that belong to the previously selected mailbox if a
+    // `CLOSED` response follows.
+    let mut before_closed = Vec::new();
+
+    let done_tag = command_tag.clone();
+    let mut responses = take_until_done(stream, command_tag);
+    while let Some(resp) = responses.next().await {
         let resp = resp?;
+        let mut keep = false;
+        let mut closed = false;
         match resp.parsed() {
-            Response::Data {
+            Response::Done {
+                tag,
                 status,
                 code,
                 information,
+            } if *tag == done_tag => {
+                check_status_ok(status, code.as_ref(), information.as_deref())?;
+                mailbox.read_only = matches!(code, Some(imap_proto::ResponseCode::ReadOnly));
+            }
+            Response::Data {
+                status: imap_proto::Status::Ok,
+                code,
+                information,
             } => {
-                use imap_proto::Status;
-
-                match status {
-                    Status::Ok => {
-                        use imap_proto::ResponseCode;
-                        match code {
-                            Some(ResponseCode::UidValidity(uid)) => {
-                                mailbox.uid_validity = Some(*uid);
-                            }
-                            Some(ResponseCode::UidNext(unext)) => {
-                                mailbox.uid_next = Some(*unext);
-                            }
-                            Some(ResponseCode::Unseen(n)) => {
-                                mailbox.unseen = Some(*n);
-                            }
-                            Some(ResponseCode::PermanentFlags(flags)) => {
-                                mailbox
-                                    .permanent_flags
-                                    .extend(flags.iter().map(|s| (*s).to_string()).map(Flag::from));
-                            }
-                            _ => {}
-                        }
+                use imap_proto::ResponseCode;
+                match code {
+                    Some(ResponseCode::UidValidity(uid)) => {
+                        mailbox.uid_validity = Some(*uid);
                     }
-                    Status::Bad => {
-                        return Err(Error::Bad(format!(
-                            "code: {:?}, info: {:?}",
-                            code, information
-                        )))
+                    Some(ResponseCode::UidNext(unext)) => {
+                        mailbox.uid_next = Some(Uid(*unext));
                     }
-                    Status::No => {
-                        return Err(Error::No(format!(
-                            "code: {:?}, info: {:?}",
-                            code, information
-                        )))
+                    Some(ResponseCode::Unseen(n)) => {
+                        mailbox.first_unseen = Some(*n);
                     }
-                    _ => {
-                        return Err(Error::Io(io::Error::new(
-                            io::ErrorKind::Other,
-                            format!(
-                                "status: {:?}, code: {:?}, information: {:?}",
-                                status, code, information
-                            ),
-                        )));
+                    Some(ResponseCode::PermanentFlags(flags)) => {
+                        mailbox
+                            .permanent_flags
+                            .extend(flags.iter().map(|s| (*s).to_string()).map(Flag::from));
+                    }
+                    Some(ResponseCode::HighestModSeq(modseq)) => {
+                        mailbox.highest_modseq = Some(*modseq);
                     }
+                    Some(ResponseCode::Alert) => {
+                        handle_unilateral(resp, unsolicited.clone()).await;
+                        continue;
+                    }
+                    Some(code) => mailbox
+                        .other_codes
+                        .push(crate::types::ResponseCode::parse(&code_text(code))),
+                    None => match split_unknown_code(information.as_deref()) {
+                        (Some(code), _) if code.eq_ignore_ascii_case("NOMODSEQ") => {
+                            mailbox.no_modseq = true;
+                        }
+                        (Some(code), _) if code.eq_ignore_ascii_case("CLOSED") => closed = true,
+                        (Some(code), _) => match parse_mailbox_id(&code) {
+                            Some(id) => mailbox.mailbox_id = Some(id.to_string()),
+                            None => mailbox
+                                .other_codes
+                                .push(crate::types::ResponseCode::parse(&code)),
+                        },
+                        (None, _) => {}
+                    },
                 }
             }
             Response::MailboxData(m) => match m {
-                MailboxDatum::Status { .. } => handle_unilateral(resp, unsolicited.clone()).await,
                 MailboxDatum::Exists(e) => {
                     mailbox.exists = *e;
+                    keep = true;
                 }
                 MailboxDatum::Recent(r) => {
-                    mailbox.recent = *r;
+                    mailbox.recent = Some(*r);
+                    keep = true;
                 }
                 MailboxDatum::Flags(flags) => {
                     mailbox
                         .flags
                         .extend(flags.iter().map(|s| (*s).to_string()).map(Flag::from));
                 }
-                MailboxDatum::List { .. } => {}
-                MailboxDatum::MetadataSolicited { .. } => {}
-                MailboxDatum::MetadataUnsolicited { .. } => {}
-                MailboxDatum::Search { .. } => {}
-                MailboxDatum::Sort { .. } => {}
-                _ => {}
+                // `STATUS` responses may arrive at any time, e.g. with `NOTIFY`.  Other mailbox
+                // data such as `LIST` or `SEARCH` responses do not belong to `SELECT`, but are
+                // passed on rather than failing the command, so that a server sending them does
+                // not keep the mailbox from being opened.
+                MailboxDatum::Status { .. } => {
+                    handle_unilateral(resp, unsolicited.clone()).await;
+                    continue;
+                }
+                _ => {
+                    log::debug!("unexpected response while opening mailbox: {:?}", m);
+                    handle_unilateral(resp, unsolicited.clone()).await;
+                    continue;
+                }
             },
+            Response::Fetch(..) | Response::Vanished { .. } if collect => {
+                changes.push(resp);
+                continue;
+            }
+            // Untagged `NO` and `BAD` responses are warnings that do not make the command fail,
+            // see RFC 3501 section 7.1, so they are passed on like other unsolicited responses.
             _ => {
                 handle_unilateral(resp, unsolicited.clone()).await;
+                continue;
+            }
+        }
+        if keep {
+            before_closed.push(resp);
+        }
+        // When a mailbox is selected while another one is, servers supporting `QRESYNC` send
+        // `CLOSED` once they are done with the previous mailbox, so the responses before it
+        // are unsolicited updates about that mailbox, see RFC 7162 section 3.2.11.
+        if closed {
+            mailbox = Mailbox::default();
+            for resp in before_closed.drain(..).chain(changes.drain(..)) {
+                handle_unilateral(resp, unsolicited.clone()).await;
             }
         }
     }
 
-    Ok(mailbox)
+    Ok((mailbox, changes))
+}
+
+/// Returns the identifier in a `MAILBOXID (<id>)` response code.
+fn parse_mailbox_id(code: &str) -> Option<&str> {
+    let (name, id) = code.split_once(' ')?;
+    if !name.eq_ignore_ascii_case("MAILBOXID") {
+        return None;
+    }
+    id.strip_prefix('(')?.strip_suffix(')')
 }
 
-pub(crate) async fn parse_ids<T: Stream<Item = io::Result<ResponseData>> + Unpin>(
+pub(crate) async fn parse_ids<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
     stream: &mut T,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
+    unsolicited: UnsolicitedSender,
     command_tag: RequestId,
 ) -> Result<HashSet<u32>> {
     let mut ids: HashSet<u32> = HashSet::new();
 
-    while let Some(resp) = stream
-        .take_while(|res| filter_sync(res, &command_tag))
-        .next()
-        .await
-    {
+    let mut responses = take_responses(stream, command_tag);
+    while let Some(resp) = responses.next().await {
         let resp = resp?;
         match resp.parsed() {
             Response::MailboxData(MailboxDatum::Search(cs)) => {
@@ -280,62 +518,472 @@ pub(crate) async fn parse_ids<T: Stream<Item = io::Result<ResponseData>> + Unpin
     Ok(ids)
 }
 
-// check if this is simply a unilateral server response
-// (see Section 7 of RFC 3501):
-pub(crate) async fn handle_unilateral(
-    res: ResponseData,
-    unsolicited: channel::Sender<UnsolicitedResponse>,
-) {
-    // ignore these if they are not being consumed
-    if unsolicited.is_full() {
-        return;
+pub(crate) async fn parse_fuzzy_search<
+    T: Stream<Item = io::Result<ResponseData>> + Unpin + Send,
+>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<FuzzySearch> {
+    let mut search = FuzzySearch::default();
+
+    let mut responses = take_responses(stream, command_tag);
+    while let Some(resp) = responses.next().await {
+        let resp = resp?;
+        match resp.parsed() {
+            Response::MailboxData(MailboxDatum::Search(ids)) => {
+                search.ids.extend(ids);
+                for extension in resp.extensions() {
+                    if let ExtensionAttribute::Relevancy(scores) = extension {
+                        search.relevancy.get_or_insert_with(Vec::new).extend(scores);
+                    }
+                }
+            }
+            _ => {
+                handle_unilateral(resp, unsolicited.clone()).await;
+            }
+        }
     }
 
-    match res.parsed() {
-        Response::MailboxData(MailboxDatum::Status { mailbox, status }) => {
-            unsolicited
-                .send(UnsolicitedResponse::Status {
-                    mailbox: (mailbox.as_ref()).into(),
-                    attributes: status.iter().cloned().collect(),
-                })
-                .await
-                .expect("Channel closed unexpectedly");
+    Ok(search)
+}
+
+pub(crate) async fn parse_conditional_store<
+    T: Stream<Item = io::Result<ResponseData>> + Unpin + Send,
+>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<ConditionalStore> {
+    let mut fetches = Vec::new();
+    let mut modified = Vec::new();
+    // An invalid `MODIFIED` code is only returned once the command completed.
+    let mut invalid = None;
+    let mut responses = take_until_done(stream, command_tag.clone());
+
+    while let Some(resp) = responses.next().await {
+        let resp = resp?;
+        match resp.parsed() {
+            Response::Fetch(..) => fetches.push(Fetch::new(resp)),
+            Response::Data {
+                status: imap_proto::Status::Ok,
+                information: Some(information),
+                ..
+            } => match parse_modified(information) {
+                Some(Ok(ids)) => modified.extend(ids),
+                Some(Err(err)) => invalid = invalid.or(Some(err)),
+                None => handle_unilateral(resp, unsolicited.clone()).await,
+            },
+            Response::Done {
+                tag,
+                status,
+                code,
+                information,
+            } if *tag == command_tag => {
+                check_status_ok(status, code.as_ref(), information.as_deref())?;
+                if let Some(ids) = information.as_deref().and_then(parse_modified) {
+                    modified.extend(ids?);
+                }
+            }
+            _ => {
+                handle_unilateral(resp, unsolicited.clone()).await;
+            }
+        }
+    }
+    if let Some(err) = invalid {
+        return Err(err);
+    }
+
+    Ok(ConditionalStore { fetches, modified })
+}
+
+pub(crate) async fn parse_metadata<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<HashMap<String, Option<String>>> {
+    let mut entries = HashMap::new();
+    let mut responses = take_until_done(stream, command_tag.clone());
+
+    while let Some(resp) = responses.next().await {
+        let resp = resp?;
+        match resp.parsed() {
+            Response::MailboxData(MailboxDatum::MetadataSolicited { values, .. }) => {
+                for metadata in values {
+                    entries.insert(metadata.entry.clone(), metadata.value.clone());
+                }
+            }
+            Response::Done {
+                tag,
+                status,
+                code,
+                information,
+            } if *tag == command_tag => {
+                check_status_ok(status, code.as_ref(), information.as_deref())?;
+            }
+            _ => {
+                handle_unilateral(resp, unsolicited.clone()).await;
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+pub(crate) async fn parse_quota<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<(Vec<QuotaRoot>, Vec<Quota>)> {
+    let mut quota_roots = Vec::new();
+    let mut quotas = Vec::new();
+    let mut responses = take_until_done(stream, command_tag.clone());
+
+    while let Some(resp) = responses.next().await {
+        let resp = resp?;
+        match resp.parsed() {
+            Response::QuotaRoot(quota_root) => quota_roots.push(QuotaRoot::from(quota_root)),
+            Response::Quota(quota) => quotas.push(Quota::from(quota)),
+            Response::Done {
+                tag,
+                status,
+                code,
+                information,
+            } if *tag == command_tag => {
+                check_status_ok(status, code.as_ref(), information.as_deref())?;
+            }
+            _ => {
+                handle_unilateral(resp, unsolicited.clone()).await;
+            }
         }
-        Response::MailboxData(MailboxDatum::Recent(n)) => {
-            unsolicited
-                .send(UnsolicitedResponse::Recent(*n))
-                .await
-                .expect("Channel closed unexpectedly");
+    }
+
+    Ok((quota_roots, quotas))
+}
+
+pub(crate) async fn parse_acl<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<Acl>> {
+    collect_until_done(stream, unsolicited, command_tag, |resp| {
+        match resp.parsed() {
+            Response::Acl(acl) => Some(Acl::from(acl)),
+            _ => None,
         }
-        Response::MailboxData(MailboxDatum::Exists(n)) => {
-            unsolicited
-                .send(UnsolicitedResponse::Exists(*n))
-                .await
-                .expect("Channel closed unexpectedly");
+    })
+    .await
+}
+
+pub(crate) async fn parse_list_rights<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<ListRights>> {
+    collect_until_done(stream, unsolicited, command_tag, |resp| {
+        match resp.parsed() {
+            Response::ListRights(list_rights) => Some(ListRights::from(list_rights)),
+            _ => None,
         }
-        Response::Expunge(n) => {
-            unsolicited
-                .send(UnsolicitedResponse::Expunge(*n))
-                .await
-                .expect("Channel closed unexpectedly");
+    })
+    .await
+}
+
+pub(crate) async fn parse_my_rights<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<MyRights>> {
+    collect_until_done(stream, unsolicited, command_tag, |resp| {
+        match resp.parsed() {
+            Response::MyRights(my_rights) => Some(MyRights::from(my_rights)),
+            _ => None,
         }
-        _ => {
-            unsolicited
-                .send(UnsolicitedResponse::Other(res))
-                .await
-                .expect("Channel closed unexpectedly");
+    })
+    .await
+}
+
+pub(crate) async fn parse_status<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<StatusAttribute>> {
+    let attributes = collect_until_done(stream, unsolicited, command_tag, |resp| {
+        match resp.parsed() {
+            Response::MailboxData(MailboxDatum::Status { status, .. }) => Some(status.clone()),
+            _ => None,
         }
+    })
+    .await?;
+    Ok(attributes.into_iter().flatten().collect())
+}
+
+pub(crate) async fn parse_genurlauth<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<String>> {
+    let urls = collect_until_done(stream, unsolicited, command_tag, |resp| {
+        resp.extensions()
+            .iter()
+            .find_map(|extension| match extension {
+                ExtensionAttribute::GenUrlAuth(urls) => Some(urls.clone()),
+                _ => None,
+            })
+    })
+    .await?;
+    Ok(urls.into_iter().flatten().collect())
+}
+
+pub(crate) async fn parse_urlfetch<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<UrlFetch>> {
+    let items = collect_until_done(stream, unsolicited, command_tag, |resp| {
+        resp.extensions()
+            .iter()
+            .find_map(|extension| match extension {
+                ExtensionAttribute::UrlFetch(items) => Some(items.clone()),
+                _ => None,
+            })
+    })
+    .await?;
+    Ok(items
+        .into_iter()
+        .flatten()
+        .map(|(url, data)| UrlFetch { url, data })
+        .collect())
+}
+
+pub(crate) async fn parse_thread<T: Stream<Item = io::Result<ResponseData>> + Unpin + Send>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+) -> Result<Vec<ThreadNode<u32>>> {
+    let threads = collect_until_done(stream, unsolicited, command_tag, |resp| {
+        resp.extensions()
+            .iter()
+            .find_map(|extension| match extension {
+                ExtensionAttribute::Thread(threads) => Some(threads.clone()),
+                _ => None,
+            })
+    })
+    .await?;
+    Ok(threads.into_iter().flatten().collect())
+}
+
+/// Collects what `select` extracts from the responses to the command `command_tag`, passing all
+/// other responses on to [`handle_unilateral`], and checks the status the command completes with.
+async fn collect_until_done<T, R, F>(
+    stream: &mut T,
+    unsolicited: UnsolicitedSender,
+    command_tag: RequestId,
+    mut select: F,
+) -> Result<Vec<R>>
+where
+    T: Stream<Item = io::Result<ResponseData>> + Unpin + Send,
+    F: FnMut(&ResponseData) -> Option<R>,
+{
+    let mut selected = Vec::new();
+    let mut responses = take_until_done(stream, command_tag.clone());
+
+    while let Some(resp) = responses.next().await {
+        let resp = resp?;
+        if let Response::Done {
+            tag,
+            status,
+            code,
+            information,
+        } = resp.parsed()
+        {
+            if *tag == command_tag {
+                check_status_ok(status, code.as_ref(), information.as_deref())?;
+                continue;
+            }
+        }
+        match select(&resp) {
+            Some(item) => selected.push(item),
+            None => handle_unilateral(resp, unsolicited.clone()).await,
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Returns the text of a response with the `ALERT` response code.
+pub(crate) fn alert_text(res: &ResponseData) -> Option<String> {
+    match res.parsed() {
+        Response::Data {
+            code: Some(imap_proto::ResponseCode::Alert),
+            information,
+            ..
+        }
+        | Response::Done {
+            code: Some(imap_proto::ResponseCode::Alert),
+            information,
+            ..
+        } => Some(information.as_deref().unwrap_or("").to_string()),
+        _ => None,
     }
 }
 
+/// The sending half of [`Session::unsolicited_responses`](crate::Session::unsolicited_responses).
+#[derive(Debug, Clone)]
+pub(crate) struct UnsolicitedSender {
+    /// Shared between all clones, as each `mpsc::Sender` may add one response beyond the
+    /// capacity of the channel, which would make it unbounded with a clone per command.
+    tx: Arc<Mutex<mpsc::Sender<UnsolicitedResponse>>>,
+    /// The mailbox that `EXISTS`, `RECENT`, `EXPUNGE` and `FETCH` responses are reported for as
+    /// [`UnsolicitedResponse::Notification`]s, set while `NOTIFY` is active.
+    pub(crate) notify_mailbox: Option<String>,
+    /// The number of messages in the selected mailbox, as last reported by the server.  Shared
+    /// between all clones, so that it is kept up to date by every command.
+    exists: Arc<AtomicU32>,
+    /// The number of responses dropped because the channel was full or closed.  Shared between
+    /// all clones, like `exists`.
+    dropped: Arc<AtomicU64>,
+}
+
+impl UnsolicitedSender {
+    pub(crate) fn new(tx: mpsc::Sender<UnsolicitedResponse>) -> Self {
+        UnsolicitedSender {
+            tx: Arc::new(Mutex::new(tx)),
+            notify_mailbox: None,
+            exists: Arc::new(AtomicU32::new(0)),
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// The number of messages in the selected mailbox, as last reported by the server.
+    pub(crate) fn exists(&self) -> u32 {
+        self.exists.load(Ordering::Relaxed)
+    }
+
+    /// Sets the number of messages in the selected mailbox, e.g. after selecting it.
+    pub(crate) fn set_exists(&self, exists: u32) {
+        self.exists.store(exists, Ordering::Relaxed);
+    }
+
+    /// Updates the number of messages in the selected mailbox for an `EXISTS`, `EXPUNGE` or
+    /// `VANISHED` response, including those that are returned to the caller instead of being
+    /// passed to [`handle_unilateral`], such as during `IDLE`.
+    pub(crate) fn track_exists(&self, response: &Response<'_>) {
+        let removed = match response {
+            Response::MailboxData(MailboxDatum::Exists(n)) => {
+                self.set_exists(*n);
+                return;
+            }
+            Response::Expunge(_) => 1,
+            Response::Vanished {
+                earlier: false,
+                uids,
+            } => uids
+                .iter()
+                .map(|range| u64::from(range.end().saturating_sub(*range.start())) + 1)
+                .sum(),
+            _ => return,
+        };
+        let _ = self
+            .exists
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |exists| {
+                Some(u64::from(exists).saturating_sub(removed) as u32)
+            });
+    }
+
+    /// The number of responses dropped so far by [`UnsolicitedSender::try_send`].
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Sends `response` unless the channel is full or closed, in which case it is dropped and
+    /// counted, see [`handle_unilateral`].
+    pub(crate) fn try_send(&self, response: UnsolicitedResponse) {
+        // A full channel is not an error.
+        if let Err(err) = self.tx.lock().unwrap().try_send(response) {
+            // Only the first drop is logged, the others are counted.
+            if self.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                log::warn!(
+                    "dropping unsolicited responses, the channel is {}",
+                    if err.is_full() { "full" } else { "closed" }
+                );
+            }
+        }
+    }
+}
+
+/// Sends `res` to the channel of unsolicited responses. Servers may send such unilateral
+/// responses at any time, see [RFC 3501](https://tools.ietf.org/html/rfc3501#section-7).
+///
+/// This never waits for the channel: if it is full because the responses are not being consumed,
+/// `res` is dropped and counted, so that an unread channel can not stall command processing.
+pub(crate) async fn handle_unilateral(res: ResponseData, unsolicited: UnsolicitedSender) {
+    send_unilateral(res, &unsolicited);
+}
+
+/// Like [`handle_unilateral`], for use where nothing can be awaited, e.g. in a stream adapter.
+pub(crate) fn send_unilateral(res: ResponseData, unsolicited: &UnsolicitedSender) {
+    unsolicited.track_exists(res.parsed());
+    let response = if let Some(alert) = alert_text(&res) {
+        UnsolicitedResponse::Alert(alert)
+    } else {
+        match res.parsed() {
+            Response::MailboxData(MailboxDatum::Status { mailbox, status }) => {
+                UnsolicitedResponse::Status {
+                    mailbox: (mailbox.as_ref()).into(),
+                    attributes: status.to_vec(),
+                }
+            }
+            Response::MailboxData(MailboxDatum::Recent(n)) => UnsolicitedResponse::Recent(*n),
+            Response::MailboxData(MailboxDatum::Exists(n)) => UnsolicitedResponse::Exists(*n),
+            Response::Expunge(n) => UnsolicitedResponse::Expunge(*n),
+            Response::Vanished { earlier, uids } => UnsolicitedResponse::Vanished {
+                earlier: *earlier,
+                uids: uids
+                    .iter()
+                    .map(|range| Uid(*range.start())..=Uid(*range.end()))
+                    .collect(),
+            },
+            _ => UnsolicitedResponse::Other(res),
+        }
+    };
+    let response = match (unsolicited.notify_mailbox.as_ref(), response) {
+        (
+            Some(mailbox),
+            response @ (UnsolicitedResponse::Recent(_)
+            | UnsolicitedResponse::Exists(_)
+            | UnsolicitedResponse::Expunge(_)
+            | UnsolicitedResponse::Vanished { .. }),
+        ) => UnsolicitedResponse::Notification {
+            mailbox: mailbox.clone(),
+            response: Box::new(response),
+        },
+        (Some(mailbox), UnsolicitedResponse::Other(res))
+            if matches!(res.parsed(), Response::Fetch(..)) =>
+        {
+            UnsolicitedResponse::Notification {
+                mailbox: mailbox.clone(),
+                response: Box::new(UnsolicitedResponse::Other(res)),
+            }
+        }
+        (_, response) => response,
+    };
+    unsolicited.try_send(response);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn unsolicited_channel() -> (UnsolicitedSender, mpsc::Receiver<UnsolicitedResponse>) {
+        let (send, recv) = mpsc::channel(10);
+        (UnsolicitedSender::new(send), recv)
+    }
+
     fn input_stream(data: &[&str]) -> Vec<io::Result<ResponseData>> {
         data.iter()
             .map(|line| {
-                let mut block = crate::imap_stream::POOL.alloc(line.as_bytes().len());
+                let mut block = crate::imap_stream::POOL.alloc(line.len());
                 block.copy_from_slice(line.as_bytes());
                 ResponseData::try_new(block, |bytes| -> io::Result<_> {
                     let (remaining, response) = imap_proto::parser::parse_response(bytes).unwrap();
@@ -347,18 +995,55 @@ mod tests {
             .collect()
     }
 
+    #[test]
+    fn status_errors() {
+        use imap_proto::{ResponseCode, Status};
+
+        match check_status_ok(&Status::No, None, Some("[NONEXISTENT] Unknown mailbox")) {
+            Err(Error::No { code, text }) => {
+                assert_eq!(code, Some(crate::types::ResponseCode::NonExistent));
+                assert_eq!(text, "Unknown mailbox");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match check_status_ok(
+            &Status::No,
+            Some(&ResponseCode::TryCreate),
+            Some("No such mailbox"),
+        ) {
+            Err(Error::No { code, text }) => {
+                assert_eq!(code, Some(crate::types::ResponseCode::TryCreate));
+                assert_eq!(text, "No such mailbox");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match check_status_ok(&Status::Bad, None, Some("Missing argument")) {
+            Err(Error::Bad { code, text }) => {
+                assert_eq!(code, None);
+                assert_eq!(text, "Missing argument");
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match check_status_ok(&Status::Bad, None, None) {
+            Err(Error::Bad { code, text }) => assert_eq!((code, text), (None, String::new())),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
     #[async_std::test]
     async fn parse_capability_test() {
         let expected_capabilities = &["IMAP4rev1", "STARTTLS", "AUTH=GSSAPI", "LOGINDISABLED"];
-        let responses =
-            input_stream(&["* CAPABILITY IMAP4rev1 STARTTLS AUTH=GSSAPI LOGINDISABLED\r\n"]);
+        let responses = input_stream(&[
+            "* CAPABILITY IMAP4rev1 STARTTLS AUTH=GSSAPI LOGINDISABLED\r\n",
+            "A0001 OK CAPABILITY completed\r\n",
+        ]);
 
         let mut stream = async_std::stream::from_iter(responses);
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let id = RequestId("A0001".into());
         let capabilities = parse_capabilities(&mut stream, send, id).await.unwrap();
         // shouldn't be any unexpected responses parsed
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         assert_eq!(capabilities.len(), 4);
         for e in expected_capabilities {
             assert!(capabilities.has_str(e));
@@ -369,15 +1054,18 @@ mod tests {
     async fn parse_capability_case_insensitive_test() {
         // Test that "IMAP4REV1" (instead of "IMAP4rev1") is accepted
         let expected_capabilities = &["IMAP4rev1", "STARTTLS"];
-        let responses = input_stream(&["* CAPABILITY IMAP4REV1 STARTTLS\r\n"]);
+        let responses = input_stream(&[
+            "* CAPABILITY IMAP4REV1 STARTTLS\r\n",
+            "A0001 OK CAPABILITY completed\r\n",
+        ]);
         let mut stream = async_std::stream::from_iter(responses);
 
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let id = RequestId("A0001".into());
         let capabilities = parse_capabilities(&mut stream, send, id).await.unwrap();
 
         // shouldn't be any unexpected responses parsed
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         assert_eq!(capabilities.len(), 2);
         for e in expected_capabilities {
             assert!(capabilities.has_str(e));
@@ -387,7 +1075,7 @@ mod tests {
     #[async_std::test]
     #[should_panic]
     async fn parse_capability_invalid_test() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&["* JUNK IMAP4rev1 STARTTLS AUTH=GSSAPI LOGINDISABLED\r\n"]);
         let mut stream = async_std::stream::from_iter(responses);
 
@@ -395,21 +1083,24 @@ mod tests {
         parse_capabilities(&mut stream, send.clone(), id)
             .await
             .unwrap();
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
     }
 
     #[async_std::test]
     async fn parse_names_test() {
-        let (send, recv) = channel::bounded(10);
-        let responses = input_stream(&["* LIST (\\HasNoChildren) \".\" \"INBOX\"\r\n"]);
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* LIST (\\HasNoChildren) \".\" \"INBOX\"\r\n",
+            "A0001 OK LIST completed\r\n",
+        ]);
         let mut stream = async_std::stream::from_iter(responses);
 
         let id = RequestId("A0001".into());
-        let names: Vec<_> = parse_names(&mut stream, send, id)
-            .collect::<Result<Vec<Name>>>()
+        let names: Vec<_> = parse_names(&mut stream, send, id, false)
+            .try_collect::<Vec<Name>>()
             .await
             .unwrap();
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         assert_eq!(names.len(), 1);
         assert_eq!(
             names[0].attributes(),
@@ -421,39 +1112,40 @@ mod tests {
 
     #[async_std::test]
     async fn parse_fetches_empty() {
-        let (send, recv) = channel::bounded(10);
-        let responses = input_stream(&[]);
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&["a OK FETCH completed\r\n"]);
         let mut stream = async_std::stream::from_iter(responses);
         let id = RequestId("a".into());
 
         let fetches = parse_fetches(&mut stream, send, id)
-            .collect::<Result<Vec<_>>>()
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         assert!(fetches.is_empty());
     }
 
     #[async_std::test]
     async fn parse_fetches_test() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&[
             "* 24 FETCH (FLAGS (\\Seen) UID 4827943)\r\n",
             "* 25 FETCH (FLAGS (\\Seen))\r\n",
+            "a OK FETCH completed\r\n",
         ]);
         let mut stream = async_std::stream::from_iter(responses);
         let id = RequestId("a".into());
 
         let fetches = parse_fetches(&mut stream, send, id)
-            .collect::<Result<Vec<_>>>()
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
 
         assert_eq!(fetches.len(), 2);
         assert_eq!(fetches[0].message, 24);
         assert_eq!(fetches[0].flags().collect::<Vec<_>>(), vec![Flag::Seen]);
-        assert_eq!(fetches[0].uid, Some(4827943));
+        assert_eq!(fetches[0].uid, Some(Uid(4827943)));
         assert_eq!(fetches[0].body(), None);
         assert_eq!(fetches[0].header(), None);
         assert_eq!(fetches[1].message, 25);
@@ -463,41 +1155,153 @@ mod tests {
         assert_eq!(fetches[1].header(), None);
     }
 
+    #[async_std::test]
+    async fn parse_fetches_no() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 1 FETCH (UID 3)\r\n",
+            "a NO Some of the requested messages no longer exist\r\n",
+            "* 2 EXISTS\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("a".into());
+
+        let fetches = parse_fetches(&mut stream, send, id)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(recv.try_recv().is_err());
+
+        assert_eq!(fetches.len(), 2);
+        assert_eq!(fetches[0].as_ref().unwrap().uid, Some(Uid(3)));
+        match &fetches[1] {
+            Err(Error::No { text, .. }) => {
+                assert_eq!(text, "Some of the requested messages no longer exist")
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+        // The stream ends with the tagged response.
+        assert!(stream.next().await.is_some());
+    }
+
+    #[async_std::test]
+    async fn parse_fetches_w_modseq() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 1 FETCH (MODSEQ (624140003))\r\n",
+            "* 2 FETCH (UID 7 FLAGS (\\Seen) MODSEQ (90060128194045007))\r\n",
+            "* 3 FETCH (UID 8)\r\n",
+            "a OK FETCH completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("a".into());
+
+        let fetches = parse_fetches(&mut stream, send, id)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(recv.try_recv().is_err());
+
+        assert_eq!(fetches.len(), 3);
+        assert_eq!(fetches[0].mod_seq(), Some(624140003));
+        assert_eq!(fetches[1].mod_seq(), Some(90060128194045007));
+        assert_eq!(fetches[1].uid, Some(Uid(7)));
+        assert_eq!(fetches[2].mod_seq(), None);
+    }
+
     #[async_std::test]
     async fn parse_fetches_w_unilateral() {
         // https://github.com/mattnenterprise/rust-imap/issues/81
-        let (send, recv) = channel::bounded(10);
-        let responses = input_stream(&["* 37 FETCH (UID 74)\r\n", "* 1 RECENT\r\n"]);
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 37 FETCH (UID 74)\r\n",
+            "* 1 RECENT\r\n",
+            "a OK FETCH completed\r\n",
+        ]);
         let mut stream = async_std::stream::from_iter(responses);
         let id = RequestId("a".into());
 
         let fetches = parse_fetches(&mut stream, send, id)
-            .collect::<Result<Vec<_>>>()
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert_eq!(recv.recv().await.unwrap(), UnsolicitedResponse::Recent(1));
+        assert_eq!(recv.next().await.unwrap(), UnsolicitedResponse::Recent(1));
 
         assert_eq!(fetches.len(), 1);
         assert_eq!(fetches[0].message, 37);
-        assert_eq!(fetches[0].uid, Some(74));
+        assert_eq!(fetches[0].uid, Some(Uid(74)));
+    }
+
+    #[async_std::test]
+    async fn parse_fetches_w_vanished() {
+        let (send, mut recv) = unsolicited_channel();
+        send.set_exists(5);
+        let responses = input_stream(&[
+            "* VANISHED (EARLIER) 1:2,4\r\n",
+            "* 1 FETCH (UID 3 FLAGS (\\Seen) MODSEQ (12))\r\n",
+            "* VANISHED 5\r\n",
+            "A0001 OK UID FETCH completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0001".into());
+        let fetches = parse_fetches(&mut stream, send.clone(), id)
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(fetches.len(), 1);
+        assert_eq!(fetches[0].uid, Some(Uid(3)));
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Vanished {
+                earlier: true,
+                uids: vec![Uid(1)..=Uid(2), Uid(4)..=Uid(4)],
+            }
+        );
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Vanished {
+                earlier: false,
+                uids: vec![Uid(5)..=Uid(5)],
+            }
+        );
+        assert_eq!(send.exists(), 4);
+    }
+
+    #[async_std::test]
+    async fn vanished_all() {
+        let (send, mut recv) = unsolicited_channel();
+        send.set_exists(5);
+        let responses = input_stream(&["* VANISHED 1:4294967295\r\n", "a OK NOOP completed\r\n"]);
+        let mut stream = async_std::stream::from_iter(responses);
+        parse_noop(&mut stream, send.clone(), RequestId("a".into()))
+            .await
+            .unwrap();
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Vanished {
+                earlier: false,
+                uids: vec![Uid(1)..=Uid(u32::MAX)],
+            }
+        );
+        assert_eq!(send.exists(), 0);
     }
 
     #[async_std::test]
     async fn parse_names_w_unilateral() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&[
             "* LIST (\\HasNoChildren) \".\" \"INBOX\"\r\n",
             "* 4 EXPUNGE\r\n",
+            "A0001 OK LIST completed\r\n",
         ]);
         let mut stream = async_std::stream::from_iter(responses);
 
         let id = RequestId("A0001".into());
-        let names = parse_names(&mut stream, send, id)
-            .collect::<Result<Vec<_>>>()
+        let names = parse_names(&mut stream, send, id, false)
+            .try_collect::<Vec<_>>()
             .await
             .unwrap();
 
-        assert_eq!(recv.recv().await.unwrap(), UnsolicitedResponse::Expunge(4));
+        assert_eq!(recv.next().await.unwrap(), UnsolicitedResponse::Expunge(4));
 
         assert_eq!(names.len(), 1);
         assert_eq!(
@@ -510,11 +1314,12 @@ mod tests {
 
     #[async_std::test]
     async fn parse_capabilities_w_unilateral() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&[
             "* CAPABILITY IMAP4rev1 STARTTLS AUTH=GSSAPI LOGINDISABLED\r\n",
             "* STATUS dev.github (MESSAGES 10 UIDNEXT 11 UIDVALIDITY 1408806928 UNSEEN 0)\r\n",
             "* 4 EXISTS\r\n",
+            "A0001 OK CAPABILITY completed\r\n",
         ]);
         let mut stream = async_std::stream::from_iter(responses);
 
@@ -529,7 +1334,7 @@ mod tests {
         }
 
         assert_eq!(
-            recv.recv().await.unwrap(),
+            recv.next().await.unwrap(),
             UnsolicitedResponse::Status {
                 mailbox: "dev.github".to_string(),
                 attributes: vec![
@@ -540,16 +1345,214 @@ mod tests {
                 ]
             }
         );
-        assert_eq!(recv.recv().await.unwrap(), UnsolicitedResponse::Exists(4));
+        assert_eq!(recv.next().await.unwrap(), UnsolicitedResponse::Exists(4));
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_recent() {
+        let (send, _recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 3 EXISTS\r\n",
+            "* 0 RECENT\r\n",
+            "A0001 OK SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send.clone(), id).await.unwrap();
+        assert_eq!(mailbox.recent, Some(0));
+
+        // IMAP4rev2 servers do not send RECENT.
+        let responses = input_stream(&["* 3 EXISTS\r\n", "A0002 OK SELECT completed\r\n"]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0002".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+        assert_eq!(mailbox.recent, None);
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_permanent_flags() {
+        let (send, _recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* OK [PERMANENTFLAGS (\\Deleted \\Seen \\*)] Limited\r\n",
+            "A0001 OK SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send.clone(), id).await.unwrap();
+        let permanent_flags = mailbox.permanent_flags;
+        assert_eq!(permanent_flags.flags, vec![Flag::Deleted, Flag::Seen]);
+        assert!(permanent_flags.can_create_keywords);
+        assert!(permanent_flags.can_store(&Flag::Seen));
+        assert!(permanent_flags.can_store(&Flag::Custom("$Junk".into())));
+        assert!(!permanent_flags.can_store(&Flag::Flagged));
+
+        let responses = input_stream(&[
+            "* OK [PERMANENTFLAGS (\\Seen)] Limited\r\n",
+            "A0002 OK SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0002".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+        assert!(!mailbox.permanent_flags.can_create_keywords);
+        assert!(!mailbox
+            .permanent_flags
+            .can_store(&Flag::Custom("$Junk".into())));
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_w_status() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 3 EXISTS\r\n",
+            "* STATUS Sent (MESSAGES 2 RECENT 0 UIDNEXT 3 UIDVALIDITY 7 UNSEEN 1 HIGHESTMODSEQ 42)\r\n",
+            "A0001 OK SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+
+        assert_eq!(mailbox.exists, 3);
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Status {
+                mailbox: "Sent".to_string(),
+                attributes: vec![
+                    StatusAttribute::Messages(2),
+                    StatusAttribute::Recent(0),
+                    StatusAttribute::UidNext(3),
+                    StatusAttribute::UidValidity(7),
+                    StatusAttribute::Unseen(1),
+                    StatusAttribute::HighestModSeq(42),
+                ]
+            }
+        );
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_w_codes() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 3 EXISTS\r\n",
+            "* OK [HIGHESTMODSEQ 715194045007] Highest\r\n",
+            "* OK [MAILBOXID (F2212ea87-6097-4256-9d51-71338625)] Ok\r\n",
+            "* OK [URLMECH INTERNAL] Mechanisms\r\n",
+            "* OK [UIDNOTSTICKY] Non-persistent UIDs\r\n",
+            "* OK [ALERT] Mailbox is almost full\r\n",
+            "A0001 OK [READ-WRITE] SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Alert("Mailbox is almost full".into())
+        );
+        assert_eq!(mailbox.highest_modseq, Some(715194045007));
+        assert!(!mailbox.no_modseq);
+        assert_eq!(
+            mailbox.mailbox_id.as_deref(),
+            Some("F2212ea87-6097-4256-9d51-71338625")
+        );
+        assert_eq!(
+            mailbox.other_codes,
+            [
+                crate::types::ResponseCode::Other("URLMECH INTERNAL".into()),
+                crate::types::ResponseCode::Other("UIDNOTSTICKY".into()),
+            ]
+        );
+
+        let (send, _recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* OK [NOMODSEQ] Sorry, this mailbox format doesn't support modsequences\r\n",
+            "A0001 OK [READ-WRITE] SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+        assert!(mailbox.no_modseq);
+        assert_eq!(mailbox.highest_modseq, None);
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_w_untagged_no() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 3 EXISTS\r\n",
+            "* NO Mailbox index is being rebuilt\r\n",
+            "* BAD Unexpected data\r\n",
+            "* OK [UIDVALIDITY 7] UIDs valid\r\n",
+            "A0001 OK [READ-WRITE] SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+
+        assert_eq!(mailbox.exists, 3);
+        assert_eq!(mailbox.uid_validity, Some(7));
+        for expected in [imap_proto::Status::No, imap_proto::Status::Bad] {
+            match recv.next().await.unwrap() {
+                UnsolicitedResponse::Other(response) => assert!(matches!(
+                    response.parsed(),
+                    Response::Data { status, .. } if *status == expected
+                )),
+                response => panic!("unexpected response: {:?}", response),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[async_std::test]
+    async fn parse_mailbox_w_list() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* 3 EXISTS\r\n",
+            "* LIST (\\HasNoChildren) \".\" \"INBOX\"\r\n",
+            "A0001 OK SELECT completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+
+        let id = RequestId("A0001".into());
+        let mailbox = parse_mailbox(&mut stream, send, id).await.unwrap();
+        assert_eq!(mailbox.exists, 3);
+        match recv.next().await.unwrap() {
+            UnsolicitedResponse::Other(response) => assert!(matches!(
+                response.parsed(),
+                Response::MailboxData(MailboxDatum::List { .. })
+            )),
+            response => panic!("unexpected response: {:?}", response),
+        }
+    }
+
+    #[async_std::test]
+    async fn parse_noop_w_alert() {
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&[
+            "* OK [ALERT] Your mailbox is full\r\n",
+            "A0001 OK NOOP completed\r\n",
+        ]);
+        let mut stream = async_std::stream::from_iter(responses);
+
+        let id = RequestId("A0001".into());
+        parse_noop(&mut stream, send, id).await.unwrap();
+
+        assert_eq!(
+            recv.next().await.unwrap(),
+            UnsolicitedResponse::Alert("Your mailbox is full".to_string())
+        );
+        assert!(recv.try_recv().is_err());
     }
 
     #[async_std::test]
     async fn parse_ids_w_unilateral() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&[
             "* SEARCH 23 42 4711\r\n",
             "* 1 RECENT\r\n",
             "* STATUS INBOX (MESSAGES 10 UIDNEXT 11 UIDVALIDITY 1408806928 UNSEEN 0)\r\n",
+            "A0001 OK SEARCH completed\r\n",
         ]);
         let mut stream = async_std::stream::from_iter(responses);
 
@@ -558,9 +1561,9 @@ mod tests {
 
         assert_eq!(ids, [23, 42, 4711].iter().cloned().collect());
 
-        assert_eq!(recv.recv().await.unwrap(), UnsolicitedResponse::Recent(1));
+        assert_eq!(recv.next().await.unwrap(), UnsolicitedResponse::Recent(1));
         assert_eq!(
-            recv.recv().await.unwrap(),
+            recv.next().await.unwrap(),
             UnsolicitedResponse::Status {
                 mailbox: "INBOX".to_string(),
                 attributes: vec![
@@ -575,17 +1578,18 @@ mod tests {
 
     #[async_std::test]
     async fn parse_ids_test() {
-        let (send, recv) = channel::bounded(10);
+        let (send, mut recv) = unsolicited_channel();
         let responses = input_stream(&[
                 "* SEARCH 1600 1698 1739 1781 1795 1885 1891 1892 1893 1898 1899 1901 1911 1926 1932 1933 1993 1994 2007 2032 2033 2041 2053 2062 2063 2065 2066 2072 2078 2079 2082 2084 2095 2100 2101 2102 2103 2104 2107 2116 2120 2135 2138 2154 2163 2168 2172 2189 2193 2198 2199 2205 2212 2213 2221 2227 2267 2275 2276 2295 2300 2328 2330 2332 2333 2334\r\n",
                 "* SEARCH 2335 2336 2337 2338 2339 2341 2342 2347 2349 2350 2358 2359 2362 2369 2371 2372 2373 2374 2375 2376 2377 2378 2379 2380 2381 2382 2383 2384 2385 2386 2390 2392 2397 2400 2401 2403 2405 2409 2411 2414 2417 2419 2420 2424 2426 2428 2439 2454 2456 2467 2468 2469 2490 2515 2519 2520 2521\r\n",
+                "A0001 OK SEARCH completed\r\n",
             ]);
         let mut stream = async_std::stream::from_iter(responses);
 
         let id = RequestId("A0001".into());
         let ids = parse_ids(&mut stream, send, id).await.unwrap();
 
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         let ids: HashSet<u32> = ids.iter().cloned().collect();
         assert_eq!(
             ids,
@@ -608,14 +1612,14 @@ mod tests {
 
     #[async_std::test]
     async fn parse_ids_search() {
-        let (send, recv) = channel::bounded(10);
-        let responses = input_stream(&["* SEARCH\r\n"]);
+        let (send, mut recv) = unsolicited_channel();
+        let responses = input_stream(&["* SEARCH\r\n", "A0001 OK SEARCH completed\r\n"]);
         let mut stream = async_std::stream::from_iter(responses);
 
         let id = RequestId("A0001".into());
         let ids = parse_ids(&mut stream, send, id).await.unwrap();
 
-        assert!(recv.is_empty());
+        assert!(recv.try_recv().is_err());
         let ids: HashSet<u32> = ids.iter().cloned().collect();
         assert_eq!(ids, HashSet::<u32>::new());
     }
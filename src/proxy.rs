@@ -0,0 +1,282 @@
+//! Tunneling the connection to the server through an HTTP or SOCKS5 proxy.
+
+use futures::io::{AsyncRead as Read, AsyncWrite as Write};
+use futures::prelude::*;
+
+use crate::runtime::{connect_tcp, TcpStream, ToSocketAddrs};
+
+use crate::error::{Error, Result};
+
+/// Connects to `host` and `port` through the HTTP proxy at `proxy`, using the [`CONNECT`
+/// method](https://tools.ietf.org/html/rfc9110#section-9.3.6).  `credentials` are the username
+/// and password for the proxy, which are sent with basic authentication.
+///
+/// The returned stream is tunneled to the IMAP server, which has not sent its greeting yet.  Use
+/// it like a stream connected to the server directly: wrap it in TLS and pass it to
+/// [`Client::from_stream`](crate::Client::from_stream) for a server expecting TLS right away, or
+/// pass it as is and [upgrade](crate::Client::secure) it with `STARTTLS`.
+///
+/// ```no_run
+/// # fn main() -> async_imap::error::Result<()> {
+/// # async_std::task::block_on(async {
+/// let tunnel = async_imap::connect_via_http_proxy(
+///     ("proxy.example.org", 3128),
+///     Some(("proxyuser", "secret")),
+///     "imap.example.org",
+///     993,
+/// )
+/// .await?;
+/// let tls = async_native_tls::TlsConnector::new();
+/// let tunnel = async_imap::runtime::connect_tls(&tls, "imap.example.org", tunnel).await?;
+/// let client = async_imap::Client::from_stream(tunnel).await?;
+/// # Ok(())
+/// # }) }
+/// ```
+///
+/// Fails with [`Error::Proxy`] if the proxy refuses to open the tunnel.
+pub async fn connect_via_http_proxy<A: ToSocketAddrs>(
+    proxy: A,
+    credentials: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream> {
+    let mut stream = connect_tcp(proxy).await?;
+    http_connect(&mut stream, credentials, host, port).await?;
+    Ok(stream)
+}
+
+/// Connects to `host` and `port` through the [SOCKS5](https://tools.ietf.org/html/rfc1928)
+/// proxy at `proxy`.  `credentials` are the username and password for the proxy, see [RFC
+/// 1929](https://tools.ietf.org/html/rfc1929).  The proxy resolves `host`, so its address does
+/// not have to be resolvable locally.
+///
+/// The returned stream is used like the one of [`connect_via_http_proxy`].  Fails with
+/// [`Error::Proxy`] if the proxy refuses to open the tunnel.
+pub async fn connect_via_socks5<A: ToSocketAddrs>(
+    proxy: A,
+    credentials: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+) -> Result<TcpStream> {
+    let mut stream = connect_tcp(proxy).await?;
+    socks5_connect(&mut stream, credentials, host, port).await?;
+    Ok(stream)
+}
+
+/// Opens a tunnel with an HTTP `CONNECT` request.
+async fn http_connect<T: Read + Write + Unpin>(
+    stream: &mut T,
+    credentials: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    if host.contains(|c: char| c.is_ascii_control() || c == ' ') {
+        return Err(Error::Proxy(format!("invalid host name {:?}", host)));
+    }
+    let authority = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    let mut request = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", authority);
+    if let Some((username, password)) = credentials {
+        let token = base64::encode(format!("{}:{}", username, password));
+        request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    stream.flush().await?;
+
+    // Read the response byte by byte, so that nothing the server sends through the tunnel is
+    // consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        let mut byte = [0];
+        stream.read_exact(&mut byte).await?;
+        response.push(byte[0]);
+        if response.len() > 16 * 1024 {
+            return Err(Error::Proxy("response header too long".into()));
+        }
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    let status = status_line.split(' ').nth(1).unwrap_or_default();
+    if !status_line.starts_with("HTTP/1.") || !status.starts_with('2') || status.len() != 3 {
+        return Err(Error::Proxy(format!(
+            "CONNECT failed: {}",
+            status_line.trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Opens a tunnel with a SOCKS5 `CONNECT` command.
+async fn socks5_connect<T: Read + Write + Unpin>(
+    stream: &mut T,
+    credentials: Option<(&str, &str)>,
+    host: &str,
+    port: u16,
+) -> Result<()> {
+    const VERSION: u8 = 5;
+    const NO_AUTHENTICATION: u8 = 0;
+    const USERNAME_PASSWORD: u8 = 2;
+
+    if host.is_empty() || host.len() > 255 {
+        return Err(Error::Proxy(format!("invalid host name {:?}", host)));
+    }
+    let method = match credentials {
+        Some((username, password)) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(Error::Proxy("username or password too long".into()));
+            }
+            USERNAME_PASSWORD
+        }
+        None => NO_AUTHENTICATION,
+    };
+    stream.write_all(&[VERSION, 1, method]).await?;
+    stream.flush().await?;
+    let mut reply = [0; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Error::Proxy("not a SOCKS5 proxy".into()));
+    }
+    if reply[1] != method {
+        return Err(Error::Proxy("no acceptable authentication method".into()));
+    }
+
+    if let Some((username, password)) = credentials {
+        let mut request = vec![1, username.len() as u8];
+        request.extend_from_slice(username.as_bytes());
+        request.push(password.len() as u8);
+        request.extend_from_slice(password.as_bytes());
+        stream.write_all(&request).await?;
+        stream.flush().await?;
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(Error::Proxy("authentication failed".into()));
+        }
+    }
+
+    // CONNECT to a domain name.
+    let mut request = vec![VERSION, 1, 0, 3, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    let mut reply = [0; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != VERSION {
+        return Err(Error::Proxy("not a SOCKS5 proxy".into()));
+    }
+    if reply[1] != 0 {
+        return Err(Error::Proxy(format!(
+            "CONNECT failed: {}",
+            socks5_reply_text(reply[1])
+        )));
+    }
+    // Skip the address the proxy bound, followed by its port.
+    let address_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0];
+            stream.read_exact(&mut len).await?;
+            len[0] as usize
+        }
+        _ => return Err(Error::Proxy("invalid address type in reply".into())),
+    };
+    let mut bound = vec![0; address_len + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Describes a SOCKS5 reply code, see [RFC 1928 section
+/// 6](https://tools.ietf.org/html/rfc1928#section-6).
+fn socks5_reply_text(code: u8) -> String {
+    match code {
+        1 => "general SOCKS server failure".into(),
+        2 => "connection not allowed by ruleset".into(),
+        3 => "network unreachable".into(),
+        4 => "host unreachable".into(),
+        5 => "connection refused".into(),
+        6 => "TTL expired".into(),
+        7 => "command not supported".into(),
+        8 => "address type not supported".into(),
+        code => format!("reply code {}", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_stream::MockStream;
+
+    #[async_std::test]
+    async fn http() {
+        let mut stream = MockStream::new(
+            b"HTTP/1.1 200 Connection established\r\nVia: proxy\r\n\r\n* OK IMAP ready\r\n"
+                .to_vec(),
+        );
+        http_connect(&mut stream, Some(("user", "pass")), "imap.example.org", 993)
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.written_buf,
+            b"CONNECT imap.example.org:993 HTTP/1.1\r\n\
+              Host: imap.example.org:993\r\n\
+              Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+        // The greeting is left for the IMAP client.
+        let mut greeting = String::new();
+        stream.read_to_string(&mut greeting).await.ok();
+        assert!(greeting.starts_with("* OK IMAP ready"), "{:?}", greeting);
+
+        let mut stream =
+            MockStream::new(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n".to_vec());
+        let err = http_connect(&mut stream, None, "imap.example.org", 993)
+            .await
+            .unwrap_err();
+        match err {
+            Error::Proxy(text) => {
+                assert_eq!(
+                    text,
+                    "CONNECT failed: HTTP/1.1 407 Proxy Authentication Required"
+                )
+            }
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[async_std::test]
+    async fn socks5() {
+        let mut stream = MockStream::new(vec![
+            5, 2, // username/password accepted
+            1, 0, // authenticated
+            5, 0, 0, 1, 10, 0, 0, 1, 0x12, 0x34, // connected from 10.0.0.1:4660
+            b'*',
+        ]);
+        socks5_connect(&mut stream, Some(("user", "pw")), "imap.example.org", 143)
+            .await
+            .unwrap();
+        let mut expected = vec![5, 1, 2, 1, 4];
+        expected.extend_from_slice(b"user");
+        expected.push(2);
+        expected.extend_from_slice(b"pw");
+        expected.extend_from_slice(&[5, 1, 0, 3, 16]);
+        expected.extend_from_slice(b"imap.example.org");
+        expected.extend_from_slice(&[0, 143]);
+        assert_eq!(stream.written_buf, expected);
+        let mut rest = [0];
+        stream.read_exact(&mut rest).await.unwrap();
+        assert_eq!(&rest, b"*");
+
+        let mut stream = MockStream::new(vec![5, 0, 5, 5, 0, 1, 0, 0, 0, 0, 0, 0]);
+        let err = socks5_connect(&mut stream, None, "imap.example.org", 143)
+            .await
+            .unwrap_err();
+        match err {
+            Error::Proxy(text) => assert_eq!(text, "CONNECT failed: connection refused"),
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Re-establishing a [`Session`] after the connection to the server was lost.
+
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use async_native_tls::TlsConnector;
+
+use crate::client::{connect, Session};
+use crate::error::Result;
+use crate::imap_stream::{DebugHook, Direction};
+use crate::runtime::{TcpStream, TlsStream};
+use crate::types::Mailbox;
+
+/// The parameters needed to connect and log in to a server over TLS, from which
+/// [`ReconnectingSession`]s are created.
+///
+/// ```no_run
+/// # fn main() -> async_imap::error::Result<()> {
+/// # async_std::task::block_on(async {
+/// use async_imap::SessionConfig;
+///
+/// let mut session = SessionConfig::new("imap.example.org", 993, "me", "password")
+///     .enable("CONDSTORE")
+///     .connect()
+///     .await?;
+/// session.select("INBOX").await?;
+///
+/// if let Err(err) = session.noop().await {
+///     if !err.is_connection_lost() {
+///         return Err(err);
+///     }
+///     let reconnected = session.reconnect().await?;
+///     if reconnected.uid_validity_changed {
+///         // UIDs cached for INBOX are no longer valid.
+///     }
+/// }
+/// # Ok(())
+/// # }) }
+/// ```
+#[derive(Clone)]
+pub struct SessionConfig {
+    host: String,
+    port: u16,
+    username: String,
+    password: String,
+    extensions: Vec<String>,
+    timeout: Option<Duration>,
+    debug: Option<DebugHook>,
+}
+
+impl fmt::Debug for SessionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionConfig")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .field("extensions", &self.extensions)
+            .field("timeout", &self.timeout)
+            .field("debug", &self.debug)
+            .finish()
+    }
+}
+
+impl SessionConfig {
+    /// Creates a configuration for logging in to `host` on `port` with `username` and
+    /// `password`. The host name is also used to validate the server's certificate.
+    pub fn new<H, U, P>(host: H, port: u16, username: U, password: P) -> Self
+    where
+        H: Into<String>,
+        U: Into<String>,
+        P: Into<String>,
+    {
+        SessionConfig {
+            host: host.into(),
+            port,
+            username: username.into(),
+            password: password.into(),
+            extensions: Vec::new(),
+            timeout: None,
+            debug: None,
+        }
+    }
+
+    /// Adds an extension to [enable](Session::enable) after every login.
+    pub fn enable<S: Into<String>>(mut self, extension: S) -> Self {
+        self.extensions.push(extension.into());
+        self
+    }
+
+    /// Sets the timeout of every session, see [`Session::set_timeout`].
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Passes the lines exchanged with the server to `hook` in every session, see
+    /// [`Session::set_debug`].
+    pub fn debug<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        self.debug = Some(DebugHook::new(hook));
+        self
+    }
+
+    /// The host name of the server.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// The port of the server.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The name of the user to log in as.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// Connects and logs in to the server, enabling the configured extensions.
+    pub async fn connect(self) -> Result<ReconnectingSession> {
+        let session = self.login().await?;
+        Ok(ReconnectingSession {
+            config: self,
+            session,
+            selected: None,
+        })
+    }
+
+    async fn login(&self) -> Result<Session<TlsStream<TcpStream>>> {
+        let mut client = connect(
+            (self.host.as_str(), self.port),
+            &self.host,
+            TlsConnector::new(),
+        )
+        .await?;
+        client.set_timeout(self.timeout);
+        client.stream.set_debug(self.debug.clone());
+        let mut session = client
+            .login(&self.username, &self.password)
+            .await
+            .map_err(|(err, _client)| err)?;
+        if !self.extensions.is_empty() {
+            session.enable(&self.extensions).await?;
+        }
+        Ok(session)
+    }
+}
+
+/// The mailbox selected through a [`ReconnectingSession`].
+#[derive(Debug, Clone)]
+struct Selected {
+    name: String,
+    read_only: bool,
+    uid_validity: Option<u32>,
+}
+
+/// A [`Session`] that remembers how it was established, so that it can be
+/// [reconnected](ReconnectingSession::reconnect) when the connection is lost.
+///
+/// All [`Session`] methods are available through `Deref`. Mailboxes have to be opened with
+/// [`ReconnectingSession::select`] and [`ReconnectingSession::examine`] for them to be opened
+/// again after reconnecting.
+#[derive(Debug)]
+pub struct ReconnectingSession {
+    config: SessionConfig,
+    session: Session<TlsStream<TcpStream>>,
+    selected: Option<Selected>,
+}
+
+/// The state restored by [`ReconnectingSession::reconnect`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Reconnected {
+    /// The mailbox that was selected again, if one was selected before the connection was lost
+    /// and could be opened again.
+    pub mailbox: Option<Mailbox>,
+    /// Whether the `UIDVALIDITY` of the selected mailbox differs from the one it had when it was
+    /// selected before, in which case any UIDs the caller kept for it are no longer valid and the
+    /// mailbox has to be synchronized from scratch.
+    pub uid_validity_changed: bool,
+}
+
+impl ReconnectingSession {
+    /// The configuration the session was created with.
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    /// Selects a mailbox like [`Session::select`], and remembers it to select it again after
+    /// reconnecting.
+    pub async fn select<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<Mailbox> {
+        self.open(mailbox_name.as_ref(), false).await
+    }
+
+    /// Opens a mailbox read-only like [`Session::examine`], and remembers it to examine it again
+    /// after reconnecting.
+    pub async fn examine<S: AsRef<str>>(&mut self, mailbox_name: S) -> Result<Mailbox> {
+        self.open(mailbox_name.as_ref(), true).await
+    }
+
+    /// Closes the selected mailbox like [`Session::close`], so that it is no longer selected
+    /// after reconnecting.
+    pub async fn close(&mut self) -> Result<()> {
+        self.selected = None;
+        self.session.close().await
+    }
+
+    /// Replaces the session with a new connection, logging in, enabling the configured
+    /// extensions and opening the previously selected mailbox again.
+    ///
+    /// This is meant to be called after a command failed with an error for which
+    /// [`Error::is_connection_lost`](crate::error::Error::is_connection_lost) is true. The old
+    /// connection is dropped without logging out. If reconnecting fails, the old session is kept
+    /// and `reconnect` can be called again later.
+    ///
+    /// If the server refuses to open the mailbox again, e.g. because it was deleted in the
+    /// meantime, the new session is kept without a selected mailbox and
+    /// [`Reconnected::mailbox`] is `None`.
+    pub async fn reconnect(&mut self) -> Result<Reconnected> {
+        let mut session = self.config.login().await?;
+        let mut reconnected = Reconnected {
+            mailbox: None,
+            uid_validity_changed: false,
+        };
+        if let Some(selected) = &mut self.selected {
+            let mailbox = if selected.read_only {
+                session.examine(&selected.name).await
+            } else {
+                session.select(&selected.name).await
+            };
+            match mailbox {
+                Ok(mailbox) => {
+                    reconnected.uid_validity_changed =
+                        mailbox.uid_validity != selected.uid_validity;
+                    selected.uid_validity = mailbox.uid_validity;
+                    reconnected.mailbox = Some(mailbox);
+                }
+                Err(err) if err.is_connection_lost() => return Err(err),
+                Err(_) => self.selected = None,
+            }
+        }
+        self.session = session;
+        Ok(reconnected)
+    }
+
+    /// Sets the timeout like [`Session::set_timeout`], and keeps it for the sessions created when
+    /// reconnecting.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.config.timeout = timeout;
+        self.session.set_timeout(timeout);
+    }
+
+    /// Sets a debug hook like [`Session::set_debug`], and keeps it for the sessions created when
+    /// reconnecting.
+    pub fn set_debug<F>(&mut self, hook: F)
+    where
+        F: Fn(Direction, &[u8]) + Send + Sync + 'static,
+    {
+        let hook = DebugHook::new(hook);
+        self.session.stream.set_debug(Some(hook.clone()));
+        self.config.debug = Some(hook);
+    }
+
+    /// Removes the debug hook like [`Session::clear_debug`], also for the sessions created when
+    /// reconnecting.
+    pub fn clear_debug(&mut self) {
+        self.config.debug = None;
+        self.session.clear_debug();
+    }
+
+    /// Returns the underlying session, which is no longer reconnected.
+    pub fn into_inner(self) -> Session<TlsStream<TcpStream>> {
+        self.session
+    }
+
+    async fn open(&mut self, name: &str, read_only: bool) -> Result<Mailbox> {
+        // A failed SELECT or EXAMINE leaves no mailbox selected.
+        self.selected = None;
+        let mailbox = if read_only {
+            self.session.examine(name).await?
+        } else {
+            self.session.select(name).await?
+        };
+        self.selected = Some(Selected {
+            name: name.to_string(),
+            read_only,
+            uid_validity: mailbox.uid_validity,
+        });
+        Ok(mailbox)
+    }
+}
+
+impl Deref for ReconnectingSession {
+    type Target = Session<TlsStream<TcpStream>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.session
+    }
+}
+
+impl DerefMut for ReconnectingSession {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_debug_hides_password() {
+        let config = SessionConfig::new("imap.example.org", 993, "me", "hunter2")
+            .enable("QRESYNC")
+            .timeout(Some(Duration::from_secs(30)));
+        let debug = format!("{:?}", config);
+        assert!(debug.contains("imap.example.org"));
+        assert!(debug.contains("QRESYNC"));
+        assert!(debug.contains("30s"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
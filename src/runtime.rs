@@ -0,0 +1,98 @@
+//! The parts of the crate that depend on the async runtime, which is selected with either the
+//! `runtime-async-std` feature, enabled by default, or the `runtime-tokio` feature.
+//!
+//! The crate works on any stream that implements the `AsyncRead` and `AsyncWrite` traits of the
+//! [`futures`] crate, which the streams of async-std implement.  With `runtime-tokio`, the TCP
+//! and TLS streams of tokio are wrapped in [`Compat`](https://docs.rs/tokio-util/0.7/tokio_util/compat/index.html) to implement
+//! them, which also works for other tokio streams:
+//!
+//! ```ignore
+//! use tokio_util::compat::TokioAsyncReadCompatExt;
+//!
+//! let stream = tokio::net::UnixStream::connect("/run/imap.sock").await?;
+//! let client = async_imap::Client::from_stream(stream.compat()).await?;
+//! ```
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_native_tls::TlsConnector;
+use futures::io::{self, AsyncRead as Read, AsyncWrite as Write};
+
+use crate::error::Result;
+
+#[cfg(not(any(feature = "runtime-async-std", feature = "runtime-tokio")))]
+compile_error!("one of the features `runtime-async-std` and `runtime-tokio` must be enabled");
+
+#[cfg(all(feature = "runtime-async-std", feature = "runtime-tokio"))]
+compile_error!("only one of the features `runtime-async-std` and `runtime-tokio` may be enabled");
+
+/// The addresses accepted by [`connect`](crate::connect), such as `("imap.example.org", 993)`.
+#[cfg(feature = "runtime-async-std")]
+pub use async_std::net::ToSocketAddrs;
+/// The addresses accepted by [`connect`](crate::connect), such as `("imap.example.org", 993)`.
+#[cfg(feature = "runtime-tokio")]
+pub use tokio::net::ToSocketAddrs;
+
+/// The TCP stream of the runtime, as opened by [`connect`](crate::connect) and the proxy
+/// functions such as [`connect_via_http_proxy`](crate::connect_via_http_proxy).
+#[cfg(feature = "runtime-async-std")]
+pub type TcpStream = async_std::net::TcpStream;
+/// The TCP stream of the runtime, as opened by [`connect`](crate::connect) and the proxy
+/// functions such as [`connect_via_http_proxy`](crate::connect_via_http_proxy).
+#[cfg(feature = "runtime-tokio")]
+pub type TcpStream = tokio_util::compat::Compat<tokio::net::TcpStream>;
+
+/// A TLS stream over the stream `S`, as set up by [`connect_tls`].
+#[cfg(feature = "runtime-async-std")]
+pub type TlsStream<S> = async_native_tls::TlsStream<S>;
+/// A TLS stream over the stream `S`, as set up by [`connect_tls`].
+#[cfg(feature = "runtime-tokio")]
+pub type TlsStream<S> =
+    tokio_util::compat::Compat<async_native_tls::TlsStream<tokio_util::compat::Compat<S>>>;
+
+/// Opens a TCP connection to `addr`.
+pub(crate) async fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<TcpStream> {
+    #[cfg(feature = "runtime-async-std")]
+    let stream = TcpStream::connect(addr).await?;
+    #[cfg(feature = "runtime-tokio")]
+    let stream = tokio_util::compat::TokioAsyncReadCompatExt::compat(
+        tokio::net::TcpStream::connect(addr).await?,
+    );
+    Ok(stream)
+}
+
+/// Performs the TLS handshake with the server `domain` over `stream`, e.g. one opened with
+/// [`connect_via_http_proxy`](crate::connect_via_http_proxy), so that the returned stream can be
+/// passed to [`Client::from_stream`](crate::Client::from_stream).
+pub async fn connect_tls<S: Read + Write + Unpin>(
+    connector: &TlsConnector,
+    domain: &str,
+    stream: S,
+) -> Result<TlsStream<S>> {
+    #[cfg(feature = "runtime-async-std")]
+    let stream = connector.connect(domain, stream).await?;
+    #[cfg(feature = "runtime-tokio")]
+    let stream = {
+        use tokio_util::compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+        connector.connect(domain, stream.compat()).await?.compat()
+    };
+    Ok(stream)
+}
+
+/// Waits for `duration` to pass.
+pub(crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "runtime-async-std")]
+    async_std::task::sleep(duration).await;
+    #[cfg(feature = "runtime-tokio")]
+    tokio::time::sleep(duration).await;
+}
+
+/// Runs `future` for at most `duration`, returning `None` if it did not complete in time.
+pub(crate) async fn timeout<F: Future>(duration: Duration, future: F) -> Option<F::Output> {
+    #[cfg(feature = "runtime-async-std")]
+    let output = async_std::future::timeout(duration, future).await;
+    #[cfg(feature = "runtime-tokio")]
+    let output = tokio::time::timeout(duration, future).await;
+    output.ok()
+}
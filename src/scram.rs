@@ -0,0 +1,291 @@
+//! The client side of the SCRAM SASL mechanisms, see [RFC 5802](https://tools.ietf.org/html/rfc5802)
+//! and [RFC 7677](https://tools.ietf.org/html/rfc7677).
+
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+
+use crate::error::{Error, Result};
+
+/// A SCRAM mechanism, named after the hash function it uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScramMechanism {
+    /// `SCRAM-SHA-1`, see [RFC 5802](https://tools.ietf.org/html/rfc5802).
+    Sha1,
+    /// `SCRAM-SHA-256`, see [RFC 7677](https://tools.ietf.org/html/rfc7677).
+    Sha256,
+}
+
+impl ScramMechanism {
+    /// The SASL name of the mechanism, as used in `AUTHENTICATE` and `AUTH=` capabilities.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScramMechanism::Sha1 => "SCRAM-SHA-1",
+            ScramMechanism::Sha256 => "SCRAM-SHA-256",
+        }
+    }
+
+    fn digest(self) -> MessageDigest {
+        match self {
+            ScramMechanism::Sha1 => MessageDigest::sha1(),
+            ScramMechanism::Sha256 => MessageDigest::sha256(),
+        }
+    }
+}
+
+/// The GS2 header for a client that does not support channel binding.
+const GS2_HEADER: &str = "n,,";
+
+enum State {
+    Initial,
+    ClientFirstSent { client_first_bare: String },
+    ClientFinalSent { server_signature: Vec<u8> },
+    Complete,
+    Failed,
+}
+
+/// The client side of a SCRAM exchange, one step per server challenge.
+pub(crate) struct Scram {
+    mechanism: ScramMechanism,
+    username: String,
+    password: String,
+    client_nonce: String,
+    state: State,
+}
+
+impl Scram {
+    pub(crate) fn new(mechanism: ScramMechanism, username: &str, password: &str) -> Result<Self> {
+        let mut nonce = [0; 18];
+        openssl::rand::rand_bytes(&mut nonce).map_err(crypto_error)?;
+        Ok(Self::with_nonce(
+            mechanism,
+            username,
+            password,
+            &base64::encode(nonce),
+        ))
+    }
+
+    pub(crate) fn with_nonce(
+        mechanism: ScramMechanism,
+        username: &str,
+        password: &str,
+        client_nonce: &str,
+    ) -> Self {
+        Scram {
+            mechanism,
+            // `,` and `=` have to be escaped in the username, see RFC 5802, section 5.1.
+            username: username.replace('=', "=3D").replace(',', "=2C"),
+            password: password.to_string(),
+            client_nonce: client_nonce.to_string(),
+            state: State::Initial,
+        }
+    }
+
+    pub(crate) fn mechanism(&self) -> ScramMechanism {
+        self.mechanism
+    }
+
+    /// Whether the server has proven that it knows the password.
+    pub(crate) fn is_complete(&self) -> bool {
+        matches!(self.state, State::Complete)
+    }
+
+    /// Computes the response to the next (base64-decoded) server challenge.
+    pub(crate) fn step(&mut self, challenge: &[u8]) -> Result<Vec<u8>> {
+        match std::mem::replace(&mut self.state, State::Failed) {
+            State::Initial => {
+                let client_first_bare = format!("n={},r={}", self.username, self.client_nonce);
+                let response = format!("{}{}", GS2_HEADER, client_first_bare);
+                self.state = State::ClientFirstSent { client_first_bare };
+                Ok(response.into_bytes())
+            }
+            State::ClientFirstSent { client_first_bare } => {
+                let (response, server_signature) =
+                    self.client_final(&client_first_bare, challenge)?;
+                self.state = State::ClientFinalSent { server_signature };
+                Ok(response.into_bytes())
+            }
+            State::ClientFinalSent { server_signature } => {
+                let server_final = std::str::from_utf8(challenge)
+                    .map_err(|_| Error::Sasl("server-final-message is not UTF-8".into()))?;
+                if let Some(error) = attribute(server_final, 'e') {
+                    return Err(Error::Sasl(format!("server error: {}", error)));
+                }
+                let verifier = attribute(server_final, 'v')
+                    .and_then(|v| base64::decode(v).ok())
+                    .ok_or_else(|| Error::Sasl("invalid server-final-message".into()))?;
+                if verifier.len() != server_signature.len()
+                    || !openssl::memcmp::eq(&verifier, &server_signature)
+                {
+                    return Err(Error::Sasl("invalid server signature".into()));
+                }
+                self.state = State::Complete;
+                Ok(Vec::new())
+            }
+            State::Complete | State::Failed => Err(Error::Sasl("unexpected challenge".into())),
+        }
+    }
+
+    /// Builds the client-final-message from the server-first-message, and computes the
+    /// signature the server has to send back.
+    fn client_final(&self, client_first_bare: &str, challenge: &[u8]) -> Result<(String, Vec<u8>)> {
+        let invalid = || Error::Sasl("invalid server-first-message".into());
+        let server_first = std::str::from_utf8(challenge).map_err(|_| invalid())?;
+        if server_first.starts_with("m=") {
+            return Err(Error::Sasl("unsupported mandatory extension".into()));
+        }
+        let nonce = attribute(server_first, 'r').ok_or_else(invalid)?;
+        if !nonce.starts_with(&self.client_nonce) {
+            return Err(Error::Sasl(
+                "server nonce does not extend client nonce".into(),
+            ));
+        }
+        let salt = attribute(server_first, 's')
+            .and_then(|salt| base64::decode(salt).ok())
+            .ok_or_else(invalid)?;
+        let iterations: usize = attribute(server_first, 'i')
+            .and_then(|i| i.parse().ok())
+            .filter(|i| *i > 0)
+            .ok_or_else(invalid)?;
+
+        let digest = self.mechanism.digest();
+        let mut salted_password = vec![0; digest.size()];
+        openssl::pkcs5::pbkdf2_hmac(
+            self.password.as_bytes(),
+            &salt,
+            iterations,
+            digest,
+            &mut salted_password,
+        )
+        .map_err(crypto_error)?;
+
+        let client_final_without_proof = format!("c={},r={}", base64::encode(GS2_HEADER), nonce);
+        let auth_message = format!(
+            "{},{},{}",
+            client_first_bare, server_first, client_final_without_proof
+        );
+
+        let client_key = hmac(digest, &salted_password, b"Client Key")?;
+        let stored_key = hash(digest, &client_key).map_err(crypto_error)?;
+        let client_signature = hmac(digest, &stored_key, auth_message.as_bytes())?;
+        let client_proof: Vec<u8> = client_key
+            .iter()
+            .zip(&client_signature)
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+
+        let server_key = hmac(digest, &salted_password, b"Server Key")?;
+        let server_signature = hmac(digest, &server_key, auth_message.as_bytes())?;
+
+        Ok((
+            format!(
+                "{},p={}",
+                client_final_without_proof,
+                base64::encode(client_proof)
+            ),
+            server_signature,
+        ))
+    }
+}
+
+/// Returns the value of the attribute called `name` in a SCRAM message.
+fn attribute(message: &str, name: char) -> Option<&str> {
+    message.split(',').find_map(|attribute| {
+        let mut chars = attribute.chars();
+        if chars.next() == Some(name) && chars.next() == Some('=') {
+            Some(&attribute[2..])
+        } else {
+            None
+        }
+    })
+}
+
+pub(crate) fn hmac(digest: MessageDigest, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key).map_err(crypto_error)?;
+    let mut signer = Signer::new(digest, &key).map_err(crypto_error)?;
+    signer.update(data).map_err(crypto_error)?;
+    signer.sign_to_vec().map_err(crypto_error)
+}
+
+fn crypto_error(error: openssl::error::ErrorStack) -> Error {
+    Error::Sasl(error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scram_sha1_rfc5802() {
+        let mut scram = Scram::with_nonce(
+            ScramMechanism::Sha1,
+            "user",
+            "pencil",
+            "fyko+d2lbbFgONRv9qkxdawL",
+        );
+        assert_eq!(
+            scram.step(b"").unwrap(),
+            b"n,,n=user,r=fyko+d2lbbFgONRv9qkxdawL".to_vec()
+        );
+        assert_eq!(
+            scram
+                .step(b"r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,s=QSXCR+Q6sek8bf92,i=4096")
+                .unwrap(),
+            b"c=biws,r=fyko+d2lbbFgONRv9qkxdawL3rfcNHYJY1ZVvWVs7j,p=v0X8v3Bz2T0CJGbJQyF0X+HI4Ts="
+                .to_vec()
+        );
+        assert!(!scram.is_complete());
+        assert_eq!(
+            scram.step(b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ=").unwrap(),
+            b"".to_vec()
+        );
+        assert!(scram.is_complete());
+    }
+
+    #[test]
+    fn scram_sha256_rfc7677() {
+        let mut scram = Scram::with_nonce(
+            ScramMechanism::Sha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO",
+        );
+        assert_eq!(
+            scram.step(b"").unwrap(),
+            b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec()
+        );
+        assert_eq!(
+            scram
+                .step(b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+                .unwrap(),
+            b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+                .to_vec()
+        );
+        assert_eq!(
+            scram
+                .step(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                .unwrap(),
+            b"".to_vec()
+        );
+        assert!(scram.is_complete());
+    }
+
+    #[test]
+    fn scram_rejects_bad_server() {
+        let mut scram = Scram::with_nonce(ScramMechanism::Sha1, "user", "pencil", "abc");
+        scram.step(b"").unwrap();
+        assert!(matches!(
+            scram.step(b"r=xyz,s=QSXCR+Q6sek8bf92,i=4096"),
+            Err(Error::Sasl(_))
+        ));
+
+        let mut scram = Scram::with_nonce(ScramMechanism::Sha1, "user", "pencil", "abc");
+        scram.step(b"").unwrap();
+        scram.step(b"r=abcdef,s=QSXCR+Q6sek8bf92,i=1").unwrap();
+        assert!(matches!(
+            scram.step(b"v=rmF9pqV8S7suAoZWja4dJRkFsKQ="),
+            Err(Error::Sasl(_))
+        ));
+        assert!(!scram.is_complete());
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::btree_set::Iter;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use imap_proto::types::AclRight;
+
+/// A set of access rights, each identified by a single character such as `l` (lookup) or `r`
+/// (read).  See [RFC 4314 section 2.1](https://tools.ietf.org/html/rfc4314#section-2.1) for the
+/// meaning of the standard rights.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct AclRights(BTreeSet<char>);
+
+impl AclRights {
+    /// Check if the given right is part of the set.
+    pub fn contains(&self, right: char) -> bool {
+        self.0.contains(&right)
+    }
+
+    /// Iterate over the rights in alphabetical order.
+    pub fn iter(&self) -> Iter<'_, char> {
+        self.0.iter()
+    }
+
+    /// Returns how many rights the set contains.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the set contains no rights.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<&str> for AclRights {
+    fn from(rights: &str) -> Self {
+        AclRights(rights.chars().collect())
+    }
+}
+
+impl From<&[AclRight]> for AclRights {
+    fn from(rights: &[AclRight]) -> Self {
+        AclRights(rights.iter().map(|right| char::from(*right)).collect())
+    }
+}
+
+impl fmt::Display for AclRights {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|right| write!(f, "{}", right))
+    }
+}
+
+/// The rights granted to a single identifier, as part of an [`Acl`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AclEntry {
+    /// The user or group the rights are granted to.
+    pub identifier: String,
+    /// The granted rights.
+    pub rights: AclRights,
+}
+
+/// The access control list of a mailbox, from an
+/// [`ACL` response](https://tools.ietf.org/html/rfc4314#section-3.6).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Acl {
+    /// The name of the mailbox.
+    pub mailbox: String,
+    /// The rights granted to each identifier.
+    pub entries: Vec<AclEntry>,
+}
+
+impl From<&imap_proto::types::Acl<'_>> for Acl {
+    fn from(acl: &imap_proto::types::Acl<'_>) -> Self {
+        Acl {
+            mailbox: acl.mailbox.to_string(),
+            entries: acl
+                .acls
+                .iter()
+                .map(|entry| AclEntry {
+                    identifier: entry.identifier.to_string(),
+                    rights: AclRights::from(&entry.rights[..]),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The rights that can be granted to an identifier, from a
+/// [`LISTRIGHTS` response](https://tools.ietf.org/html/rfc4314#section-3.7).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ListRights {
+    /// The name of the mailbox.
+    pub mailbox: String,
+    /// The user or group the rights apply to.
+    pub identifier: String,
+    /// The rights that are always granted to the identifier.
+    pub required: AclRights,
+    /// The rights that may additionally be granted to the identifier.
+    pub optional: AclRights,
+}
+
+impl From<&imap_proto::types::ListRights<'_>> for ListRights {
+    fn from(list_rights: &imap_proto::types::ListRights<'_>) -> Self {
+        ListRights {
+            mailbox: list_rights.mailbox.to_string(),
+            identifier: list_rights.identifier.to_string(),
+            required: AclRights::from(&list_rights.required[..]),
+            optional: AclRights::from(&list_rights.optional[..]),
+        }
+    }
+}
+
+/// The rights of the logged in user, from a
+/// [`MYRIGHTS` response](https://tools.ietf.org/html/rfc4314#section-3.8).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MyRights {
+    /// The name of the mailbox.
+    pub mailbox: String,
+    /// The rights of the user on the mailbox.
+    pub rights: AclRights,
+}
+
+impl From<&imap_proto::types::MyRights<'_>> for MyRights {
+    fn from(my_rights: &imap_proto::types::MyRights<'_>) -> Self {
+        MyRights {
+            mailbox: my_rights.mailbox.to_string(),
+            rights: AclRights::from(&my_rights.rights[..]),
+        }
+    }
+}
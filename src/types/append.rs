@@ -0,0 +1,147 @@
+use chrono::{DateTime, FixedOffset};
+use imap_proto::types::{ResponseCode, UidSetMember};
+use imap_proto::Response;
+
+use super::{validate_flag, Flag, ResponseData, Uid};
+use crate::error::Result;
+use crate::ext_parse::MAX_EXPANDED_IDS;
+
+/// Format of the `date-time` argument to `APPEND`, see
+/// [RFC 3501 section 9](https://tools.ietf.org/html/rfc3501#section-9).
+const DATE_TIME_FORMAT: &str = "%e-%b-%Y %H:%M:%S %z";
+
+/// A message to be appended to a mailbox with [`crate::Session::multi_append`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppendMessage<'a> {
+    /// The flags to set on the new message.
+    pub flags: Vec<Flag<'a>>,
+    /// The internal date of the new message.  If `None`, the server uses the current time.
+    pub internal_date: Option<DateTime<FixedOffset>>,
+    /// The message itself, in [RFC-2822](https://tools.ietf.org/html/rfc2822) format.
+    pub body: &'a [u8],
+}
+
+impl<'a> AppendMessage<'a> {
+    /// Creates a message without flags and without an internal date.
+    pub fn new(body: &'a [u8]) -> Self {
+        AppendMessage {
+            flags: Vec::new(),
+            internal_date: None,
+            body,
+        }
+    }
+
+    /// The optional flag list and date arguments to `APPEND`, each followed by a space.  Fails
+    /// with [`Error::InvalidFlag`](crate::error::Error::InvalidFlag) if one of the flags can not
+    /// be sent as is.
+    pub(crate) fn arguments(&self) -> Result<String> {
+        let mut arguments = String::new();
+        if !self.flags.is_empty() {
+            let flags = self
+                .flags
+                .iter()
+                .map(|flag| {
+                    let flag = flag.to_string();
+                    validate_flag(&flag)?;
+                    Ok(flag)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            arguments.push('(');
+            arguments.push_str(&flags.join(" "));
+            arguments.push_str(") ");
+        }
+        if let Some(date) = self.internal_date {
+            arguments.push_str(&format!("\"{}\" ", date.format(DATE_TIME_FORMAT)));
+        }
+        Ok(arguments)
+    }
+}
+
+/// A part of a message assembled by the server with
+/// [`Session::append_catenate`](crate::Session::append_catenate), see the [`CATENATE`
+/// extension](https://tools.ietf.org/html/rfc4469).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CatenatePart {
+    /// Data sent along with the command, such as a new header or a text to prepend.
+    Text(Vec<u8>),
+    /// An [IMAP URL](https://tools.ietf.org/html/rfc5092) referring to a message or body part on
+    /// the same server, e.g. `/INBOX;UIDVALIDITY=785799047/;UID=113330/;SECTION=1.5.9`, which the
+    /// server copies without the client having to download it.
+    Url(String),
+}
+
+/// The [`APPENDUID` response code](https://tools.ietf.org/html/rfc4315#section-3) returned by
+/// servers supporting the `UIDPLUS` extension, listing the [`Uid`]s of appended messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AppendUid {
+    /// The `UIDVALIDITY` of the destination mailbox.
+    pub uid_validity: u32,
+    /// The UIDs assigned to the appended messages, in the order the messages were appended.
+    pub uids: Vec<Uid>,
+}
+
+impl AppendUid {
+    pub(crate) fn from_response(response: &ResponseData) -> Option<Self> {
+        match response.parsed() {
+            Response::Done {
+                code: Some(ResponseCode::AppendUid(uid_validity, uid_set)),
+                ..
+            } => Some(AppendUid {
+                uid_validity: *uid_validity,
+                uids: expand_uid_set(uid_set)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Lists the UIDs of a set sent by the server, or returns `None` if it has more than
+/// [`MAX_EXPANDED_IDS`] of them.
+pub(crate) fn expand_uid_set(uid_set: &[UidSetMember]) -> Option<Vec<Uid>> {
+    let ranges = uid_set.iter().map(|member| match member {
+        UidSetMember::UidRange(range) => range.clone(),
+        UidSetMember::Uid(uid) => *uid..=*uid,
+    });
+    let count: u64 = ranges
+        .clone()
+        .map(|range| u64::from(range.end().saturating_sub(*range.start())) + 1)
+        .sum();
+    if count > MAX_EXPANDED_IDS as u64 {
+        return None;
+    }
+    Some(ranges.flatten().map(Uid).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use chrono::TimeZone;
+
+    #[test]
+    fn append_arguments() {
+        let date = FixedOffset::east_opt(3600)
+            .unwrap()
+            .with_ymd_and_hms(2020, 3, 7, 8, 30, 0)
+            .unwrap();
+        let message = AppendMessage {
+            flags: vec![Flag::Seen, Flag::from("$Forwarded")],
+            internal_date: Some(date),
+            body: b"",
+        };
+        assert_eq!(
+            message.arguments().unwrap(),
+            "(\\Seen $Forwarded) \" 7-Mar-2020 08:30:00 +0100\" "
+        );
+        assert_eq!(AppendMessage::new(b"").arguments().unwrap(), "");
+
+        let message = AppendMessage {
+            flags: vec![Flag::from("$Forwarded) {5}")],
+            ..AppendMessage::new(b"")
+        };
+        assert!(matches!(
+            message.arguments(),
+            Err(Error::InvalidFlag(flag)) if flag == "$Forwarded) {5}"
+        ));
+    }
+}
@@ -1,19 +1,110 @@
 use imap_proto::types::Capability as CapabilityRef;
-use std::collections::hash_set::Iter;
-use std::collections::HashSet;
+use std::fmt;
 
 const IMAP4REV1_CAPABILITY: &str = "IMAP4rev1";
 const AUTH_CAPABILITY_PREFIX: &str = "AUTH=";
+const COMPRESS_CAPABILITY_PREFIX: &str = "COMPRESS=";
+
+/// The capabilities with their own variant, other than `AUTH=` and `COMPRESS=`.
+const KNOWN_ATOMS: &[(&str, Capability)] = &[
+    (IMAP4REV1_CAPABILITY, Capability::Imap4rev1),
+    ("IMAP4rev2", Capability::Imap4rev2),
+    ("STARTTLS", Capability::StartTls),
+    ("LOGINDISABLED", Capability::LoginDisabled),
+    ("IDLE", Capability::Idle),
+    ("CONDSTORE", Capability::CondStore),
+    ("QRESYNC", Capability::QResync),
+    ("MOVE", Capability::Move),
+    ("UIDPLUS", Capability::UidPlus),
+    ("ENABLE", Capability::Enable),
+    ("NAMESPACE", Capability::Namespace),
+];
 
 /// List of available Capabilities.
-#[derive(Debug, Eq, PartialEq, Hash)]
+///
+/// Well-known capabilities have their own variant, matched case-insensitively, all others are
+/// kept as [`Capability::Other`].
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
 pub enum Capability {
     /// The crucial imap capability.
     Imap4rev1,
+    /// `IMAP4rev2`, see [RFC 9051](https://tools.ietf.org/html/rfc9051).
+    Imap4rev2,
+    /// `STARTTLS`, see [`Client::starttls`](crate::Client::starttls).
+    StartTls,
+    /// `LOGINDISABLED`, the `LOGIN` command must not be used.
+    LoginDisabled,
+    /// `IDLE`, see [`Session::idle`](crate::Session::idle).
+    Idle,
+    /// `CONDSTORE`, see [RFC 7162](https://tools.ietf.org/html/rfc7162).
+    CondStore,
+    /// `QRESYNC`, see [RFC 7162](https://tools.ietf.org/html/rfc7162).
+    QResync,
+    /// `MOVE`, see [`Session::mv`](crate::Session::mv).
+    Move,
+    /// `UIDPLUS`, see [RFC 4315](https://tools.ietf.org/html/rfc4315).
+    UidPlus,
+    /// `COMPRESS=<algorithm>`, see [RFC 4978](https://tools.ietf.org/html/rfc4978).
+    Compress(String),
+    /// `ENABLE`, see [RFC 5161](https://tools.ietf.org/html/rfc5161).
+    Enable,
+    /// `NAMESPACE`, see [RFC 2342](https://tools.ietf.org/html/rfc2342).
+    Namespace,
     /// Auth type capability.
     Auth(String),
     /// Any other atoms.
-    Atom(String),
+    Other(String),
+}
+
+impl Capability {
+    /// Returns the capability for an atom of a capability list, other than `AUTH=<mechanism>`.
+    fn from_atom(atom: &str) -> Self {
+        if let Some((_, capability)) = KNOWN_ATOMS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(atom))
+        {
+            return capability.clone();
+        }
+        match strip_prefix_ignore_case(atom, COMPRESS_CAPABILITY_PREFIX) {
+            Some(algorithm) => Capability::Compress(algorithm.to_string()),
+            None => Capability::Other(atom.to_string()),
+        }
+    }
+
+    /// Whether `name`, as it appears in a capability list, is this capability.  Capability names
+    /// are compared case-insensitively.
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            Capability::Auth(mechanism) => strip_prefix_ignore_case(name, AUTH_CAPABILITY_PREFIX)
+                .is_some_and(|offered| offered.eq_ignore_ascii_case(mechanism)),
+            Capability::Compress(algorithm) => {
+                strip_prefix_ignore_case(name, COMPRESS_CAPABILITY_PREFIX)
+                    .is_some_and(|offered| offered.eq_ignore_ascii_case(algorithm))
+            }
+            Capability::Other(atom) => name.eq_ignore_ascii_case(atom),
+            cap => KNOWN_ATOMS
+                .iter()
+                .any(|(known, known_cap)| known_cap == cap && known.eq_ignore_ascii_case(name)),
+        }
+    }
+
+    /// Parses a capability as it appears in a capability list, e.g. `IDLE` or `AUTH=PLAIN`.
+    fn parse(s: &str) -> Self {
+        match strip_prefix_ignore_case(s, AUTH_CAPABILITY_PREFIX) {
+            Some(mechanism) => Capability::Auth(mechanism.to_string()),
+            None => Capability::from_atom(s),
+        }
+    }
+}
+
+/// Returns the rest of `s` if it starts with `prefix`, ignoring case, and has more after it.
+fn strip_prefix_ignore_case<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    if s.len() <= prefix.len() || !s.is_char_boundary(prefix.len()) {
+        return None;
+    }
+    let (pre, rest) = s.split_at(prefix.len());
+    pre.eq_ignore_ascii_case(prefix).then_some(rest)
 }
 
 impl From<&CapabilityRef<'_>> for Capability {
@@ -21,7 +112,27 @@ impl From<&CapabilityRef<'_>> for Capability {
         match c {
             CapabilityRef::Imap4rev1 => Capability::Imap4rev1,
             CapabilityRef::Auth(s) => Capability::Auth(s.clone().into_owned()),
-            CapabilityRef::Atom(s) => Capability::Atom(s.clone().into_owned()),
+            CapabilityRef::Atom(s) => Capability::from_atom(s),
+        }
+    }
+}
+
+/// Writes the capability as it appears in a capability list.
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capability::Compress(algorithm) => {
+                write!(f, "{}{}", COMPRESS_CAPABILITY_PREFIX, algorithm)
+            }
+            Capability::Auth(mechanism) => write!(f, "{}{}", AUTH_CAPABILITY_PREFIX, mechanism),
+            Capability::Other(atom) => f.write_str(atom),
+            cap => {
+                let (name, _) = KNOWN_ATOMS
+                    .iter()
+                    .find(|(_, known)| known == cap)
+                    .expect("all other capabilities are listed");
+                f.write_str(name)
+            }
         }
     }
 }
@@ -51,41 +162,168 @@ impl From<&CapabilityRef<'_>> for Capability {
 ///
 /// Client implementations SHOULD NOT require any capability name other than `IMAP4rev1`, and MUST
 /// ignore any unknown capability names.
-pub struct Capabilities(pub(crate) HashSet<Capability>);
+#[derive(Clone, Default)]
+pub struct Capabilities {
+    /// The capability names as listed by the server, each followed by a space, so that the
+    /// whole list takes up a single allocation.
+    names: String,
+}
 
 impl Capabilities {
+    /// Adds a capability of a `CAPABILITY` response or response code, unless it is already
+    /// listed.
+    pub(crate) fn insert(&mut self, capability: &CapabilityRef<'_>) {
+        let start = self.names.len();
+        match capability {
+            CapabilityRef::Imap4rev1 => self.names.push_str(IMAP4REV1_CAPABILITY),
+            CapabilityRef::Auth(mechanism) => {
+                self.names.push_str(AUTH_CAPABILITY_PREFIX);
+                self.names.push_str(mechanism);
+            }
+            CapabilityRef::Atom(atom) => self.names.push_str(atom),
+        }
+        let (listed, name) = self.names.split_at(start);
+        if listed
+            .split_terminator(' ')
+            .any(|listed| listed.eq_ignore_ascii_case(name))
+        {
+            self.names.truncate(start);
+        } else {
+            self.names.push(' ');
+        }
+    }
+
+    /// Returns the capability names as listed by the server.
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.split_terminator(' ')
+    }
+
     /// Check if the server has the given capability.
     pub fn has(&self, cap: &Capability) -> bool {
-        self.0.contains(cap)
+        self.names().any(|name| cap.matches(name))
     }
 
-    /// Check if the server has the given capability via str.
+    /// Check if the server has the given capability via str.  Capability names are compared
+    /// case-insensitively.
     pub fn has_str<S: AsRef<str>>(&self, cap: S) -> bool {
-        let s = cap.as_ref();
-        if s.eq_ignore_ascii_case(IMAP4REV1_CAPABILITY) {
-            return self.has(&Capability::Imap4rev1);
-        }
-        if s.len() > AUTH_CAPABILITY_PREFIX.len() {
-            let (pre, val) = s.split_at(AUTH_CAPABILITY_PREFIX.len());
-            if pre.eq_ignore_ascii_case(AUTH_CAPABILITY_PREFIX) {
-                return self.has(&Capability::Auth(val.into())); // TODO: avoid clone
-            }
-        }
-        self.has(&Capability::Atom(s.into())) // TODO: avoid clone
+        let cap = cap.as_ref();
+        self.names().any(|name| name.eq_ignore_ascii_case(cap))
+    }
+
+    /// Whether the server supports the `IDLE` command, see
+    /// [`Session::idle`](crate::Session::idle).
+    pub fn supports_idle(&self) -> bool {
+        self.has(&Capability::Idle)
+    }
+
+    /// Whether the server supports the `MOVE` command, see [`Session::mv`](crate::Session::mv).
+    pub fn supports_move(&self) -> bool {
+        self.has(&Capability::Move)
+    }
+
+    /// Whether the server supports `STARTTLS`, see [`Client::starttls`](crate::Client::starttls).
+    pub fn supports_starttls(&self) -> bool {
+        self.has(&Capability::StartTls)
+    }
+
+    /// Whether the server supports `UIDPLUS`, which adds `UID EXPUNGE` and reports the UIDs of
+    /// appended and copied messages.
+    pub fn supports_uidplus(&self) -> bool {
+        self.has(&Capability::UidPlus)
+    }
+
+    /// Whether the server supports `CONDSTORE`, or `QRESYNC`, which implies it.
+    pub fn supports_condstore(&self) -> bool {
+        self.has(&Capability::CondStore) || self.supports_qresync()
+    }
+
+    /// Whether the server supports `QRESYNC`.
+    pub fn supports_qresync(&self) -> bool {
+        self.has(&Capability::QResync)
+    }
+
+    /// Returns the names of the authentication mechanisms the server offers with
+    /// `AUTH=<mechanism>` capabilities, such as `PLAIN` or `XOAUTH2`, for use with
+    /// [`Client::authenticate`](crate::Client::authenticate).
+    pub fn auth_mechanisms(&self) -> impl Iterator<Item = &str> {
+        self.names()
+            .filter_map(|name| strip_prefix_ignore_case(name, AUTH_CAPABILITY_PREFIX))
+    }
+
+    /// Whether the server offers the authentication mechanism `mechanism`, e.g. `"PLAIN"`.
+    /// Mechanism names are compared case-insensitively.
+    pub fn supports_auth(&self, mechanism: &str) -> bool {
+        self.auth_mechanisms()
+            .any(|offered| offered.eq_ignore_ascii_case(mechanism))
     }
 
     /// Iterate over all the server's capabilities
-    pub fn iter(&self) -> Iter<'_, Capability> {
-        self.0.iter()
+    pub fn iter(&self) -> impl Iterator<Item = Capability> + '_ {
+        self.names().map(Capability::parse)
     }
 
     /// Returns how many capabilities the server has.
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.names().count()
     }
 
     /// Returns true if the server purports to have no capabilities.
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.names.is_empty()
+    }
+}
+
+impl fmt::Debug for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let mut caps = Capabilities::default();
+        for cap in &[
+            CapabilityRef::Imap4rev1,
+            CapabilityRef::Atom("IMAP4rev2".into()),
+            CapabilityRef::Atom("Idle".into()),
+            CapabilityRef::Auth("PLAIN".into()),
+            CapabilityRef::Atom("COMPRESS=DEFLATE".into()),
+            CapabilityRef::Atom("X-FOO".into()),
+            CapabilityRef::Atom("IDLE".into()),
+        ] {
+            caps.insert(cap);
+        }
+        assert_eq!(caps.len(), 6);
+        assert!(caps.has(&Capability::Imap4rev1));
+        assert!(caps.has(&Capability::Imap4rev2));
+        assert!(caps.supports_idle());
+        assert!(!caps.supports_move());
+        assert!(caps.has(&Capability::Compress("DEFLATE".into())));
+        assert!(caps.has(&Capability::Other("x-foo".into())));
+        assert!(!caps.has(&Capability::Other("FOO".into())));
+        assert!(caps.has_str("IDLE"));
+        assert!(caps.has_str("auth=PLAIN"));
+        assert!(!caps.has_str("AUTH=XOAUTH2"));
+        assert_eq!(caps.auth_mechanisms().collect::<Vec<_>>(), ["PLAIN"]);
+        assert!(caps.supports_auth("plain"));
+        assert!(!caps.supports_auth("XOAUTH2"));
+
+        let mut names = caps.iter().map(|cap| cap.to_string()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "AUTH=PLAIN",
+                "COMPRESS=DEFLATE",
+                "IDLE",
+                "IMAP4rev1",
+                "IMAP4rev2",
+                "X-FOO"
+            ]
+        );
     }
 }
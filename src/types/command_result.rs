@@ -0,0 +1,47 @@
+use imap_proto::{ResponseCode, Status};
+
+use super::ResponseData;
+
+/// The tagged response completing a command, such as `A0001 OK [READ-WRITE] Select completed`.
+///
+/// See [RFC 3501 section 7.1](https://tools.ietf.org/html/rfc3501#section-7.1) for the response
+/// codes a server may include.
+#[derive(Debug)]
+pub struct CommandResult {
+    response: ResponseData,
+}
+
+impl CommandResult {
+    /// Wraps a `Response::Done`.
+    pub(crate) fn new(response: ResponseData) -> Self {
+        debug_assert!(matches!(
+            response.parsed(),
+            imap_proto::Response::Done { .. }
+        ));
+        CommandResult { response }
+    }
+
+    /// The status of the response, `OK` unless the command failed.
+    pub fn status(&self) -> &Status {
+        match self.response.parsed() {
+            imap_proto::Response::Done { status, .. } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The response code, e.g. [`ResponseCode::ReadWrite`], if the server sent one.
+    pub fn code(&self) -> Option<&ResponseCode<'_>> {
+        match self.response.parsed() {
+            imap_proto::Response::Done { code, .. } => code.as_ref(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The human-readable text of the response, which may be empty.
+    pub fn text(&self) -> &str {
+        match self.response.parsed() {
+            imap_proto::Response::Done { information, .. } => information.as_deref().unwrap_or(""),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use imap_proto::types::ResponseCode;
+
+use super::append::expand_uid_set;
+use super::Uid;
+
+/// The [`COPYUID` response code](https://tools.ietf.org/html/rfc4315#section-3) returned by
+/// servers supporting the `UIDPLUS` extension, telling which [`Uid`]s copied messages got in
+/// the destination mailbox.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CopyUid {
+    /// The `UIDVALIDITY` of the destination mailbox.
+    pub uid_validity: u32,
+    /// The UID of each copied message in the source mailbox, paired with the UID of its copy in
+    /// the destination mailbox, in ascending order of the source UIDs.
+    pub uids: Vec<(Uid, Uid)>,
+}
+
+impl CopyUid {
+    pub(crate) fn from_code(code: &ResponseCode<'_>) -> Option<Self> {
+        match code {
+            ResponseCode::CopyUid(uid_validity, source, destination) => {
+                let source = expand_uid_set(source)?;
+                let destination = expand_uid_set(destination)?;
+                if source.len() != destination.len() {
+                    return None;
+                }
+                Some(CopyUid {
+                    uid_validity: *uid_validity,
+                    uids: source.into_iter().zip(destination).collect(),
+                })
+            }
+            _ => None,
+        }
+    }
+}
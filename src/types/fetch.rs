@@ -4,6 +4,7 @@ use imap_proto::types::{
 };
 
 use super::{Flag, Seq, Uid};
+use crate::ext_parse::ExtensionAttribute;
 use crate::types::ResponseData;
 
 /// Format of Date and Time as defined RFC3501.
@@ -14,6 +15,9 @@ const DATE_TIME_FORMAT: &str = "%d-%b-%Y %H:%M:%S %z";
 /// An IMAP [`FETCH` response](https://tools.ietf.org/html/rfc3501#section-7.4.2) that contains
 /// data about a particular message. This response occurs as the result of a `FETCH` or `STORE`
 /// command, as well as by unilateral server decision (e.g., flag updates).
+///
+/// A `Fetch` owns the response it was parsed from and borrows nothing from the session, so it
+/// can be kept after the stream that returned it is dropped, or sent to another task.
 #[derive(Debug)]
 pub struct Fetch {
     response: ResponseData,
@@ -28,35 +32,51 @@ pub struct Fetch {
     /// A number expressing the [RFC-2822](https://tools.ietf.org/html/rfc2822) size of the message.
     /// Only present if `RFC822.SIZE` was specified in the query argument to `FETCH`.
     pub size: Option<u32>,
+
+    gmail_labels: Option<Vec<String>>,
 }
 
 impl Fetch {
     pub(crate) fn new(response: ResponseData) -> Self {
-        let (message, uid, size) = if let Response::Fetch(message, attrs) = response.parsed() {
-            let mut uid = None;
-            let mut size = None;
-
-            for attr in attrs {
-                match attr {
-                    AttributeValue::Uid(id) => uid = Some(*id),
-                    AttributeValue::Rfc822Size(sz) => size = Some(*sz),
-                    _ => {}
+        let (message, uid, size, gmail_labels) =
+            if let Response::Fetch(message, attrs) = response.parsed() {
+                let mut uid = None;
+                let mut size = None;
+                let mut gmail_labels = None;
+
+                for attr in attrs {
+                    match attr {
+                        AttributeValue::Uid(id) => uid = Some(*id),
+                        AttributeValue::Rfc822Size(sz) => size = Some(*sz),
+                        AttributeValue::GmailLabels(labels) => {
+                            gmail_labels = Some(labels.iter().map(|l| l.to_string()).collect())
+                        }
+                        _ => {}
+                    }
                 }
-            }
-            (*message, uid, size)
-        } else {
-            unreachable!()
-        };
+                (Seq(*message), uid.map(Uid), size, gmail_labels)
+            } else {
+                unreachable!()
+            };
 
         Fetch {
             response,
             message,
             uid,
             size,
+            gmail_labels,
         }
     }
 
-    /// A list of flags that are set for this message.
+    /// Returns the response this was parsed from.
+    pub(crate) fn into_response(self) -> ResponseData {
+        self.response
+    }
+
+    /// A list of flags that are set for this message, if `FLAGS` was included in the `query`
+    /// argument to `FETCH`, or the flags were changed with [`Session::store`](crate::Session::store).
+    ///
+    /// This includes [`Flag::Recent`], which the server sets and which can not be stored.
     pub fn flags(&self) -> impl Iterator<Item = Flag<'_>> {
         if let Response::Fetch(_, attrs) = self.response.parsed() {
             attrs
@@ -73,6 +93,41 @@ impl Fetch {
         }
     }
 
+    /// Whether the message has [`Flag::Seen`] set.  Like the other flag predicates, this is
+    /// `false` if the flags were not fetched, see [`Fetch::flags`].
+    pub fn is_seen(&self) -> bool {
+        self.has_flag(Flag::Seen)
+    }
+
+    /// Whether the message has [`Flag::Answered`] set.
+    pub fn is_answered(&self) -> bool {
+        self.has_flag(Flag::Answered)
+    }
+
+    /// Whether the message has [`Flag::Flagged`] set.
+    pub fn is_flagged(&self) -> bool {
+        self.has_flag(Flag::Flagged)
+    }
+
+    /// Whether the message has [`Flag::Deleted`] set.
+    pub fn is_deleted(&self) -> bool {
+        self.has_flag(Flag::Deleted)
+    }
+
+    /// Whether the message has [`Flag::Draft`] set.
+    pub fn is_draft(&self) -> bool {
+        self.has_flag(Flag::Draft)
+    }
+
+    /// Whether the message has [`Flag::Recent`] set.
+    pub fn is_recent(&self) -> bool {
+        self.has_flag(Flag::Recent)
+    }
+
+    fn has_flag(&self, flag: Flag<'_>) -> bool {
+        self.flags().any(|f| f == flag)
+    }
+
     /// The bytes that make up the header of this message, if `BODY[HEADER]`, `BODY.PEEK[HEADER]`,
     /// or `RFC822.HEADER` was included in the `query` argument to `FETCH`.
     pub fn header(&self) -> Option<&[u8]> {
@@ -94,6 +149,36 @@ impl Fetch {
         }
     }
 
+    /// The raw header lines returned for `BODY[HEADER.FIELDS (<fields>)]`,
+    /// `BODY.PEEK[HEADER.FIELDS (<fields>)]` or `BODY[HEADER.FIELDS.NOT (<fields>)]`.
+    ///
+    /// The server reports these sections like `BODY[HEADER]`, so this returns the same bytes as
+    /// [`Fetch::header`]; use [`Fetch::header_field`] to get the value of a single field.
+    pub fn header_fields(&self) -> Option<&[u8]> {
+        self.header()
+    }
+
+    /// The value of the first header field called `name`, compared case-insensitively, in the
+    /// [header](Fetch::header) of this message, e.g. as fetched with
+    /// `BODY.PEEK[HEADER.FIELDS (FROM SUBJECT DATE)]`.
+    ///
+    /// Folded values spanning several lines are unfolded as described in [RFC 5322 section
+    /// 2.2.3](https://tools.ietf.org/html/rfc5322#section-2.2.3), and surrounding whitespace is
+    /// removed. The value is returned as sent, so encoded words are not decoded, and bytes that
+    /// are not valid UTF-8 are replaced.
+    pub fn header_field(&self, name: &str) -> Option<String> {
+        header_field_values(self.header()?, name).next()
+    }
+
+    /// The values of all header fields called `name`, in the order they appear in the header,
+    /// see [`Fetch::header_field`].
+    pub fn header_field_all(&self, name: &str) -> Vec<String> {
+        match self.header() {
+            Some(header) => header_field_values(header, name).collect(),
+            None => Vec::new(),
+        }
+    }
+
     /// The bytes that make up this message, included if `BODY[]` or `RFC822` was included in the
     /// `query` argument to `FETCH`. The bytes SHOULD be interpreted by the client according to the
     /// content transfer encoding, body type, and subtype.
@@ -116,6 +201,13 @@ impl Fetch {
         }
     }
 
+    /// The bytes that make up this message, if `RFC822`, `BODY[]` or `BODY.PEEK[]` was included in
+    /// the `query` argument to `FETCH`.  `RFC822` is the older form of `BODY[]`, so this is the
+    /// same as [`Fetch::body`].
+    pub fn rfc822(&self) -> Option<&[u8]> {
+        self.body()
+    }
+
     /// The bytes that make up the text of this message, included if `BODY[TEXT]`, `RFC822.TEXT`,
     /// or `BODY.PEEK[TEXT]` was included in the `query` argument to `FETCH`. The bytes SHOULD be
     /// interpreted by the client according to the content transfer encoding, body type, and
@@ -182,6 +274,158 @@ impl Fetch {
         }
     }
 
+    /// The bytes of the body section given by its section specifier as used in the `query`
+    /// argument to `FETCH`, i.e. what is between the brackets of `BODY[<spec>]`, such as `1.2`,
+    /// `HEADER`, `TEXT` or `2.MIME`. An empty `spec` returns the entire message like
+    /// [`Fetch::body`].
+    ///
+    /// Each section requested in the same `FETCH` can be retrieved separately, e.g. `1` and `2`
+    /// after fetching `(BODY[1] BODY[2])`. `HEADER.FIELDS` sections are reported by the server
+    /// like `HEADER`, see [`Fetch::header_fields`]. Returns `None` if `spec` is not a valid
+    /// section specifier.
+    ///
+    /// The data of the older `RFC822`, `RFC822.HEADER` and `RFC822.TEXT` items is returned for
+    /// the equivalent `""`, `HEADER` and `TEXT` sections.
+    pub fn section_spec(&self, spec: &str) -> Option<&[u8]> {
+        match parse_section_spec(spec)? {
+            Some(SectionPath::Full(MessageSection::Header)) => self.header(),
+            Some(SectionPath::Full(MessageSection::Text)) => self.text(),
+            Some(path) => self.section(&path),
+            None => self.body(),
+        }
+    }
+
+    /// The bytes of a partial fetch together with the offset at which they start, if a
+    /// `BODY[<section>]<<offset>.<length>>` item was included in the `query` argument to
+    /// `FETCH`, e.g. with [`Session::fetch_partial`](crate::Session::fetch_partial).
+    ///
+    /// The length of the returned bytes is the number of bytes actually returned by the server,
+    /// which is smaller than the requested length near the end of the section.
+    pub fn partial(&self) -> Option<(u32, &[u8])> {
+        if let Response::Fetch(_, attrs) = self.response.parsed() {
+            attrs
+                .iter()
+                .filter_map(|av| match av {
+                    AttributeValue::BodySection {
+                        index: Some(offset),
+                        data,
+                        ..
+                    } => Some((*offset, data.as_deref().unwrap_or_default())),
+                    _ => None,
+                })
+                .next()
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// The offset at which the bytes of a partial fetch start, see [`Fetch::partial`].
+    pub fn partial_offset(&self) -> Option<u32> {
+        self.partial().map(|(offset, _)| offset)
+    }
+
+    /// The decoded bytes of the given body part, if `BINARY[section]` or `BINARY.PEEK[section]`
+    /// was included in the `query` argument to `FETCH`. An empty `section` refers to the entire
+    /// message.
+    ///
+    /// Unlike [`Fetch::section`], the server has already removed any content transfer encoding,
+    /// so the returned data may contain arbitrary bytes. See
+    /// [RFC 3516](https://tools.ietf.org/html/rfc3516) for details.
+    pub fn binary(&self, section: &[u32]) -> Option<&[u8]> {
+        self.response
+            .extensions()
+            .iter()
+            .filter_map(|ext| match ext {
+                ExtensionAttribute::Binary {
+                    section: s,
+                    data: Some(data),
+                } if s.as_slice() == section => Some(data.as_slice()),
+                _ => None,
+            })
+            .next()
+    }
+
+    /// The size of the given body part after decoding, if `BINARY.SIZE[section]` was included
+    /// in the `query` argument to `FETCH`.
+    pub fn binary_size(&self, section: &[u32]) -> Option<u32> {
+        self.response
+            .extensions()
+            .iter()
+            .filter_map(|ext| match ext {
+                ExtensionAttribute::BinarySize { section: s, size } if s.as_slice() == section => {
+                    Some(*size)
+                }
+                _ => None,
+            })
+            .next()
+    }
+
+    /// The Gmail message ID, if `X-GM-MSGID` was included in the `query` argument to `FETCH`.
+    ///
+    /// Only servers that advertise the `X-GM-EXT-1` capability support this, see
+    /// [Gmail IMAP extensions](https://developers.google.com/gmail/imap/imap-extensions).
+    pub fn gmail_msgid(&self) -> Option<u64> {
+        if let Response::Fetch(_, attrs) = self.response.parsed() {
+            attrs
+                .iter()
+                .filter_map(|av| match av {
+                    AttributeValue::GmailMsgId(id) => Some(*id),
+                    _ => None,
+                })
+                .next()
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// The Gmail thread ID, if `X-GM-THRID` was included in the `query` argument to `FETCH`.
+    ///
+    /// Only servers that advertise the `X-GM-EXT-1` capability support this, see
+    /// [Gmail IMAP extensions](https://developers.google.com/gmail/imap/imap-extensions).
+    pub fn gmail_thrid(&self) -> Option<u64> {
+        if let Response::Fetch(_, attrs) = self.response.parsed() {
+            attrs
+                .iter()
+                .filter_map(|av| match av {
+                    AttributeValue::GmailThrId(id) => Some(*id),
+                    _ => None,
+                })
+                .next()
+        } else {
+            unreachable!()
+        }
+    }
+
+    /// The Gmail labels of this message, if `X-GM-LABELS` was included in the `query` argument
+    /// to `FETCH`, or the labels were changed with [`Session::store`](crate::Session::store).
+    ///
+    /// The labels are returned as sent by the server, without decoding them. System labels start
+    /// with a backslash, e.g. `\Inbox`, and user labels may be encoded in modified UTF-7.
+    pub fn gmail_labels(&self) -> Option<&[String]> {
+        self.gmail_labels.as_deref()
+    }
+
+    /// The mod-sequence of this message, if `MODSEQ` was included in the `query` argument to
+    /// `FETCH`, see the [`CONDSTORE` extension](https://tools.ietf.org/html/rfc7162#section-3.1.4).
+    ///
+    /// Once `CONDSTORE` is enabled, e.g. with [`Session::enable`](crate::Session::enable) or by
+    /// selecting a mailbox with the `CONDSTORE` parameter, servers include it in all `FETCH`
+    /// responses, also those to [`Session::store`](crate::Session::store) and ones that were not
+    /// asked for, so it is available whenever the server sent it.
+    pub fn mod_seq(&self) -> Option<u64> {
+        if let Response::Fetch(_, attrs) = self.response.parsed() {
+            attrs
+                .iter()
+                .filter_map(|av| match av {
+                    AttributeValue::ModSeq(modseq) => Some(*modseq),
+                    _ => None,
+                })
+                .next()
+        } else {
+            unreachable!()
+        }
+    }
+
     /// Extract the `INTERNALDATE` of a `FETCH` response
     ///
     /// See [section 2.3.3 of RFC 3501](https://tools.ietf.org/html/rfc3501#section-2.3.3) for
@@ -195,12 +439,7 @@ impl Fetch {
                     _ => None,
                 })
                 .next()
-                .and_then(
-                    |date_time| match DateTime::parse_from_str(date_time, DATE_TIME_FORMAT) {
-                        Ok(date_time) => Some(date_time),
-                        Err(_) => None,
-                    },
-                )
+                .and_then(|date_time| DateTime::parse_from_str(date_time, DATE_TIME_FORMAT).ok())
         } else {
             unreachable!()
         }
@@ -224,3 +463,213 @@ impl Fetch {
         }
     }
 }
+
+/// Parses a section specifier such as `1.2.MIME`, returning `Some(None)` for the empty specifier
+/// of the entire message.
+pub(crate) fn parse_section_spec(spec: &str) -> Option<Option<SectionPath>> {
+    let text = |name: &str| {
+        let upper = name.to_ascii_uppercase();
+        match upper.as_str() {
+            "HEADER" => Some(MessageSection::Header),
+            "TEXT" => Some(MessageSection::Text),
+            "MIME" => Some(MessageSection::Mime),
+            _ if upper.starts_with("HEADER.FIELDS") => Some(MessageSection::Header),
+            _ => None,
+        }
+    };
+    let spec = spec.trim();
+    if spec.is_empty() {
+        return Some(None);
+    }
+    let mut parts = Vec::new();
+    let mut rest = spec;
+    loop {
+        let end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if end == 0 {
+            break;
+        }
+        parts.push(rest[..end].parse().ok()?);
+        rest = &rest[end..];
+        if rest.is_empty() {
+            break;
+        }
+        rest = rest.strip_prefix('.')?;
+    }
+    let section = match rest {
+        "" => None,
+        rest => Some(text(rest)?),
+    };
+    match (parts.is_empty(), section) {
+        (true, Some(MessageSection::Mime)) | (true, None) => None,
+        (true, Some(section)) => Some(Some(SectionPath::Full(section))),
+        (false, section) => Some(Some(SectionPath::Part(parts, section))),
+    }
+}
+
+/// Returns the unfolded values of the fields called `name` in `header`.
+fn header_field_values<'a>(header: &'a [u8], name: &'a str) -> impl Iterator<Item = String> + 'a {
+    let mut fields: Vec<Vec<u8>> = Vec::new();
+    for line in header.split(|&b| b == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        match line.first() {
+            // The empty line separating the header from the body.
+            None => break,
+            Some(b' ' | b'\t') => {
+                if let Some(field) = fields.last_mut() {
+                    field.extend_from_slice(line);
+                }
+            }
+            Some(_) => fields.push(line.to_vec()),
+        }
+    }
+    fields.into_iter().filter_map(move |field| {
+        let colon = field.iter().position(|&b| b == b':')?;
+        let field_name = std::str::from_utf8(&field[..colon]).ok()?;
+        if !field_name.trim_end().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        Some(String::from_utf8_lossy(field[colon + 1..].trim_ascii()).into_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imap_stream::ImapStream;
+    use crate::mock_stream::MockStream;
+    use crate::types::Name;
+    use async_std::prelude::*;
+
+    fn assert_owned<T: Send + 'static>() {}
+
+    #[test]
+    fn owned() {
+        assert_owned::<Fetch>();
+        assert_owned::<Name>();
+    }
+
+    #[test]
+    fn section_spec() {
+        assert_eq!(parse_section_spec(""), Some(None));
+        assert_eq!(
+            parse_section_spec("header"),
+            Some(Some(SectionPath::Full(MessageSection::Header)))
+        );
+        assert_eq!(
+            parse_section_spec("HEADER.FIELDS (FROM)"),
+            Some(Some(SectionPath::Full(MessageSection::Header)))
+        );
+        assert_eq!(
+            parse_section_spec("1.2"),
+            Some(Some(SectionPath::Part(vec![1, 2], None)))
+        );
+        assert_eq!(
+            parse_section_spec("2.MIME"),
+            Some(Some(SectionPath::Part(vec![2], Some(MessageSection::Mime))))
+        );
+        assert_eq!(parse_section_spec("MIME"), None);
+        assert_eq!(parse_section_spec("1.X"), None);
+        assert_eq!(parse_section_spec("1,2"), None);
+    }
+
+    #[async_std::test]
+    async fn flag_predicates() {
+        let response = b"* 1 FETCH (FLAGS (\\Seen \\FLAGGED \\Recent $Forwarded))\r\n\
+                         * 2 FETCH (UID 2)\r\n";
+        let mut stream = ImapStream::new(MockStream::new(response.to_vec()));
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+        assert_eq!(
+            fetch.flags().collect::<Vec<_>>(),
+            [
+                Flag::Seen,
+                Flag::Flagged,
+                Flag::Recent,
+                Flag::Custom("$Forwarded".into())
+            ]
+        );
+        assert!(fetch.is_seen());
+        assert!(fetch.is_flagged());
+        assert!(fetch.is_recent());
+        assert!(!fetch.is_answered());
+        assert!(!fetch.is_deleted());
+        assert!(!fetch.is_draft());
+
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+        assert!(!fetch.is_seen());
+        assert!(!fetch.is_recent());
+    }
+
+    #[async_std::test]
+    async fn sections() {
+        let response = b"* 1 FETCH (BODY[1] {5}\r\nfirst BODY[2] {6}\r\nsecond \
+                         BODY[2.MIME] {26}\r\nContent-Type: text/plain\r\n \
+                         BODY[TEXT] {4}\r\ntext)\r\n";
+        let mut stream = ImapStream::new(MockStream::new(response.to_vec()));
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+
+        assert_eq!(fetch.section_spec("1"), Some(&b"first"[..]));
+        assert_eq!(fetch.section_spec("2"), Some(&b"second"[..]));
+        assert_eq!(
+            fetch.section_spec("2.MIME"),
+            Some(&b"Content-Type: text/plain\r\n"[..])
+        );
+        assert_eq!(fetch.section_spec("TEXT"), Some(&b"text"[..]));
+        assert_eq!(fetch.section_spec("3"), None);
+        assert_eq!(fetch.section_spec(""), None);
+    }
+
+    #[async_std::test]
+    async fn rfc822() {
+        let response = b"* 1 FETCH (RFC822.TEXT {5}\r\nhello)\r\n\
+                         * 2 FETCH (RFC822 {20}\r\nSubject: hi\r\n\r\nhello \
+                         RFC822.HEADER {15}\r\nSubject: hi\r\n\r\n)\r\n";
+        let mut stream = ImapStream::new(MockStream::new(response.to_vec()));
+
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+        assert_eq!(fetch.text(), Some(&b"hello"[..]));
+        assert_eq!(fetch.section_spec("TEXT"), Some(&b"hello"[..]));
+        assert_eq!(fetch.rfc822(), None);
+        assert_eq!(fetch.header(), None);
+
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+        assert_eq!(fetch.rfc822(), Some(&b"Subject: hi\r\n\r\nhello"[..]));
+        assert_eq!(fetch.body(), fetch.rfc822());
+        assert_eq!(fetch.section_spec(""), fetch.rfc822());
+        assert_eq!(fetch.header(), Some(&b"Subject: hi\r\n\r\n"[..]));
+        assert_eq!(fetch.section_spec("HEADER"), fetch.header());
+        assert_eq!(fetch.header_field("subject").as_deref(), Some("hi"));
+        assert_eq!(fetch.text(), None);
+    }
+
+    #[async_std::test]
+    async fn header_fields() {
+        let header = "Subject: A rather long subject\r\n \tthat was folded\r\n\
+                      From: Alice <alice@example.com>\r\n\
+                      received: by mx1\r\n\
+                      Received: by mx2\r\n\
+                      \r\n";
+        let response = format!(
+            "* 1 FETCH (BODY[HEADER.FIELDS (SUBJECT FROM RECEIVED)] {{{}}}\r\n{})\r\n",
+            header.len(),
+            header
+        );
+        let mut stream = ImapStream::new(MockStream::new(response.into_bytes()));
+        let fetch = Fetch::new(stream.next().await.unwrap().unwrap());
+
+        assert_eq!(fetch.header_fields(), Some(header.as_bytes()));
+        assert_eq!(
+            fetch.header_field("subject").as_deref(),
+            Some("A rather long subject \tthat was folded")
+        );
+        assert_eq!(
+            fetch.header_field("From").as_deref(),
+            Some("Alice <alice@example.com>")
+        );
+        assert_eq!(fetch.header_field("Received").as_deref(), Some("by mx1"));
+        assert_eq!(fetch.header_field_all("Received"), vec!["by mx1", "by mx2"]);
+        assert_eq!(fetch.header_field("Date"), None);
+        assert!(fetch.header_field_all("Date").is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+use std::fmt;
+
+/// The data items to fetch with [`Session::fetch`](crate::Session::fetch) and
+/// [`Session::uid_fetch`](crate::Session::uid_fetch), built without writing the query by hand:
+///
+/// ```
+/// use async_imap::types::FetchQuery;
+///
+/// let query = FetchQuery::new()
+///     .uid()
+///     .flags()
+///     .body_peek("HEADER.FIELDS (FROM SUBJECT)");
+/// assert_eq!(
+///     query.to_string(),
+///     "(UID FLAGS BODY.PEEK[HEADER.FIELDS (FROM SUBJECT)])"
+/// );
+/// assert_eq!(FetchQuery::new().envelope().to_string(), "ENVELOPE");
+/// ```
+///
+/// Body sections are fetched with `BODY.PEEK`, so that fetching a message does not mark it as
+/// [`Flag::Seen`](super::Flag::Seen), unless [`FetchQuery::mark_seen`] is used.
+///
+/// Queries can also be given as strings such as `"(UID FLAGS)"`, which are sent as they are.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FetchQuery {
+    items: Vec<Item>,
+    /// Whether body sections are fetched with `BODY` instead of `BODY.PEEK`.
+    mark_seen: bool,
+}
+
+/// A data item of a [`FetchQuery`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Item {
+    /// A body section, fetched with `BODY` or `BODY.PEEK`.
+    Body(String),
+    Other(String),
+}
+
+impl FetchQuery {
+    /// Creates an empty query, to which data items are added with the other methods.
+    pub fn new() -> Self {
+        FetchQuery::default()
+    }
+
+    /// Adds `UID`, the [`Uid`](super::Uid) of the message.
+    pub fn uid(self) -> Self {
+        self.item("UID")
+    }
+
+    /// Adds `FLAGS`, the flags that are set for the message.
+    pub fn flags(self) -> Self {
+        self.item("FLAGS")
+    }
+
+    /// Adds `ENVELOPE`, the envelope structure the server parsed from the message header.
+    pub fn envelope(self) -> Self {
+        self.item("ENVELOPE")
+    }
+
+    /// Adds `INTERNALDATE`, the date the server received the message.
+    pub fn internal_date(self) -> Self {
+        self.item("INTERNALDATE")
+    }
+
+    /// Adds `RFC822.SIZE`, the size of the message in bytes.
+    pub fn rfc822_size(self) -> Self {
+        self.item("RFC822.SIZE")
+    }
+
+    /// Adds `MODSEQ`, the mod-sequence of the message, see the [`CONDSTORE`
+    /// extension](https://tools.ietf.org/html/rfc7162#section-3.1.4).
+    pub fn modseq(self) -> Self {
+        self.item("MODSEQ")
+    }
+
+    /// Adds the items of the `FAST` macro: `FLAGS`, `INTERNALDATE` and `RFC822.SIZE`.
+    ///
+    /// Unlike the macro itself, which can not be combined with other items, the items are added
+    /// one by one, so that e.g. `FetchQuery::new().fast().uid()` is valid.
+    pub fn fast(self) -> Self {
+        self.flags().internal_date().rfc822_size()
+    }
+
+    /// Adds the items of the `ALL` macro, those of [`FetchQuery::fast`] and `ENVELOPE`.
+    pub fn all(self) -> Self {
+        self.fast().envelope()
+    }
+
+    /// Adds the items of the `FULL` macro, those of [`FetchQuery::all`] and `BODY`, the body
+    /// structure of the message without extension data, see
+    /// [`Fetch::bodystructure`](super::Fetch::bodystructure).
+    pub fn full(self) -> Self {
+        self.all().item("BODY")
+    }
+
+    /// Adds the body section `section` of the message.  `section` is given as in
+    /// `BODY[<section>]`, e.g. `""` for the entire message, `"2.1"` for a body part or
+    /// `"HEADER.FIELDS (FROM SUBJECT)"` for some header fields, see [`Session::fetch`].
+    ///
+    /// The section is fetched with `BODY.PEEK[<section>]`, so that the message is not marked as
+    /// [`Flag::Seen`](super::Flag::Seen), unless [`FetchQuery::mark_seen`] is used.  Note that
+    /// this differs from passing `BODY[<section>]` in a query string, which does mark the
+    /// message as seen; replacing such a string by this method changes that behavior.
+    ///
+    /// [`Session::fetch`]: crate::Session::fetch
+    pub fn body(self, section: impl AsRef<str>) -> Self {
+        self.push(Item::Body(section.as_ref().to_string()))
+    }
+
+    /// Adds `BODY.PEEK[<section>]`, like [`FetchQuery::body`], but never marks the message as
+    /// [`Flag::Seen`](super::Flag::Seen), even with [`FetchQuery::mark_seen`].
+    pub fn body_peek(self, section: impl AsRef<str>) -> Self {
+        self.item(format!("BODY.PEEK[{}]", section.as_ref()))
+    }
+
+    /// Fetches body sections with `BODY[<section>]` instead of `BODY.PEEK[<section>]`, so that
+    /// the server marks the messages as [`Flag::Seen`](super::Flag::Seen), as a mail client
+    /// does when the user opens a message.
+    pub fn mark_seen(mut self) -> Self {
+        self.mark_seen = true;
+        self
+    }
+
+    /// Adds any other data item, such as `BODYSTRUCTURE` or `X-GM-LABELS`, which is sent as it
+    /// is.  Items that are already part of the query are not added again.
+    pub fn item(self, item: impl Into<String>) -> Self {
+        self.push(Item::Other(item.into()))
+    }
+
+    fn push(mut self, item: Item) -> Self {
+        if !self.items.contains(&item) {
+            self.items.push(item);
+        }
+        self
+    }
+}
+
+impl fmt::Display for FetchQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let items = self
+            .items
+            .iter()
+            .map(|item| match item {
+                Item::Body(section) if self.mark_seen => format!("BODY[{}]", section),
+                Item::Body(section) => format!("BODY.PEEK[{}]", section),
+                Item::Other(item) => item.clone(),
+            })
+            .collect::<Vec<_>>();
+        match &items[..] {
+            [item] => f.write_str(item),
+            items => write!(f, "({})", items.join(" ")),
+        }
+    }
+}
+
+impl From<&str> for FetchQuery {
+    fn from(query: &str) -> Self {
+        FetchQuery::new().item(query)
+    }
+}
+
+impl From<String> for FetchQuery {
+    fn from(query: String) -> Self {
+        FetchQuery::new().item(query)
+    }
+}
+
+impl From<&String> for FetchQuery {
+    fn from(query: &String) -> Self {
+        FetchQuery::new().item(query.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let query = FetchQuery::new()
+            .uid()
+            .flags()
+            .rfc822_size()
+            .internal_date()
+            .modseq()
+            .body("TEXT")
+            .uid();
+        assert_eq!(
+            query.to_string(),
+            "(UID FLAGS RFC822.SIZE INTERNALDATE MODSEQ BODY.PEEK[TEXT])"
+        );
+        assert_eq!(FetchQuery::new().body_peek("").to_string(), "BODY.PEEK[]");
+        assert_eq!(
+            FetchQuery::new()
+                .body("")
+                .mark_seen()
+                .body("1")
+                .body_peek("HEADER")
+                .to_string(),
+            "(BODY[] BODY[1] BODY.PEEK[HEADER])"
+        );
+        assert_eq!(
+            FetchQuery::new().uid().fast().to_string(),
+            "(UID FLAGS INTERNALDATE RFC822.SIZE)"
+        );
+        assert_eq!(
+            FetchQuery::new().full().flags().to_string(),
+            "(FLAGS INTERNALDATE RFC822.SIZE ENVELOPE BODY)"
+        );
+        assert_eq!(FetchQuery::from("(UID FLAGS)").to_string(), "(UID FLAGS)");
+        assert_eq!(
+            FetchQuery::from("(FLAGS) (CHANGEDSINCE 5)".to_string()).to_string(),
+            "(FLAGS) (CHANGEDSINCE 5)"
+        );
+    }
+}
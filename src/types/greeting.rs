@@ -0,0 +1,57 @@
+use imap_proto::{ResponseCode, Status};
+
+use super::ResponseData;
+
+/// The greeting the server sends when the connection is established, such as
+/// `* OK [CAPABILITY IMAP4rev1 STARTTLS] Dovecot ready.`, see
+/// [`Client::greeting`](crate::Client::greeting).
+///
+/// The status is `OK` if the client still has to log in, or `PREAUTH` if the connection is
+/// already authenticated, e.g. by a client certificate, see [RFC 3501 section
+/// 7.1.4](https://tools.ietf.org/html/rfc3501#section-7.1.4).  A `BYE` greeting, with which the
+/// server rejects the connection, is returned as [`Error::Bye`](crate::error::Error::Bye)
+/// instead.
+#[derive(Debug)]
+pub struct Greeting {
+    response: ResponseData,
+}
+
+impl Greeting {
+    /// Wraps an untagged `Response::Data`.
+    pub(crate) fn new(response: ResponseData) -> Self {
+        debug_assert!(matches!(
+            response.parsed(),
+            imap_proto::Response::Data { .. }
+        ));
+        Greeting { response }
+    }
+
+    /// The status of the greeting, `OK` or `PREAUTH`.
+    pub fn status(&self) -> &Status {
+        match self.response.parsed() {
+            imap_proto::Response::Data { status, .. } => status,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Whether the connection is already authenticated, so that no login is needed.
+    pub fn is_preauth(&self) -> bool {
+        *self.status() == Status::PreAuth
+    }
+
+    /// The response code, usually [`ResponseCode::Capabilities`], if the server sent one.
+    pub fn code(&self) -> Option<&ResponseCode<'_>> {
+        match self.response.parsed() {
+            imap_proto::Response::Data { code, .. } => code.as_ref(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The human-readable text of the greeting, which may be empty.
+    pub fn text(&self) -> &str {
+        match self.response.parsed() {
+            imap_proto::Response::Data { information, .. } => information.as_deref().unwrap_or(""),
+            _ => unreachable!(),
+        }
+    }
+}
@@ -1,9 +1,9 @@
-use super::{Flag, Uid};
+use super::{Flag, ResponseCode, Uid};
 use std::fmt;
 
 /// Meta-information about an IMAP mailbox, as returned by
 /// [`SELECT`](https://tools.ietf.org/html/rfc3501#section-6.3.1) and friends.
-#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
 pub struct Mailbox {
     /// Defined flags in the mailbox.  See the description of the [FLAGS
     /// response](https://tools.ietf.org/html/rfc3501#section-7.2.6) for more detail.
@@ -15,18 +15,24 @@ pub struct Mailbox {
 
     /// The number of messages with the \Recent flag set. See the description of the [RECENT
     /// response](https://tools.ietf.org/html/rfc3501#section-7.3.2) for more detail.
-    pub recent: u32,
+    ///
+    /// This is `None` if the server did not send a `RECENT` response.  `IMAP4rev2` ([RFC 9051
+    /// section 7.4](https://tools.ietf.org/html/rfc9051#section-7.4)) removed the `\Recent` flag,
+    /// so servers using it do not report it.
+    pub recent: Option<u32>,
 
-    /// The message sequence number of the first unseen message in the mailbox.  If this is
-    /// missing, the client can not make any assumptions about the first unseen message in the
-    /// mailbox, and needs to issue a `SEARCH` command if it wants to find it.
-    pub unseen: Option<u32>,
+    /// The message sequence number of the first unseen message in the mailbox, sent in the
+    /// `UNSEEN` response code.  This is _not_ the number of unseen messages, which is returned by
+    /// [`Session::unread_count`](crate::Session::unread_count).  If this is missing, the client
+    /// can not make any assumptions about the first unseen message in the mailbox, and needs to
+    /// issue a `SEARCH` command if it wants to find it.
+    pub first_unseen: Option<u32>,
 
-    /// A list of message flags that the client can change permanently.  If this is missing, the
-    /// client should assume that all flags can be changed permanently. If the client attempts to
-    /// STORE a flag that is not in this list list, the server will either ignore the change or
-    /// store the state change for the remainder of the current session only.
-    pub permanent_flags: Vec<Flag<'static>>,
+    /// The message flags that the client can change permanently.  If this is missing, the client
+    /// should assume that all flags can be changed permanently. If the client attempts to STORE a
+    /// flag that is not in this list, the server will either ignore the change or store the state
+    /// change for the remainder of the current session only.
+    pub permanent_flags: PermanentFlags,
 
     /// The next unique identifier value.  If this is missing, the client can not make any
     /// assumptions about the next unique identifier value.
@@ -35,18 +41,59 @@ pub struct Mailbox {
     /// The unique identifier validity value.  See [`Uid`] for more details.  If this is missing,
     /// the server does not support unique identifiers.
     pub uid_validity: Option<u32>,
+
+    /// Whether the mailbox was opened read-only, as indicated by the `READ-ONLY` response code,
+    /// so that changes such as `STORE` and `EXPUNGE` will be rejected.  Mailboxes opened with
+    /// [`Session::examine`](crate::Session::examine) are always read-only.
+    pub read_only: bool,
+
+    /// The highest mod-sequence of all messages in the mailbox, sent in the `HIGHESTMODSEQ`
+    /// response code by servers supporting the [`CONDSTORE`
+    /// extension](https://tools.ietf.org/html/rfc7162#section-3.1.2.1).
+    pub highest_modseq: Option<u64>,
+
+    /// Whether the server sent the `NOMODSEQ` response code, meaning that it does not keep
+    /// mod-sequences for this mailbox, see [RFC 7162 section
+    /// 3.1.2.2](https://tools.ietf.org/html/rfc7162#section-3.1.2.2).
+    pub no_modseq: bool,
+
+    /// The unique and immutable identifier of the mailbox, sent in the `MAILBOXID` response code
+    /// of the [`OBJECTID` extension](https://tools.ietf.org/html/rfc8474#section-4.1).
+    pub mailbox_id: Option<String>,
+
+    /// Any other response codes the server sent while opening the mailbox, so that data of
+    /// extensions without a field here is not lost.
+    pub other_codes: Vec<ResponseCode>,
+}
+
+/// The flags that can be changed permanently in a mailbox, as sent in the [`PERMANENTFLAGS`
+/// response code](https://tools.ietf.org/html/rfc3501#section-7.1).
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PermanentFlags {
+    /// The flags listed by the server, without the special `\*` flag.
+    pub flags: Vec<Flag<'static>>,
+
+    /// Whether the server listed the special `\*` flag ([`Flag::MayCreate`]), meaning that new
+    /// keywords can be created by storing them on a message.
+    pub can_create_keywords: bool,
+}
+
+impl PermanentFlags {
+    /// Whether `flag` can be changed permanently, either because it is listed or because it is a
+    /// [`Flag::Custom`] keyword and new keywords can be created.
+    pub fn can_store(&self, flag: &Flag<'_>) -> bool {
+        self.flags.iter().any(|listed| listed == flag)
+            || (self.can_create_keywords && matches!(flag, Flag::Custom(_)))
+    }
 }
 
-impl Default for Mailbox {
-    fn default() -> Mailbox {
-        Mailbox {
-            flags: Vec::new(),
-            exists: 0,
-            recent: 0,
-            unseen: None,
-            permanent_flags: Vec::new(),
-            uid_next: None,
-            uid_validity: None,
+impl Extend<Flag<'static>> for PermanentFlags {
+    fn extend<I: IntoIterator<Item = Flag<'static>>>(&mut self, flags: I) {
+        for flag in flags {
+            match flag {
+                Flag::MayCreate => self.can_create_keywords = true,
+                flag => self.flags.push(flag),
+            }
         }
     }
 }
@@ -55,15 +102,21 @@ impl fmt::Display for Mailbox {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "flags: {:?}, exists: {}, recent: {}, unseen: {:?}, permanent_flags: {:?},\
-             uid_next: {:?}, uid_validity: {:?}",
+            "flags: {:?}, exists: {}, recent: {:?}, first_unseen: {:?}, permanent_flags: {:?},\
+             uid_next: {:?}, uid_validity: {:?}, read_only: {}, highest_modseq: {:?}, \
+             no_modseq: {}, mailbox_id: {:?}, other_codes: {:?}",
             self.flags,
             self.exists,
             self.recent,
-            self.unseen,
+            self.first_unseen,
             self.permanent_flags,
             self.uid_next,
-            self.uid_validity
+            self.uid_validity,
+            self.read_only,
+            self.highest_modseq,
+            self.no_modseq,
+            self.mailbox_id,
+            self.other_codes
         )
     }
 }
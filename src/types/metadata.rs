@@ -0,0 +1,52 @@
+use std::fmt;
+
+/// How far below the requested entries [`crate::Session::get_metadata_with_options`] should
+/// look, see [RFC 5464 section 4.2.2](https://tools.ietf.org/html/rfc5464#section-4.2.2).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MetadataDepth {
+    /// Only the requested entries themselves.
+    #[default]
+    Zero,
+    /// The requested entries and their immediate children.
+    One,
+    /// The requested entries and all entries below them.
+    Infinity,
+}
+
+impl fmt::Display for MetadataDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataDepth::Zero => write!(f, "0"),
+            MetadataDepth::One => write!(f, "1"),
+            MetadataDepth::Infinity => write!(f, "infinity"),
+        }
+    }
+}
+
+/// Options for [`crate::Session::get_metadata_with_options`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MetadataOptions {
+    /// The `DEPTH` option.
+    pub depth: MetadataDepth,
+    /// The `MAXSIZE` option: entries with values larger than this many octets are omitted.
+    pub max_size: Option<u64>,
+}
+
+impl MetadataOptions {
+    /// The options in the form expected by `GETMETADATA`, followed by a space, or an empty
+    /// string if all options have their default values.
+    pub(crate) fn arguments(&self) -> String {
+        let mut options = Vec::new();
+        if let Some(max_size) = self.max_size {
+            options.push(format!("MAXSIZE {}", max_size));
+        }
+        if self.depth != MetadataDepth::Zero {
+            options.push(format!("DEPTH {}", self.depth));
+        }
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!("({}) ", options.join(" "))
+        }
+    }
+}
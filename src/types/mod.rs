@@ -1,6 +1,11 @@
 //! This module contains types used throughout the IMAP protocol.
 
 use std::borrow::Cow;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::error::Error;
 
 /// From section [2.3.1.1 of RFC 3501](https://tools.ietf.org/html/rfc3501#section-2.3.1.1).
 ///
@@ -77,7 +82,12 @@ use std::borrow::Cow;
 /// >      fetch data items) must never change.  This does not
 /// >      include message numbers, nor does it include attributes
 /// >      that can be set by a `STORE` command (e.g., `FLAGS`).
-pub type Uid = u32;
+///
+/// UIDs and [message sequence numbers](Seq) are distinct types, so that one can not be passed
+/// where the other is expected, e.g. to [`Session::uid_fetch`](crate::Session::uid_fetch). Both
+/// convert from and to `u32`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Uid(pub u32);
 
 /// From section [2.3.1.2 of RFC 3501](https://tools.ietf.org/html/rfc3501#section-2.3.1.2).
 ///
@@ -103,7 +113,8 @@ pub type Uid = u32;
 /// Another example, if message 287 in a 523 message mailbox has UID
 /// 12345, there are exactly 286 messages which have lesser UIDs and 236
 /// messages which have greater UIDs.
-pub type Seq = u32;
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seq(pub u32);
 
 /// With the exception of [`Flag::Custom`], these flags are system flags that are pre-defined in
 /// [RFC 3501 section 2.3.2](https://tools.ietf.org/html/rfc3501#section-2.3.2). All system flags
@@ -144,10 +155,16 @@ pub enum Flag<'a> {
     /// If multiple connections have the same mailbox selected simultaneously, it is undefined
     /// which of these connections will see newly-arrived messages with `\Recent` set and which
     /// will see it without `\Recent` set.
+    ///
+    /// It is returned by [`Fetch::flags`], but can not be set or removed with
+    /// [`Session::store`](crate::Session::store), and servers ignore or reject it in
+    /// [`Session::append`](crate::Session::append).  IMAP4rev2 removed it, so such servers never
+    /// set it.
     Recent,
 
-    /// The [`Mailbox::permanent_flags`] can include this special flag (`\*`), which indicates that
-    /// it is possible to create new keywords by attempting to store those flags in the mailbox.
+    /// The `PERMANENTFLAGS` of a mailbox can include this special flag (`\*`), which indicates
+    /// that it is possible to create new keywords by attempting to store those flags in the
+    /// mailbox.  It is reported as [`PermanentFlags::can_create_keywords`].
     MayCreate,
 
     /// A non-standard user- or server-defined flag.
@@ -155,20 +172,69 @@ pub enum Flag<'a> {
 }
 
 impl Flag<'static> {
+    /// Parses a system flag, ignoring case as flags are case-insensitive, e.g. `\RECENT`.
     fn system(s: &str) -> Option<Self> {
-        match s {
-            "\\Seen" => Some(Flag::Seen),
-            "\\Answered" => Some(Flag::Answered),
-            "\\Flagged" => Some(Flag::Flagged),
-            "\\Deleted" => Some(Flag::Deleted),
-            "\\Draft" => Some(Flag::Draft),
-            "\\Recent" => Some(Flag::Recent),
-            "\\*" => Some(Flag::MayCreate),
-            _ => None,
-        }
+        let flags = [
+            ("\\Seen", Flag::Seen),
+            ("\\Answered", Flag::Answered),
+            ("\\Flagged", Flag::Flagged),
+            ("\\Deleted", Flag::Deleted),
+            ("\\Draft", Flag::Draft),
+            ("\\Recent", Flag::Recent),
+            ("\\*", Flag::MayCreate),
+        ];
+        flags
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, flag)| flag.clone())
+    }
+}
+
+impl fmt::Display for Flag<'_> {
+    /// Writes the flag as it is sent to the server, e.g. `\Seen` or `$Forwarded`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Flag::Seen => "\\Seen",
+            Flag::Answered => "\\Answered",
+            Flag::Flagged => "\\Flagged",
+            Flag::Deleted => "\\Deleted",
+            Flag::Draft => "\\Draft",
+            Flag::Recent => "\\Recent",
+            Flag::MayCreate => "\\*",
+            Flag::Custom(flag) => flag,
+        })
+    }
+}
+
+impl FromStr for Flag<'static> {
+    type Err = Error;
+
+    /// Parses a flag as it is sent to the server, which is the inverse of `Display`.
+    ///
+    /// System flags start with `\`, any other flag is a [`Flag::Custom`] keyword, such as
+    /// `$Forwarded`.  Both have to be [atoms](https://tools.ietf.org/html/rfc3501#section-9),
+    /// which may not be empty or contain spaces, control characters, non-ASCII characters or any
+    /// of `(){%*"\]`, except for the `\` that starts a system flag and the special flag `\*`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        validate_flag(s)?;
+        Ok(Flag::from(s.to_string()))
     }
 }
 
+/// Checks that `flag` can be sent to the server as is, see [`Flag::from_str`].
+pub(crate) fn validate_flag(flag: &str) -> Result<(), Error> {
+    if Flag::system(flag).is_some() {
+        return Ok(());
+    }
+    let atom = flag.strip_prefix('\\').unwrap_or(flag);
+    let is_atom_char =
+        |c: char| c.is_ascii() && !c.is_ascii_control() && !" (){%*\"\\]".contains(c);
+    if atom.is_empty() || !atom.chars().all(is_atom_char) {
+        return Err(Error::InvalidFlag(flag.to_string()));
+    }
+    Ok(())
+}
+
 impl<'a> From<String> for Flag<'a> {
     fn from(s: String) -> Self {
         if let Some(f) = Flag::system(&s) {
@@ -189,15 +255,68 @@ impl<'a> From<&'a str> for Flag<'a> {
     }
 }
 
+mod sequence_set;
+pub use self::sequence_set::SequenceSet;
+
 mod mailbox;
-pub use self::mailbox::Mailbox;
+pub use self::mailbox::{Mailbox, PermanentFlags};
 
 mod fetch;
+pub(crate) use self::fetch::parse_section_spec;
 pub use self::fetch::Fetch;
 
+mod fetch_query;
+pub use self::fetch_query::FetchQuery;
+
 mod name;
 pub use self::name::{Name, NameAttribute};
 
+mod append;
+pub use self::append::{AppendMessage, AppendUid, CatenatePart};
+
+mod copy;
+pub use self::copy::CopyUid;
+
+mod metadata;
+pub use self::metadata::{MetadataDepth, MetadataOptions};
+
+mod quota;
+pub use self::quota::{Quota, QuotaResource, QuotaResourceName, QuotaRoot};
+
+mod acl;
+pub use self::acl::{Acl, AclEntry, AclRights, ListRights, MyRights};
+
+mod command_result;
+pub use self::command_result::CommandResult;
+
+mod greeting;
+pub use self::greeting::Greeting;
+
+mod response_code;
+pub use self::response_code::ResponseCode;
+
+mod search;
+pub use self::search::{FuzzySearch, Within};
+
+mod thread;
+pub use self::thread::ThreadNode;
+
+mod sync;
+pub use self::sync::{SyncDelta, SyncState};
+
+mod store;
+pub(crate) use self::store::{flags_query, parse_modified, validate_store_query};
+pub use self::store::{ConditionalStore, StoreMode};
+
+mod pipeline;
+pub use self::pipeline::PipelineResponses;
+
+mod notify;
+pub use self::notify::{NotifyEvent, NotifyFilter, NotifySpec};
+
+mod urlauth;
+pub use self::urlauth::UrlFetch;
+
 mod capabilities;
 pub use self::capabilities::{Capabilities, Capability};
 
@@ -208,7 +327,7 @@ mod id_generator;
 pub(crate) use self::id_generator::IdGenerator;
 
 mod response_data;
-pub(crate) use self::response_data::ResponseData;
+pub use self::response_data::ResponseData;
 
 mod request;
 pub(crate) use self::request::Request;
@@ -271,6 +390,83 @@ pub enum UnsolicitedResponse {
     /// sequence numbers 9, 8, 7, 6, and 5.
     // TODO: the spec doesn't seem to say anything about when these may be received as unsolicited?
     Expunge(u32),
+    /// A [`VANISHED` response](https://tools.ietf.org/html/rfc7162#section-3.2.10), which servers
+    /// send instead of `EXPUNGE` once `QRESYNC` is [enabled](crate::Session::enable), and in
+    /// answer to `UID FETCH` with the `VANISHED` modifier, e.g.
+    /// `UID FETCH 1:* (FLAGS) (CHANGEDSINCE 12345 VANISHED)`.
+    Vanished {
+        /// Whether the response is `VANISHED (EARLIER)`, which lists messages expunged before,
+        /// as opposed to messages that were just expunged, which also reduces the number of
+        /// messages in the mailbox.
+        earlier: bool,
+        /// The UIDs of the messages as ranges, e.g. `1:3,5` as `[1..=3, 5..=5]`.  They are not
+        /// expanded, as a single range may cover billions of UIDs, so check whether a known
+        /// message vanished with [`RangeInclusive::contains`].
+        uids: Vec<RangeInclusive<Uid>>,
+    },
+    /// A response with the [`ALERT` response code](https://tools.ietf.org/html/rfc3501#section-7.1),
+    /// whose text the client must present to the user, e.g. "Your mailbox is full".
+    Alert(String),
+    /// A response about the mailbox `mailbox`, received while [`Session::notify`] is active.
+    ///
+    /// `response` is the [`Recent`](UnsolicitedResponse::Recent),
+    /// [`Exists`](UnsolicitedResponse::Exists), [`Expunge`](UnsolicitedResponse::Expunge),
+    /// [`Vanished`](UnsolicitedResponse::Vanished) or `FETCH`
+    /// ([`Other`](UnsolicitedResponse::Other)) response the server sent for the selected
+    /// mailbox.  Changes to other mailboxes are reported as
+    /// [`Status`](UnsolicitedResponse::Status) responses.
+    ///
+    /// [`Session::notify`]: crate::Session::notify
+    Notification {
+        /// The mailbox that the response is for.
+        mailbox: String,
+        /// The response.
+        response: Box<UnsolicitedResponse>,
+    },
     /// Any other kind of unsolicted response.
     Other(ResponseData),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_round_trip() {
+        let flags = [
+            Flag::Seen,
+            Flag::Answered,
+            Flag::Flagged,
+            Flag::Deleted,
+            Flag::Draft,
+            Flag::Recent,
+            Flag::MayCreate,
+            Flag::Custom("$Forwarded".into()),
+            Flag::Custom("$label1".into()),
+            Flag::Custom("Junk.Spam-Score_7+&'!#~".into()),
+            Flag::Custom("\\Important".into()),
+        ];
+        for flag in flags {
+            let wire = flag.to_string();
+            assert_eq!(wire.parse::<Flag<'_>>().unwrap(), flag, "{}", wire);
+            assert_eq!(Flag::from(wire), flag);
+        }
+        assert_eq!(Flag::Seen.to_string(), "\\Seen");
+        assert_eq!(Flag::MayCreate.to_string(), "\\*");
+        assert_eq!(Flag::from("\\RECENT"), Flag::Recent);
+        assert_eq!(Flag::from("\\seen"), Flag::Seen);
+    }
+
+    #[test]
+    fn flag_invalid() {
+        for flag in [
+            "", "\\", "my flag", "(", "a)", "{5}", "%", "*", "a\"b", "a\\b", "]", "\r\n", "é",
+        ] {
+            assert!(
+                matches!(flag.parse::<Flag<'_>>(), Err(Error::InvalidFlag(_))),
+                "{:?}",
+                flag
+            );
+        }
+    }
+}
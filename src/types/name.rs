@@ -1,14 +1,18 @@
 use std::borrow::Cow;
+use std::fmt;
 
 use imap_proto::{MailboxDatum, Response};
 
 use crate::types::ResponseData;
+use crate::utf7::decode_mailbox_name;
 
 rental! {
     pub mod rents {
         use super::*;
 
         /// A name that matches a `LIST` or `LSUB` command.
+        ///
+        /// Like [`Fetch`](crate::types::Fetch), a `Name` owns the response it was parsed from.
         #[rental(debug, covariant)]
         pub struct Name {
             response: Box<ResponseData>,
@@ -22,6 +26,7 @@ pub struct InnerName<'a> {
     attributes: Vec<NameAttribute<'a>>,
     delimiter: Option<&'a str>,
     name: &'a str,
+    utf8: bool,
 }
 
 pub use rents::Name;
@@ -62,6 +67,18 @@ impl NameAttribute<'static> {
     }
 }
 
+impl fmt::Display for NameAttribute<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameAttribute::NoInferiors => f.write_str("\\Noinferiors"),
+            NameAttribute::NoSelect => f.write_str("\\Noselect"),
+            NameAttribute::Marked => f.write_str("\\Marked"),
+            NameAttribute::Unmarked => f.write_str("\\Unmarked"),
+            NameAttribute::Custom(s) => f.write_str(s),
+        }
+    }
+}
+
 impl<'a> From<String> for NameAttribute<'a> {
     fn from(s: String) -> Self {
         if let Some(f) = NameAttribute::system(&s) {
@@ -82,20 +99,43 @@ impl<'a> From<&'a str> for NameAttribute<'a> {
     }
 }
 
+impl<'a> From<&'a imap_proto::NameAttribute<'a>> for NameAttribute<'a> {
+    fn from(attribute: &'a imap_proto::NameAttribute<'a>) -> Self {
+        use imap_proto::NameAttribute as Proto;
+
+        match attribute {
+            Proto::NoInferiors => NameAttribute::NoInferiors,
+            Proto::NoSelect => NameAttribute::NoSelect,
+            Proto::Marked => NameAttribute::Marked,
+            Proto::Unmarked => NameAttribute::Unmarked,
+            Proto::All => NameAttribute::Custom(Cow::Borrowed("\\All")),
+            Proto::Archive => NameAttribute::Custom(Cow::Borrowed("\\Archive")),
+            Proto::Drafts => NameAttribute::Custom(Cow::Borrowed("\\Drafts")),
+            Proto::Flagged => NameAttribute::Custom(Cow::Borrowed("\\Flagged")),
+            Proto::Junk => NameAttribute::Custom(Cow::Borrowed("\\Junk")),
+            Proto::Sent => NameAttribute::Custom(Cow::Borrowed("\\Sent")),
+            Proto::Trash => NameAttribute::Custom(Cow::Borrowed("\\Trash")),
+            Proto::Extension(s) => NameAttribute::from(s.as_ref()),
+            // Attributes added to `imap-proto` later on, which have no textual representation.
+            other => NameAttribute::Custom(Cow::Owned(format!("{:?}", other))),
+        }
+    }
+}
+
 impl Name {
-    pub(crate) fn from_mailbox_data(resp: ResponseData) -> Self {
+    /// `utf8` tells whether the name is sent as UTF-8 instead of modified UTF-7 because
+    /// `UTF8=ACCEPT` is enabled.
+    pub(crate) fn from_mailbox_data(resp: ResponseData, utf8: bool) -> Self {
         Name::new(Box::new(resp), |response| match response.parsed() {
             Response::MailboxData(MailboxDatum::List {
-                flags,
+                name_attributes,
                 delimiter,
                 name,
             }) => InnerName {
-                attributes: flags
-                    .iter()
-                    .map(|s| NameAttribute::from(s.as_ref()))
-                    .collect(),
+                attributes: name_attributes.iter().map(NameAttribute::from).collect(),
                 delimiter: delimiter.as_deref(),
                 name,
+                utf8,
             },
             _ => panic!("cannot construct from non mailbox data"),
         })
@@ -118,7 +158,23 @@ impl Name {
     /// reference in `LIST` and `LSUB` commands. Unless [`NameAttribute::NoSelect`] is indicated,
     /// the name is also valid as an argument for commands, such as `SELECT`, that accept mailbox
     /// names.
-    pub fn name(&self) -> &str {
+    ///
+    /// The name is decoded from [modified UTF-7](crate::decode_mailbox_name), as the commands
+    /// taking mailbox names encode them again. Names that are not valid modified UTF-7 are
+    /// returned as they are, and so are names received after enabling `UTF8=ACCEPT` with
+    /// [`Session::enable`](crate::Session::enable).
+    pub fn name(&self) -> String {
+        let raw = self.name_raw();
+        if self.suffix().utf8 {
+            return raw.to_string();
+        }
+        decode_mailbox_name(raw).unwrap_or_else(|_| raw.to_string())
+    }
+
+    /// The name as sent by the server, which is encoded in [modified
+    /// UTF-7](https://tools.ietf.org/html/rfc3501#section-5.1.3) unless `UTF8=ACCEPT` is
+    /// enabled.
+    pub fn name_raw(&self) -> &str {
         self.suffix().name
     }
 }
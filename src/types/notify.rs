@@ -0,0 +1,115 @@
+use std::fmt;
+
+/// An event that the server can be asked to report with
+/// [`Session::notify`](crate::Session::notify), see [RFC 5465 section
+/// 5](https://tools.ietf.org/html/rfc5465#section-5).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotifyEvent {
+    /// A message was added to a mailbox.
+    MessageNew,
+    /// A message was expunged from a mailbox.
+    MessageExpunge,
+    /// The flags of a message changed.
+    FlagChange,
+    /// An annotation of a message changed.
+    AnnotationChange,
+    /// A mailbox was created, deleted or renamed.
+    MailboxName,
+    /// A mailbox was subscribed or unsubscribed.
+    SubscriptionChange,
+    /// Metadata of a mailbox changed.
+    MailboxMetadataChange,
+    /// Metadata of the server changed.
+    ServerMetadataChange,
+}
+
+impl fmt::Display for NotifyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            NotifyEvent::MessageNew => "MessageNew",
+            NotifyEvent::MessageExpunge => "MessageExpunge",
+            NotifyEvent::FlagChange => "FlagChange",
+            NotifyEvent::AnnotationChange => "AnnotationChange",
+            NotifyEvent::MailboxName => "MailboxName",
+            NotifyEvent::SubscriptionChange => "SubscriptionChange",
+            NotifyEvent::MailboxMetadataChange => "MailboxMetadataChange",
+            NotifyEvent::ServerMetadataChange => "ServerMetadataChange",
+        })
+    }
+}
+
+/// The mailboxes that a group of [`NotifyEvent`]s is requested for, see [RFC 5465 section
+/// 6](https://tools.ietf.org/html/rfc5465#section-6).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NotifyFilter {
+    /// The selected mailbox, whose changes are reported as they happen.
+    Selected,
+    /// The selected mailbox, whose expunges are only reported when the client could handle them.
+    SelectedDelayed,
+    /// All mailboxes that receive new mail.
+    Inboxes,
+    /// All mailboxes in the personal namespace.
+    Personal,
+    /// All subscribed mailboxes.
+    Subscribed,
+    /// The given mailboxes and all mailboxes below them.
+    Subtree(Vec<String>),
+    /// The given mailboxes.
+    Mailboxes(Vec<String>),
+}
+
+/// The events that [`Session::notify`](crate::Session::notify) asks the server to report.
+///
+/// ```
+/// use async_imap::types::{NotifyEvent, NotifySpec};
+///
+/// // NOTIFY SET (selected (MessageNew MessageExpunge)) (mailboxes ("INBOX") (MessageNew))
+/// let spec = NotifySpec::new()
+///     .selected(&[NotifyEvent::MessageNew, NotifyEvent::MessageExpunge])
+///     .mailboxes(&["INBOX"], &[NotifyEvent::MessageNew]);
+/// ```
+///
+/// If `MessageNew` is requested for the selected mailbox, `MessageExpunge` has to be requested
+/// as well, and `FlagChange` requires both; servers reject other combinations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NotifySpec {
+    pub(crate) status: bool,
+    pub(crate) groups: Vec<(NotifyFilter, Vec<NotifyEvent>)>,
+}
+
+impl NotifySpec {
+    /// Creates a specification without any events, which turns notifications off.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the server to send a `STATUS` response for each mailbox matched by the filters right
+    /// away, so that the client starts from the current state.
+    pub fn status(mut self) -> Self {
+        self.status = true;
+        self
+    }
+
+    /// Requests `events` for the selected mailbox.
+    pub fn selected(self, events: &[NotifyEvent]) -> Self {
+        self.filter(NotifyFilter::Selected, events)
+    }
+
+    /// Requests `events` for the mailboxes named `mailboxes`.
+    pub fn mailboxes<S: AsRef<str>>(self, mailboxes: &[S], events: &[NotifyEvent]) -> Self {
+        let mailboxes = mailboxes.iter().map(|m| m.as_ref().to_string()).collect();
+        self.filter(NotifyFilter::Mailboxes(mailboxes), events)
+    }
+
+    /// Requests `events` for the mailboxes matched by `filter`.  Without events, the server
+    /// reports nothing for these mailboxes, even if they are matched by another filter.
+    pub fn filter(mut self, filter: NotifyFilter, events: &[NotifyEvent]) -> Self {
+        self.groups.push((filter, events.to_vec()));
+        self
+    }
+
+    /// Whether no events are requested.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
@@ -0,0 +1,14 @@
+use super::ResponseData;
+use crate::error::Result;
+
+/// The responses to commands sent with [`Session::pipeline`](crate::Session::pipeline).
+#[derive(Debug)]
+pub struct PipelineResponses {
+    /// The untagged responses received while the commands ran, in the order they were received.
+    ///
+    /// Servers may interleave the responses to pipelined commands, so they can not be
+    /// attributed to a single command.
+    pub untagged: Vec<ResponseData>,
+    /// The outcome of each command, in the order the commands were given.
+    pub results: Vec<Result<()>>,
+}
@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// The name of a resource limited by a quota, see
+/// [RFC 2087 section 3](https://tools.ietf.org/html/rfc2087#section-3).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum QuotaResourceName {
+    /// Sum of the messages' `RFC822.SIZE`, in units of 1024 octets.
+    Storage,
+    /// Number of messages.
+    Message,
+    /// Any other resource.
+    Atom(String),
+}
+
+impl From<&imap_proto::QuotaResourceName<'_>> for QuotaResourceName {
+    fn from(name: &imap_proto::QuotaResourceName<'_>) -> Self {
+        match name {
+            imap_proto::QuotaResourceName::Storage => QuotaResourceName::Storage,
+            imap_proto::QuotaResourceName::Message => QuotaResourceName::Message,
+            imap_proto::QuotaResourceName::Atom(atom) => QuotaResourceName::Atom(atom.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for QuotaResourceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaResourceName::Storage => write!(f, "STORAGE"),
+            QuotaResourceName::Message => write!(f, "MESSAGE"),
+            QuotaResourceName::Atom(atom) => write!(f, "{}", atom),
+        }
+    }
+}
+
+/// The usage and limit of a single resource in a [`Quota`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QuotaResource {
+    /// The limited resource.
+    pub name: QuotaResourceName,
+    /// The current usage of the resource.
+    pub usage: u64,
+    /// The limit of the resource.
+    pub limit: u64,
+}
+
+impl From<&imap_proto::QuotaResource<'_>> for QuotaResource {
+    fn from(resource: &imap_proto::QuotaResource<'_>) -> Self {
+        QuotaResource {
+            name: QuotaResourceName::from(&resource.name),
+            usage: resource.usage,
+            limit: resource.limit,
+        }
+    }
+}
+
+/// The resource limits of a quota root, from a
+/// [`QUOTA` response](https://tools.ietf.org/html/rfc2087#section-5.1).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Quota {
+    /// The name of the quota root.
+    pub root_name: String,
+    /// The limited resources.
+    pub resources: Vec<QuotaResource>,
+}
+
+impl From<&imap_proto::Quota<'_>> for Quota {
+    fn from(quota: &imap_proto::Quota<'_>) -> Self {
+        Quota {
+            root_name: quota.root_name.to_string(),
+            resources: quota.resources.iter().map(QuotaResource::from).collect(),
+        }
+    }
+}
+
+/// The quota roots of a mailbox, from a
+/// [`QUOTAROOT` response](https://tools.ietf.org/html/rfc2087#section-5.2).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct QuotaRoot {
+    /// The name of the mailbox.
+    pub mailbox_name: String,
+    /// The names of the quota roots the mailbox belongs to.
+    pub quota_root_names: Vec<String>,
+}
+
+impl From<&imap_proto::QuotaRoot<'_>> for QuotaRoot {
+    fn from(quota_root: &imap_proto::QuotaRoot<'_>) -> Self {
+        QuotaRoot {
+            mailbox_name: quota_root.mailbox_name.to_string(),
+            quota_root_names: quota_root
+                .quota_root_names
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+
+/// The response code of a `NO` or `BAD` response, as carried by
+/// [`Error::No`](crate::error::Error::No) and [`Error::Bad`](crate::error::Error::Bad).
+///
+/// Most variants are the codes of [RFC 5530](https://tools.ietf.org/html/rfc5530), which tell
+/// why a command failed, e.g. to distinguish wrong credentials from a disabled account or a
+/// temporary outage:
+///
+/// ```
+/// use async_imap::error::Error;
+/// use async_imap::types::ResponseCode;
+///
+/// fn explain(err: &Error) -> &'static str {
+///     match err {
+///         Error::No {
+///             code: Some(ResponseCode::AuthenticationFailed),
+///             ..
+///         } => "wrong user name or password",
+///         Error::No {
+///             code: Some(ResponseCode::Unavailable),
+///             ..
+///         } => "the server is unavailable, try again later",
+///         _ => "login failed",
+///     }
+/// }
+/// ```
+///
+/// Codes without a variant, including those with arguments, are kept as
+/// [`ResponseCode::Other`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ResponseCode {
+    /// `UNAVAILABLE`: a subsystem needed for the command is temporarily unavailable, so the
+    /// command may succeed later.
+    Unavailable,
+    /// `AUTHENTICATIONFAILED`: the credentials are wrong.
+    AuthenticationFailed,
+    /// `AUTHORIZATIONFAILED`: the credentials are right, but the user may not act as the given
+    /// authorization identity.
+    AuthorizationFailed,
+    /// `EXPIRED`: the credentials or the account have expired.
+    Expired,
+    /// `PRIVACYREQUIRED`: the command requires an encrypted connection.
+    PrivacyRequired,
+    /// `CONTACTADMIN`: the user has to contact the administrator, e.g. because the account is
+    /// disabled.
+    ContactAdmin,
+    /// `NOPERM`: the user lacks the access rights for the command.  `NO` responses with this
+    /// code are reported as [`Error::NoPerm`](crate::error::Error::NoPerm).
+    NoPerm,
+    /// `INUSE`: the command can not be completed because another session uses the mailbox.
+    InUse,
+    /// `EXPUNGEISSUED`: another session expunged messages while the command ran.
+    ExpungeIssued,
+    /// `CORRUPTION`: the server found corrupted data.
+    Corruption,
+    /// `SERVERBUG`: the server ran into a bug.
+    ServerBug,
+    /// `CLIENTBUG`: the server considers the command to be a bug in the client.
+    ClientBug,
+    /// `CANNOT`: the command can never succeed, e.g. because the server does not allow a
+    /// mailbox name.
+    Cannot,
+    /// `LIMIT`: the command exceeds a limit of the server, such as the number of mailboxes.
+    Limit,
+    /// `OVERQUOTA`: the user is over a quota, see
+    /// [`Session::get_quota_root`](crate::Session::get_quota_root).
+    OverQuota,
+    /// `ALREADYEXISTS`: the mailbox or other object to be created already exists.
+    AlreadyExists,
+    /// `NONEXISTENT`: the mailbox or other object the command refers to does not exist.
+    NonExistent,
+    /// `TRYCREATE` of [RFC 3501](https://tools.ietf.org/html/rfc3501#section-7.1): the target
+    /// mailbox of `APPEND` or `COPY` does not exist, but could be created.
+    TryCreate,
+    /// Any other response code, as sent by the server without the brackets.
+    Other(String),
+}
+
+/// The codes with their own variant, as sent by the server.
+const CODES: &[(&str, ResponseCode)] = &[
+    ("UNAVAILABLE", ResponseCode::Unavailable),
+    ("AUTHENTICATIONFAILED", ResponseCode::AuthenticationFailed),
+    ("AUTHORIZATIONFAILED", ResponseCode::AuthorizationFailed),
+    ("EXPIRED", ResponseCode::Expired),
+    ("PRIVACYREQUIRED", ResponseCode::PrivacyRequired),
+    ("CONTACTADMIN", ResponseCode::ContactAdmin),
+    ("NOPERM", ResponseCode::NoPerm),
+    ("INUSE", ResponseCode::InUse),
+    ("EXPUNGEISSUED", ResponseCode::ExpungeIssued),
+    ("CORRUPTION", ResponseCode::Corruption),
+    ("SERVERBUG", ResponseCode::ServerBug),
+    ("CLIENTBUG", ResponseCode::ClientBug),
+    ("CANNOT", ResponseCode::Cannot),
+    ("LIMIT", ResponseCode::Limit),
+    ("OVERQUOTA", ResponseCode::OverQuota),
+    ("ALREADYEXISTS", ResponseCode::AlreadyExists),
+    ("NONEXISTENT", ResponseCode::NonExistent),
+    ("TRYCREATE", ResponseCode::TryCreate),
+];
+
+impl ResponseCode {
+    /// Parses a response code as sent by the server, without the brackets.
+    pub(crate) fn parse(code: &str) -> Self {
+        CODES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(code))
+            .map(|(_, code)| code.clone())
+            .unwrap_or_else(|| ResponseCode::Other(code.to_string()))
+    }
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let ResponseCode::Other(code) = self {
+            return f.write_str(code);
+        }
+        let (name, _) = CODES
+            .iter()
+            .find(|(_, code)| code == self)
+            .expect("all other codes are listed");
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ResponseCode::parse("AUTHENTICATIONFAILED"),
+            ResponseCode::AuthenticationFailed
+        );
+        assert_eq!(
+            ResponseCode::parse("nonexistent"),
+            ResponseCode::NonExistent
+        );
+        assert_eq!(
+            ResponseCode::parse("MODIFIED 7,9"),
+            ResponseCode::Other("MODIFIED 7,9".into())
+        );
+        for (name, code) in CODES {
+            assert_eq!(code.to_string(), *name);
+        }
+        assert_eq!(ResponseCode::Other("X-FOO".into()).to_string(), "X-FOO");
+    }
+}
@@ -3,8 +3,10 @@ use std::fmt;
 use byte_pool::Block;
 use imap_proto::{RequestId, Response};
 
+use crate::ext_parse::ExtensionAttribute;
+
 rental! {
-    pub mod rents {
+    mod rents {
         use super::*;
 
         #[rental(covariant)]
@@ -15,11 +17,16 @@ rental! {
     }
 }
 
-pub use rents::ResponseData;
+/// A response received from the server, together with the raw bytes it was parsed from.
+pub struct ResponseData {
+    inner: rents::ResponseData,
+    /// Data items parsed by this crate because `imap-proto` does not support them.
+    extensions: Vec<ExtensionAttribute>,
+}
 
 impl std::cmp::PartialEq for ResponseData {
     fn eq(&self, other: &Self) -> bool {
-        self.parsed() == other.parsed()
+        self.parsed() == other.parsed() && self.extensions == other.extensions
     }
 }
 
@@ -29,20 +36,51 @@ impl fmt::Debug for ResponseData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ResponseData")
             .field("raw", &self.head().len())
-            .field("response", self.suffix())
+            .field("response", self.inner.suffix())
+            .field("extensions", &self.extensions)
             .finish()
     }
 }
 
 impl ResponseData {
+    /// Creates a new `ResponseData` from the `raw` bytes and a function parsing them.
+    ///
+    /// On failure the error and the `raw` block are handed back.
+    pub(crate) fn try_new<E>(
+        raw: Block<'static>,
+        parse: impl for<'raw> FnOnce(&'raw [u8]) -> Result<Response<'raw>, E>,
+    ) -> Result<Self, rental::RentalError<E, Block<'static>>> {
+        let inner = rents::ResponseData::try_new(raw, |raw| parse(raw))?;
+        Ok(ResponseData {
+            inner,
+            extensions: Vec::new(),
+        })
+    }
+
+    pub(crate) fn with_extensions(mut self, extensions: Vec<ExtensionAttribute>) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// The raw bytes of the response.
+    pub fn head(&self) -> &[u8] {
+        self.inner.head()
+    }
+
+    /// The tag of a tagged response completing a command, `None` for other responses.
     pub fn request_id(&self) -> Option<&RequestId> {
-        match self.suffix() {
+        match self.inner.suffix() {
             Response::Done { ref tag, .. } => Some(tag),
             _ => None,
         }
     }
 
+    /// The response as parsed by `imap-proto`.
     pub fn parsed(&self) -> &Response<'_> {
-        self.suffix()
+        self.inner.suffix()
+    }
+
+    pub(crate) fn extensions(&self) -> &[ExtensionAttribute] {
+        &self.extensions
     }
 }
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+/// Format of dates in search keys such as `SINCE`, see `date` in [RFC 3501 section
+/// 9](https://tools.ietf.org/html/rfc3501#section-9).
+const DATE_FORMAT: &str = "%d-%b-%Y";
+
+/// A search key of the [`WITHIN` extension](https://tools.ietf.org/html/rfc5032), matching
+/// messages by the age of their internal date in seconds.
+///
+/// The key is rendered by its `Display` implementation, for example
+/// `Within::Younger(86400).to_string()` is `YOUNGER 86400`, and can be passed to
+/// [`Session::search`](crate::Session::search) as part of the query.  Servers that do not
+/// support `WITHIN` reject it; see
+/// [`Session::within_criterion`](crate::Session::within_criterion) for falling back to dates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Within {
+    /// `YOUNGER <interval>`: messages whose internal date is at most this many seconds ago.
+    Younger(u32),
+    /// `OLDER <interval>`: messages whose internal date is at least this many seconds ago.
+    Older(u32),
+}
+
+impl fmt::Display for Within {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Within::Younger(seconds) => write!(f, "YOUNGER {}", seconds),
+            Within::Older(seconds) => write!(f, "OLDER {}", seconds),
+        }
+    }
+}
+
+impl Within {
+    /// Approximates the key with a `SINCE` or `BEFORE` key for servers without `WITHIN`.
+    ///
+    /// These keys only compare the day of the internal date, in the server's timezone, so
+    /// `YOUNGER` becomes `SINCE` the day the interval started, which may match messages up to a
+    /// day older than requested, and `OLDER` becomes `BEFORE` that day, which may miss messages
+    /// up to a day older than requested.
+    pub fn to_date_criterion(self) -> String {
+        self.date_criterion_at(Utc::now())
+    }
+
+    fn date_criterion_at(self, now: DateTime<Utc>) -> String {
+        let (key, seconds) = match self {
+            Within::Younger(seconds) => ("SINCE", seconds),
+            Within::Older(seconds) => ("BEFORE", seconds),
+        };
+        let start = now - Duration::seconds(i64::from(seconds));
+        format!("{} {}", key, start.format(DATE_FORMAT))
+    }
+}
+
+/// The result of a [fuzzy search](crate::Session::fuzzy_search).
+///
+/// How closely messages have to match, and how their relevancy is scored, is up to the server,
+/// so scores are only comparable within the results of one server, and the same query may match
+/// different messages on different servers.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FuzzySearch {
+    /// The matching messages, in the order returned by the server.
+    pub ids: Vec<u32>,
+    /// The relevancy score, from 1 to 100, of each message in `ids`, if the server returned
+    /// scores.
+    pub relevancy: Option<Vec<u8>>,
+}
+
+impl FuzzySearch {
+    /// Returns the matching messages with their scores, most relevant first.  Without scores,
+    /// the messages are returned in the order of [`FuzzySearch::ids`].
+    pub fn ranked(&self) -> Vec<(u32, Option<u8>)> {
+        let mut ranked: Vec<_> = match &self.relevancy {
+            Some(scores) if scores.len() == self.ids.len() => self
+                .ids
+                .iter()
+                .zip(scores)
+                .map(|(id, score)| (*id, Some(*score)))
+                .collect(),
+            _ => self.ids.iter().map(|id| (*id, None)).collect(),
+        };
+        ranked.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn render() {
+        assert_eq!(Within::Younger(86400).to_string(), "YOUNGER 86400");
+        assert_eq!(Within::Older(3600).to_string(), "OLDER 3600");
+    }
+
+    #[test]
+    fn ranked() {
+        let search = FuzzySearch {
+            ids: vec![1, 2, 3, 5],
+            relevancy: Some(vec![4, 99, 42, 99]),
+        };
+        assert_eq!(
+            search.ranked(),
+            vec![(2, Some(99)), (5, Some(99)), (3, Some(42)), (1, Some(4))]
+        );
+
+        let search = FuzzySearch {
+            ids: vec![3, 1],
+            relevancy: None,
+        };
+        assert_eq!(search.ranked(), vec![(3, None), (1, None)]);
+    }
+
+    #[test]
+    fn date_criterion() {
+        let now = Utc.with_ymd_and_hms(2020, 3, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            Within::Younger(86400).date_criterion_at(now),
+            "SINCE 29-Feb-2020"
+        );
+        assert_eq!(
+            Within::Older(7 * 86400).date_criterion_at(now),
+            "BEFORE 23-Feb-2020"
+        );
+    }
+}
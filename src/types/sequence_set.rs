@@ -0,0 +1,373 @@
+use std::fmt;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::{RangeFrom, RangeInclusive};
+
+use super::{Seq, Uid};
+use crate::error::{Error, Result};
+
+/// A set of [message sequence numbers](Seq) or [UIDs](Uid), as passed to commands such as
+/// [`Session::fetch`](crate::Session::fetch) and [`Session::uid_fetch`](crate::Session::uid_fetch),
+/// see `sequence-set` in [RFC 3501 section 9](https://tools.ietf.org/html/rfc3501#section-9).
+///
+/// The type parameter tells which kind of numbers the set holds, so that a set built from
+/// sequence numbers can not be passed to a `UID` command and vice versa:
+///
+/// ```
+/// use async_imap::types::{Seq, SequenceSet, Uid};
+///
+/// let seqs: SequenceSet<Seq> = (Seq(1)..=Seq(5)).into();
+/// assert_eq!(seqs.to_string(), "1:5");
+/// let uids: SequenceSet<Uid> = (Uid(100)..).into();
+/// assert_eq!(uids.to_string(), "100:*");
+/// let uids: SequenceSet<Uid> = vec![Uid(3), Uid(7)].into();
+/// assert_eq!(uids.to_string(), "3,7");
+///
+/// let set = SequenceSet::new()
+///     .single(Seq(1))
+///     .single(Seq(3))
+///     .range(Seq(5), Seq(9))
+///     .single(Seq(4))
+///     .range_from(Seq(100));
+/// assert_eq!(set.to_string(), "1,3:9,100:*");
+/// ```
+///
+/// The set is kept sorted, with overlapping and adjacent ranges merged, so it is sent in its
+/// most compact form.
+///
+/// Sets can also be given as strings such as `"1,3:5"` or `"$"`, which can be used for either
+/// kind. Commands fail with [`Error::InvalidSequenceSet`] before sending anything if the set is
+/// empty, contains `0`, which is not a valid message number, or is not a valid sequence set.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SequenceSet<T = Seq> {
+    /// Sorted, non-overlapping and non-adjacent ranges.
+    ranges: Vec<(Bound, Bound)>,
+    /// Whether the set includes the result of the last `SEARCH` saved with `RETURN (SAVE)`,
+    /// see [RFC 5182](https://tools.ietf.org/html/rfc5182).
+    saved: bool,
+    /// Why the set is invalid, if it is.
+    invalid: Option<String>,
+    kind: PhantomData<T>,
+}
+
+/// The start or end of a range in a [`SequenceSet`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Bound {
+    Number(u32),
+    /// `*`, the largest number in use in the mailbox.
+    Last,
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bound::Number(number) => number.fmt(f),
+            Bound::Last => f.write_str("*"),
+        }
+    }
+}
+
+impl<T> Default for SequenceSet<T> {
+    fn default() -> Self {
+        SequenceSet {
+            ranges: Vec::new(),
+            saved: false,
+            invalid: None,
+            kind: PhantomData,
+        }
+    }
+}
+
+impl<T: Into<u32>> SequenceSet<T> {
+    /// Creates an empty set, to which messages are added with the other methods.
+    pub fn new() -> Self {
+        SequenceSet::default()
+    }
+
+    /// Adds the message `number`.
+    pub fn single(self, number: T) -> Self {
+        let number = Bound::Number(number.into());
+        self.insert(number, number)
+    }
+
+    /// Adds the messages from `start` to `end`, including both. `start` must not be larger
+    /// than `end`.
+    pub fn range(self, start: T, end: T) -> Self {
+        let (start, end) = (start.into(), end.into());
+        if start > end {
+            return self.invalidate(format!("empty range {}:{}", start, end));
+        }
+        self.insert(Bound::Number(start), Bound::Number(end))
+    }
+
+    /// Adds the messages from `start` to the last one in the mailbox, i.e. `<start>:*`.
+    pub fn range_from(self, start: T) -> Self {
+        self.insert(Bound::Number(start.into()), Bound::Last)
+    }
+}
+
+impl<T> SequenceSet<T> {
+    /// Adds all messages of `other`.
+    pub fn union(mut self, other: impl Into<SequenceSet<T>>) -> Self {
+        let other = other.into();
+        self.saved |= other.saved;
+        if self.invalid.is_none() {
+            self.invalid = other.invalid;
+        }
+        other
+            .ranges
+            .into_iter()
+            .fold(self, |set, (start, end)| set.insert(start, end))
+    }
+
+    /// Whether the set contains no messages.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty() && !self.saved
+    }
+
+    /// Returns the set as sent with a command, failing if it is not valid.
+    pub(crate) fn validated(&self) -> Result<String> {
+        if let Some(reason) = &self.invalid {
+            return Err(Error::InvalidSequenceSet(reason.clone()));
+        }
+        if self.is_empty() {
+            return Err(Error::InvalidSequenceSet("empty set".into()));
+        }
+        Ok(self.to_string())
+    }
+
+    fn insert(mut self, start: Bound, end: Bound) -> Self {
+        if start == Bound::Number(0) || end == Bound::Number(0) {
+            return self.invalidate("0 is not a valid message number".into());
+        }
+        let (mut start, mut end) = (start.min(end), start.max(end));
+        // Merge all ranges that overlap with or are adjacent to the new one.
+        self.ranges.retain(|&(s, e)| {
+            if adjacent_or_overlapping((s, e), (start, end)) {
+                start = start.min(s);
+                end = end.max(e);
+                false
+            } else {
+                true
+            }
+        });
+        let pos = self.ranges.partition_point(|&(s, _)| s < start);
+        self.ranges.insert(pos, (start, end));
+        self
+    }
+
+    fn invalidate(mut self, reason: String) -> Self {
+        if self.invalid.is_none() {
+            self.invalid = Some(reason);
+        }
+        self
+    }
+
+    /// Parses a sequence set given as a string.
+    fn parse(text: &str) -> Self {
+        let bound = |text: &str| match text {
+            "*" => Some(Bound::Last),
+            _ if text.bytes().all(|b| b.is_ascii_digit()) => text.parse().ok().map(Bound::Number),
+            _ => None,
+        };
+        let mut set = SequenceSet::default();
+        for member in text.split(',') {
+            if member == "$" {
+                set.saved = true;
+                continue;
+            }
+            let (start, end) = match member.split_once(':') {
+                Some((start, end)) => (bound(start), bound(end)),
+                None => (bound(member), bound(member)),
+            };
+            set = match (start, end) {
+                (Some(start), Some(end)) => set.insert(start, end),
+                _ => set.invalidate(format!("{:?} is not a valid sequence set", text)),
+            };
+        }
+        set
+    }
+}
+
+/// Whether the ranges `a` and `b` can be merged into one.
+fn adjacent_or_overlapping(a: (Bound, Bound), b: (Bound, Bound)) -> bool {
+    let (first, second) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+    match first.1 {
+        Bound::Last => true,
+        Bound::Number(end) => second.0 <= Bound::Number(end.saturating_add(1)),
+    }
+}
+
+impl<T> fmt::Display for SequenceSet<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        for (start, end) in &self.ranges {
+            if start == end {
+                write!(f, "{}{}", separator, start)?;
+            } else {
+                write!(f, "{}{}:{}", separator, start, end)?;
+            }
+            separator = ",";
+        }
+        if self.saved {
+            write!(f, "{}$", separator)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> From<&str> for SequenceSet<T> {
+    fn from(text: &str) -> Self {
+        SequenceSet::parse(text)
+    }
+}
+
+impl<T> From<String> for SequenceSet<T> {
+    fn from(text: String) -> Self {
+        SequenceSet::parse(&text)
+    }
+}
+
+impl<T> From<&String> for SequenceSet<T> {
+    fn from(text: &String) -> Self {
+        SequenceSet::parse(text)
+    }
+}
+
+/// Implements the conversions of a message number type and of sets of it.
+macro_rules! message_number {
+    ($name:ident) => {
+        impl From<u32> for $name {
+            fn from(number: u32) -> Self {
+                $name(number)
+            }
+        }
+
+        impl From<$name> for u32 {
+            fn from(number: $name) -> Self {
+                number.0
+            }
+        }
+
+        impl PartialEq<u32> for $name {
+            fn eq(&self, other: &u32) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<$name> for SequenceSet<$name> {
+            fn from(number: $name) -> Self {
+                SequenceSet::new().single(number)
+            }
+        }
+
+        /// The messages from the start to the end of the range.
+        impl From<RangeInclusive<$name>> for SequenceSet<$name> {
+            fn from(range: RangeInclusive<$name>) -> Self {
+                let (start, end) = range.into_inner();
+                SequenceSet::new().range(start, end)
+            }
+        }
+
+        /// The messages from the start of the range to the last one in the mailbox, i.e.
+        /// `<start>:*`.
+        impl From<RangeFrom<$name>> for SequenceSet<$name> {
+            fn from(range: RangeFrom<$name>) -> Self {
+                SequenceSet::new().range_from(range.start)
+            }
+        }
+
+        impl From<&[$name]> for SequenceSet<$name> {
+            fn from(numbers: &[$name]) -> Self {
+                numbers.iter().copied().collect()
+            }
+        }
+
+        impl From<Vec<$name>> for SequenceSet<$name> {
+            fn from(numbers: Vec<$name>) -> Self {
+                numbers.into_iter().collect()
+            }
+        }
+
+        impl FromIterator<$name> for SequenceSet<$name> {
+            fn from_iter<I: IntoIterator<Item = $name>>(numbers: I) -> Self {
+                numbers
+                    .into_iter()
+                    .fold(SequenceSet::new(), SequenceSet::single)
+            }
+        }
+    };
+}
+
+message_number!(Seq);
+message_number!(Uid);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        assert_eq!(SequenceSet::<Seq>::from(Seq(4)).to_string(), "4");
+        assert_eq!(SequenceSet::from(Uid(2)..=Uid(9)).to_string(), "2:9");
+        assert_eq!(SequenceSet::from(Seq(10)..).to_string(), "10:*");
+        assert_eq!(
+            [Uid(5), Uid(1)]
+                .iter()
+                .copied()
+                .collect::<SequenceSet<_>>()
+                .to_string(),
+            "1,5"
+        );
+        assert_eq!(SequenceSet::<Uid>::from("1:*,$").to_string(), "1:*,$");
+        assert_eq!(Seq::from(3), 3);
+        assert_eq!(u32::from(Uid(8)), 8);
+    }
+
+    #[test]
+    fn compact() {
+        let set = SequenceSet::new()
+            .single(Seq(7))
+            .range(Seq(1), Seq(3))
+            .single(Seq(4))
+            .range(Seq(9), Seq(12))
+            .single(Seq(8));
+        assert_eq!(set.validated().unwrap(), "1:4,7:12");
+
+        let set = SequenceSet::new()
+            .range(Uid(5), Uid(6))
+            .range_from(Uid(20))
+            .union("30:40,2")
+            .union(vec![Uid(7), Uid(19)]);
+        assert_eq!(set.validated().unwrap(), "2,5:7,19:*");
+
+        assert_eq!(SequenceSet::<Seq>::from("9:3,*").to_string(), "3:9,*");
+    }
+
+    #[test]
+    fn invalid() {
+        for set in [
+            SequenceSet::<Seq>::new(),
+            SequenceSet::new().single(Seq(0)),
+            SequenceSet::new().range(Seq(5), Seq(3)),
+            SequenceSet::new().single(Seq(1)).union("0:4"),
+            "".into(),
+            "1,".into(),
+            "1:x".into(),
+            "1 UID".into(),
+            "1\r\nA1 LOGOUT".into(),
+        ] {
+            assert!(
+                matches!(set.validated(), Err(Error::InvalidSequenceSet(_))),
+                "{:?}",
+                set
+            );
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use super::{validate_flag, Fetch, Flag};
+use crate::error::{Error, ParseError, Result};
+use crate::ext_parse::expand_sequence_set;
+
+/// How [`Session::store_flags`](crate::Session::store_flags) changes the flags of messages.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StoreMode {
+    /// Adds the flags, with `+FLAGS`.
+    Add,
+    /// Removes the flags, with `-FLAGS`.
+    Remove,
+    /// Replaces all flags with the given ones, with `FLAGS`.
+    Replace,
+}
+
+/// Returns the `STORE` query for changing flags with `mode`, checking that each flag is valid.
+pub(crate) fn flags_query(mode: StoreMode, flags: &[Flag<'_>]) -> Result<String> {
+    let flags = flags
+        .iter()
+        .map(|flag| {
+            let flag = flag.to_string();
+            validate_flag(&flag)?;
+            Ok(flag)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let item = match mode {
+        StoreMode::Add => "+FLAGS",
+        StoreMode::Remove => "-FLAGS",
+        StoreMode::Replace => "FLAGS",
+    };
+    Ok(format!("{} ({})", item, flags.join(" ")))
+}
+
+/// Checks the flags in a `STORE` query such as `+FLAGS.SILENT (\Seen $Forwarded)`, so that an
+/// invalid flag can not change the meaning of the command.  Queries for other data items are
+/// sent unchecked.
+pub(crate) fn validate_store_query(query: &str) -> Result<()> {
+    let item_end = query.find([' ', '(']).unwrap_or(query.len());
+    let item = query[..item_end].trim_start_matches(['+', '-']);
+    if !item.eq_ignore_ascii_case("FLAGS") && !item.eq_ignore_ascii_case("FLAGS.SILENT") {
+        return Ok(());
+    }
+    let list = query[item_end..].trim();
+    let list = list
+        .strip_prefix('(')
+        .and_then(|list| list.strip_suffix(')'))
+        .unwrap_or(list);
+    for flag in list.split(' ').filter(|flag| !flag.is_empty()) {
+        validate_flag(flag)?;
+    }
+    Ok(())
+}
+
+/// The result of a conditional `STORE` (see [RFC 7162 section
+/// 3.1.3](https://tools.ietf.org/html/rfc7162#section-3.1.3)), as sent by
+/// [`Session::store_unchanged_since`](crate::Session::store_unchanged_since).
+#[derive(Debug)]
+pub struct ConditionalStore {
+    /// The `FETCH` responses returned for the updated messages.
+    pub fetches: Vec<Fetch>,
+    /// The messages that were not updated because they were modified after the given
+    /// mod-sequence, as listed in the `MODIFIED` response code.  These are sequence numbers for
+    /// `STORE` and [`Uid`](super::Uid)s for `UID STORE`.
+    pub modified: Vec<u32>,
+}
+
+/// Parses the `MODIFIED` response code at the start of `information`, which `imap-proto` does
+/// not know about and therefore leaves in the text.  Returns `None` for other texts, and an
+/// error if the set of messages is not valid or too large to be listed, as the caller would
+/// otherwise assume that those messages were stored.
+pub(crate) fn parse_modified(information: &str) -> Option<Result<Vec<u32>>> {
+    let text = information.strip_prefix('[')?;
+    let (code, _) = text.split_once(']')?;
+    let (name, set) = code.split_once(' ')?;
+    if !name.eq_ignore_ascii_case("MODIFIED") {
+        return None;
+    }
+    Some(expand_sequence_set(set).ok_or_else(|| {
+        Error::Parse(ParseError::Unexpected(format!(
+            "invalid MODIFIED response code: {}",
+            set
+        )))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn flags() {
+        assert_eq!(
+            flags_query(
+                StoreMode::Add,
+                &[Flag::Seen, Flag::Custom("$Forwarded".into())]
+            )
+            .unwrap(),
+            "+FLAGS (\\Seen $Forwarded)"
+        );
+        assert_eq!(flags_query(StoreMode::Replace, &[]).unwrap(), "FLAGS ()");
+        assert!(matches!(
+            flags_query(StoreMode::Remove, &[Flag::Custom("my flag".into())]),
+            Err(Error::InvalidFlag(_))
+        ));
+
+        assert!(validate_store_query("+FLAGS.SILENT (\\Seen $label1)").is_ok());
+        assert!(validate_store_query("-flags \\Deleted").is_ok());
+        assert!(validate_store_query("X-GM-LABELS (\"my label\")").is_ok());
+        assert!(validate_store_query("+FLAGS (a(b))").is_err());
+        assert!(validate_store_query("FLAGS (\\Seen) (x)").is_err());
+        assert!(validate_store_query("+FLAGS (\\Seen\r\nA0002 DELETE INBOX)").is_err());
+    }
+
+    #[test]
+    fn modified() {
+        assert_eq!(
+            parse_modified("[MODIFIED 7,9] Conditional STORE failed").map(Result::unwrap),
+            Some(vec![7, 9])
+        );
+        assert_eq!(
+            parse_modified("[modified 3:1,8]").map(Result::unwrap),
+            Some(vec![1, 2, 3, 8])
+        );
+        assert!(parse_modified("[HIGHESTMODSEQ 9] done").is_none());
+        assert!(parse_modified("Conditional STORE failed").is_none());
+        assert!(matches!(
+            parse_modified("[MODIFIED 1:*] done"),
+            Some(Err(Error::Parse(_)))
+        ));
+        assert!(matches!(
+            parse_modified("[MODIFIED 1:4294967295] done"),
+            Some(Err(Error::Parse(_)))
+        ));
+    }
+}
@@ -0,0 +1,59 @@
+use super::{Flag, Mailbox, Uid};
+
+/// What a client knows about a mailbox from its last synchronization with
+/// [`Session::incremental_sync`](crate::Session::incremental_sync), to be stored along with the
+/// local copy of the mailbox.
+///
+/// For the first synchronization, only the name is set:
+///
+/// ```
+/// use async_imap::types::SyncState;
+///
+/// let state = SyncState::new("INBOX");
+/// assert!(state.known_uids.is_empty());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SyncState {
+    /// The name of the mailbox.
+    pub mailbox: String,
+    /// The [`Mailbox::uid_validity`] at the last synchronization.  If it changed since, the UIDs
+    /// are no longer valid and everything is synchronized again.
+    pub uid_validity: Option<u32>,
+    /// The [`Mailbox::highest_modseq`] at the last synchronization, with which servers
+    /// supporting `CONDSTORE` or `QRESYNC` only report what changed since.
+    pub highest_modseq: Option<u64>,
+    /// The UIDs of the messages the client knows about, in ascending order.
+    pub known_uids: Vec<Uid>,
+}
+
+impl SyncState {
+    /// Creates the state of a mailbox that was never synchronized.
+    pub fn new(mailbox: impl Into<String>) -> Self {
+        SyncState {
+            mailbox: mailbox.into(),
+            ..SyncState::default()
+        }
+    }
+}
+
+/// The changes to a mailbox since the [`SyncState`] passed to
+/// [`Session::incremental_sync`](crate::Session::incremental_sync).
+///
+/// All lists are ordered by UID.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncDelta {
+    /// The mailbox as returned by selecting it.
+    pub mailbox: Mailbox,
+    /// Whether the UID validity changed, so that all previously known messages are listed in
+    /// [`SyncDelta::vanished`] and all current ones in [`SyncDelta::new`].
+    pub uid_validity_changed: bool,
+    /// The messages that are not among the known UIDs, with their flags.
+    pub new: Vec<(Uid, Vec<Flag<'static>>)>,
+    /// The known messages whose flags changed, with their current flags.  Without `CONDSTORE`,
+    /// the server can not tell which flags changed, so all known messages are listed.
+    pub changed: Vec<(Uid, Vec<Flag<'static>>)>,
+    /// The known messages that were expunged.
+    pub vanished: Vec<Uid>,
+    /// The state to pass to the next synchronization once the changes are applied.
+    pub state: SyncState,
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use super::Seq;
+
+/// A message in the result of [`Session::thread`](crate::Session::thread), with the replies to
+/// it, see the [`THREAD` extension](https://tools.ietf.org/html/rfc5256#section-4).
+///
+/// `T` is [`Seq`] for [`Session::thread`](crate::Session::thread) and [`Uid`](super::Uid) for
+/// [`Session::uid_thread`](crate::Session::uid_thread).  The threads and the replies are kept in
+/// the order the server sent them in, which for the `ORDEREDSUBJECT` and `REFERENCES`
+/// algorithms is by the sent date of their first message:
+///
+/// ```
+/// use async_imap::types::{Seq, ThreadNode};
+///
+/// // The thread `(3 6 (4 23)(44 7 96))` of RFC 5256.
+/// let node = |id, children| ThreadNode { id: Some(Seq(id)), children };
+/// let thread = node(3, vec![node(6, vec![
+///     node(4, vec![node(23, vec![])]),
+///     node(44, vec![node(7, vec![node(96, vec![])])]),
+/// ])]);
+/// assert_eq!(
+///     thread.flatten_depth_first(),
+///     [3, 6, 4, 23, 44, 7, 96].iter().map(|&id| Seq(id)).collect::<Vec<_>>()
+/// );
+/// assert_eq!(
+///     thread.flatten_by_latest(),
+///     [3, 6, 44, 7, 96, 4, 23].iter().map(|&id| Seq(id)).collect::<Vec<_>>()
+/// );
+/// ```
+///
+/// A reply chain of thousands of messages is a tree of that depth, so the tree is never walked
+/// recursively, which could overflow the stack; this includes dropping, cloning, comparing and
+/// formatting it.
+pub struct ThreadNode<T = Seq> {
+    /// The message, or `None` if the server groups messages whose common parent is not in the
+    /// mailbox or did not match the search, as in `((3)(5))`.
+    pub id: Option<T>,
+    /// The replies to the message, in the order the server sent them in.
+    pub children: Vec<ThreadNode<T>>,
+}
+
+impl<T> ThreadNode<T> {
+    /// Lists the nodes of the thread in depth-first order, each with its number of replies,
+    /// which is enough to rebuild the thread.
+    fn walk(&self) -> impl Iterator<Item = (Option<&T>, usize)> + '_ {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some((node.id.as_ref(), node.children.len()))
+        })
+    }
+
+    /// Builds a thread from its nodes as listed by [`ThreadNode::walk`].
+    fn from_walk(nodes: impl Iterator<Item = (Option<T>, usize)>) -> Self {
+        let nodes: Vec<_> = nodes.collect();
+        // Going backwards, the replies to a node are built before it, with the first reply last.
+        let mut built: Vec<ThreadNode<T>> = Vec::new();
+        for (id, replies) in nodes.into_iter().rev() {
+            let children = (0..replies).filter_map(|_| built.pop()).collect();
+            built.push(ThreadNode { id, children });
+        }
+        built.pop().expect("a thread has at least one node")
+    }
+}
+
+impl<T: Copy + Ord> ThreadNode<T> {
+    /// Lists the messages of the thread in depth-first order: each message is followed by its
+    /// replies, and each reply by its own replies before the next reply, which is the order in
+    /// which a threaded message list shows them.  Nodes without a message are skipped.
+    pub fn flatten_depth_first(&self) -> Vec<T> {
+        self.walk().filter_map(|(id, _)| id.copied()).collect()
+    }
+
+    /// Lists the messages of the thread like [`ThreadNode::flatten_depth_first`], except that
+    /// the replies to a message are ordered by their latest message, the one with the highest
+    /// sequence number or UID, newest first.  As both grow with the arrival of messages, the
+    /// discussion that was last active comes right after the message it replies to.
+    pub fn flatten_by_latest(&self) -> Vec<T> {
+        let latest = self.latest();
+        let mut ids = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            ids.extend(node.id);
+            let mut children: Vec<_> = node.children.iter().collect();
+            // The sort is stable, so replies without any message keep the order of the server.
+            children.sort_by_key(|child| std::cmp::Reverse(latest[&(*child as *const Self)]));
+            stack.extend(children.into_iter().rev());
+        }
+        ids
+    }
+
+    /// The highest id in the thread of each node, keyed by its address.
+    fn latest(&self) -> HashMap<*const Self, Option<T>> {
+        let mut nodes = Vec::new();
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            nodes.push(node);
+            stack.extend(node.children.iter());
+        }
+        // Replies come after the message they reply to, so they are done first going backwards.
+        let mut latest = HashMap::with_capacity(nodes.len());
+        for node in nodes.into_iter().rev() {
+            let highest = node
+                .children
+                .iter()
+                .map(|child| latest[&(child as *const Self)])
+                .chain(Some(node.id))
+                .max()
+                .flatten();
+            latest.insert(node as *const Self, highest);
+        }
+        latest
+    }
+}
+
+impl ThreadNode<u32> {
+    /// Converts the ids of the thread as parsed from the response.
+    pub(crate) fn map<T>(self, f: fn(u32) -> T) -> ThreadNode<T> {
+        ThreadNode::from_walk(
+            self.walk()
+                .map(|(id, replies)| (id.copied().map(f), replies)),
+        )
+    }
+}
+
+impl<T> Drop for ThreadNode<T> {
+    fn drop(&mut self) {
+        // Take the replies apart first, so that each node is dropped without any.
+        let mut nodes = std::mem::take(&mut self.children);
+        while let Some(mut node) = nodes.pop() {
+            nodes.append(&mut node.children);
+        }
+    }
+}
+
+impl<T: Clone> Clone for ThreadNode<T> {
+    fn clone(&self) -> Self {
+        ThreadNode::from_walk(self.walk().map(|(id, replies)| (id.cloned(), replies)))
+    }
+}
+
+impl<T: PartialEq> PartialEq for ThreadNode<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.walk().eq(other.walk())
+    }
+}
+
+impl<T: Eq> Eq for ThreadNode<T> {}
+
+impl<T: Hash> Hash for ThreadNode<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for node in self.walk() {
+            node.hash(state);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ThreadNode<T> {
+    /// Writes the thread with each message in parentheses followed by its replies, e.g.
+    /// `(3 (6 (4 (23)) (44 (7 (96)))))`, and `None` for nodes without a message.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The number of replies left to write of each node that is not closed yet.
+        let mut open: Vec<usize> = Vec::new();
+        for (id, replies) in self.walk() {
+            if let Some(left) = open.last_mut() {
+                *left -= 1;
+                f.write_str(" ")?;
+            }
+            match id {
+                Some(id) => write!(f, "({:?}", id)?,
+                None => f.write_str("(None")?,
+            }
+            open.push(replies);
+            while open.last() == Some(&0) {
+                open.pop();
+                f.write_str(")")?;
+            }
+        }
+        Ok(())
+    }
+}
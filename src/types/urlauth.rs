@@ -0,0 +1,10 @@
+/// The data of a message or body part returned by
+/// [`Session::urlfetch`](crate::Session::urlfetch), see the [`URLAUTH`
+/// extension](https://tools.ietf.org/html/rfc4467#section-7.2).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UrlFetch {
+    /// The URL as it was requested.
+    pub url: String,
+    /// The data the URL refers to, or `None` if the server could not or would not resolve it.
+    pub data: Option<Vec<u8>>,
+}
@@ -0,0 +1,136 @@
+//! Modified UTF-7, the encoding of international mailbox names, see
+//! [RFC 3501 section 5.1.3](https://tools.ietf.org/html/rfc3501#section-5.1.3).
+
+use crate::error::{Error, ParseError, Result};
+
+/// Encodes a mailbox name in modified UTF-7, the form in which it is sent to the server.
+///
+/// Printable ASCII characters other than `&` are kept as they are, `&` becomes `&-`, and runs of
+/// all other characters are encoded as `&<modified base64 of UTF-16>-`.
+///
+/// ```
+/// use async_imap::encode_mailbox_name;
+///
+/// assert_eq!(encode_mailbox_name("Entwürfe"), "Entw&APw-rfe");
+/// assert_eq!(encode_mailbox_name("Tom & Jerry"), "Tom &- Jerry");
+/// ```
+pub fn encode_mailbox_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    let mut pending: Vec<u8> = Vec::new();
+    for c in name.chars() {
+        if is_direct(c) {
+            flush(&mut encoded, &mut pending);
+            if c == '&' {
+                encoded.push_str("&-");
+            } else {
+                encoded.push(c);
+            }
+        } else {
+            let mut units = [0; 2];
+            for unit in c.encode_utf16(&mut units) {
+                pending.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+    }
+    flush(&mut encoded, &mut pending);
+    encoded
+}
+
+/// Decodes a mailbox name received from the server from modified UTF-7.
+///
+/// Fails with [`ParseError::ModifiedUtf7`] if `name` is not valid modified UTF-7, which some
+/// servers send nonetheless; such names have to be used as they are.
+///
+/// ```
+/// use async_imap::decode_mailbox_name;
+///
+/// assert_eq!(decode_mailbox_name("Entw&APw-rfe").unwrap(), "Entwürfe");
+/// assert_eq!(decode_mailbox_name("Tom &- Jerry").unwrap(), "Tom & Jerry");
+/// ```
+pub fn decode_mailbox_name(name: &str) -> Result<String> {
+    let invalid = || Error::Parse(ParseError::ModifiedUtf7(name.to_string()));
+
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let end = rest.find('-').ok_or_else(invalid)?;
+        if end == 0 {
+            decoded.push('&');
+        } else {
+            let bytes =
+                base64::decode_config(&rest[..end], base64::IMAP_MUTF7).map_err(|_| invalid())?;
+            if bytes.len() % 2 != 0 {
+                return Err(invalid());
+            }
+            let units = bytes
+                .chunks(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+            for c in std::char::decode_utf16(units) {
+                let c = c.map_err(|_| invalid())?;
+                // Characters that can be represented directly must not be encoded.
+                if is_direct(c) {
+                    return Err(invalid());
+                }
+                decoded.push(c);
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    if !rest.chars().all(is_direct) {
+        return Err(invalid());
+    }
+    decoded.push_str(rest);
+    Ok(decoded)
+}
+
+/// Whether `c` represents itself in modified UTF-7 (`&` being escaped as `&-`).
+fn is_direct(c: char) -> bool {
+    (' '..='~').contains(&c)
+}
+
+/// Appends the UTF-16 code units in `pending` as a base64 encoded run, if there are any.
+fn flush(encoded: &mut String, pending: &mut Vec<u8>) {
+    if !pending.is_empty() {
+        encoded.push('&');
+        encoded.push_str(&base64::encode_config(&pending, base64::IMAP_MUTF7));
+        encoded.push('-');
+        pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        for (decoded, encoded) in &[
+            ("INBOX", "INBOX"),
+            ("&", "&-"),
+            ("a&b&&c", "a&-b&-&-c"),
+            ("Отправленные", "&BB4EQgQ,BEAEMAQyBDsENQQ9BD0ESwQ1-"),
+            ("~peter/mail/台北/日本語", "~peter/mail/&U,BTFw-/&ZeVnLIqe-"),
+            ("Entwürfe & Co", "Entw&APw-rfe &- Co"),
+            ("😀", "&2D3eAA-"),
+        ] {
+            assert_eq!(encode_mailbox_name(decoded), *encoded);
+            assert_eq!(decode_mailbox_name(encoded).unwrap(), *decoded);
+        }
+    }
+
+    #[test]
+    fn decode_invalid() {
+        for name in &["&", "&Jjo", "&AGE-", "&2D0-", "Entwürfe", "&Jjo!-"] {
+            assert!(
+                matches!(
+                    decode_mailbox_name(name),
+                    Err(Error::Parse(ParseError::ModifiedUtf7(_)))
+                ),
+                "{} was decoded",
+                name
+            );
+        }
+    }
+}
@@ -1,14 +1,15 @@
 use std::borrow::Cow;
 use std::time::Duration;
 
+use async_imap::types::Seq;
 use async_imap::Session;
 use async_native_tls::TlsConnector;
 use async_std::net::TcpStream;
 use async_std::prelude::*;
 use async_std::task;
 
-fn native_tls() -> async_native_tls::TlsConnector {
-    async_native_tls::TlsConnector::new()
+fn native_tls() -> async_native_tls_03::TlsConnector {
+    async_native_tls_03::TlsConnector::new()
         .danger_accept_invalid_certs(true)
         .danger_accept_invalid_hostnames(true)
 }
@@ -110,7 +111,7 @@ fn make_email(to: &str) -> async_smtp::SendableEmail {
             vec![to.parse().unwrap()],
         )
         .unwrap(),
-        message_id.to_string(),
+        message_id,
         format!("To: <{}>\r\nFrom: <sender@localhost>\r\nMessage-ID: <{}.msg@localhost>\r\nSubject: My first e-mail\r\n\r\nHello world from SMTP", to, message_id),
     )
 }
@@ -138,14 +139,14 @@ fn inbox() {
         let inbox = c.search("ALL").await.unwrap();
         // and the one message should have the first message sequence number
         assert_eq!(inbox.len(), 1);
-        assert!(inbox.contains(&1));
+        assert!(inbox.contains(&Seq(1)));
 
         // we should also get two unsolicited responses: Exists and Recent
         c.noop().await.unwrap();
         println!("noop done");
         let mut unsolicited = Vec::new();
-        while !c.unsolicited_responses.is_empty() {
-            unsolicited.push(c.unsolicited_responses.recv().await.unwrap());
+        while let Ok(response) = c.unsolicited_responses.try_recv() {
+            unsolicited.push(response);
         }
 
         assert_eq!(unsolicited.len(), 2);
@@ -224,8 +225,8 @@ fn inbox_uid() {
         // we should also get two unsolicited responses: Exists and Recent
         c.noop().await.unwrap();
         let mut unsolicited = Vec::new();
-        while !c.unsolicited_responses.is_empty() {
-            unsolicited.push(c.unsolicited_responses.recv().await.unwrap());
+        while let Ok(response) = c.unsolicited_responses.try_recv() {
+            unsolicited.push(response);
         }
 
         assert_eq!(unsolicited.len(), 2);
@@ -238,7 +239,7 @@ fn inbox_uid() {
 
         // let's see that we can also fetch the e-mail
         let fetch: Vec<_> = c
-            .uid_fetch(format!("{}", uid), "(ALL UID)")
+            .uid_fetch(uid, "(ALL UID)")
             .await
             .unwrap()
             .collect::<Result<_, _>>()
@@ -0,0 +1,53 @@
+use async_imap::mock_stream::MockStream;
+use async_imap::types::{Flag, Uid};
+use async_std::prelude::*;
+
+#[async_std::test]
+async fn select_and_fetch() {
+    let stream = MockStream::new(b"* OK [CAPABILITY IMAP4rev1 AUTH=PLAIN] ready\r\n".to_vec())
+        .with_reply(b"A0001 OK [CAPABILITY IMAP4rev1 UIDPLUS] Logged in\r\n")
+        .with_reply(
+            b"* FLAGS (\\Seen \\Deleted)\r\n\
+              * 2 EXISTS\r\n\
+              * OK [UIDVALIDITY 3857529045] UIDs valid\r\n\
+              * OK [UIDNEXT 9] Predicted next UID\r\n\
+              A0002 OK [READ-WRITE] SELECT completed\r\n",
+        )
+        .with_reply(
+            b"* 1 FETCH (UID 7 FLAGS (\\Seen) BODY[] {5}\r\nhello)\r\n\
+              * 2 FETCH (UID 8 FLAGS () BODY[] {5}\r\nworld)\r\n\
+              A0003 OK FETCH completed\r\n",
+        )
+        .with_reply(b"* BYE Logging out\r\nA0004 OK LOGOUT completed\r\n");
+
+    let client = async_imap::Client::from_stream(stream).await.unwrap();
+    let mut session = client
+        .login("user", "secret")
+        .await
+        .map_err(|e| e.0)
+        .unwrap();
+
+    let mailbox = session.select("INBOX").await.unwrap();
+    assert_eq!(mailbox.exists, 2);
+    assert_eq!(mailbox.uid_next, Some(Uid(9)));
+
+    let messages = session
+        .fetch("1:2", "(UID FLAGS BODY[])")
+        .await
+        .unwrap()
+        .collect::<async_imap::error::Result<Vec<_>>>()
+        .await
+        .unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].uid, Some(Uid(7)));
+    assert_eq!(messages[0].flags().collect::<Vec<_>>(), [Flag::Seen]);
+    assert_eq!(messages[1].body(), Some(&b"world"[..]));
+
+    assert_eq!(
+        std::str::from_utf8(&session.get_ref().written_buf).unwrap(),
+        "A0001 LOGIN \"user\" \"secret\"\r\n\
+         A0002 SELECT \"INBOX\"\r\n\
+         A0003 FETCH 1:2 (UID FLAGS BODY[])\r\n"
+    );
+    session.logout().await.unwrap();
+}